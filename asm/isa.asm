@@ -34,6 +34,10 @@
     NOP  => 0x0000
     HALT => 0x0100
     HALT { code: u8 } => 0x01 @ code
+    SYS  => 0x0200
+    EI   => 0x0300
+    DI   => 0x0400
+    RETI => 0x0500
     MOV { dst: register } { src: register } => 0x10 @ dst @ src
     ADD { dst: register } { src: register } => 0x11 @ dst @ src
     SUB { dst: register } { src: register } => 0x12 @ dst @ src
@@ -55,5 +59,5 @@
     PUSH { src: register } => 0x42 @ src @ 0x0
     POP { dst: register } => 0x43 @ dst @ 0x0
     LD { dst: register } [{ hi: register }:{ lo: register }] => 0x5 @ dst @ hi @ lo
-    ST [{ hi: register }:{ lo: register }] { dst: register } => 0x6 @ dst @ hi @ lo
+    ST [{ hi: register }:{ lo: register }] { src: register } => 0x6 @ src @ hi @ lo
 }
@@ -1,22 +1,272 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+};
+
 use clap::Parser;
 use mb8::{
-    dev::gpu::registers::{TTY_COLS, TTY_ROWS},
+    debug::Symbols,
+    dev::{bus::BusBuilder, disk::DRIVES},
+    gdb::{GdbStub, Pipe},
+    history::History,
+    mpu::Mpu,
+    serial::SerialBridge,
+    trace::{BinaryTracer, NoopTracer, TextTracer, Tracer},
     vm,
 };
-use mb8_cli::config;
-use mb8_cli::{tty::Tty, vmrun};
+use mb8_asm::assemble_program;
+use mb8_cli::config::{self, MachineArgs, Serial, TraceFormat};
+use mb8_cli::{debug::Repl, filesystem::makefs, vmrun};
+use mb8_isa::disasm::disassemble;
 use mb8c::compile;
 
+/// Open the instruction trace output selected on the command line.
+fn tracer(path: Option<&Path>, format: TraceFormat) -> std::io::Result<Box<dyn Tracer>> {
+    let Some(path) = path else {
+        return Ok(Box::new(NoopTracer));
+    };
+    let out: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let out = BufWriter::new(out);
+    Ok(match format {
+        TraceFormat::Text => Box::new(TextTracer::new(out)),
+        TraceFormat::Binary => Box::new(BinaryTracer::new(out)),
+    })
+}
+
+/// Machine with the memory map, ROM policy and disks selected on the command line.
+fn machine(args: &MachineArgs) -> vm::VirtualMachine {
+    let mut vm = vm::VirtualMachine::new(BusBuilder::with_banks(args.banks).build());
+    vm.devices.rom().write_policy = args.rom_writes.into();
+    vm.mpu = args.mpu.then(Mpu::standard);
+    if args.disks.len() > DRIVES {
+        eprintln!("At most {DRIVES} disk images can be attached");
+        std::process::exit(1);
+    }
+    for (drive, path) in args.disks.iter().enumerate() {
+        if let Err(err) = vm.devices.disk().attach(drive, path) {
+            eprintln!("Failed to open disk image {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    }
+    vm
+}
+
+/// Load the kernel and user programs like `run` does, without starting the machine.
+fn load(
+    mut vm: vm::VirtualMachine,
+    kernel: &Path,
+    user: Vec<PathBuf>,
+    seed: Option<u16>,
+) -> vm::VirtualMachine {
+    let rom = match std::fs::read(kernel) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("Failed to read executable file: {err}");
+            std::process::exit(1);
+        }
+    };
+    vm.load_rom(&rom);
+    vm.devices.rand().number = (seed.unwrap_or(1) as u8).max(1);
+    makefs(user, &mut vm);
+    vm
+}
+
+/// Open the host end of the serial port selected on the command line.
+fn serial(serial: &Serial) -> SerialBridge {
+    let bridge = match serial {
+        Serial::Stdio => Ok(SerialBridge::stdio()),
+        Serial::Tcp(addr) => serial_client(addr),
+    };
+    match bridge {
+        Ok(bridge) => bridge,
+        Err(err) => {
+            eprintln!("Failed to open serial port: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn serial_client(listen: &str) -> std::io::Result<SerialBridge> {
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("Waiting for a serial client on {}", listener.local_addr()?);
+    let (bridge, peer) = SerialBridge::tcp(&listener)?;
+    eprintln!("Serial client connected from {peer}");
+    Ok(bridge)
+}
+
+/// Serve a single GDB session on `listen` or on stdio.
+fn gdb(vm: &mut vm::VirtualMachine, listen: &str, stdio: bool) {
+    if let Err(err) = serve_gdb(vm, listen, stdio) {
+        eprintln!("GDB session failed: {err}");
+        std::process::exit(1);
+    }
+}
+
+fn serve_gdb(vm: &mut vm::VirtualMachine, listen: &str, stdio: bool) -> std::io::Result<()> {
+    if stdio {
+        let pipe = Pipe {
+            input: std::io::stdin().lock(),
+            output: std::io::stdout().lock(),
+        };
+        return GdbStub::new(pipe).serve(vm);
+    }
+    let listener = TcpListener::bind(listen)?;
+    eprintln!("Waiting for GDB on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    eprintln!("GDB connected from {peer}");
+    // Packets are small and every one waits for an answer
+    stream.set_nodelay(true)?;
+    GdbStub::new(stream).serve(vm)
+}
+
+/// Load the kernel and user programs like `run` does and start the debugger on stdin.
+fn debug(
+    vm: vm::VirtualMachine,
+    kernel: &Path,
+    user: Vec<PathBuf>,
+    symbols: Option<&Path>,
+    seed: Option<u16>,
+) {
+    let symbols = match symbols.map(std::fs::read_to_string).transpose() {
+        Ok(text) => match Symbols::parse(text.as_deref().unwrap_or_default()) {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                eprintln!("Failed to parse symbol file: {err}");
+                std::process::exit(1);
+            }
+        },
+        Err(err) => {
+            eprintln!("Failed to read symbol file: {err}");
+            std::process::exit(1);
+        }
+    };
+    let vm = load(vm, kernel, user, seed);
+    let mut repl = Repl::new(vm, symbols);
+    if let Err(err) = repl.run(std::io::stdin().lock(), &mut std::io::stdout()) {
+        eprintln!("Debugger I/O error: {err}");
+        std::process::exit(1);
+    }
+}
+
+/// Assemble `source`, writing the binary and optionally a symbol file.
+fn asm(source: &Path, output: Option<PathBuf>, symbols: Option<PathBuf>) {
+    let output = output.unwrap_or_else(|| source.with_extension("bin"));
+    let program = match assemble_program(source) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("Assembly error: {err}");
+            std::process::exit(1);
+        }
+    };
+    if let Err(err) = std::fs::write(&output, &program.binary) {
+        eprintln!("Failed to write output file: {err}");
+        std::process::exit(1);
+    }
+    if let Some(symbols) = symbols {
+        if let Err(err) = std::fs::write(symbols, program.symbols()) {
+            eprintln!("Failed to write symbol file: {err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print the disassembly of `binary` loaded at `base`.
+fn disasm(binary: &Path, base: u16) {
+    let bytes = match std::fs::read(binary) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Failed to read binary file: {err}");
+            std::process::exit(1);
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    for line in disassemble(&bytes, base) {
+        // Stop quietly when the output is piped into a pager that exits early
+        if writeln!(stdout, "{line}").is_err() {
+            break;
+        }
+    }
+}
+
 fn main() {
     let cli = config::Cli::parse();
 
     match cli.command {
-        config::Commands::Run { kernel, user } => {
-            let vm = vm::VirtualMachine::default();
-            let tty = Tty::new(TTY_COLS as usize, TTY_ROWS as usize, 1024);
-            let mut vm_desk = vmrun::VmRun::new(vm, tty);
+        config::Commands::Run {
+            kernel,
+            user,
+            clock,
+            trace,
+            trace_format,
+            snapshot,
+            wav,
+            serial: serial_port,
+            joystick,
+            machine: args,
+        } => {
+            let machine = machine(&args);
+            let tracer = match tracer(trace.as_deref(), trace_format) {
+                Ok(tracer) => tracer,
+                Err(err) => {
+                    eprintln!("Failed to open trace file: {err}");
+                    std::process::exit(1);
+                }
+            };
+            let vm = vm::VirtualMachine {
+                clock_hz: clock,
+                tracer,
+                ..machine
+            };
+            let mut vm_desk = vmrun::VmRun::new(vm);
+            vm_desk.snapshot = snapshot;
+            vm_desk.wav = wav;
+            vm_desk.serial = serial_port.as_ref().map(serial);
+            vm_desk.joystick = joystick;
             vm_desk.run_desktop(kernel, user, cli.seed);
         }
+        config::Commands::Console {
+            kernel,
+            user,
+            clock,
+            serial: serial_port,
+            machine: args,
+        } => {
+            let machine = machine(&args);
+            let mut vm_console = vmrun::VmRun::new(vm::VirtualMachine {
+                clock_hz: clock,
+                ..machine
+            });
+            vm_console.serial = Some(serial(&serial_port));
+            vm_console.run_console(kernel, user, cli.seed);
+        }
+        config::Commands::Debug {
+            kernel,
+            user,
+            symbols,
+            machine: args,
+        } => {
+            let machine = machine(&args);
+            debug(machine, &kernel, user, symbols.as_deref(), cli.seed);
+        }
+        config::Commands::Gdb {
+            kernel,
+            user,
+            listen,
+            stdio,
+            record,
+            machine: args,
+        } => {
+            let machine = machine(&args);
+            let mut vm = load(machine, &kernel, user, cli.seed);
+            vm.history = record.map(History::new);
+            gdb(&mut vm, &listen, stdio);
+        }
         config::Commands::Compile { source } => {
             let code = match std::fs::read_to_string(source) {
                 Ok(code) => code,
@@ -32,5 +282,11 @@ fn main() {
                 }
             }
         }
+        config::Commands::Asm {
+            source,
+            output,
+            symbols,
+        } => asm(&source, output, symbols),
+        config::Commands::Disasm { binary, base } => disasm(&binary, base),
     }
 }
@@ -1,6 +1,15 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use mb8::{
+    dev::{expansion::DEFAULT_BANKS, rom::WritePolicy},
+    vm::DEFAULT_CLOCK_HZ,
+};
+
+use crate::{
+    joystick::{Keymap, DEFAULT_KEYMAP},
+    vmrun::DEFAULT_SNAPSHOT,
+};
 
 #[derive(Parser, Debug)]
 #[command(name = "mb8", version, about = "MB8 VM")]
@@ -10,6 +19,26 @@ pub struct Cli {
     pub seed: Option<u16>,
 }
 
+/// Options for the machine the VM subcommands start.
+#[derive(Args, Debug)]
+pub struct MachineArgs {
+    /// Number of 8 KiB expansion memory banks
+    #[arg(long, default_value_t = DEFAULT_BANKS)]
+    pub banks: u8,
+
+    /// What happens to stores into the ROM
+    #[arg(long, value_enum, default_value_t = RomWrites::Fault)]
+    pub rom_writes: RomWrites,
+
+    /// Protect the kernel from user programs with the memory protection unit
+    #[arg(long)]
+    pub mpu: bool,
+
+    /// Disk image file for the next drive, created if missing (repeat for drives 1-3)
+    #[arg(long = "disk", value_name = "IMAGE")]
+    pub disks: Vec<PathBuf>,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Run an executable file for the VM
@@ -19,10 +48,194 @@ pub enum Commands {
 
         /// Path to the user spaace
         user: Vec<PathBuf>,
+
+        /// CPU clock frequency in Hz
+        #[arg(
+            long,
+            default_value_t = DEFAULT_CLOCK_HZ,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        clock: u32,
+
+        /// Write a trace of every executed instruction to a file (`-` for stdout)
+        #[arg(long)]
+        trace: Option<PathBuf>,
+
+        /// Format of the instruction trace
+        #[arg(long, value_enum, default_value_t = TraceFormat::Text)]
+        trace_format: TraceFormat,
+
+        /// Snapshot file for quick-save (F5) and quick-load (F9)
+        #[arg(long, default_value = DEFAULT_SNAPSHOT)]
+        snapshot: PathBuf,
+
+        /// Record the sound output to a WAV file, written when the window closes
+        #[arg(long)]
+        wav: Option<PathBuf>,
+
+        /// Connect the serial port to `stdio` or to a client on `tcp:ADDR`
+        #[arg(long, value_parser = parse_serial)]
+        serial: Option<Serial>,
+
+        /// Keys for joystick port 0, in the order up, down, left, right, fire
+        #[arg(long, default_value = DEFAULT_KEYMAP, value_parser = Keymap::parse)]
+        joystick: Keymap,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+    /// Run an executable file without a window, talking over the serial port
+    Console {
+        /// Path to the executable file
+        kernel: PathBuf,
+
+        /// Path to the user space
+        user: Vec<PathBuf>,
+
+        /// CPU clock frequency in Hz
+        #[arg(
+            long,
+            default_value_t = DEFAULT_CLOCK_HZ,
+            value_parser = clap::value_parser!(u32).range(1..)
+        )]
+        clock: u32,
+
+        /// Connect the serial port to `stdio` or to a client on `tcp:ADDR`
+        #[arg(long, default_value = "stdio", value_parser = parse_serial)]
+        serial: Serial,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+    /// Debug an executable file interactively
+    Debug {
+        /// Path to the executable file
+        kernel: PathBuf,
+
+        /// Path to the user space
+        user: Vec<PathBuf>,
+
+        /// Symbol file written by `asm --symbols`
+        #[arg(short, long)]
+        symbols: Option<PathBuf>,
+
+        #[command(flatten)]
+        machine: MachineArgs,
+    },
+    /// Serve the GDB remote protocol for an executable file
+    Gdb {
+        /// Path to the executable file
+        kernel: PathBuf,
+
+        /// Path to the user space
+        user: Vec<PathBuf>,
+
+        /// Address to listen on for the debugger
+        #[arg(long, default_value = "127.0.0.1:1234")]
+        listen: String,
+
+        /// Talk to the debugger over stdin and stdout instead of a socket
+        #[arg(long)]
+        stdio: bool,
+
+        /// Record the last N steps so the debugger can run backwards
+        #[arg(long, value_name = "N")]
+        record: Option<usize>,
+
+        #[command(flatten)]
+        machine: MachineArgs,
     },
     /// Compile a source file to an executable file
     Compile {
         /// Path to the source file
         source: PathBuf,
     },
+    /// Assemble a source file to a binary file
+    Asm {
+        /// Path to the source file
+        source: PathBuf,
+
+        /// Path to the output file (defaults to the source file with a `.bin` extension)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Also write the label addresses to a symbol file
+        #[arg(long)]
+        symbols: Option<PathBuf>,
+    },
+    /// Disassemble a binary file
+    Disasm {
+        /// Path to the binary file
+        binary: PathBuf,
+
+        /// Address the binary is loaded at (e.g. `0xE000` for the kernel, `0x1000` for user programs)
+        #[arg(short, long, default_value = "0", value_parser = parse_address)]
+        base: u16,
+    },
+}
+
+/// Format of the instruction trace written by `run --trace`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction
+    Text,
+    /// Compact binary records
+    Binary,
+}
+
+/// What happens to stores into the ROM, selected with `--rom-writes`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RomWrites {
+    /// Stop the VM with a ROM write fault
+    Fault,
+    /// Drop the store
+    Ignore,
+}
+
+impl From<RomWrites> for WritePolicy {
+    fn from(value: RomWrites) -> Self {
+        match value {
+            RomWrites::Fault => WritePolicy::Fault,
+            RomWrites::Ignore => WritePolicy::Ignore,
+        }
+    }
+}
+
+/// Host end of the serial port, selected with `--serial`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Serial {
+    /// The process's stdin and stdout
+    Stdio,
+    /// The first client connecting to the address
+    Tcp(String),
+}
+
+/// Parse `stdio` or `tcp:ADDR`.
+///
+/// # Errors
+/// Returns an error if the value names neither.
+pub fn parse_serial(value: &str) -> Result<Serial, String> {
+    match value.strip_prefix("tcp:") {
+        Some("") => Err("missing address after `tcp:`".to_string()),
+        Some(addr) => Ok(Serial::Tcp(addr.to_string())),
+        None if value == "stdio" => Ok(Serial::Stdio),
+        None => Err(format!(
+            "invalid serial port `{value}`: expected `stdio` or `tcp:ADDR`"
+        )),
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal address.
+///
+/// # Errors
+/// Returns an error if the value is not a valid 16-bit address.
+pub fn parse_address(value: &str) -> Result<u16, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map_err(|err| format!("invalid address `{value}`: {err}"))
 }
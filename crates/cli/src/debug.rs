@@ -0,0 +1,499 @@
+use std::io::{self, BufRead, Write};
+
+use mb8::{
+    debug::{Breakpoint, Compare, Condition, Debugger, Stop, Symbols, Watch, Watchpoint},
+    history::{History, DEFAULT_CAPACITY},
+    mpu::Mode,
+    trace::Access,
+    vm::VirtualMachine,
+};
+use mb8_asm::parser::parse_register;
+use mb8_isa::{
+    disasm::disassemble,
+    registers::{
+        flags::{C_FLAG, N_FLAG, Z_FLAG},
+        Register,
+    },
+};
+
+use crate::config::parse_address;
+
+/// Instructions `continue`, `next` and `finish` run before giving up.
+pub const DEFAULT_LIMIT: u64 = 10_000_000;
+
+const HELP: &str = "\
+step [n]           execute n instructions (s)
+next               step over CALL (n)
+finish             run until the current function returns (f)
+continue           run until a breakpoint, watchpoint or fault (c)
+record [n|off]     record the last n steps (100000 by default) for reverse execution
+back [n]           undo n recorded steps (bs)
+rcontinue          run backwards to a breakpoint or watched write (rc)
+who LOC            show the last recorded write to LOC
+break LOC [if REG OP VALUE]
+                   stop before LOC, optionally when e.g. `R0 == 0x41` (b)
+watch LOC [r|w|rw] stop after an access to LOC, writes by default (w)
+delete ID          remove a breakpoint or watchpoint (d)
+info               list breakpoints and watchpoints (i)
+regs               show registers (r)
+x LOC [len]        dump memory
+dis [LOC] [n]      disassemble n instructions, from the PC by default (u)
+sym NAME|ADDR      resolve a label or an address
+quit               leave the debugger (q)
+LOC is an address (`0xE000`, `57344`) or a label from the symbol file (`main`, `main+4`).";
+
+/// Outcome of a debugger command.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+/// Line-based debugger front end for `mb8 debug`.
+#[derive(Debug)]
+pub struct Repl {
+    pub vm: VirtualMachine,
+    pub debugger: Debugger,
+    pub symbols: Symbols,
+    /// Instructions `continue`, `next` and `finish` run before giving up.
+    pub limit: u64,
+}
+
+impl Repl {
+    #[must_use]
+    pub fn new(vm: VirtualMachine, symbols: Symbols) -> Self {
+        Self {
+            vm,
+            debugger: Debugger::default(),
+            symbols,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    /// Read commands until `quit` or the end of the input.
+    ///
+    /// # Errors
+    /// Returns an error if reading the input or writing the output fails.
+    pub fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        self.show_location(out)?;
+        write!(out, "(mb8) ")?;
+        out.flush()?;
+        for line in input.lines() {
+            if self.command(&line?, out)? == Flow::Quit {
+                return Ok(());
+            }
+            write!(out, "(mb8) ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    /// Execute a single command line.
+    ///
+    /// # Errors
+    /// Returns an error if writing the output fails. Invalid commands are reported in the output.
+    pub fn command(&mut self, line: &str, out: &mut impl Write) -> io::Result<Flow> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let Some((&name, args)) = args.split_first() else {
+            return Ok(Flow::Continue);
+        };
+        let result = match name {
+            "step" | "s" => self.step(args, out),
+            "next" | "n" => {
+                let stop = self.debugger.step_over(&mut self.vm, self.limit);
+                self.show_stop(stop, out)
+            }
+            "finish" | "f" => {
+                let stop = self.debugger.step_out(&mut self.vm, self.limit);
+                self.show_stop(stop, out)
+            }
+            "continue" | "c" => {
+                let stop = self.debugger.resume(&mut self.vm, self.limit);
+                self.show_stop(stop, out)
+            }
+            "record" => self.record(args, out),
+            "back" | "bs" => self.step_back(args, out),
+            "rcontinue" | "rc" => {
+                let stop = self.debugger.reverse_continue(&mut self.vm);
+                self.show_stop(stop, out)
+            }
+            "who" => self.who(args, out),
+            "break" | "b" => self.add_breakpoint(args, out),
+            "watch" | "w" => self.add_watchpoint(args, out),
+            "delete" | "d" => self.delete(args, out),
+            "info" | "i" => self.info(out).map_err(Into::into),
+            "regs" | "r" => self.regs(out).map_err(Into::into),
+            "x" => self.dump(args, out),
+            "dis" | "u" => self.disassemble(args, out),
+            "sym" => self.resolve(args, out),
+            "help" | "h" | "?" => writeln!(out, "{HELP}").map_err(Into::into),
+            "quit" | "q" => return Ok(Flow::Quit),
+            _ => Err(Error::Usage(format!(
+                "unknown command `{name}`, try `help`"
+            ))),
+        };
+        match result {
+            Ok(()) => {}
+            Err(Error::Usage(message)) => writeln!(out, "error: {message}")?,
+            Err(Error::Io(err)) => return Err(err),
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn step(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let count = match args {
+            [] => 1,
+            [count] => count
+                .parse()
+                .map_err(|_| Error::Usage(format!("invalid count `{count}`")))?,
+            _ => return Err(usage("step [n]")),
+        };
+        let mut stop = Stop::Step;
+        for _ in 0..count {
+            stop = self.debugger.step_into(&mut self.vm);
+            if stop != Stop::Step {
+                break;
+            }
+        }
+        self.show_stop(stop, out)
+    }
+
+    fn record(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let capacity = match args {
+            [] => DEFAULT_CAPACITY,
+            ["off"] => {
+                self.vm.history = None;
+                writeln!(out, "Recording off")?;
+                return Ok(());
+            }
+            [count] => count
+                .parse()
+                .map_err(|_| Error::Usage(format!("invalid count `{count}`")))?,
+            _ => return Err(usage("record [n|off]")),
+        };
+        self.vm.history = Some(History::new(capacity));
+        writeln!(out, "Recording the last {capacity} steps")?;
+        Ok(())
+    }
+
+    fn step_back(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        if self.vm.history.is_none() {
+            return Err(Error::Usage(
+                "not recording, use `record` first".to_string(),
+            ));
+        }
+        let count = match args {
+            [] => 1,
+            [count] => count
+                .parse()
+                .map_err(|_| Error::Usage(format!("invalid count `{count}`")))?,
+            _ => return Err(usage("back [n]")),
+        };
+        let mut stop = Stop::Step;
+        for _ in 0..count {
+            stop = self.debugger.step_back(&mut self.vm);
+            if stop != Stop::Step {
+                break;
+            }
+        }
+        self.show_stop(stop, out)
+    }
+
+    fn who(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let [loc] = args else {
+            return Err(usage("who LOC"));
+        };
+        let addr = self.location(loc)?;
+        let Some(history) = &self.vm.history else {
+            return Err(Error::Usage(
+                "not recording, use `record` first".to_string(),
+            ));
+        };
+        match history.last_write(addr) {
+            Some((step, write)) => writeln!(
+                out,
+                "{} written by {} at cycle {}: {:02X} -> {:02X}",
+                self.describe(addr),
+                self.describe(step.pc),
+                step.cycles,
+                write.old,
+                write.new
+            )?,
+            None => writeln!(
+                out,
+                "{} not written since recording started",
+                self.describe(addr)
+            )?,
+        }
+        Ok(())
+    }
+
+    fn add_breakpoint(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let (addr, condition) = match args {
+            [loc] => (self.location(loc)?, None),
+            [loc, "if", register, compare, value] => (
+                self.location(loc)?,
+                Some(parse_condition(register, compare, value)?),
+            ),
+            _ => return Err(usage("break LOC [if REG OP VALUE]")),
+        };
+        let id = self.debugger.add_breakpoint(Breakpoint { addr, condition });
+        writeln!(out, "Breakpoint {id} at {}", self.describe(addr))?;
+        Ok(())
+    }
+
+    fn add_watchpoint(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let (loc, watch) = match args {
+            [loc] | [loc, "w"] => (loc, Watch::Write),
+            [loc, "r"] => (loc, Watch::Read),
+            [loc, "rw"] => (loc, Watch::Access),
+            _ => return Err(usage("watch LOC [r|w|rw]")),
+        };
+        let addr = self.location(loc)?;
+        let id = self.debugger.add_watchpoint(Watchpoint { addr, watch });
+        writeln!(out, "Watchpoint {id} on {}", self.describe(addr))?;
+        Ok(())
+    }
+
+    fn delete(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let [id] = args else {
+            return Err(usage("delete ID"));
+        };
+        let id = id
+            .parse()
+            .map_err(|_| Error::Usage(format!("invalid id `{id}`")))?;
+        if !self.debugger.remove(id) {
+            return Err(Error::Usage(format!("no breakpoint or watchpoint {id}")));
+        }
+        writeln!(out, "Deleted {id}")?;
+        Ok(())
+    }
+
+    fn info(&self, out: &mut impl Write) -> io::Result<()> {
+        for (id, breakpoint) in self.debugger.breakpoints() {
+            write!(out, "{id:>3}  break  {}", self.describe(breakpoint.addr))?;
+            if let Some(condition) = breakpoint.condition {
+                write!(out, " if {condition}")?;
+            }
+            writeln!(out)?;
+        }
+        for (id, watchpoint) in self.debugger.watchpoints() {
+            let watch = match watchpoint.watch {
+                Watch::Read => "r",
+                Watch::Write => "w",
+                Watch::Access => "rw",
+            };
+            writeln!(
+                out,
+                "{id:>3}  watch  {} {watch}",
+                self.describe(watchpoint.addr)
+            )?;
+        }
+        Ok(())
+    }
+
+    fn regs(&self, out: &mut impl Write) -> io::Result<()> {
+        for (first, values) in (0..).step_by(8).zip(self.vm.registers.registers.chunks(8)) {
+            let line: Vec<String> = values
+                .iter()
+                .zip(first..)
+                .map(|(value, index)| format!("R{index:<2}={value:02X}"))
+                .collect();
+            writeln!(out, "{}", line.join("  "))?;
+        }
+        let flags = self.vm.registers.read(Register::F);
+        let flag = |mask: u8, name: char| if flags & mask == 0 { '-' } else { name };
+        let sp = u16::from_be_bytes([
+            self.vm.registers.read(Register::SPH),
+            self.vm.registers.read(Register::SPL),
+        ]);
+        write!(
+            out,
+            "PC={}  SP={sp:04X}  F={}{}{}  IE={}  cycles={}",
+            self.describe(self.vm.program_counter),
+            flag(Z_FLAG, 'Z'),
+            flag(N_FLAG, 'N'),
+            flag(C_FLAG, 'C'),
+            u8::from(self.vm.interrupts_enabled),
+            self.vm.cycles
+        )?;
+        if self.vm.mpu.is_some() {
+            let mode = match self.vm.mode {
+                Mode::Supervisor => "supervisor",
+                Mode::User => "user",
+            };
+            write!(out, "  mode={mode}")?;
+        }
+        writeln!(out)
+    }
+
+    fn dump(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let (addr, len) = match args {
+            [loc] => (self.location(loc)?, 16),
+            [loc, len] => (
+                self.location(loc)?,
+                parse_address(len).map_err(Error::Usage)?,
+            ),
+            _ => return Err(usage("x LOC [len]")),
+        };
+        let bytes = self.read(addr, len);
+        for (row, chunk) in (0u16..).zip(bytes.chunks(16)) {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{byte:02X}")).collect();
+            let text: String = chunk
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let start = addr.wrapping_add(row * 16);
+            writeln!(out, "{start:04X}  {:<47}  {text}", hex.join(" "))?;
+        }
+        Ok(())
+    }
+
+    fn disassemble(&mut self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let (addr, count) = match args {
+            [] => (self.vm.program_counter, 8),
+            [loc] => (self.location(loc)?, 8),
+            [loc, count] => (
+                self.location(loc)?,
+                count
+                    .parse()
+                    .map_err(|_| Error::Usage(format!("invalid count `{count}`")))?,
+            ),
+            _ => return Err(usage("dis [LOC] [n]")),
+        };
+        self.listing(addr, count, out)?;
+        Ok(())
+    }
+
+    fn resolve(&self, args: &[&str], out: &mut impl Write) -> Result<(), Error> {
+        let [name] = args else {
+            return Err(usage("sym NAME|ADDR"));
+        };
+        if let Some(addr) = self.symbols.addr(name) {
+            writeln!(out, "{name} = {addr:04X}")?;
+        } else {
+            let addr = parse_address(name)
+                .map_err(|_| Error::Usage(format!("unknown symbol `{name}`")))?;
+            writeln!(out, "{}", self.describe(addr))?;
+        }
+        Ok(())
+    }
+
+    fn show_stop(&mut self, stop: Stop, out: &mut impl Write) -> Result<(), Error> {
+        match stop {
+            Stop::Step => {}
+            Stop::Breakpoint { id } => writeln!(out, "Breakpoint {id}")?,
+            Stop::Watchpoint { id, access } => {
+                let direction = match access.access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                };
+                writeln!(
+                    out,
+                    "Watchpoint {id}: {direction} {:02X} at {}",
+                    access.value,
+                    self.describe(access.addr)
+                )?;
+            }
+            Stop::Fault(fault) => writeln!(out, "Stopped: {fault}")?,
+            Stop::Limit => writeln!(out, "Stopped after {} instructions", self.limit)?,
+            Stop::HistoryStart => writeln!(out, "Reached the start of the recorded history")?,
+        }
+        self.show_location(out)?;
+        Ok(())
+    }
+
+    /// Print the instruction at the PC.
+    fn show_location(&mut self, out: &mut impl Write) -> io::Result<()> {
+        self.listing(self.vm.program_counter, 1, out)
+    }
+
+    fn listing(&mut self, addr: u16, count: u16, out: &mut impl Write) -> io::Result<()> {
+        let bytes = self.read(addr, count.saturating_mul(2));
+        for line in disassemble(&bytes, addr) {
+            if let Some((name, 0)) = self.symbols.locate(line.address) {
+                writeln!(out, "{name}:")?;
+            }
+            let marker = if line.address == self.vm.program_counter {
+                "=>"
+            } else {
+                "  "
+            };
+            writeln!(out, "{marker} {line}")?;
+        }
+        Ok(())
+    }
+
+    /// Peek memory, leaving devices alone. Device registers read as `0`.
+    fn read(&self, addr: u16, len: u16) -> Vec<u8> {
+        (0..len)
+            .map(|offset| self.vm.devices.peek(addr.wrapping_add(offset)))
+            .collect()
+    }
+
+    /// Resolve a numeric address, a label or a label with an offset (`main+4`).
+    fn location(&self, loc: &str) -> Result<u16, Error> {
+        let (name, offset) = loc.split_once('+').unwrap_or((loc, "0"));
+        let addr = self.symbols.addr(name).map_or_else(
+            || parse_address(loc),
+            |addr| parse_address(offset).map(|offset| addr.wrapping_add(offset)),
+        );
+        addr.map_err(|_| Error::Usage(format!("unknown location `{loc}`")))
+    }
+
+    /// Address with the closest label, e.g. `E00C <main+4>`.
+    fn describe(&self, addr: u16) -> String {
+        match self.symbols.locate(addr) {
+            Some((name, 0)) => format!("{addr:04X} <{name}>"),
+            Some((name, offset)) => format!("{addr:04X} <{name}+{offset}>"),
+            None => format!("{addr:04X}"),
+        }
+    }
+}
+
+/// Error of a single command.
+#[derive(Debug)]
+enum Error {
+    /// Reported to the user, who can fix the command.
+    Usage(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+fn usage(syntax: &str) -> Error {
+    Error::Usage(format!("usage: {syntax}"))
+}
+
+fn parse_condition(register: &str, compare: &str, value: &str) -> Result<Condition, Error> {
+    let register = parse_register(register)
+        .ok_or_else(|| Error::Usage(format!("unknown register `{register}`")))?;
+    let compare = match compare {
+        "==" => Compare::Eq,
+        "!=" => Compare::Ne,
+        "<" => Compare::Lt,
+        "<=" => Compare::Le,
+        ">" => Compare::Gt,
+        ">=" => Compare::Ge,
+        _ => return Err(Error::Usage(format!("unknown comparison `{compare}`"))),
+    };
+    let value = parse_address(value)
+        .ok()
+        .and_then(|value| u8::try_from(value).ok())
+        .ok_or_else(|| Error::Usage(format!("invalid value `{value}`")))?;
+    Ok(Condition {
+        register,
+        compare,
+        value,
+    })
+}
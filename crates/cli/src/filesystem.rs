@@ -1,7 +1,26 @@
-use mb8::vm::VirtualMachine;
+use mb8::{
+    dev::disk::{Disk, BLOCK_SIZE},
+    vm::VirtualMachine,
+};
 use std::path::PathBuf;
 
+/// Whether block `0` of drive `0`, the directory, is empty.
+fn is_blank(disk: &Disk) -> bool {
+    let mut directory = [0; BLOCK_SIZE];
+    disk.read_block(0, 0, &mut directory).is_ok() && directory.iter().all(|byte| *byte == 0)
+}
+
+/// Write a file system holding the `user` programs to drive `0`. A drive backed by a host file
+/// keeps its files, unless it is blank.
 pub fn makefs(user: Vec<PathBuf>, vm: &mut VirtualMachine) {
+    let disk = vm.devices.disk();
+    if let Some(path) = disk.path(0) {
+        if !is_blank(disk) {
+            eprintln!("Using the file system on {}", path.display());
+            return;
+        }
+    }
+
     let mut fs = vec![0u8; 65536];
     let mut blocks = 1;
     let mut files = 0;
@@ -42,11 +61,18 @@ pub fn makefs(user: Vec<PathBuf>, vm: &mut VirtualMachine) {
         files += 1;
     }
 
+    let disk = vm.devices.disk();
+    if disk.path(0).is_some() {
+        if let Err(err) = disk.write_image(0, &fs) {
+            eprintln!("Failed to write file system: {err}");
+        }
+        return;
+    }
     let Ok(fs) = fs.try_into() else {
         eprintln!("Failed to convert file system");
         return;
     };
-    vm.devices.disk().set(fs);
+    disk.set(fs);
 }
 
 //you cannot access local memory that I know of, they way you would commiicate with
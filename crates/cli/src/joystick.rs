@@ -0,0 +1,105 @@
+use mb8::dev::joystick::registers::{DOWN, FIRE, LEFT, RIGHT, UP};
+use minifb::Key;
+
+use crate::keyboard::{DIGITS, LETTERS};
+
+/// Keymap used when none is given: the cursor keys and space.
+pub const DEFAULT_KEYMAP: &str = "Up,Down,Left,Right,Space";
+
+/// Keys that drive a joystick port from the desktop keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+    pub fire: Key,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            up: Key::Up,
+            down: Key::Down,
+            left: Key::Left,
+            right: Key::Right,
+            fire: Key::Space,
+        }
+    }
+}
+
+impl Keymap {
+    /// Parse five comma-separated key names in the order up, down, left, right, fire,
+    /// e.g. `W,S,A,D,LeftCtrl`.
+    ///
+    /// # Errors
+    /// Returns an error if there are not five names or a name is not a known key.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let keys = value
+            .split(',')
+            .map(|name| {
+                key_by_name(name.trim()).ok_or_else(|| format!("unknown key `{}`", name.trim()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let [up, down, left, right, fire] = keys[..] else {
+            return Err(format!(
+                "expected five keys (up, down, left, right, fire), got {}",
+                keys.len()
+            ));
+        };
+        Ok(Self {
+            up,
+            down,
+            left,
+            right,
+            fire,
+        })
+    }
+
+    /// Joystick state for the keys currently held.
+    #[must_use]
+    pub fn state(&self, held: &[Key]) -> u8 {
+        [
+            (self.up, UP),
+            (self.down, DOWN),
+            (self.left, LEFT),
+            (self.right, RIGHT),
+            (self.fire, FIRE),
+        ]
+        .into_iter()
+        .filter(|(key, _)| held.contains(key))
+        .fold(0, |state, (_, bit)| state | bit)
+    }
+}
+
+/// Key with the given name: a letter, a digit or one of the named keys below.
+fn key_by_name(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(ch), None) = (chars.next(), chars.next()) {
+        let ch = ch.to_ascii_uppercase();
+        if ch.is_ascii_uppercase() {
+            return Some(LETTERS[usize::from(ch as u8 - b'A')]);
+        }
+        if let Some(digit) = ch.to_digit(10) {
+            // `DIGITS` starts at `1`
+            return Some(DIGITS[(digit as usize + 9) % 10]);
+        }
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "space" => Key::Space,
+        "enter" => Key::Enter,
+        "tab" => Key::Tab,
+        "leftctrl" => Key::LeftCtrl,
+        "rightctrl" => Key::RightCtrl,
+        "leftshift" => Key::LeftShift,
+        "rightshift" => Key::RightShift,
+        "leftalt" => Key::LeftAlt,
+        "rightalt" => Key::RightAlt,
+        _ => return None,
+    };
+    Some(key)
+}
@@ -3,9 +3,65 @@ use std::{
     time::{Duration, Instant},
 };
 
-use mb8::vm;
+use mb8::{dev::keyboard::scancodes, vm};
 use minifb::{Key, KeyRepeat, Window};
 
+pub(crate) const LETTERS: [Key; 26] = [
+    Key::A,
+    Key::B,
+    Key::C,
+    Key::D,
+    Key::E,
+    Key::F,
+    Key::G,
+    Key::H,
+    Key::I,
+    Key::J,
+    Key::K,
+    Key::L,
+    Key::M,
+    Key::N,
+    Key::O,
+    Key::P,
+    Key::Q,
+    Key::R,
+    Key::S,
+    Key::T,
+    Key::U,
+    Key::V,
+    Key::W,
+    Key::X,
+    Key::Y,
+    Key::Z,
+];
+/// In scan code order, `0` last.
+pub(crate) const DIGITS: [Key; 10] = [
+    Key::Key1,
+    Key::Key2,
+    Key::Key3,
+    Key::Key4,
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::Key9,
+    Key::Key0,
+];
+const FUNCTION_KEYS: [Key; 12] = [
+    Key::F1,
+    Key::F2,
+    Key::F3,
+    Key::F4,
+    Key::F5,
+    Key::F6,
+    Key::F7,
+    Key::F8,
+    Key::F9,
+    Key::F10,
+    Key::F11,
+    Key::F12,
+];
+
 #[derive(Debug)]
 pub struct Keyboard {
     pub left_shift: bool,
@@ -236,8 +292,55 @@ impl Keyboard {
         Some(ch)
     }
 
+    /// Scan code of `key`, see [`scancodes`].
+    #[must_use]
+    pub fn map_key_to_scancode(key: Key) -> Option<u8> {
+        let in_range = |keys: &[Key], base: u8| {
+            keys.iter()
+                .zip(base..)
+                .find_map(|(candidate, code)| (*candidate == key).then_some(code))
+        };
+        if let Some(code) = in_range(&LETTERS, scancodes::A)
+            .or_else(|| in_range(&DIGITS, scancodes::KEY_1))
+            .or_else(|| in_range(&FUNCTION_KEYS, scancodes::F1))
+        {
+            return Some(code);
+        }
+
+        let code = match key {
+            Key::Enter => scancodes::ENTER,
+            Key::Escape => scancodes::ESCAPE,
+            Key::Backspace => scancodes::BACKSPACE,
+            Key::Tab => scancodes::TAB,
+            Key::Space => scancodes::SPACE,
+            Key::Minus => scancodes::MINUS,
+            Key::Equal => scancodes::EQUAL,
+            Key::Comma => scancodes::COMMA,
+            Key::Period => scancodes::PERIOD,
+            Key::Slash => scancodes::SLASH,
+            Key::Right => scancodes::RIGHT,
+            Key::Left => scancodes::LEFT,
+            Key::Down => scancodes::DOWN,
+            Key::Up => scancodes::UP,
+            Key::LeftCtrl => scancodes::LEFT_CTRL,
+            Key::LeftShift => scancodes::LEFT_SHIFT,
+            Key::LeftAlt => scancodes::LEFT_ALT,
+            Key::LeftSuper => scancodes::LEFT_META,
+            Key::RightCtrl => scancodes::RIGHT_CTRL,
+            Key::RightShift => scancodes::RIGHT_SHIFT,
+            Key::RightAlt => scancodes::RIGHT_ALT,
+            Key::RightSuper => scancodes::RIGHT_META,
+            _ => return None,
+        };
+        Some(code)
+    }
+
     pub fn key_pressed(&mut self, window: &Window, vm: &mut vm::VirtualMachine) {
         for key in window.get_keys_pressed(KeyRepeat::No) {
+            if let Some(code) = Keyboard::map_key_to_scancode(key) {
+                vm.devices.keyboard().key_down(code);
+            }
+
             let current_time = Instant::now();
 
             if let Some(last_time) = self.key_last_pressed.get(&key) {
@@ -264,8 +367,11 @@ impl Keyboard {
         }
     }
 
-    pub fn key_released(&mut self, window: &Window) {
+    pub fn key_released(&mut self, window: &Window, vm: &mut vm::VirtualMachine) {
         for key in window.get_keys_released() {
+            if let Some(code) = Keyboard::map_key_to_scancode(key) {
+                vm.devices.keyboard().key_up(code);
+            }
             if key == Key::LeftShift {
                 self.left_shift = false;
             }
@@ -1,8 +1,12 @@
 #[cfg(feature = "desktop")]
 pub mod config;
+#[cfg(feature = "desktop")]
+pub mod debug;
 
 pub mod filesystem;
+pub mod joystick;
 pub mod keyboard;
+pub mod screen;
 pub mod tty;
 pub mod vmrun;
 
@@ -17,11 +21,11 @@ use web_sys::{console, CanvasRenderingContext2d, HtmlCanvasElement, ImageData, K
 use std::cell::RefCell;
 #[cfg(feature = "wasm")]
 use std::rc::Rc;
-
 #[cfg(feature = "wasm")]
-use crate::tty::Tty;
+use std::time::Duration;
+
 #[cfg(feature = "wasm")]
-use mb8::dev::gpu::registers::{TTY_COLS, TTY_ROWS};
+use crate::screen::{HEIGHT, WIDTH};
 #[cfg(feature = "wasm")]
 use mb8::vm::VirtualMachine;
 
@@ -38,16 +42,10 @@ pub fn start() {
 #[cfg(feature = "wasm")]
 #[wasm_bindgen]
 pub fn run_wasm() -> Result<(), JsValue> {
-    const OPS_PER_FRAME: u32 = 1024;
-    const WIDTH: usize = 320;
-    const HEIGHT: usize = 200;
+    // Matches the browser's `requestAnimationFrame` rate
+    const FRAMES_PER_SECOND: u32 = 60;
 
     let vm = Rc::new(RefCell::new(VirtualMachine::default()));
-    let tty = Rc::new(RefCell::new(Tty::new(
-        TTY_COLS as usize,
-        TTY_ROWS as usize,
-        1024,
-    )));
     let framebuffer = Rc::new(RefCell::new(vec![0u32; WIDTH * HEIGHT]));
 
     // Load kernel
@@ -100,34 +98,21 @@ pub fn run_wasm() -> Result<(), JsValue> {
     *g.borrow_mut() = Some(Closure::new(move || {
         {
             let mut vm = vm.borrow_mut();
-            for _ in 0..OPS_PER_FRAME {
-                if vm.halted {
-                    break;
-                }
-                vm.step();
+            let budget = vm.cycles_for(Duration::from_secs(1) / FRAMES_PER_SECOND);
+            if let Err(fault) = vm.run_for_cycles(budget) {
+                console::log_1(&format!("VM fault: {fault}").into());
             }
-        }
-
-        let tty_read = Rc::new(RefCell::new(0usize));
-        // GPU → TTY
-        {
-            let mut vm = vm.borrow_mut();
-            let gpu = vm.devices.gpu();
-            let mut tty = tty.borrow_mut();
-
-            let buf = gpu.tty_buffer();
-            let mut read = tty_read.borrow_mut();
-
-            while *read < buf.len() {
-                tty.write_byte(buf[*read]);
-                *read += 1;
+            // No serial host in the browser, show what the guest sends in the console
+            let output = vm.devices.uart().take_output();
+            if !output.is_empty() {
+                console::log_1(&String::from_utf8_lossy(&output).into_owned().into());
             }
         }
 
         // Render framebuffer to canvas
         {
             let mut fb = framebuffer.borrow_mut();
-            tty.borrow_mut().render(&mut fb, WIDTH);
+            screen::render(vm.borrow_mut().devices.gpu(), &mut fb);
 
             let mut bytes = vec![0u8; fb.len() * 4];
             for (i, px) in fb.iter().enumerate() {
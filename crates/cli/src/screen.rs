@@ -0,0 +1,34 @@
+//! Host-side rasteriser shared by the desktop and wasm frontends.
+
+use mb8::dev::gpu::{
+    registers::{BITMAP_HEIGHT, BITMAP_PIXELS, BITMAP_WIDTH},
+    Mode, GPU,
+};
+
+use crate::tty;
+
+/// Framebuffer width in pixels.
+pub const WIDTH: usize = 320;
+/// Framebuffer height in pixels.
+pub const HEIGHT: usize = 200;
+
+/// Draw the GPU output into a `WIDTH` x `HEIGHT` framebuffer of `0x00RRGGBB` pixels.
+pub fn render(gpu: &GPU, framebuffer: &mut [u32]) {
+    match gpu.mode() {
+        Mode::Bitmap | Mode::Tiles => draw_pixels(gpu, framebuffer),
+        Mode::Off | Mode::Tty => tty::render(gpu, framebuffer, WIDTH),
+    }
+}
+
+/// Scale the composed bitmap or tile layer and sprites up to fill the framebuffer.
+fn draw_pixels(gpu: &GPU, framebuffer: &mut [u32]) {
+    let (width, height) = (usize::from(BITMAP_WIDTH), usize::from(BITMAP_HEIGHT));
+    let mut bitmap = vec![0; BITMAP_PIXELS];
+    gpu.compose(&mut bitmap);
+    for (y, row) in framebuffer.chunks_exact_mut(WIDTH).take(HEIGHT).enumerate() {
+        let src = &bitmap[y * height / HEIGHT * width..][..width];
+        for (x, pixel) in row.iter_mut().enumerate() {
+            *pixel = gpu.color(src[x * width / WIDTH]);
+        }
+    }
+}
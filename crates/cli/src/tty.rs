@@ -1,5 +1,9 @@
-const PIXEL_ON_COLOR: u32 = 0x006a_bfc6;
-const PIXEL_OFF_COLOR: u32 = 0x0050_459b;
+//! TTY text renderer, drawing the GPU character cells with an 8x8 font.
+
+use mb8::dev::gpu::{
+    registers::{TTY_COLS, TTY_ROWS, TTY_STYLE_BLINK, TTY_STYLE_INVERSE},
+    GPU,
+};
 
 static FONT: [[u8; 8]; 128] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // U+0000 (nul)
@@ -132,120 +136,52 @@ static FONT: [[u8; 8]; 128] = [
     [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // U+007F
 ];
 
-fn draw_glyph(framebuffer: &mut [u32], fb_width: usize, x: usize, y: usize, ch: usize, color: u32) {
-    let glyph = FONT[ch];
+fn draw_glyph(
+    framebuffer: &mut [u32],
+    fb_width: usize,
+    (x, y): (usize, usize),
+    ch: u8,
+    (fg, bg): (u32, u32),
+) {
+    let blank = [0; 8];
+    let glyph = FONT.get(usize::from(ch)).unwrap_or(&blank);
 
     for (gy, row) in glyph.iter().enumerate() {
-        let mut reverse_row = 0;
-        for i in 0..8 {
-            reverse_row |= ((row >> i) & 1) << (7 - i);
-        }
         for gx in 0..8 {
-            let bit = (reverse_row >> (7 - gx)) & 1;
-
-            let px = x + gx;
-            let py = y + gy;
-
-            if px < fb_width && py < fb_width {
-                framebuffer[py * fb_width + px] = if bit == 1 { color } else { PIXEL_OFF_COLOR }
+            let (px, py) = (x + gx, y + gy);
+            if px >= fb_width {
+                continue;
+            }
+            if let Some(pixel) = framebuffer.get_mut(py * fb_width + px) {
+                *pixel = if (row >> gx) & 1 == 1 { fg } else { bg };
             }
         }
     }
 }
 
-#[derive(Debug)]
-#[must_use]
-pub struct Tty {
-    buffer: Vec<char>,
-    head: usize,
-    tail: usize,
-    full: bool,
-
-    cols: usize,
-    rows: usize,
-}
-
-impl Tty {
-    pub fn new(col: usize, row: usize, capacity: usize) -> Self {
-        Self {
-            buffer: vec![' '; capacity],
-            head: 0,
-            tail: 0,
-            full: false,
-            cols: col,
-            rows: row,
-        }
-    }
-
-    pub fn push_char(&mut self, c: char) {
-        self.buffer[self.tail] = c;
-        self.tail = (self.tail + 1) % self.buffer.len();
-
-        if self.tail == self.head {
-            self.head = (self.head + 1) % self.buffer.len();
-        }
-    }
-
-    pub fn write_byte(&mut self, byte: u8) {
-        let c = byte as char;
-
-        match c {
-            '\n' => self.move_to_next_line(),
-            _ => self.push_char(c),
-        }
-    }
-
-    fn move_to_next_line(&mut self) {
-        if self.tail + self.cols < self.buffer.len() {
-            self.tail += self.cols;
-        } else {
-            self.tail %= self.buffer.len();
+/// Draw every TTY cell in its colour and style, and the cursor as an inverted cell.
+pub fn render(gpu: &GPU, framebuffer: &mut [u32], fb_width: usize) {
+    let cols = usize::from(TTY_COLS);
+    let (cursor_x, cursor_y) = gpu.cursor();
+    let cursor = usize::from(cursor_y) * cols + usize::from(cursor_x);
+    let blink = gpu.blink();
+
+    for cell in 0..usize::from(TTY_ROWS) * cols {
+        let ch = gpu.tty_buffer()[cell];
+        let color = gpu.tty_colors()[cell];
+        let style = gpu.tty_styles()[cell];
+
+        let (mut fg, mut bg) = (gpu.color(color & 0x0F), gpu.color(color >> 4));
+        if style & TTY_STYLE_BLINK != 0 && !blink {
+            fg = bg;
         }
-
-        if self.tail == self.head {
-            self.head = (self.head + self.cols) % self.buffer.len();
+        let inverse = style & TTY_STYLE_INVERSE != 0;
+        let on_cursor = cell == cursor && gpu.cursor_visible() && blink;
+        if inverse != on_cursor {
+            (fg, bg) = (bg, fg);
         }
-    }
-
-    pub fn get_visible(&mut self) -> Vec<char> {
-        let visible_len = self.rows * self.cols;
-        let mut out = Vec::with_capacity(visible_len);
 
-        let total = if self.full {
-            self.buffer.len()
-        } else if self.tail >= self.head {
-            self.tail - self.head
-        } else {
-            self.buffer.len() - self.head + self.tail
-        };
-
-        let start_offset = total.saturating_sub(visible_len);
-
-        let mut idx = (self.head + start_offset) % self.buffer.len();
-
-        for _ in 0..visible_len {
-            out.push(self.buffer[idx]);
-            idx = (idx + 1) % self.buffer.len();
-        }
-
-        out
-    }
-
-    pub fn render(&mut self, framebuffer: &mut [u32], fb_width: usize) {
-        let chars = self.get_visible();
-
-        for row in 0..self.rows {
-            for col in 0..self.cols {
-                let ch = chars[row * self.cols + col] as usize;
-
-                let color = if chars[row * self.cols + col] == '\0' {
-                    PIXEL_OFF_COLOR
-                } else {
-                    PIXEL_ON_COLOR
-                };
-
-                draw_glyph(framebuffer, fb_width, col * 8, row * 8, ch, color);
-            }
-        }
+        let position = (cell % cols * 8, cell / cols * 8);
+        draw_glyph(framebuffer, fb_width, position, ch, (fg, bg));
     }
 }
@@ -1,21 +1,38 @@
 use std::{
+    io::{BufWriter, Write},
     path::PathBuf,
     time::{Duration, Instant},
 };
 
-use crate::{filesystem::makefs, keyboard::Keyboard};
-use mb8::vm;
-use minifb::{Window, WindowOptions};
-
-use crate::tty::Tty;
+use crate::{
+    filesystem::makefs,
+    joystick::Keymap,
+    keyboard::Keyboard,
+    screen::{self, HEIGHT, WIDTH},
+};
+use mb8::{
+    dev::sound::{write_wav, DEFAULT_SAMPLE_RATE},
+    serial::SerialBridge,
+    vm,
+};
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
 
-const OPS_PER_FRAME: u32 = 1024;
+const FRAMES_PER_SECOND: u32 = 60;
 const RENDER_INTERVAL: u32 = 1000;
+/// Default file for quick-save and quick-load.
+pub const DEFAULT_SNAPSHOT: &str = "mb8.snapshot";
 
 #[derive(Debug)]
 pub struct VmRun {
     pub vm: vm::VirtualMachine,
-    pub tty: Tty,
+    /// File written by quick-save (F5) and read by quick-load (F9).
+    pub snapshot: PathBuf,
+    /// File the sound output is recorded to.
+    pub wav: Option<PathBuf>,
+    /// Host end of the serial port. Without one, the guest's serial output is dropped.
+    pub serial: Option<SerialBridge>,
+    /// Keys that drive joystick port 0.
+    pub joystick: Keymap,
     ticks: u32,
     width: usize,
     height: usize,
@@ -23,35 +40,56 @@ pub struct VmRun {
 
 impl VmRun {
     #[must_use]
-    pub fn new(vm: vm::VirtualMachine, tty: Tty) -> Self {
+    pub fn new(vm: vm::VirtualMachine) -> Self {
         Self {
             vm,
-            tty,
+            snapshot: PathBuf::from(DEFAULT_SNAPSHOT),
+            wav: None,
+            serial: None,
+            joystick: Keymap::default(),
             ticks: 0,
-            width: 320,
-            height: 200,
+            width: WIDTH,
+            height: HEIGHT,
         }
     }
 
-    pub fn run_desktop(&mut self, kernel: PathBuf, user: Vec<PathBuf>, seed: Option<u16>) {
+    /// Load the kernel, seed the random generator and write the user programs to the disk.
+    fn boot(&mut self, kernel: PathBuf, user: Vec<PathBuf>, seed: Option<u16>) -> bool {
         let Ok(rom) = std::fs::read(kernel) else {
-            return;
+            return false;
         };
         self.vm.load_rom(&rom);
 
-        let Ok(mut window) = Window::new("MB8", 640, 480, WindowOptions::default()) else {
-            return;
-        };
-
         let seed = seed.unwrap_or(1);
 
         self.vm.devices.rand().number = (seed as u8).max(1);
 
         makefs(user, &mut self.vm);
+        true
+    }
+
+    pub fn run_desktop(&mut self, kernel: PathBuf, user: Vec<PathBuf>, seed: Option<u16>) {
+        if !self.boot(kernel, user, seed) {
+            return;
+        }
+
+        let Ok(mut window) = Window::new("MB8", 640, 480, WindowOptions::default()) else {
+            return;
+        };
+        // `update_with_buffer` sleeps to keep the frame rate, which throttles the VM too
+        window.set_target_fps(FRAMES_PER_SECOND as usize);
+        let frame = Duration::from_secs(1) / FRAMES_PER_SECOND;
+
+        if self.wav.is_some() {
+            let clock_hz = self.vm.clock_hz;
+            self.vm
+                .devices
+                .sound()
+                .capture(clock_hz, DEFAULT_SAMPLE_RATE);
+        }
 
         let mut buf = vec![0u32; self.width * self.height];
         self.ticks = RENDER_INTERVAL - 1;
-        let mut last_render = Instant::now();
         let l_shift = false;
         let r_shift = false;
         let key = &mut Keyboard::new(l_shift, r_shift);
@@ -61,33 +99,114 @@ impl VmRun {
 
             Keyboard::key_pressed(key, &window, &mut self.vm);
 
-            Keyboard::key_released(key, &window);
+            Keyboard::key_released(key, &window, &mut self.vm);
+            let state = self.joystick.state(&window.get_keys());
+            self.vm.devices.joystick().set_port(0, state);
 
-            self.vm_step();
+            if window.is_key_pressed(Key::F5, KeyRepeat::No) {
+                self.quick_save();
+            }
+            if window.is_key_pressed(Key::F9, KeyRepeat::No) {
+                self.quick_load();
+            }
 
-            if last_render.elapsed() >= Duration::from_millis(16) {
-                let gpu = self.vm.devices.gpu();
-                for &byte in gpu.tty_buffer() {
-                    self.tty.write_byte(byte);
-                }
+            let budget = self.vm.cycles_for(frame);
+            if let Err(fault) = self.vm.run_for_cycles(budget) {
+                eprintln!("VM fault: {fault}");
+            }
+            self.pump_serial();
 
-                self.tty.render(buf.as_mut_slice(), 320);
+            screen::render(self.vm.devices.gpu(), &mut buf);
 
-                if window.update_with_buffer(&buf, 320, 200).is_err() {
-                    return;
-                }
-                last_render = Instant::now();
+            if window
+                .update_with_buffer(&buf, self.width, self.height)
+                .is_err()
+            {
+                break;
             }
         }
+
+        self.save_wav();
+        self.flush_disks();
     }
 
-    fn vm_step(&mut self) {
-        for _ in 0..OPS_PER_FRAME {
-            if self.vm.halted {
+    /// Run without a window until the VM halts. The serial port is the only way in and out,
+    /// paced to the CPU clock in frames like the desktop.
+    pub fn run_console(&mut self, kernel: PathBuf, user: Vec<PathBuf>, seed: Option<u16>) {
+        if !self.boot(kernel, user, seed) {
+            return;
+        }
+        let frame = Duration::from_secs(1) / FRAMES_PER_SECOND;
+
+        while !self.vm.halted {
+            let start = Instant::now();
+            let budget = self.vm.cycles_for(frame);
+            if let Err(fault) = self.vm.run_for_cycles(budget) {
+                eprintln!("VM fault: {fault}");
                 break;
             }
+            self.pump_serial();
+            if let Some(rest) = frame.checked_sub(start.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+        self.pump_serial();
+        self.flush_disks();
+    }
+
+    /// Make sure the disk images in host files are written out.
+    pub fn flush_disks(&mut self) {
+        if let Err(err) = self.vm.devices.disk().flush() {
+            eprintln!("Failed to flush disk images: {err}");
+        }
+    }
+
+    /// Exchange bytes between the UART and the serial host.
+    pub fn pump_serial(&mut self) {
+        let uart = self.vm.devices.uart();
+        let Some(serial) = &mut self.serial else {
+            uart.take_output();
+            return;
+        };
+        if let Err(err) = serial.pump(uart) {
+            eprintln!("Serial port closed: {err}");
+            self.serial = None;
+        }
+    }
+
+    /// Write the captured sound to the WAV file, if one was requested.
+    pub fn save_wav(&mut self) {
+        let Some(path) = &self.wav else {
+            return;
+        };
+        let samples = self.vm.devices.sound().stop_capture();
+        let result = std::fs::File::create(path).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write_wav(&mut out, DEFAULT_SAMPLE_RATE, &samples)?;
+            out.flush()
+        });
+        match result {
+            Ok(()) => eprintln!("Saved sound to {}", path.display()),
+            Err(err) => eprintln!("Failed to save sound: {err}"),
+        }
+    }
+
+    /// Save the machine state to the snapshot file.
+    pub fn quick_save(&self) {
+        match std::fs::write(&self.snapshot, self.vm.save_state()) {
+            Ok(()) => eprintln!("Saved snapshot to {}", self.snapshot.display()),
+            Err(err) => eprintln!("Failed to save snapshot: {err}"),
+        }
+    }
 
-            self.vm.step();
+    /// Restore the machine state from the snapshot file.
+    pub fn quick_load(&mut self) {
+        let result = std::fs::read(&self.snapshot)
+            .map_err(|err| err.to_string())
+            .and_then(|data| self.vm.load_state(&data).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => eprintln!("Loaded snapshot from {}", self.snapshot.display()),
+            Err(err) => eprintln!("Failed to load snapshot: {err}"),
         }
     }
 }
@@ -0,0 +1,51 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+    use mb8_cli::config::{parse_address, parse_serial, Cli, Commands, RomWrites, Serial};
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("0xE000"), Ok(0xE000));
+        assert_eq!(parse_address("4096"), Ok(0x1000));
+        assert!(parse_address("0x10000").is_err());
+        assert!(parse_address("label").is_err());
+    }
+
+    #[test]
+    fn test_parse_serial() {
+        assert_eq!(parse_serial("stdio"), Ok(Serial::Stdio));
+        assert_eq!(
+            parse_serial("tcp:127.0.0.1:4000"),
+            Ok(Serial::Tcp("127.0.0.1:4000".to_string()))
+        );
+        assert!(parse_serial("tcp:").is_err());
+        assert!(parse_serial("com1").is_err());
+    }
+
+    #[test]
+    fn test_machine_options_belong_to_vm_commands() {
+        let cli = Cli::try_parse_from([
+            "mb8",
+            "run",
+            "kernel.bin",
+            "--disk",
+            "a.img",
+            "--banks",
+            "4",
+            "--mpu",
+        ])
+        .unwrap();
+        let Commands::Run { machine, .. } = cli.command else {
+            panic!("expected `run`");
+        };
+        assert_eq!(machine.disks, [PathBuf::from("a.img")]);
+        assert_eq!(machine.banks, 4);
+        assert_eq!(machine.rom_writes, RomWrites::Fault);
+        assert!(machine.mpu);
+        for option in ["--disk=a.img", "--banks=4", "--rom-writes=ignore", "--mpu"] {
+            assert!(Cli::try_parse_from(["mb8", "asm", "main.asm", option]).is_err());
+        }
+    }
+}
@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod tests {
+    use mb8::{debug::Symbols, vm::VirtualMachine};
+    use mb8_cli::debug::{Flow, Repl};
+    use mb8_isa::{encode::encode_program, opcodes::Opcode, registers::Register};
+
+    /// `main` calls `func` at `0xE00A`, then halts.
+    fn repl() -> Repl {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xE0,
+            },
+            Opcode::Ldi {
+                dst: Register::R1,
+                value: 0x0A,
+            },
+            Opcode::Call {
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+            Opcode::Push { src: Register::R3 },
+            Opcode::Halt,
+            Opcode::Ldi {
+                dst: Register::R3,
+                value: 7,
+            },
+            Opcode::Ret,
+        ]));
+        let symbols = Symbols::parse("E000 main\nE00A func\n").unwrap();
+        Repl::new(vm, symbols)
+    }
+
+    fn run(repl: &mut Repl, line: &str) -> String {
+        let mut out = Vec::new();
+        assert_eq!(repl.command(line, &mut out).unwrap(), Flow::Continue);
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_break_on_label_and_continue() {
+        let mut repl = repl();
+        assert_eq!(
+            run(&mut repl, "break func"),
+            "Breakpoint 1 at E00A <func>\n"
+        );
+        assert_eq!(
+            run(&mut repl, "c"),
+            "Breakpoint 1\nfunc:\n=> E00A  23 07  LDI R3 0x07\n"
+        );
+        assert_eq!(run(&mut repl, "finish"), "=> E006  42 30  PUSH R3\n");
+        assert_eq!(repl.vm.registers.read(Register::R3), 7);
+        assert!(run(&mut repl, "c").starts_with("Stopped: halted at PC 0xE008\n"));
+    }
+
+    #[test]
+    fn test_conditional_breakpoints_and_watchpoints() {
+        let mut repl = repl();
+        assert_eq!(
+            run(&mut repl, "b main+4"),
+            "Breakpoint 1 at E004 <main+4>\n"
+        );
+        assert_eq!(run(&mut repl, "d 1"), "Deleted 1\n");
+        assert_eq!(
+            run(&mut repl, "b 0xE004 if R1 == 0x0A"),
+            "Breakpoint 2 at E004 <main+4>\n"
+        );
+        assert_eq!(run(&mut repl, "watch 0xBFFF"), "Watchpoint 3 on BFFF\n");
+        assert_eq!(
+            run(&mut repl, "info"),
+            "  2  break  E004 <main+4> if R1 == 0x0A\n  3  watch  BFFF w\n"
+        );
+        assert!(run(&mut repl, "c").starts_with("Breakpoint 2\n"));
+        assert!(run(&mut repl, "c").starts_with("Watchpoint 3: write 06 at BFFF\n"));
+        assert_eq!(run(&mut repl, "d 3"), "Deleted 3\n");
+        assert_eq!(
+            run(&mut repl, "d 3"),
+            "error: no breakpoint or watchpoint 3\n"
+        );
+    }
+
+    #[test]
+    fn test_inspect_state() {
+        let mut repl = repl();
+        run(&mut repl, "next");
+        run(&mut repl, "next");
+        assert_eq!(run(&mut repl, "n"), "=> E006  42 30  PUSH R3\n");
+        assert_eq!(
+            run(&mut repl, "regs"),
+            "R0 =E0  R1 =0A  R2 =00  R3 =07  R4 =00  R5 =00  R6 =00  R7 =00\n\
+             R8 =00  R9 =00  R10=00  R11=00  R12=00  R13=BF  R14=FF  R15=00\n\
+             PC=E006 <main+6>  SP=BFFF  F=---  IE=0  cycles=11\n"
+        );
+        assert_eq!(
+            run(&mut repl, "x 0xBFFE 2"),
+            format!("BFFE  E0 06{:<42}  ..\n", "")
+        );
+        assert_eq!(
+            run(&mut repl, "dis func 2"),
+            "func:\n   E00A  23 07  LDI R3 0x07\n   E00C  41 00  RET\n"
+        );
+        assert_eq!(run(&mut repl, "sym func"), "func = E00A\n");
+        assert_eq!(run(&mut repl, "sym 0xE00C"), "E00C <func+2>\n");
+        assert_eq!(
+            run(&mut repl, "x nowhere"),
+            "error: unknown location `nowhere`\n"
+        );
+    }
+
+    #[test]
+    fn test_reverse_execution() {
+        let mut repl = repl();
+        assert_eq!(
+            run(&mut repl, "back"),
+            "error: not recording, use `record` first\n"
+        );
+        assert_eq!(
+            run(&mut repl, "record"),
+            "Recording the last 100000 steps\n"
+        );
+        assert!(run(&mut repl, "c").starts_with("Stopped: halted"));
+        assert_eq!(
+            run(&mut repl, "who 0xBFFF"),
+            "BFFF written by E006 <main+6> at cycle 11: 06 -> 07\n"
+        );
+        assert_eq!(run(&mut repl, "back 2"), "=> E006  42 30  PUSH R3\n");
+        run(&mut repl, "b func");
+        assert_eq!(
+            run(&mut repl, "rc"),
+            "Breakpoint 1\nfunc:\n=> E00A  23 07  LDI R3 0x07\n"
+        );
+        assert_eq!(
+            run(&mut repl, "rc"),
+            "Reached the start of the recorded history\nmain:\n=> E000  20 E0  LDI R0 0xE0\n"
+        );
+    }
+
+    #[test]
+    fn test_repl_reads_until_quit() {
+        let mut repl = repl();
+        let mut out = Vec::new();
+        repl.run("step 2\nquit\nstep\n".as_bytes(), &mut out)
+            .unwrap();
+        assert_eq!(repl.vm.program_counter, 0xE004);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "main:\n=> E000  20 E0  LDI R0 0xE0\n(mb8) => E004  40 01  CALL [R0:R1]\n(mb8) "
+        );
+    }
+}
@@ -66,3 +66,27 @@ fn test_two_files() {
     assert_eq!(start_block_b, 2);
     assert_eq!(size_blocks_b, 1);
 }
+
+#[test]
+fn test_file_backed_disk_keeps_files() {
+    let dir = tempdir().unwrap();
+    let image = dir.path().join("disk.img");
+    let a = dir.path().join("a.bin");
+    let b = dir.path().join("b.bin");
+    fs::write(&a, b"AAAA").unwrap();
+    fs::write(&b, b"BBBB").unwrap();
+
+    // A new image is blank, so it gets the file system
+    let mut vm = VirtualMachine::default();
+    vm.devices.disk().attach(0, &image).unwrap();
+    makefs(vec![a.clone()], &mut vm);
+    assert_eq!(fs::read(&image).unwrap()[3], b'a');
+
+    // The next run finds the files and leaves them alone
+    let mut vm = VirtualMachine::default();
+    vm.devices.disk().attach(0, &image).unwrap();
+    makefs(vec![b.clone()], &mut vm);
+    let disk_img = vm.devices.disk().dump();
+    assert_eq!(disk_img[3], b'a');
+    assert_eq!(disk_img[256], b'A');
+}
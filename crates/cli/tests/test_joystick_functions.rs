@@ -0,0 +1,37 @@
+#[cfg(test)]
+mod tests {
+    use mb8::dev::joystick::registers::{FIRE, LEFT, UP};
+    use mb8_cli::joystick::{Keymap, DEFAULT_KEYMAP};
+    use minifb::Key;
+
+    #[test]
+    fn test_parse_keymap() {
+        assert_eq!(Keymap::parse(DEFAULT_KEYMAP), Ok(Keymap::default()));
+        assert_eq!(
+            Keymap::parse("w, s, a, d, LeftCtrl"),
+            Ok(Keymap {
+                up: Key::W,
+                down: Key::S,
+                left: Key::A,
+                right: Key::D,
+                fire: Key::LeftCtrl,
+            })
+        );
+        assert_eq!(
+            Keymap::parse("8,2,4,6,0").map(|keymap| keymap.fire),
+            Ok(Key::Key0)
+        );
+        assert!(Keymap::parse("W,S,A,D").is_err());
+        assert!(Keymap::parse("W,S,A,D,Joy").is_err());
+    }
+
+    #[test]
+    fn test_keymap_state() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.state(&[]), 0);
+        assert_eq!(
+            keymap.state(&[Key::Left, Key::Up, Key::Space, Key::A]),
+            UP | LEFT | FIRE
+        );
+    }
+}
@@ -1,5 +1,6 @@
 #[cfg(test)]
 mod tests {
+    use mb8::dev::keyboard::scancodes;
     use mb8_cli::keyboard::Keyboard;
     use minifb::Key;
 
@@ -18,4 +19,31 @@ mod tests {
         assert_eq!(Keyboard::map_key_to_char(Key::Key0, false), Some(b'0'));
         assert_eq!(Keyboard::map_key_to_char(Key::Key9, false), Some(b'9'));
     }
+
+    #[test]
+    fn test_map_key_to_scancode() {
+        assert_eq!(Keyboard::map_key_to_scancode(Key::A), Some(scancodes::A));
+        assert_eq!(
+            Keyboard::map_key_to_scancode(Key::Z),
+            Some(scancodes::A + 25)
+        );
+        assert_eq!(
+            Keyboard::map_key_to_scancode(Key::Key1),
+            Some(scancodes::KEY_1)
+        );
+        assert_eq!(
+            Keyboard::map_key_to_scancode(Key::Key0),
+            Some(scancodes::KEY_0)
+        );
+        assert_eq!(
+            Keyboard::map_key_to_scancode(Key::F12),
+            Some(scancodes::F1 + 11)
+        );
+        assert_eq!(Keyboard::map_key_to_scancode(Key::Up), Some(scancodes::UP));
+        assert_eq!(
+            Keyboard::map_key_to_scancode(Key::RightShift),
+            Some(scancodes::RIGHT_SHIFT)
+        );
+        assert_eq!(Keyboard::map_key_to_scancode(Key::NumLock), None);
+    }
 }
@@ -0,0 +1,101 @@
+#[cfg(test)]
+mod tests {
+    use mb8::{
+        dev::{
+            gpu::{registers::*, GPU},
+            Device,
+        },
+        vm::VirtualMachine,
+    };
+    use mb8_cli::screen::{self, HEIGHT, WIDTH};
+
+    #[test]
+    fn test_render_bitmap_scaled() {
+        let mut gpu = GPU::default();
+        gpu.write(GPU_REG_MODE, GPU_MODE_BITMAP).unwrap();
+        gpu.write(GPU_REG_X, 1).unwrap();
+        gpu.write(GPU_REG_Y, 1).unwrap();
+        gpu.write(GPU_REG_PLOT, 8).unwrap();
+
+        let mut framebuffer = vec![0; WIDTH * HEIGHT];
+        screen::render(&gpu, &mut framebuffer);
+
+        // Each bitmap pixel covers 2x2 framebuffer pixels
+        for (x, y) in [(2, 2), (3, 2), (2, 3), (3, 3)] {
+            assert_eq!(framebuffer[y * WIDTH + x], gpu.color(8));
+        }
+        assert_eq!(framebuffer[4 * WIDTH + 4], gpu.color(0));
+        assert_eq!(framebuffer[WIDTH * HEIGHT - 1], gpu.color(0));
+    }
+
+    #[test]
+    fn test_render_tiles_and_sprites_headless() {
+        let mut vm = VirtualMachine::default();
+        let gpu = |reg: u16| 0xF000 + reg;
+        vm.devices.write(gpu(GPU_REG_MODE), GPU_MODE_TILES);
+        // Pattern 1 is solid colour 5, pattern 2 solid colour 9
+        vm.devices.write(gpu(GPU_REG_PLANE), GPU_PLANE_PATTERNS);
+        vm.devices.write(gpu(GPU_REG_ADDR_LO), 0x40);
+        for color in [5, 9] {
+            for _ in 0..TILE_PIXELS {
+                vm.devices.write(gpu(GPU_REG_DATA), color);
+            }
+        }
+        // The top row of tiles uses pattern 1
+        vm.devices.write(gpu(GPU_REG_PLANE), GPU_PLANE_MAP);
+        vm.devices.write(gpu(GPU_REG_ADDR_LO), 0x00);
+        for _ in 0..MAP_COLS {
+            vm.devices.write(gpu(GPU_REG_DATA), 1);
+        }
+        let sprite = GPU_REG_SPRITES;
+        vm.devices.write(gpu(sprite + GPU_SPRITE_X as u16), 20);
+        vm.devices.write(gpu(sprite + GPU_SPRITE_Y as u16), 4);
+        vm.devices.write(gpu(sprite + GPU_SPRITE_PATTERN as u16), 2);
+        vm.devices
+            .write(gpu(sprite + GPU_SPRITE_FLAGS as u16), GPU_SPRITE_VISIBLE);
+
+        let gpu = vm.devices.gpu();
+        let mut framebuffer = vec![0; WIDTH * HEIGHT];
+        screen::render(gpu, &mut framebuffer);
+
+        let expected: Vec<u32> = (0..WIDTH * HEIGHT)
+            .map(|index| {
+                let (x, y) = (index % WIDTH / 2, index / WIDTH / 2);
+                if (20..28).contains(&x) && (4..12).contains(&y) {
+                    gpu.color(9)
+                } else if y < 8 {
+                    gpu.color(5)
+                } else {
+                    gpu.color(0)
+                }
+            })
+            .collect();
+        assert!(framebuffer == expected, "framebuffers differ");
+    }
+
+    #[test]
+    fn test_render_tty_attributes_and_cursor() {
+        let mut gpu = GPU::default();
+        gpu.write(GPU_REG_MODE, GPU_MODE_TTY).unwrap();
+        gpu.write(GPU_REG_TTY_COLOR, 0x21).unwrap();
+        gpu.write(GPU_REG_TTY, b'_').unwrap();
+        gpu.write(GPU_REG_TTY_STYLE, TTY_STYLE_INVERSE).unwrap();
+        gpu.write(GPU_REG_TTY, b' ').unwrap();
+
+        let mut framebuffer = vec![0; WIDTH * HEIGHT];
+        screen::render(&gpu, &mut framebuffer);
+        let (fg, bg) = (gpu.color(1), gpu.color(2));
+        // `_` fills the bottom row of its glyph
+        assert_eq!(framebuffer[0], bg);
+        assert_eq!(framebuffer[7 * WIDTH], fg);
+        // The inverse space is drawn in the foreground colour
+        assert_eq!(framebuffer[8], fg);
+        // The cursor cell after it is inverted while the cursor blinks on
+        let default = gpu.color(TTY_DEFAULT_COLOR & 0x0F);
+        assert_eq!(framebuffer[16], default);
+
+        gpu.write(GPU_REG_TTY_CURSOR, 0).unwrap();
+        screen::render(&gpu, &mut framebuffer);
+        assert_eq!(framebuffer[16], gpu.color(TTY_DEFAULT_COLOR >> 4));
+    }
+}
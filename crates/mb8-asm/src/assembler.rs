@@ -0,0 +1,466 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{AsmError, AsmErrorKind, AsmResult},
+    expr::{Env, Expr},
+    lexer::tokenize,
+    parser::{parse, BankDef, Statement},
+    rules::{assemble_instruction, Emitter},
+};
+
+/// Upper bound on layout passes before giving up on symbols that keep moving.
+const MAX_PASSES: usize = 8;
+
+/// Statement together with the place it came from.
+#[derive(Debug)]
+struct Located {
+    statement: Statement,
+    file: PathBuf,
+    line: usize,
+}
+
+impl Located {
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.clone(),
+            line: self.line,
+            kind,
+        }
+    }
+}
+
+/// Reads source files and flattens `#include` directives.
+#[derive(Debug, Default)]
+struct Loader {
+    once: HashSet<PathBuf>,
+    stack: Vec<PathBuf>,
+    statements: Vec<Located>,
+}
+
+impl Loader {
+    fn load_file(&mut self, path: &Path) -> AsmResult<()> {
+        let io_error = |err: std::io::Error| AsmError {
+            file: path.to_path_buf(),
+            line: 0,
+            kind: AsmErrorKind::Io {
+                message: err.to_string(),
+            },
+        };
+        let canonical = fs::canonicalize(path).map_err(io_error)?;
+        if self.once.contains(&canonical) {
+            return Ok(());
+        }
+        if self.stack.contains(&canonical) {
+            return Err(AsmError {
+                file: path.to_path_buf(),
+                line: 0,
+                kind: AsmErrorKind::RecursiveInclude { path: canonical },
+            });
+        }
+        let source = fs::read_to_string(path).map_err(io_error)?;
+
+        self.stack.push(canonical);
+        let result = self.load_source(&source, path);
+        self.stack.pop();
+        result
+    }
+
+    fn load_source(&mut self, source: &str, path: &Path) -> AsmResult<()> {
+        let located_error = |(kind, line)| AsmError {
+            file: path.to_path_buf(),
+            line,
+            kind,
+        };
+        let tokens = tokenize(source).map_err(located_error)?;
+        let statements = parse(&tokens).map_err(located_error)?;
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for (statement, line) in statements {
+            match statement {
+                Statement::Once => {
+                    if let Some(current) = self.stack.last() {
+                        self.once.insert(current.clone());
+                    }
+                }
+                Statement::Include(include) => self.load_file(&dir.join(include))?,
+                statement => self.statements.push(Located {
+                    statement,
+                    file: path.to_path_buf(),
+                    line,
+                }),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Output region with its own address space.
+#[derive(Debug)]
+struct Bank {
+    addr: i128,
+    size: Option<i128>,
+    /// Position in the output file, in bytes. Banks without one are not written.
+    outp: Option<i128>,
+    fill: bool,
+    cursor: i128,
+    data: Vec<Option<u8>>,
+}
+
+impl Bank {
+    fn new(addr: i128, size: Option<i128>, outp: Option<i128>, fill: bool) -> Self {
+        Self {
+            addr,
+            size,
+            outp,
+            fill,
+            cursor: addr,
+            data: Vec::new(),
+        }
+    }
+
+    fn contains(&self, address: i128) -> bool {
+        address >= self.addr && self.size.is_none_or(|size| address <= self.addr + size)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), AsmErrorKind> {
+        for &byte in bytes {
+            let address = self.cursor;
+            if !self.contains(address + 1) {
+                return Err(AsmErrorKind::AddressOutOfBank { address });
+            }
+            let index = (address - self.addr) as usize;
+            if index >= self.data.len() {
+                self.data.resize(index + 1, None);
+            }
+            if self.data[index].replace(byte).is_some() {
+                return Err(AsmErrorKind::OverlappingOutput { address });
+            }
+            self.cursor += 1;
+        }
+        Ok(())
+    }
+
+    /// Number of bytes the bank contributes to the output.
+    fn len(&self) -> usize {
+        match self.size {
+            Some(size) if self.fill => size as usize,
+            _ => self.data.len(),
+        }
+    }
+}
+
+/// Symbol lookups for a single statement.
+struct Scope<'a> {
+    current: &'a HashMap<String, i128>,
+    previous: &'a HashMap<String, i128>,
+    parent: &'a str,
+    pc: i128,
+    strict: bool,
+}
+
+impl Scope<'_> {
+    fn key(&self, name: &str, local: bool) -> String {
+        if local {
+            format!("{}.{name}", self.parent)
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+impl Env for Scope<'_> {
+    fn symbol(&self, name: &str, local: bool) -> Result<i128, AsmErrorKind> {
+        let key = self.key(name, local);
+        match self.current.get(&key).or_else(|| self.previous.get(&key)) {
+            Some(value) => Ok(*value),
+            None if !self.strict => Ok(0),
+            None => Err(AsmErrorKind::UnknownSymbol {
+                symbol: if local { format!(".{name}") } else { key },
+            }),
+        }
+    }
+
+    fn pc(&self) -> i128 {
+        self.pc
+    }
+}
+
+/// Result of a single pass over the program.
+#[derive(Debug)]
+struct Pass {
+    symbols: HashMap<String, i128>,
+    /// Keys of `symbols` defined by labels and sublabels.
+    labels: Vec<String>,
+    banks: Vec<Bank>,
+}
+
+/// Assembled program together with the addresses of its labels.
+#[derive(Debug)]
+pub struct Program {
+    pub binary: Vec<u8>,
+    /// Labels and sublabels (`parent.sub`) with their addresses, sorted by address.
+    pub labels: Vec<(u16, String)>,
+}
+
+impl Program {
+    /// Symbol file listing the labels, one `ADDR name` line each.
+    #[must_use]
+    pub fn symbols(&self) -> String {
+        let mut symbols = String::new();
+        for (addr, name) in &self.labels {
+            let _ = writeln!(symbols, "{addr:04X} {name}");
+        }
+        symbols
+    }
+}
+
+fn eval(expr: &Expr, scope: &Scope) -> Result<i128, AsmErrorKind> {
+    expr.eval(scope)
+}
+
+fn run_pass(
+    statements: &[Located],
+    previous: &HashMap<String, i128>,
+    strict: bool,
+) -> AsmResult<Pass> {
+    let mut symbols: HashMap<String, i128> = HashMap::new();
+    let mut labels = Vec::new();
+    let mut banks = vec![Bank::new(0, None, Some(0), false)];
+    let mut bank_names: HashMap<String, usize> = HashMap::new();
+    let mut active = 0;
+    let mut parent = String::new();
+
+    for located in statements {
+        let err = |kind| located.error(kind);
+        let pc = banks[active].cursor;
+        let scope = Scope {
+            current: &symbols,
+            previous,
+            parent: &parent,
+            pc,
+            strict,
+        };
+
+        match &located.statement {
+            Statement::Label(name) => {
+                define(&mut symbols, name.clone(), pc).map_err(err)?;
+                labels.push(name.clone());
+                parent.clone_from(name);
+            }
+            Statement::Sublabel(name) => {
+                let key = format!("{parent}.{name}");
+                define(&mut symbols, key.clone(), pc).map_err(err)?;
+                labels.push(key);
+            }
+            Statement::Constant { name, local, value } => {
+                let key = scope.key(name, *local);
+                let value = eval(value, &scope).map_err(err)?;
+                define(&mut symbols, key, value).map_err(err)?;
+            }
+            Statement::Addr(addr) => {
+                let addr = eval(addr, &scope).map_err(err)?;
+                let bank = &mut banks[active];
+                if !bank.contains(addr) {
+                    return Err(err(AsmErrorKind::AddressOutOfBank { address: addr }));
+                }
+                bank.cursor = addr;
+            }
+            Statement::Bankdef(def) => {
+                let bank = define_bank(def, &scope).map_err(err)?;
+                if bank_names.insert(def.name.clone(), banks.len()).is_some() {
+                    return Err(err(AsmErrorKind::DuplicateSymbol {
+                        symbol: def.name.clone(),
+                    }));
+                }
+                active = banks.len();
+                banks.push(bank);
+            }
+            Statement::Bank(name) => {
+                active = *bank_names
+                    .get(name)
+                    .ok_or_else(|| err(AsmErrorKind::UnknownBank { name: name.clone() }))?;
+            }
+            Statement::Data { bits, values } => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    data_bytes(value, *bits, &scope, &mut bytes).map_err(err)?;
+                }
+                banks[active].write(&bytes).map_err(err)?;
+            }
+            Statement::Instruction(instruction) => {
+                let mut emitter = Emitter::new(pc, strict);
+                assemble_instruction(instruction, &mut emitter, &scope).map_err(err)?;
+                banks[active].write(&emitter.into_bytes()).map_err(err)?;
+            }
+            Statement::Include(_) | Statement::Once => {}
+        }
+    }
+
+    Ok(Pass {
+        symbols,
+        labels,
+        banks,
+    })
+}
+
+fn define(
+    symbols: &mut HashMap<String, i128>,
+    key: String,
+    value: i128,
+) -> Result<(), AsmErrorKind> {
+    if symbols.contains_key(&key) {
+        return Err(AsmErrorKind::DuplicateSymbol { symbol: key });
+    }
+    symbols.insert(key, value);
+    Ok(())
+}
+
+fn define_bank(def: &BankDef, scope: &Scope) -> Result<Bank, AsmErrorKind> {
+    let addr = def
+        .addr
+        .as_ref()
+        .map(|addr| eval(addr, scope))
+        .transpose()?
+        .unwrap_or_default();
+    let size = def
+        .size
+        .as_ref()
+        .map(|size| eval(size, scope))
+        .transpose()?;
+    // `#outp` is given in bits
+    let outp = def
+        .outp
+        .as_ref()
+        .map(|outp| eval(outp, scope).map(|bits| bits / 8))
+        .transpose()?;
+    Ok(Bank::new(addr, size, outp, def.fill))
+}
+
+/// Encode a `#d` value as big-endian bytes.
+fn data_bytes(
+    value: &Expr,
+    bits: Option<u32>,
+    scope: &Scope,
+    bytes: &mut Vec<u8>,
+) -> Result<(), AsmErrorKind> {
+    if let (Expr::Str(string), None) = (value, bits) {
+        bytes.extend_from_slice(string);
+        return Ok(());
+    }
+    let bits = bits
+        .or_else(|| value.bits())
+        .ok_or(AsmErrorKind::UnsizedData)?;
+    let number = eval(value, scope)?;
+    if scope.strict && bits < 127 {
+        let min = -(1i128 << (bits - 1));
+        let max = (1i128 << bits) - 1;
+        if !(min..=max).contains(&number) {
+            return Err(AsmErrorKind::ValueOutOfRange {
+                value: number,
+                min,
+                max,
+            });
+        }
+    }
+    let fill = if number < 0 { 0xFF } else { 0x00 };
+    let be = number.to_be_bytes();
+    let len = (bits / 8) as usize;
+    for i in (0..len).rev() {
+        bytes.push(if i < be.len() {
+            be[be.len() - 1 - i]
+        } else {
+            fill
+        });
+    }
+    Ok(())
+}
+
+fn output(statements: &[Located], banks: &[Bank]) -> AsmResult<Vec<u8>> {
+    let mut image: Vec<Option<u8>> = Vec::new();
+    for bank in banks {
+        let Some(outp) = bank.outp else {
+            continue;
+        };
+        let len = bank.len();
+        let start = outp as usize;
+        if image.len() < start + len {
+            image.resize(start + len, None);
+        }
+        for offset in 0..len {
+            let byte = bank.data.get(offset).copied().flatten();
+            if bank.fill || byte.is_some() {
+                let slot = &mut image[start + offset];
+                if slot.is_some() {
+                    let kind = AsmErrorKind::OverlappingOutput {
+                        address: bank.addr + offset as i128,
+                    };
+                    return Err(statements.last().map_or_else(
+                        || AsmError {
+                            kind: kind.clone(),
+                            ..AsmError::default()
+                        },
+                        |located| located.error(kind.clone()),
+                    ));
+                }
+                *slot = Some(byte.unwrap_or_default());
+            }
+        }
+    }
+    Ok(image.into_iter().map(Option::unwrap_or_default).collect())
+}
+
+fn assemble_statements(statements: &[Located]) -> AsmResult<Program> {
+    let mut symbols = HashMap::new();
+    for _ in 0..MAX_PASSES {
+        let pass = run_pass(statements, &symbols, false)?;
+        let stable = pass.symbols == symbols;
+        symbols = pass.symbols;
+        if stable {
+            break;
+        }
+    }
+    let pass = run_pass(statements, &symbols, true)?;
+    let mut labels: Vec<_> = pass
+        .labels
+        .iter()
+        .filter_map(|name| Some((u16::try_from(pass.symbols[name]).ok()?, name.clone())))
+        .collect();
+    labels.sort();
+    Ok(Program {
+        binary: output(statements, &pass.banks)?,
+        labels,
+    })
+}
+
+/// Assemble a source file, resolving `#include` directives relative to it.
+///
+/// # Errors
+/// Returns the first error found in the file or its includes.
+pub fn assemble_file(path: impl AsRef<Path>) -> AsmResult<Vec<u8>> {
+    assemble_program(path).map(|program| program.binary)
+}
+
+/// Assemble a source file like [`assemble_file`], keeping the label addresses.
+///
+/// # Errors
+/// Returns the first error found in the file or its includes.
+pub fn assemble_program(path: impl AsRef<Path>) -> AsmResult<Program> {
+    let mut loader = Loader::default();
+    loader.load_file(path.as_ref())?;
+    assemble_statements(&loader.statements)
+}
+
+/// Assemble source code, resolving `#include` directives relative to the working directory.
+///
+/// # Errors
+/// Returns the first error found in the source or its includes.
+pub fn assemble(source: &str) -> AsmResult<Vec<u8>> {
+    let mut loader = Loader::default();
+    loader.load_source(source, Path::new("<source>"))?;
+    assemble_statements(&loader.statements).map(|program| program.binary)
+}
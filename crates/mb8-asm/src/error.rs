@@ -0,0 +1,119 @@
+use std::{
+    fmt::{self, Display},
+    path::PathBuf,
+};
+
+pub type AsmResult<T, E = AsmError> = Result<T, E>;
+
+/// Reason an assembly failed, without the source location.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum AsmErrorKind {
+    #[default]
+    Unknown,
+    Io {
+        message: String,
+    },
+    UnexpectedToken,
+    UnexpectedEnd,
+    InvalidNumber,
+    InvalidString,
+    UnknownDirective {
+        name: String,
+    },
+    UnknownInstruction {
+        mnemonic: String,
+    },
+    UnknownSymbol {
+        symbol: String,
+    },
+    DuplicateSymbol {
+        symbol: String,
+    },
+    UnknownBank {
+        name: String,
+    },
+    RecursiveInclude {
+        path: PathBuf,
+    },
+    RecursiveSymbol {
+        symbol: String,
+    },
+    ValueOutOfRange {
+        value: i128,
+        min: i128,
+        max: i128,
+    },
+    JumpOutOfRange {
+        offset: i128,
+    },
+    UnsizedData,
+    DivisionByZero,
+    AddressOutOfBank {
+        address: i128,
+    },
+    OverlappingOutput {
+        address: i128,
+    },
+}
+
+impl Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmErrorKind::Unknown => f.write_str("unknown error"),
+            AsmErrorKind::Io { message } => write!(f, "{message}"),
+            AsmErrorKind::UnexpectedToken => f.write_str("unexpected token"),
+            AsmErrorKind::UnexpectedEnd => f.write_str("unexpected end of line"),
+            AsmErrorKind::InvalidNumber => f.write_str("invalid number literal"),
+            AsmErrorKind::InvalidString => f.write_str("invalid string literal"),
+            AsmErrorKind::UnknownDirective { name } => write!(f, "unknown directive `#{name}`"),
+            AsmErrorKind::UnknownInstruction { mnemonic } => {
+                write!(f, "no instruction `{mnemonic}` matches these operands")
+            }
+            AsmErrorKind::UnknownSymbol { symbol } => write!(f, "unknown symbol `{symbol}`"),
+            AsmErrorKind::DuplicateSymbol { symbol } => write!(f, "duplicate symbol `{symbol}`"),
+            AsmErrorKind::UnknownBank { name } => write!(f, "unknown bank `{name}`"),
+            AsmErrorKind::RecursiveInclude { path } => {
+                write!(f, "recursive include of `{}`", path.display())
+            }
+            AsmErrorKind::RecursiveSymbol { symbol } => {
+                write!(f, "symbol `{symbol}` depends on itself")
+            }
+            AsmErrorKind::ValueOutOfRange { value, min, max } => {
+                write!(f, "value {value} is out of range {min}..={max}")
+            }
+            AsmErrorKind::JumpOutOfRange { offset } => {
+                write!(
+                    f,
+                    "relative jump offset {offset} is out of range -128..=127"
+                )
+            }
+            AsmErrorKind::UnsizedData => f.write_str("cannot infer the size of data value"),
+            AsmErrorKind::DivisionByZero => f.write_str("division by zero"),
+            AsmErrorKind::AddressOutOfBank { address } => {
+                write!(f, "address 0x{address:X} is outside of the current bank")
+            }
+            AsmErrorKind::OverlappingOutput { address } => {
+                write!(
+                    f,
+                    "output at address 0x{address:X} overlaps previous output"
+                )
+            }
+        }
+    }
+}
+
+/// Assembly error together with the place in the source that caused it.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AsmError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file.display(), self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
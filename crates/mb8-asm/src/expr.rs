@@ -0,0 +1,280 @@
+use crate::{
+    error::AsmErrorKind,
+    lexer::{Literal, Token},
+    parser::Cursor,
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    LogicalNot,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+impl BinaryOp {
+    fn from_token(token: &Token) -> Option<(BinaryOp, u8)> {
+        let op = match token {
+            Token::Or => (BinaryOp::LogicalOr, 1),
+            Token::And => (BinaryOp::LogicalAnd, 2),
+            Token::Eq => (BinaryOp::Eq, 3),
+            Token::Ne => (BinaryOp::Ne, 3),
+            Token::Lt => (BinaryOp::Lt, 3),
+            Token::Le => (BinaryOp::Le, 3),
+            Token::Gt => (BinaryOp::Gt, 3),
+            Token::Ge => (BinaryOp::Ge, 3),
+            Token::Pipe => (BinaryOp::Or, 4),
+            Token::Caret => (BinaryOp::Xor, 5),
+            Token::Ampersand => (BinaryOp::And, 6),
+            Token::Shl => (BinaryOp::Shl, 7),
+            Token::Shr => (BinaryOp::Shr, 7),
+            Token::Plus => (BinaryOp::Add, 8),
+            Token::Minus => (BinaryOp::Sub, 8),
+            Token::Star => (BinaryOp::Mul, 9),
+            Token::Slash => (BinaryOp::Div, 9),
+            Token::Percent => (BinaryOp::Rem, 9),
+            _ => return None,
+        };
+        Some(op)
+    }
+}
+
+/// Assembler expression, evaluated once all symbols are known.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Number(Literal),
+    Str(Vec<u8>),
+    /// Reference to a global symbol.
+    Symbol(String),
+    /// Reference to a sublabel of the current global label (`.name`).
+    Local(String),
+    /// Address of the current instruction (`$`).
+    Pc,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+/// Symbol lookups needed to evaluate an expression.
+pub trait Env {
+    /// Value of a global symbol, or of a sublabel when `local` is set.
+    ///
+    /// # Errors
+    /// Returns an error if the symbol cannot be resolved.
+    fn symbol(&self, name: &str, local: bool) -> Result<i128, AsmErrorKind>;
+
+    /// Address of the instruction being assembled.
+    fn pc(&self) -> i128;
+}
+
+/// Interpret a string literal as a big-endian integer.
+///
+/// # Errors
+/// Returns an error if the string does not fit into the integer.
+pub fn string_value(bytes: &[u8]) -> Result<i128, AsmErrorKind> {
+    if bytes.len() > 15 {
+        return Err(AsmErrorKind::InvalidString);
+    }
+    Ok(bytes
+        .iter()
+        .fold(0i128, |acc, &byte| (acc << 8) | i128::from(byte)))
+}
+
+impl Expr {
+    /// Width in bits of the expression if it is a bare sized literal.
+    #[must_use]
+    pub fn bits(&self) -> Option<u32> {
+        match self {
+            Expr::Number(literal) => literal.bits,
+            Expr::Str(bytes) => Some(bytes.len() as u32 * 8),
+            _ => None,
+        }
+    }
+
+    /// Evaluate the expression.
+    ///
+    /// # Errors
+    /// Returns an error if a symbol is unknown or the arithmetic is invalid.
+    pub fn eval(&self, env: &dyn Env) -> Result<i128, AsmErrorKind> {
+        match self {
+            Expr::Number(literal) => Ok(literal.value),
+            Expr::Str(bytes) => string_value(bytes),
+            Expr::Symbol(name) => env.symbol(name, false),
+            Expr::Local(name) => env.symbol(name, true),
+            Expr::Pc => Ok(env.pc()),
+            Expr::Unary(op, inner) => {
+                let value = inner.eval(env)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => !value,
+                    UnaryOp::LogicalNot => i128::from(value == 0),
+                })
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(env)?;
+                let rhs = rhs.eval(env)?;
+                Ok(match op {
+                    BinaryOp::Add => lhs.wrapping_add(rhs),
+                    BinaryOp::Sub => lhs.wrapping_sub(rhs),
+                    BinaryOp::Mul => lhs.wrapping_mul(rhs),
+                    BinaryOp::Div => lhs.checked_div(rhs).ok_or(AsmErrorKind::DivisionByZero)?,
+                    BinaryOp::Rem => lhs.checked_rem(rhs).ok_or(AsmErrorKind::DivisionByZero)?,
+                    BinaryOp::Shl => lhs.checked_shl(shift_amount(rhs)?).unwrap_or(0),
+                    BinaryOp::Shr => {
+                        lhs.checked_shr(shift_amount(rhs)?)
+                            .unwrap_or(if lhs < 0 { -1 } else { 0 })
+                    }
+                    BinaryOp::And => lhs & rhs,
+                    BinaryOp::Or => lhs | rhs,
+                    BinaryOp::Xor => lhs ^ rhs,
+                    BinaryOp::Eq => i128::from(lhs == rhs),
+                    BinaryOp::Ne => i128::from(lhs != rhs),
+                    BinaryOp::Lt => i128::from(lhs < rhs),
+                    BinaryOp::Le => i128::from(lhs <= rhs),
+                    BinaryOp::Gt => i128::from(lhs > rhs),
+                    BinaryOp::Ge => i128::from(lhs >= rhs),
+                    BinaryOp::LogicalAnd => i128::from(lhs != 0 && rhs != 0),
+                    BinaryOp::LogicalOr => i128::from(lhs != 0 || rhs != 0),
+                })
+            }
+        }
+    }
+}
+
+fn shift_amount(value: i128) -> Result<u32, AsmErrorKind> {
+    u32::try_from(value).map_err(|_| AsmErrorKind::ValueOutOfRange {
+        value,
+        min: 0,
+        max: i128::from(u32::MAX),
+    })
+}
+
+/// Parse an expression starting at the cursor.
+///
+/// # Errors
+/// Returns an error if the tokens do not form an expression.
+pub fn parse_expr(cursor: &mut Cursor) -> Result<Expr, AsmErrorKind> {
+    parse_binary(cursor, 0)
+}
+
+fn parse_binary(cursor: &mut Cursor, min_precedence: u8) -> Result<Expr, AsmErrorKind> {
+    let mut lhs = parse_unary(cursor)?;
+    while let Some((op, precedence)) = cursor.peek().and_then(BinaryOp::from_token) {
+        if precedence <= min_precedence {
+            break;
+        }
+        cursor.advance();
+        let rhs = parse_binary(cursor, precedence)?;
+        lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+    }
+    Ok(lhs)
+}
+
+fn parse_unary(cursor: &mut Cursor) -> Result<Expr, AsmErrorKind> {
+    let op = match cursor.peek() {
+        Some(Token::Minus) => UnaryOp::Neg,
+        Some(Token::Tilde) => UnaryOp::Not,
+        Some(Token::Bang) => UnaryOp::LogicalNot,
+        _ => return parse_primary(cursor),
+    };
+    cursor.advance();
+    Ok(Expr::Unary(op, Box::new(parse_unary(cursor)?)))
+}
+
+fn parse_primary(cursor: &mut Cursor) -> Result<Expr, AsmErrorKind> {
+    match cursor.advance() {
+        Some(Token::Number(literal)) => Ok(Expr::Number(*literal)),
+        Some(Token::Str(bytes)) => Ok(Expr::Str(bytes.clone())),
+        Some(Token::Ident(name)) => Ok(Expr::Symbol(name.clone())),
+        Some(Token::Dot) => Ok(Expr::Local(cursor.expect_ident()?)),
+        Some(Token::Dollar) => Ok(Expr::Pc),
+        Some(Token::LParen) => {
+            let inner = parse_expr(cursor)?;
+            cursor.expect(&Token::RParen)?;
+            Ok(inner)
+        }
+        Some(Token::Newline) | None => Err(AsmErrorKind::UnexpectedEnd),
+        Some(_) => Err(AsmErrorKind::UnexpectedToken),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::tokenize;
+
+    use super::*;
+
+    struct TestEnv;
+
+    impl Env for TestEnv {
+        fn symbol(&self, name: &str, local: bool) -> Result<i128, AsmErrorKind> {
+            match (name, local) {
+                ("label", false) => Ok(0x1234),
+                ("loop", true) => Ok(0x10),
+                _ => Err(AsmErrorKind::UnknownSymbol {
+                    symbol: name.to_string(),
+                }),
+            }
+        }
+
+        fn pc(&self) -> i128 {
+            0x100
+        }
+    }
+
+    fn eval(source: &str) -> Result<i128, AsmErrorKind> {
+        let tokens = tokenize(source).unwrap();
+        let mut cursor = Cursor::new(&tokens);
+        parse_expr(&mut cursor)?.eval(&TestEnv)
+    }
+
+    #[test]
+    fn respects_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("label >> 8 & 0xF"), Ok(0x2));
+        assert_eq!(eval("-1 + 3"), Ok(2));
+    }
+
+    #[test]
+    fn resolves_symbols_and_pc() {
+        assert_eq!(eval("label & 0xFF"), Ok(0x34));
+        assert_eq!(eval(".loop - $ - 2"), Ok(0x10 - 0x100 - 2));
+    }
+
+    #[test]
+    fn evaluates_strings_as_integers() {
+        assert_eq!(eval(r#""A""#), Ok(0x41));
+        assert_eq!(eval(r#""\0""#), Ok(0));
+    }
+
+    #[test]
+    fn reports_unknown_symbols() {
+        assert_eq!(
+            eval("missing"),
+            Err(AsmErrorKind::UnknownSymbol {
+                symbol: "missing".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use logos::Logos;
+
+use crate::error::AsmErrorKind;
+
+/// Integer literal with the width implied by its digits, if any.
+///
+/// Hex and binary literals are sized (`0x0F` is 8 bits), decimal literals are not.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Literal {
+    pub value: i128,
+    pub bits: Option<u32>,
+}
+
+fn parse_radix(digits: &str, radix: u32, bits_per_digit: u32) -> Result<Literal, AsmErrorKind> {
+    let digits = digits.replace('_', "");
+    if digits.is_empty() {
+        return Err(AsmErrorKind::InvalidNumber);
+    }
+    let value = i128::from_str_radix(&digits, radix).map_err(|_| AsmErrorKind::InvalidNumber)?;
+    Ok(Literal {
+        value,
+        bits: Some(digits.len() as u32 * bits_per_digit),
+    })
+}
+
+fn parse_dec(slice: &str) -> Result<Literal, AsmErrorKind> {
+    let value = slice
+        .replace('_', "")
+        .parse::<i128>()
+        .map_err(|_| AsmErrorKind::InvalidNumber)?;
+    Ok(Literal { value, bits: None })
+}
+
+fn parse_string(slice: &str) -> Result<Vec<u8>, AsmErrorKind> {
+    let inner = &slice[1..slice.len() - 1];
+    let mut result = Vec::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            result.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push(b'\n'),
+            Some('r') => result.push(b'\r'),
+            Some('t') => result.push(b'\t'),
+            Some('0') => result.push(0),
+            Some('\\') => result.push(b'\\'),
+            Some('"') => result.push(b'"'),
+            Some('\'') => result.push(b'\''),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16).map_err(|_| AsmErrorKind::InvalidString)?;
+                result.push(byte);
+            }
+            _ => return Err(AsmErrorKind::InvalidString),
+        }
+    }
+    Ok(result)
+}
+
+#[derive(Logos, Debug, PartialEq, Clone)]
+#[logos(error = AsmErrorKind)]
+#[logos(skip r"[ \t\r]+")]
+#[logos(skip r";[^\n]*")]
+pub enum Token {
+    #[token("\n")]
+    Newline,
+
+    /// Identifier (mnemonic, register, label or directive name)
+    #[regex(r"[A-Za-z_][A-Za-z0-9_]*", |lex| lex.slice().to_string())]
+    Ident(String),
+    /// Integer literal
+    #[regex(r"0[xX][0-9A-Fa-f_]*", |lex| parse_radix(&lex.slice()[2..], 16, 4))]
+    #[regex(r"0[bB][01_]*", |lex| parse_radix(&lex.slice()[2..], 2, 1))]
+    #[regex(r"[0-9][0-9_]*", |lex| parse_dec(lex.slice()))]
+    Number(Literal),
+    /// String literal, already unescaped
+    #[regex(r#""([^"\\\n]|\\.)*""#, |lex| parse_string(lex.slice()))]
+    #[regex(r"'([^'\\\n]|\\.)*'", |lex| parse_string(lex.slice()))]
+    Str(Vec<u8>),
+
+    #[token("#")]
+    Hash,
+    #[token(".")]
+    Dot,
+    #[token(":")]
+    Colon,
+    #[token(",")]
+    Comma,
+    #[token("=")]
+    Assign,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[token("{")]
+    LBrace,
+    #[token("}")]
+    RBrace,
+    #[token("$")]
+    Dollar,
+
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*")]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("%")]
+    Percent,
+    #[token("<<")]
+    Shl,
+    #[token(">>")]
+    Shr,
+    #[token("&")]
+    Ampersand,
+    #[token("|")]
+    Pipe,
+    #[token("^")]
+    Caret,
+    #[token("~")]
+    Tilde,
+    #[token("!")]
+    Bang,
+    #[token("==")]
+    Eq,
+    #[token("!=")]
+    Ne,
+    #[token("<")]
+    Lt,
+    #[token("<=")]
+    Le,
+    #[token(">")]
+    Gt,
+    #[token(">=")]
+    Ge,
+    #[token("&&")]
+    And,
+    #[token("||")]
+    Or,
+
+    /* Only seen inside `#ruledef` bodies, which are skipped */
+    #[token("=>")]
+    FatArrow,
+    #[token("@")]
+    At,
+    #[token("`")]
+    Backtick,
+}
+
+/// Split a source file into tokens, each paired with its 1-based line number.
+///
+/// # Errors
+/// Returns the offending line when the source contains an invalid token.
+pub fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, (AsmErrorKind, usize)> {
+    let mut line = 1;
+    let mut tokens = Vec::new();
+    for token in Token::lexer(source) {
+        let token = token.map_err(|err| (err, line))?;
+        let is_newline = token == Token::Newline;
+        tokens.push((token, line));
+        if is_newline {
+            line += 1;
+        }
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(source: &str) -> Vec<Token> {
+        tokenize(source)
+            .unwrap()
+            .into_iter()
+            .map(|(token, _)| token)
+            .collect()
+    }
+
+    #[test]
+    fn sizes_hex_and_binary_literals() {
+        assert_eq!(
+            kinds("0x0F 0b1010_1010 42"),
+            vec![
+                Token::Number(Literal {
+                    value: 0x0F,
+                    bits: Some(8)
+                }),
+                Token::Number(Literal {
+                    value: 0xAA,
+                    bits: Some(8)
+                }),
+                Token::Number(Literal {
+                    value: 42,
+                    bits: None
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        assert_eq!(
+            kinds(r#""hi\n\0""#),
+            vec![Token::Str(vec![b'h', b'i', b'\n', 0])]
+        );
+    }
+
+    #[test]
+    fn skips_comments_and_counts_lines() {
+        let tokens = tokenize("NOP ; comment\nHALT").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Ident("NOP".to_string()), 1),
+                (Token::Newline, 1),
+                (Token::Ident("HALT".to_string()), 2),
+            ]
+        );
+    }
+}
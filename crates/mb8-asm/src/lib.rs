@@ -0,0 +1,8 @@
+pub use assembler::{assemble, assemble_file, assemble_program};
+
+pub mod assembler;
+pub mod error;
+pub mod expr;
+pub mod lexer;
+pub mod parser;
+pub mod rules;
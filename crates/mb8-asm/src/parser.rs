@@ -0,0 +1,453 @@
+use mb8_isa::registers::Register;
+
+use crate::{
+    error::AsmErrorKind,
+    expr::{parse_expr, Expr},
+    lexer::Token,
+};
+
+/// Token stream position used by the statement and expression parsers.
+#[derive(Debug)]
+pub struct Cursor<'a> {
+    tokens: &'a [(Token, usize)],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    #[must_use]
+    pub fn new(tokens: &'a [(Token, usize)]) -> Self {
+        Self { tokens, pos: 0 }
+    }
+
+    #[must_use]
+    pub fn peek(&self) -> Option<&'a Token> {
+        self.peek_nth(0)
+    }
+
+    #[must_use]
+    pub fn peek_nth(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + n).map(|(token, _)| token)
+    }
+
+    pub fn advance(&mut self) -> Option<&'a Token> {
+        let token = self.peek();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    /// Line of the next token, or of the last one at the end of input.
+    #[must_use]
+    pub fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    /// Whether the cursor is at the end of the current line.
+    #[must_use]
+    pub fn at_line_end(&self) -> bool {
+        matches!(self.peek(), None | Some(Token::Newline))
+    }
+
+    pub fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume the expected token.
+    ///
+    /// # Errors
+    /// Returns an error if the next token is different.
+    pub fn expect(&mut self, token: &Token) -> Result<(), AsmErrorKind> {
+        if self.eat(token) {
+            Ok(())
+        } else if self.at_line_end() {
+            Err(AsmErrorKind::UnexpectedEnd)
+        } else {
+            Err(AsmErrorKind::UnexpectedToken)
+        }
+    }
+
+    /// Consume an identifier.
+    ///
+    /// # Errors
+    /// Returns an error if the next token is not an identifier.
+    pub fn expect_ident(&mut self) -> Result<String, AsmErrorKind> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                Ok(name.clone())
+            }
+            _ if self.at_line_end() => Err(AsmErrorKind::UnexpectedEnd),
+            _ => Err(AsmErrorKind::UnexpectedToken),
+        }
+    }
+
+    /// Consume the rest of the current line, including the newline.
+    ///
+    /// # Errors
+    /// Returns an error if there are unconsumed tokens on the line.
+    pub fn expect_line_end(&mut self) -> Result<(), AsmErrorKind> {
+        match self.advance() {
+            None | Some(Token::Newline) => Ok(()),
+            Some(_) => Err(AsmErrorKind::UnexpectedToken),
+        }
+    }
+}
+
+/// Parse a register name as defined by the `register` subrule in `asm/isa.asm`.
+#[must_use]
+pub fn parse_register(name: &str) -> Option<Register> {
+    let register = match name.to_ascii_uppercase().as_str() {
+        "R0" | "A" => Register::R0,
+        "R1" => Register::R1,
+        "R2" => Register::R2,
+        "R3" => Register::R3,
+        "R4" => Register::R4,
+        "R5" => Register::R5,
+        "R6" => Register::R6,
+        "R7" => Register::R7,
+        "R8" => Register::R8,
+        "R9" | "IL" => Register::R9,
+        "R10" | "IH" => Register::R10,
+        "R11" | "FPH" => Register::R11,
+        "R12" | "FPL" => Register::R12,
+        "R13" | "SPH" => Register::R13,
+        "R14" | "SPL" => Register::R14,
+        "R15" | "F" => Register::R15,
+        _ => return None,
+    };
+    Some(register)
+}
+
+/// Single instruction operand.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Operand {
+    /// `R0`
+    Register(Register),
+    /// `[R0:R1]`
+    Pair(Register, Register),
+    /// `[R0:R1 - offset]`
+    PairOffset(Register, Register, Expr),
+    /// `[addr]`
+    Indirect(Expr),
+    /// `value`
+    Expr(Expr),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Instruction {
+    /// Upper-cased mnemonic.
+    pub mnemonic: String,
+    pub operands: Vec<Operand>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct BankDef {
+    pub name: String,
+    pub addr: Option<Expr>,
+    pub size: Option<Expr>,
+    pub outp: Option<Expr>,
+    pub fill: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Label(String),
+    Sublabel(String),
+    Constant {
+        name: String,
+        local: bool,
+        value: Expr,
+    },
+    Include(String),
+    Once,
+    Addr(Expr),
+    Bankdef(BankDef),
+    Bank(String),
+    Data {
+        bits: Option<u32>,
+        values: Vec<Expr>,
+    },
+    Instruction(Instruction),
+}
+
+/// Parse a tokenized source file into statements paired with their line numbers.
+///
+/// # Errors
+/// Returns the error and the line it occurred on.
+pub fn parse(tokens: &[(Token, usize)]) -> Result<Vec<(Statement, usize)>, (AsmErrorKind, usize)> {
+    let mut cursor = Cursor::new(tokens);
+    let mut statements = Vec::new();
+    while cursor.peek().is_some() {
+        let line = cursor.line();
+        match parse_statement(&mut cursor) {
+            Ok(Some(statement)) => statements.push((statement, line)),
+            Ok(None) => {}
+            Err(err) => return Err((err, cursor.line())),
+        }
+    }
+    Ok(statements)
+}
+
+fn parse_statement(cursor: &mut Cursor) -> Result<Option<Statement>, AsmErrorKind> {
+    match (cursor.peek(), cursor.peek_nth(1), cursor.peek_nth(2)) {
+        (Some(Token::Newline), _, _) => {
+            cursor.advance();
+            Ok(None)
+        }
+        (Some(Token::Hash), _, _) => {
+            cursor.advance();
+            parse_directive(cursor)
+        }
+        (Some(Token::Ident(name)), Some(Token::Colon), _) => {
+            let name = name.clone();
+            cursor.pos += 2;
+            Ok(Some(Statement::Label(name)))
+        }
+        (Some(Token::Dot), Some(Token::Ident(name)), Some(Token::Colon)) => {
+            let name = name.clone();
+            cursor.pos += 3;
+            Ok(Some(Statement::Sublabel(name)))
+        }
+        (Some(Token::Ident(name)), Some(Token::Assign), _) => {
+            let name = name.clone();
+            cursor.pos += 2;
+            let value = parse_expr(cursor)?;
+            cursor.expect_line_end()?;
+            Ok(Some(Statement::Constant {
+                name,
+                local: false,
+                value,
+            }))
+        }
+        (Some(Token::Dot), Some(Token::Ident(name)), Some(Token::Assign)) => {
+            let name = name.clone();
+            cursor.pos += 3;
+            let value = parse_expr(cursor)?;
+            cursor.expect_line_end()?;
+            Ok(Some(Statement::Constant {
+                name,
+                local: true,
+                value,
+            }))
+        }
+        (Some(Token::Ident(_)), _, _) => {
+            let instruction = parse_instruction(cursor)?;
+            cursor.expect_line_end()?;
+            Ok(Some(Statement::Instruction(instruction)))
+        }
+        _ => Err(AsmErrorKind::UnexpectedToken),
+    }
+}
+
+fn parse_directive(cursor: &mut Cursor) -> Result<Option<Statement>, AsmErrorKind> {
+    let name = cursor.expect_ident()?;
+    let statement = match name.as_str() {
+        "include" => match cursor.advance() {
+            Some(Token::Str(path)) => Statement::Include(
+                String::from_utf8(path.clone()).map_err(|_| AsmErrorKind::InvalidString)?,
+            ),
+            _ => return Err(AsmErrorKind::UnexpectedToken),
+        },
+        "once" => Statement::Once,
+        "addr" => Statement::Addr(parse_expr(cursor)?),
+        "bank" => Statement::Bank(cursor.expect_ident()?),
+        "bankdef" => Statement::Bankdef(parse_bankdef(cursor)?),
+        "ruledef" | "subruledef" => {
+            skip_block(cursor)?;
+            return Ok(None);
+        }
+        "d" => Statement::Data {
+            bits: None,
+            values: parse_data_values(cursor)?,
+        },
+        _ => match name.strip_prefix('d').map(str::parse::<u32>) {
+            Some(Ok(bits)) if bits > 0 && bits % 8 == 0 => Statement::Data {
+                bits: Some(bits),
+                values: parse_data_values(cursor)?,
+            },
+            _ => return Err(AsmErrorKind::UnknownDirective { name }),
+        },
+    };
+    cursor.expect_line_end()?;
+    Ok(Some(statement))
+}
+
+fn parse_data_values(cursor: &mut Cursor) -> Result<Vec<Expr>, AsmErrorKind> {
+    let mut values = vec![parse_expr(cursor)?];
+    while cursor.eat(&Token::Comma) {
+        // Allow a trailing comma followed by values on the next lines
+        while cursor.eat(&Token::Newline) {}
+        values.push(parse_expr(cursor)?);
+    }
+    Ok(values)
+}
+
+fn parse_bankdef(cursor: &mut Cursor) -> Result<BankDef, AsmErrorKind> {
+    let mut bank = BankDef {
+        name: cursor.expect_ident()?,
+        ..BankDef::default()
+    };
+    while cursor.eat(&Token::Newline) {}
+    cursor.expect(&Token::LBrace)?;
+    loop {
+        match cursor.advance() {
+            Some(Token::Newline) => {}
+            Some(Token::RBrace) => break,
+            Some(Token::Hash) => {
+                let field = cursor.expect_ident()?;
+                match field.as_str() {
+                    "addr" => bank.addr = Some(parse_expr(cursor)?),
+                    "size" => bank.size = Some(parse_expr(cursor)?),
+                    "outp" => bank.outp = Some(parse_expr(cursor)?),
+                    "fill" => bank.fill = true,
+                    _ => return Err(AsmErrorKind::UnknownDirective { name: field }),
+                }
+            }
+            None => return Err(AsmErrorKind::UnexpectedEnd),
+            Some(_) => return Err(AsmErrorKind::UnexpectedToken),
+        }
+    }
+    Ok(bank)
+}
+
+/// Skip an optional name and a brace-delimited block, such as a `#ruledef` body.
+fn skip_block(cursor: &mut Cursor) -> Result<(), AsmErrorKind> {
+    while !cursor.eat(&Token::LBrace) {
+        if cursor.advance().is_none() {
+            return Err(AsmErrorKind::UnexpectedEnd);
+        }
+    }
+    let mut depth = 1;
+    while depth > 0 {
+        match cursor.advance() {
+            Some(Token::LBrace) => depth += 1,
+            Some(Token::RBrace) => depth -= 1,
+            Some(_) => {}
+            None => return Err(AsmErrorKind::UnexpectedEnd),
+        }
+    }
+    Ok(())
+}
+
+fn parse_instruction(cursor: &mut Cursor) -> Result<Instruction, AsmErrorKind> {
+    let mnemonic = cursor.expect_ident()?.to_ascii_uppercase();
+    let mut operands = Vec::new();
+    while !cursor.at_line_end() {
+        operands.push(parse_operand(cursor)?);
+    }
+    Ok(Instruction { mnemonic, operands })
+}
+
+fn peek_register(cursor: &Cursor, n: usize) -> Option<Register> {
+    match cursor.peek_nth(n) {
+        Some(Token::Ident(name)) => parse_register(name),
+        _ => None,
+    }
+}
+
+fn parse_operand(cursor: &mut Cursor) -> Result<Operand, AsmErrorKind> {
+    if let Some(register) = peek_register(cursor, 0) {
+        cursor.advance();
+        return Ok(Operand::Register(register));
+    }
+    if !cursor.eat(&Token::LBracket) {
+        return Ok(Operand::Expr(parse_expr(cursor)?));
+    }
+
+    let operand = match (
+        peek_register(cursor, 0),
+        cursor.peek_nth(1),
+        peek_register(cursor, 2),
+    ) {
+        (Some(hi), Some(Token::Colon), Some(lo)) => {
+            cursor.pos += 3;
+            if cursor.eat(&Token::Minus) {
+                Operand::PairOffset(hi, lo, parse_expr(cursor)?)
+            } else {
+                Operand::Pair(hi, lo)
+            }
+        }
+        _ => Operand::Indirect(parse_expr(cursor)?),
+    };
+    cursor.expect(&Token::RBracket)?;
+    Ok(operand)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{tokenize, Literal};
+
+    use super::*;
+
+    fn statements(source: &str) -> Vec<Statement> {
+        parse(&tokenize(source).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|(statement, _)| statement)
+            .collect()
+    }
+
+    #[test]
+    fn parses_labels_and_instructions_on_one_line() {
+        assert_eq!(
+            statements("loop: JR [loop]"),
+            vec![
+                Statement::Label("loop".to_string()),
+                Statement::Instruction(Instruction {
+                    mnemonic: "JR".to_string(),
+                    operands: vec![Operand::Indirect(Expr::Symbol("loop".to_string()))],
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_register_pairs() {
+        assert_eq!(
+            statements("ld r0 [IH:IL - 2]"),
+            vec![Statement::Instruction(Instruction {
+                mnemonic: "LD".to_string(),
+                operands: vec![
+                    Operand::Register(Register::R0),
+                    Operand::PairOffset(
+                        Register::R10,
+                        Register::R9,
+                        Expr::Number(Literal {
+                            value: 2,
+                            bits: None
+                        })
+                    ),
+                ],
+            })]
+        );
+    }
+
+    #[test]
+    fn skips_ruledef_blocks() {
+        assert_eq!(
+            statements("#ruledef test\n{\n    NOP => { asm { HALT } }\n}\n.end:"),
+            vec![Statement::Sublabel("end".to_string())]
+        );
+    }
+
+    #[test]
+    fn parses_bankdef() {
+        let source = "#bankdef rom\n{\n    #addr 0xE000\n    #size 0x1000\n    #fill\n}";
+        let statements = statements(source);
+        let [Statement::Bankdef(bank)] = statements.as_slice() else {
+            panic!("expected a single bankdef");
+        };
+        assert_eq!(bank.name, "rom");
+        assert!(bank.fill);
+        assert!(bank.outp.is_none());
+    }
+}
@@ -0,0 +1,516 @@
+//! Instruction rules.
+//!
+//! Mirrors the rules from `asm/isa.asm`, the pseudo-instructions from `asm/ext.asm` and the
+//! macros from `asm/std.asm`, so the output matches what `customasm` produces for them.
+//! `tests/test_rules.rs` fails when a rule in those files changes or its bytes drift.
+
+use std::collections::HashMap;
+
+use mb8_isa::{encode::encode, opcodes::Opcode, registers::Register};
+
+use crate::{
+    error::AsmErrorKind,
+    expr::{Env, Expr},
+    parser::{Instruction, Operand},
+};
+
+/// Scratch registers used by `CALL [addr]` and `JMP [addr]`.
+const ABS_HI: Register = Register::R6;
+const ABS_LO: Register = Register::R7;
+/// Scratch register used by immediate pseudo-instructions.
+const SCRATCH: Register = Register::R7;
+/// `IH` and `IL` as defined by the `register` subrule in `asm/isa.asm`.
+const INDEX_HI: Register = Register::R10;
+const INDEX_LO: Register = Register::R9;
+
+/// Collects the bytes produced by a single instruction.
+///
+/// When `strict` is unset the emitter only computes the layout: unresolved symbols are
+/// tolerated and range checks are skipped.
+#[derive(Debug)]
+pub struct Emitter {
+    base: i128,
+    bytes: Vec<u8>,
+    strict: bool,
+    /// Local labels of the enclosing macro, known from the previous run.
+    labels: HashMap<&'static str, i128>,
+    defined: HashMap<&'static str, i128>,
+}
+
+impl Emitter {
+    #[must_use]
+    pub fn new(base: i128, strict: bool) -> Self {
+        Self {
+            base,
+            bytes: Vec::new(),
+            strict,
+            labels: HashMap::new(),
+            defined: HashMap::new(),
+        }
+    }
+
+    #[must_use]
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    fn pc(&self) -> i128 {
+        self.base + self.bytes.len() as i128
+    }
+
+    fn word(&mut self, word: u16) {
+        self.bytes.extend_from_slice(&word.to_be_bytes());
+    }
+
+    fn op(&mut self, opcode: &Opcode) {
+        self.word(encode(opcode));
+    }
+
+    fn check(&self, value: i128, min: i128, max: i128) -> Result<i128, AsmErrorKind> {
+        if self.strict && !(min..=max).contains(&value) {
+            return Err(AsmErrorKind::ValueOutOfRange { value, min, max });
+        }
+        Ok(value)
+    }
+
+    fn u8(&self, value: i128) -> Result<u8, AsmErrorKind> {
+        Ok(self.check(value, 0, 0xFF)? as u8)
+    }
+
+    fn u16(&self, value: i128) -> Result<u16, AsmErrorKind> {
+        Ok(self.check(value, 0, 0xFFFF)? as u16)
+    }
+
+    fn i8(&self, value: i128) -> Result<i8, AsmErrorKind> {
+        Ok(self.check(value, -0x80, 0x7F)? as i8)
+    }
+
+    /// Run a macro body with its own local labels.
+    ///
+    /// The body runs twice: the first run only records where the labels are, the second
+    /// one emits the code with every label known.
+    fn scoped(
+        &mut self,
+        body: impl Fn(&mut Emitter) -> Result<(), AsmErrorKind>,
+    ) -> Result<(), AsmErrorKind> {
+        let mut probe = Emitter::new(self.pc(), false);
+        body(&mut probe)?;
+
+        let mut inner = Emitter::new(self.pc(), self.strict);
+        inner.labels = probe.defined;
+        body(&mut inner)?;
+        self.bytes.extend(inner.bytes);
+        Ok(())
+    }
+
+    fn define(&mut self, label: &'static str) {
+        self.defined.insert(label, self.pc());
+    }
+
+    fn label(&self, label: &'static str) -> i128 {
+        self.labels.get(label).copied().unwrap_or_default()
+    }
+
+    /* ext.asm */
+
+    fn ldi16(&mut self, hi: Register, lo: Register, value: i128) -> Result<(), AsmErrorKind> {
+        let [value_hi, value_lo] = self.u16(value)?.to_be_bytes();
+        self.op(&Opcode::Ldi {
+            dst: hi,
+            value: value_hi,
+        });
+        self.op(&Opcode::Ldi {
+            dst: lo,
+            value: value_lo,
+        });
+        Ok(())
+    }
+
+    fn call_abs(&mut self, addr: i128) -> Result<(), AsmErrorKind> {
+        self.ldi16(ABS_HI, ABS_LO, addr)?;
+        self.op(&Opcode::Call {
+            hi: ABS_HI,
+            lo: ABS_LO,
+        });
+        Ok(())
+    }
+
+    fn jmp_abs(&mut self, addr: i128) -> Result<(), AsmErrorKind> {
+        self.ldi16(ABS_HI, ABS_LO, addr)?;
+        self.op(&Opcode::Jmp {
+            hi: ABS_HI,
+            lo: ABS_LO,
+        });
+        Ok(())
+    }
+
+    /// Relative jump to an absolute address.
+    fn jump_to(&mut self, jump: fn(i8) -> Opcode, addr: i128) -> Result<(), AsmErrorKind> {
+        let addr = self.u16(addr)?;
+        let offset = i128::from(addr) - self.pc() - 2;
+        if self.strict && !(-0x80..=0x7F).contains(&offset) {
+            return Err(AsmErrorKind::JumpOutOfRange { offset });
+        }
+        self.op(&jump(offset as i8));
+        Ok(())
+    }
+
+    fn zero(&mut self, reg: Register) {
+        self.op(&Opcode::Ldi { dst: reg, value: 0 });
+    }
+
+    /// `PUSH R7; LDI R7 value; <op>; POP R7`
+    fn with_scratch(&mut self, value: u8, op: &Opcode) {
+        self.op(&Opcode::Push { src: SCRATCH });
+        self.op(&Opcode::Ldi {
+            dst: SCRATCH,
+            value,
+        });
+        self.op(op);
+        self.op(&Opcode::Pop { dst: SCRATCH });
+    }
+
+    fn inc(&mut self, reg: Register) {
+        self.with_scratch(
+            1,
+            &Opcode::Add {
+                dst: reg,
+                src: SCRATCH,
+            },
+        );
+    }
+
+    fn dec(&mut self, reg: Register) {
+        self.with_scratch(
+            1,
+            &Opcode::Sub {
+                dst: reg,
+                src: SCRATCH,
+            },
+        );
+    }
+
+    fn not(&mut self, reg: Register) {
+        self.with_scratch(
+            0xFF,
+            &Opcode::Xor {
+                dst: reg,
+                src: SCRATCH,
+            },
+        );
+    }
+
+    fn cmpi(&mut self, reg: Register, value: i128) -> Result<(), AsmErrorKind> {
+        let value = self.u8(value)?;
+        self.with_scratch(
+            value,
+            &Opcode::Sub {
+                dst: SCRATCH,
+                src: reg,
+            },
+        );
+        Ok(())
+    }
+
+    fn shri(&mut self, reg: Register, value: i128) -> Result<(), AsmErrorKind> {
+        let value = self.u8(value)?;
+        self.with_scratch(
+            value,
+            &Opcode::Shr {
+                dst: reg,
+                src: SCRATCH,
+            },
+        );
+        Ok(())
+    }
+
+    fn shli(&mut self, reg: Register, value: i128) -> Result<(), AsmErrorKind> {
+        let value = self.u8(value)?;
+        self.with_scratch(
+            value,
+            &Opcode::Shl {
+                dst: reg,
+                src: SCRATCH,
+            },
+        );
+        Ok(())
+    }
+
+    fn inc16(&mut self, hi: Register, lo: Register) -> Result<(), AsmErrorKind> {
+        self.scoped(|em| {
+            em.cmpi(lo, 0xFF)?;
+            em.jump_to(|offset| Opcode::Jzr { offset }, em.label("inc_hi"))?;
+            em.inc(lo);
+            em.jump_to(|offset| Opcode::Jr { offset }, em.label("end"))?;
+            em.define("inc_hi");
+            em.zero(lo);
+            em.inc(hi);
+            em.define("end");
+            em.op(&Opcode::Nop);
+            Ok(())
+        })
+    }
+
+    fn swap(&mut self, a: Register, b: Register) {
+        self.op(&Opcode::Push { src: a });
+        self.op(&Opcode::Mov { dst: a, src: b });
+        self.op(&Opcode::Pop { dst: b });
+    }
+
+    fn mul(&mut self, dst: Register, a: Register, b: Register) -> Result<(), AsmErrorKind> {
+        self.scoped(|em| {
+            em.zero(dst);
+            em.op(&Opcode::Push { src: b });
+            em.define("iter");
+            em.op(&Opcode::Add { dst, src: a });
+            em.dec(b);
+            em.cmpi(b, 0)?;
+            em.jump_to(|offset| Opcode::Jnzr { offset }, em.label("iter"))?;
+            em.op(&Opcode::Pop { dst: b });
+            Ok(())
+        })
+    }
+
+    fn st_abs(&mut self, addr: i128, src: Register) -> Result<(), AsmErrorKind> {
+        self.ldi16(INDEX_HI, INDEX_LO, addr)?;
+        self.op(&Opcode::St {
+            src,
+            hi: INDEX_HI,
+            lo: INDEX_LO,
+        });
+        Ok(())
+    }
+
+    fn ld_abs(&mut self, dst: Register, addr: i128) -> Result<(), AsmErrorKind> {
+        self.ldi16(INDEX_HI, INDEX_LO, addr)?;
+        self.op(&Opcode::Ld {
+            dst,
+            hi: INDEX_HI,
+            lo: INDEX_LO,
+        });
+        Ok(())
+    }
+
+    fn ld_offset(
+        &mut self,
+        dst: Register,
+        hi: Register,
+        lo: Register,
+        offset: i128,
+    ) -> Result<(), AsmErrorKind> {
+        let offset = self.u8(offset)?;
+        self.scoped(|em| {
+            em.op(&Opcode::Ldi {
+                dst: Register::R0,
+                value: offset,
+            });
+            em.op(&Opcode::Sub {
+                dst: lo,
+                src: Register::R0,
+            });
+            em.jump_to(|offset| Opcode::Jncr { offset }, em.label("no_borrow"))?;
+            em.dec(hi);
+            em.define("no_borrow");
+            em.op(&Opcode::Ld { dst, hi, lo });
+            Ok(())
+        })
+    }
+
+    /* std.asm */
+
+    fn memcpy(
+        &mut self,
+        (dst_hi, dst_lo): (Register, Register),
+        (src_hi, src_lo): (Register, Register),
+        len: Register,
+    ) -> Result<(), AsmErrorKind> {
+        self.scoped(|em| {
+            em.op(&Opcode::Push { src: Register::R0 });
+            em.zero(Register::R0);
+            em.define("loop");
+            em.op(&Opcode::Push { src: Register::R0 });
+            em.op(&Opcode::Ld {
+                dst: Register::R0,
+                hi: src_hi,
+                lo: src_lo,
+            });
+            em.op(&Opcode::St {
+                src: Register::R0,
+                hi: dst_hi,
+                lo: dst_lo,
+            });
+            em.op(&Opcode::Pop { dst: Register::R0 });
+            em.op(&Opcode::Cmp {
+                dst: Register::R0,
+                src: len,
+            });
+            em.jump_to(|offset| Opcode::Jzr { offset }, em.label("end"))?;
+            em.inc(Register::R0);
+            em.inc16(src_hi, src_lo)?;
+            em.inc16(dst_hi, dst_lo)?;
+            em.jump_to(|offset| Opcode::Jr { offset }, em.label("loop"))?;
+            em.define("end");
+            em.op(&Opcode::Pop { dst: Register::R0 });
+            Ok(())
+        })
+    }
+
+    fn strcmp(&mut self, regs: [Register; 6]) -> Result<(), AsmErrorKind> {
+        let [i, j, src_hi, src_lo, dst_hi, dst_lo] = regs;
+        self.scoped(|em| {
+            em.define("loop");
+            em.op(&Opcode::Ld {
+                dst: i,
+                hi: src_hi,
+                lo: src_lo,
+            });
+            em.op(&Opcode::Ld {
+                dst: j,
+                hi: dst_hi,
+                lo: dst_lo,
+            });
+            em.op(&Opcode::Cmp { dst: i, src: j });
+            em.jump_to(|offset| Opcode::Jnzr { offset }, em.label("error"))?;
+            em.cmpi(j, 0)?;
+            em.jump_to(|offset| Opcode::Jzr { offset }, em.label("success"))?;
+            em.inc16(src_hi, src_lo)?;
+            em.inc16(dst_hi, dst_lo)?;
+            em.jmp_abs(em.label("loop"))?;
+            em.define("error");
+            em.op(&Opcode::Ldi { dst: i, value: 1 });
+            em.jump_to(|offset| Opcode::Jr { offset }, em.label("end"))?;
+            em.define("success");
+            em.op(&Opcode::Ldi { dst: i, value: 0 });
+            em.define("end");
+            Ok(())
+        })
+    }
+}
+
+fn reg_reg(mnemonic: &str, dst: Register, src: Register) -> Option<Opcode> {
+    let opcode = match mnemonic {
+        "MOV" => Opcode::Mov { dst, src },
+        "ADD" => Opcode::Add { dst, src },
+        "SUB" => Opcode::Sub { dst, src },
+        "AND" => Opcode::And { dst, src },
+        "OR" => Opcode::Or { dst, src },
+        "XOR" => Opcode::Xor { dst, src },
+        "SHR" => Opcode::Shr { dst, src },
+        "SHL" => Opcode::Shl { dst, src },
+        "CMP" => Opcode::Cmp { dst, src },
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+fn relative_jump(mnemonic: &str) -> Option<fn(i8) -> Opcode> {
+    let jump: fn(i8) -> Opcode = match mnemonic {
+        "JR" => |offset| Opcode::Jr { offset },
+        "JZR" => |offset| Opcode::Jzr { offset },
+        "JNZR" => |offset| Opcode::Jnzr { offset },
+        "JCR" => |offset| Opcode::Jcr { offset },
+        "JNCR" => |offset| Opcode::Jncr { offset },
+        _ => return None,
+    };
+    Some(jump)
+}
+
+/// Assemble a single instruction at the emitter position.
+///
+/// # Errors
+/// Returns an error if no rule matches the instruction or an operand is out of range.
+#[allow(clippy::too_many_lines)]
+pub fn assemble_instruction(
+    instruction: &Instruction,
+    em: &mut Emitter,
+    env: &dyn Env,
+) -> Result<(), AsmErrorKind> {
+    use Operand::{Expr as E, Indirect as I, Pair as P, PairOffset, Register as R};
+
+    let eval = |expr: &Expr| expr.eval(env);
+    let mnemonic = instruction.mnemonic.as_str();
+
+    match (mnemonic, instruction.operands.as_slice()) {
+        /* isa.asm */
+        ("NOP", []) => em.op(&Opcode::Nop),
+        ("HALT", []) => em.op(&Opcode::Halt),
+        ("HALT", [E(code)]) => {
+            let code = em.u8(eval(code)?)?;
+            em.word(encode(&Opcode::Halt) | u16::from(code));
+        }
+        ("SYS", []) => em.op(&Opcode::Sys),
+        ("EI", []) => em.op(&Opcode::Ei),
+        ("DI", []) => em.op(&Opcode::Di),
+        ("RETI", []) => em.op(&Opcode::Reti),
+        (_, [R(dst), R(src)]) if reg_reg(mnemonic, *dst, *src).is_some() => {
+            if let Some(opcode) = reg_reg(mnemonic, *dst, *src) {
+                em.op(&opcode);
+            }
+        }
+        ("LDI", [R(dst), E(value)]) => {
+            let value = em.u8(eval(value)?)?;
+            em.op(&Opcode::Ldi { dst: *dst, value });
+        }
+        ("JMP", [P(hi, lo)]) => em.op(&Opcode::Jmp { hi: *hi, lo: *lo }),
+        ("CALL", [P(hi, lo)]) => em.op(&Opcode::Call { hi: *hi, lo: *lo }),
+        ("RET", []) => em.op(&Opcode::Ret),
+        ("PUSH", [R(src)]) => em.op(&Opcode::Push { src: *src }),
+        ("POP", [R(dst)]) => em.op(&Opcode::Pop { dst: *dst }),
+        ("LD", [R(dst), P(hi, lo)]) => em.op(&Opcode::Ld {
+            dst: *dst,
+            hi: *hi,
+            lo: *lo,
+        }),
+        ("ST", [P(hi, lo), R(src)]) => em.op(&Opcode::St {
+            src: *src,
+            hi: *hi,
+            lo: *lo,
+        }),
+        (_, [E(offset)]) if relative_jump(mnemonic).is_some() => {
+            let offset = em.i8(eval(offset)?)?;
+            if let Some(jump) = relative_jump(mnemonic) {
+                em.op(&jump(offset));
+            }
+        }
+
+        /* ext.asm */
+        ("LDI", [R(hi), R(lo), E(value)]) => em.ldi16(*hi, *lo, eval(value)?)?,
+        ("CALL", [I(addr)]) => em.call_abs(eval(addr)?)?,
+        ("JMP", [I(addr)]) => em.jmp_abs(eval(addr)?)?,
+        ("JR" | "JZR" | "JNZR" | "JNCR", [I(addr)]) => {
+            if let Some(jump) = relative_jump(mnemonic) {
+                em.jump_to(jump, eval(addr)?)?;
+            }
+        }
+        ("ZERO", [R(reg)]) => em.zero(*reg),
+        ("INC", [R(reg)]) => em.inc(*reg),
+        ("DEC", [R(reg)]) => em.dec(*reg),
+        ("INC16", [R(hi), R(lo)]) => em.inc16(*hi, *lo)?,
+        ("NOT", [R(reg)]) => em.not(*reg),
+        ("CMPI", [R(reg), E(value)]) => em.cmpi(*reg, eval(value)?)?,
+        ("SHRI", [R(reg), E(value)]) => em.shri(*reg, eval(value)?)?,
+        ("SHLI", [R(reg), E(value)]) => em.shli(*reg, eval(value)?)?,
+        ("SWAP", [R(a), R(b)]) => em.swap(*a, *b),
+        ("MUL", [R(dst), R(a), R(b)]) => em.mul(*dst, *a, *b)?,
+        ("ST", [I(addr), R(src)]) => em.st_abs(eval(addr)?, *src)?,
+        ("LD", [R(dst), I(addr)]) => em.ld_abs(*dst, eval(addr)?)?,
+        ("LD", [R(dst), PairOffset(hi, lo, offset)]) => {
+            em.ld_offset(*dst, *hi, *lo, eval(offset)?)?;
+        }
+
+        /* std.asm */
+        ("MEMCPY", [P(dst_hi, dst_lo), P(src_hi, src_lo), R(len)]) => {
+            em.memcpy((*dst_hi, *dst_lo), (*src_hi, *src_lo), *len)?;
+        }
+        ("STRCMP", [R(i), R(j), R(src_hi), R(src_lo), R(dst_hi), R(dst_lo)]) => {
+            em.strcmp([*i, *j, *src_hi, *src_lo, *dst_hi, *dst_lo])?;
+        }
+
+        _ => {
+            return Err(AsmErrorKind::UnknownInstruction {
+                mnemonic: instruction.mnemonic.clone(),
+            })
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use mb8_asm::{assemble, assemble_file, assemble_program, error::AsmErrorKind};
+
+#[test]
+fn test_isa_instructions() {
+    let bin =
+        assemble("NOP\nLDI R1 0x42\nadd r1 r2\nPUSH A\nST [IH:IL] R3\nJR -2\nHALT 0x05").unwrap();
+    assert_eq!(
+        bin,
+        [0x00, 0x00, 0x21, 0x42, 0x11, 0x12, 0x42, 0x00, 0x63, 0xA9, 0x31, 0xFE, 0x01, 0x05]
+    );
+}
+
+#[test]
+fn test_labels_and_relative_jumps() {
+    let bin = assemble("start:\n  NOP\n.loop:\n  JNZR [.loop]\n  JR [start]").unwrap();
+    assert_eq!(bin, [0x00, 0x00, 0x33, 0xFE, 0x31, 0xFA]);
+}
+
+#[test]
+fn test_ext_absolute_call() {
+    let bin = assemble("CALL [target]\ntarget:\n  RET").unwrap();
+    assert_eq!(bin, [0x26, 0x00, 0x27, 0x06, 0x40, 0x67, 0x41, 0x00]);
+}
+
+#[test]
+fn test_data_directives() {
+    let bin = assemble("#d \"hi\", 0x00\n#d16 0x1234\n#d8 -1").unwrap();
+    assert_eq!(bin, [b'h', b'i', 0x00, 0x12, 0x34, 0xFF]);
+}
+
+#[test]
+fn test_bankdef_fills_and_places_output() {
+    let source =
+        "#bankdef rom\n{\n #addr 0xE000\n #size 0x10\n #outp 0\n #fill\n}\n#addr 0xE004\nNOP";
+    let bin = assemble(source).unwrap();
+    assert_eq!(bin.len(), 0x10);
+    assert!(bin.iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn test_constants_forward_reference() {
+    let bin = assemble("LDI R0 VALUE\nVALUE = 0x10 + 2").unwrap();
+    assert_eq!(bin, [0x20, 0x12]);
+}
+
+#[test]
+fn test_relative_jump_out_of_range() {
+    let err = assemble("JR [far]\n#d256 0\n#d256 0\n#d256 0\n#d256 0\n#d256 0\nfar:").unwrap_err();
+    assert_eq!(err.line, 1);
+    assert!(matches!(err.kind, AsmErrorKind::JumpOutOfRange { .. }));
+}
+
+#[test]
+fn test_unknown_symbol() {
+    let err = assemble("NOP\nJMP [missing]").unwrap_err();
+    assert_eq!(err.line, 2);
+    assert_eq!(
+        err.kind,
+        AsmErrorKind::UnknownSymbol {
+            symbol: "missing".to_string()
+        }
+    );
+}
+
+#[test]
+fn test_kernel_test_program() {
+    let bin = assemble_file("../../kernel/tests/test_sys_write.asm").unwrap();
+    assert_eq!(bin.len(), 0x1000);
+    // `start` is assembled at the beginning of the ROM bank
+    assert_eq!(bin[0..2], [0x20, 0x01]);
+}
+
+#[test]
+fn test_program_symbols() {
+    let program = assemble_program("../../kernel/tests/test_sys_write.asm").unwrap();
+    assert_eq!(program.labels.first(), Some(&(0xE000, "start".to_string())));
+    assert!(program.symbols().starts_with("E000 start\n"));
+}
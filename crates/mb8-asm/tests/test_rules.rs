@@ -0,0 +1,265 @@
+//! Keeps `rules.rs` in step with `asm/ext.asm` and `asm/std.asm`.
+//!
+//! Every rule in those files needs an entry in [`RULES`] with a sample use, the bytes
+//! customasm 0.14 emits for it with `--legacy=on`, and the fingerprint of the rule as it was
+//! when those bytes were taken. Editing a rule changes its fingerprint, so the test fails
+//! until the bytes are taken again and `rules.rs` is updated to match.
+
+use std::{fmt::Write, fs};
+
+use mb8_asm::assemble;
+
+struct Rule {
+    pattern: &'static str,
+    fingerprint: u64,
+    sample: &'static str,
+    bytes: &'static str,
+}
+
+/// Samples are assembled at address `0` after `#include "std.asm"`.
+const RULES: &[Rule] = &[
+    /* ext.asm */
+    Rule {
+        pattern: "LDI { rh: register } { rl: register } { addr: u16 }",
+        fingerprint: 0x671a_122a_ac0a_21a9,
+        sample: "LDI R1 R2 0x1234",
+        bytes: "21122234",
+    },
+    Rule {
+        pattern: "CALL [{ addr: u16 }]",
+        fingerprint: 0xb74e_f4b3_37e7_0526,
+        sample: "CALL [0x1234]",
+        bytes: "261227344067",
+    },
+    Rule {
+        pattern: "JMP [{ addr: u16 }]",
+        fingerprint: 0x184a_c8ae_6c5c_736c,
+        sample: "JMP [0x1234]",
+        bytes: "261227343067",
+    },
+    Rule {
+        pattern: "JR [{ addr: u16 }]",
+        fingerprint: 0xabed_870f_d177_b53d,
+        sample: "JR [target]\nNOP\ntarget:",
+        bytes: "31020000",
+    },
+    Rule {
+        pattern: "JZR [{ addr: u16 }]",
+        fingerprint: 0xf1c7_4bf3_3ea1_b76e,
+        sample: "JZR [target]\nNOP\ntarget:",
+        bytes: "32020000",
+    },
+    Rule {
+        pattern: "JNZR [{ addr: u16 }]",
+        fingerprint: 0x4a17_0bb7_476f_6dbb,
+        sample: "JNZR [target]\nNOP\ntarget:",
+        bytes: "33020000",
+    },
+    Rule {
+        pattern: "JNCR [{ addr: u16 }]",
+        fingerprint: 0x0c5a_7bea_1f07_857c,
+        sample: "JNCR [target]\nNOP\ntarget:",
+        bytes: "35020000",
+    },
+    Rule {
+        pattern: "ZERO { reg: register }",
+        fingerprint: 0x3692_b788_d683_77e5,
+        sample: "ZERO R3",
+        bytes: "2300",
+    },
+    Rule {
+        pattern: "INC { reg: register }",
+        fingerprint: 0x9c52_84e3_3e28_bcd4,
+        sample: "INC R3",
+        bytes: "4270270111374370",
+    },
+    Rule {
+        pattern: "DEC { reg: register }",
+        fingerprint: 0xfccb_009a_1313_a011,
+        sample: "DEC R3",
+        bytes: "4270270112374370",
+    },
+    Rule {
+        pattern: "INC16 { hi: register } { lo: register }",
+        fingerprint: 0x2fc6_37dc_5e80_c7fd,
+        sample: "INC16 R2 R3",
+        bytes: "427027ff12734370320a4270270111374370310a230042702701112743700000",
+    },
+    Rule {
+        pattern: "NOT { reg: register }",
+        fingerprint: 0x8162_bf62_7333_6bf2,
+        sample: "NOT R3",
+        bytes: "427027ff15374370",
+    },
+    Rule {
+        pattern: "CMPI { reg: register } { val: u8 }",
+        fingerprint: 0x6f03_be78_2795_347a,
+        sample: "CMPI R3 0x41",
+        bytes: "4270274112734370",
+    },
+    Rule {
+        pattern: "SHRI { reg: register } { val: u8 }",
+        fingerprint: 0x2a63_5f83_07f2_4fd6,
+        sample: "SHRI R3 2",
+        bytes: "4270270216374370",
+    },
+    Rule {
+        pattern: "SHLI { reg: register } { val: u8 }",
+        fingerprint: 0xb987_65b5_21b5_f93a,
+        sample: "SHLI R3 2",
+        bytes: "4270270217374370",
+    },
+    Rule {
+        pattern: "SWAP { reg1: register } { reg2: register }",
+        fingerprint: 0xb181_69f0_4cf7_6d26,
+        sample: "SWAP R1 R2",
+        bytes: "421010124320",
+    },
+    Rule {
+        pattern: "MUL { dst: register } { a: register } { b: register }",
+        fingerprint: 0xefc2_5c62_8ac1_c1b4,
+        sample: "MUL R1 R2 R3",
+        bytes: "2100423011124270270112374370427027001273437033ec4330",
+    },
+    Rule {
+        pattern: "ST [{ addr: u16 }] { src: register }",
+        fingerprint: 0xff5c_cfcf_12f8_0cc9,
+        sample: "ST [0x1234] R1",
+        bytes: "2a12293461a9",
+    },
+    Rule {
+        pattern: "LD { dst: register } [{ addr: u16 }]",
+        fingerprint: 0xf0a0_b67a_eb6f_cd23,
+        sample: "LD R1 [0x1234]",
+        bytes: "2a12293451a9",
+    },
+    Rule {
+        pattern: "LD { dst: register } [{ hi: register }:{ lo: register } - { offset: u8 }]",
+        fingerprint: 0xdfe1_1a9f_f9e9_dd90,
+        sample: "LD R1 [R2:R3 - 4]",
+        bytes: "20041230350842702701122743705123",
+    },
+    /* std.asm */
+    Rule {
+        pattern: "MEMCPY [{ dsthi: register }:{ dstlo: register}] \
+                  [{ srchi: register }:{ srclo: register}] { len: register }",
+        fingerprint: 0xdf65_5e02_cb16_5475,
+        sample: "MEMCPY [R1:R2] [R3:R4] R5",
+        bytes: "4200200042005034601243001805324a4270270111074370427027ff12744370320a\
+                4270270111474370310a240042702701113743700000427027ff12724370320a\
+                4270270111274370310a22004270270111174370000031aa4300",
+    },
+    Rule {
+        pattern: "STRCMP { i: register } { j: register } { srchi: register } \
+                  { srclo: register } { dsthi: register } { dstlo: register }",
+        fingerprint: 0x7f73_dbd5_86a0_9fa0,
+        sample: "STRCMP R1 R2 R3 R4 R5 R6",
+        bytes: "51345256181233504270270012724370324a427027ff12744370320a42702701114743\
+                70310a240042702701113743700000427027ff12764370320a4270270111674370310a\
+                260042702701115743700000260027003067210131022100",
+    },
+];
+
+/// Pattern and full text of every rule in the `#ruledef` blocks of `source`, with comments
+/// dropped and whitespace collapsed.
+fn parse_rules(source: &str) -> Vec<(String, String)> {
+    let mut rules = Vec::new();
+    let mut in_ruledef = false;
+    let mut current = String::new();
+    for line in source.lines() {
+        let line = line.split(';').next().unwrap_or_default().trim();
+        if line.starts_with("#ruledef") {
+            in_ruledef = true;
+            continue;
+        }
+        if !in_ruledef || line.is_empty() || (line == "{" && current.is_empty()) {
+            continue;
+        }
+        if line == "}" && current.is_empty() {
+            in_ruledef = false;
+            continue;
+        }
+
+        current.push(' ');
+        current.push_str(line);
+        if current.contains("=>") && current.matches('{').count() == current.matches('}').count() {
+            let text = normalize(&current);
+            let pattern = normalize(text.split("=>").next().unwrap_or_default());
+            rules.push((pattern, text));
+            current.clear();
+        }
+    }
+    rules
+}
+
+fn normalize(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// FNV-1a.
+fn fingerprint(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[test]
+fn test_every_rule_is_mirrored() {
+    let mut rules = Vec::new();
+    for file in ["ext.asm", "std.asm"] {
+        let source = fs::read_to_string(format!("../../asm/{file}")).unwrap();
+        rules.extend(parse_rules(&source));
+    }
+
+    let mut problems = Vec::new();
+    for (pattern, text) in &rules {
+        let Some(rule) = RULES
+            .iter()
+            .find(|rule| normalize(rule.pattern) == *pattern)
+        else {
+            problems.push(format!("`{pattern}` has no entry in RULES"));
+            continue;
+        };
+        let fingerprint = fingerprint(text);
+        if rule.fingerprint != fingerprint {
+            problems.push(format!(
+                "`{pattern}` changed (fingerprint {fingerprint:#018x}), take the customasm \
+                 bytes again and update rules.rs"
+            ));
+        }
+    }
+    for rule in RULES {
+        if !rules
+            .iter()
+            .any(|(pattern, _)| *pattern == normalize(rule.pattern))
+        {
+            problems.push(format!("`{}` is no longer defined", rule.pattern));
+        }
+    }
+    assert!(problems.is_empty(), "{}", problems.join("\n"));
+}
+
+#[test]
+fn test_rules_match_customasm() {
+    let mut problems = Vec::new();
+    for rule in RULES {
+        match assemble(rule.sample) {
+            Ok(bytes) if hex(&bytes) == rule.bytes => {}
+            Ok(bytes) => problems.push(format!(
+                "`{}`: expected {}, got {}",
+                rule.sample,
+                rule.bytes,
+                hex(&bytes)
+            )),
+            Err(err) => problems.push(format!("`{}`: {err}", rule.sample)),
+        }
+    }
+    assert!(problems.is_empty(), "{}", problems.join("\n"));
+}
@@ -1,9 +1,4 @@
-use crate::{opcodes::Opcode, registers::Register};
-
-const OPCODE_MASK: u16 = 0xF000;
-const A_MASK: u16 = 0x0F00;
-const B_MASK: u16 = 0x00F0;
-const C_MASK: u16 = 0x000F;
+use crate::{meta::lookup, opcodes::Opcode, registers::Register};
 
 /// Parse a 4-bit register value into a Register enum.
 #[must_use]
@@ -32,118 +27,10 @@ pub fn decode_register(reg: u16) -> Option<Register> {
 
 /// Decode a 16-bit instruction into an Opcode.
 #[must_use]
-#[allow(clippy::too_many_lines)]
 pub fn decode(instruction: u16) -> Option<Opcode> {
-    let opcode = (instruction & OPCODE_MASK) >> 12;
-    let a = (instruction & A_MASK) >> 8;
-    let b = (instruction & B_MASK) >> 4;
-    let c = instruction & C_MASK;
-    match opcode {
-        0x0 => {
-            // Control instructions
-            match a {
-                0x0 => Some(Opcode::Nop),
-                0x1 => Some(Opcode::Halt),
-                0x2 => Some(Opcode::Sys),
-                _ => None,
-            }
-        }
-        0x1 => {
-            // Group of reg-reg instructions
-            match a {
-                0x0 => Some(Opcode::Mov {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x1 => Some(Opcode::Add {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x2 => Some(Opcode::Sub {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x3 => Some(Opcode::And {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x4 => Some(Opcode::Or {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x5 => Some(Opcode::Xor {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x6 => Some(Opcode::Shr {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x7 => Some(Opcode::Shl {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                0x8 => Some(Opcode::Cmp {
-                    dst: decode_register(b)?,
-                    src: decode_register(c)?,
-                }),
-                _ => None,
-            }
-        }
-        0x2 => Some(Opcode::Ldi {
-            dst: decode_register(a)?,
-            value: (b << 4 | c) as u8,
-        }),
-        0x3 => match a {
-            0x0 => Some(Opcode::Jmp {
-                hi: decode_register(b)?,
-                lo: decode_register(c)?,
-            }),
-            0x1 => Some(Opcode::Jr {
-                offset: (b << 4 | c) as u8 as i8,
-            }),
-            0x2 => Some(Opcode::Jzr {
-                offset: (b << 4 | c) as u8 as i8,
-            }),
-            0x3 => Some(Opcode::Jnzr {
-                offset: (b << 4 | c) as u8 as i8,
-            }),
-            0x4 => Some(Opcode::Jcr {
-                offset: (b << 4 | c) as u8 as i8,
-            }),
-            0x5 => Some(Opcode::Jncr {
-                offset: (b << 4 | c) as u8 as i8,
-            }),
-            _ => None,
-        },
-
-        0x4 => match a {
-            0x0 => Some(Opcode::Call {
-                hi: decode_register(b)?,
-                lo: decode_register(c)?,
-            }),
-            0x1 => Some(Opcode::Ret),
-            0x2 => Some(Opcode::Push {
-                src: decode_register(b)?,
-            }),
-            0x3 => Some(Opcode::Pop {
-                dst: decode_register(b)?,
-            }),
-            _ => None,
-        },
-        0x5 => Some(Opcode::Ld {
-            dst: decode_register(a)?,
-            hi: decode_register(b)?,
-            lo: decode_register(c)?,
-        }),
-        0x6 => Some(Opcode::St {
-            src: decode_register(a)?,
-            hi: decode_register(b)?,
-            lo: decode_register(c)?,
-        }),
-        _ => None,
-    }
+    lookup(instruction).and_then(|info| (info.decode)(instruction))
 }
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,6 +82,13 @@ mod tests {
         assert_eq!(decode(0x0200), Some(Opcode::Sys));
     }
 
+    #[test]
+    fn test_parse_interrupt_control() {
+        assert_eq!(decode(0x0300), Some(Opcode::Ei));
+        assert_eq!(decode(0x0400), Some(Opcode::Di));
+        assert_eq!(decode(0x0500), Some(Opcode::Reti));
+    }
+
     #[test]
     fn test_parse_mov() {
         assert_eq!(
@@ -0,0 +1,177 @@
+//! Disassembler for MB8 binaries.
+//! Turns raw bytes back into `asm/isa.asm` syntax, one line per instruction.
+
+use std::fmt::{self, Display};
+
+use crate::{decode::decode, encode::encode, meta::OperandKind, opcodes::Opcode};
+
+/// Contents of a disassembled line.
+#[derive(Debug, PartialEq)]
+pub enum LineKind {
+    /// Valid instruction. Words that decode to an opcode but differ from its encoding, for
+    /// example in bits the opcode ignores, are marked with a comment.
+    Instruction(Opcode),
+    /// Bytes that do not form a valid instruction.
+    Data,
+}
+
+/// Single disassembled line.
+#[derive(Debug, PartialEq)]
+pub struct Line {
+    /// Address of the first byte.
+    pub address: u16,
+    /// Raw bytes of the line, two for instructions and data words, one for a trailing byte.
+    pub bytes: Vec<u8>,
+    pub kind: LineKind,
+}
+
+impl Line {
+    /// Absolute target of a relative jump.
+    #[must_use]
+    pub fn target(&self) -> Option<u16> {
+        let LineKind::Instruction(opcode) = &self.kind else {
+            return None;
+        };
+        let operand = opcode
+            .info()
+            .operands
+            .iter()
+            .find(|operand| operand.kind == OperandKind::Offset)?;
+        let offset = operand.field.extract(encode(opcode)) as u8 as i8;
+        Some(
+            self.address
+                .wrapping_add(2)
+                .wrapping_add(offset as i16 as u16),
+        )
+    }
+
+    /// Assembly text of the line, without address and raw bytes.
+    #[must_use]
+    pub fn text(&self) -> String {
+        let LineKind::Instruction(opcode) = &self.kind else {
+            return match self.bytes.as_slice() {
+                [hi, lo] => format!("#d16 0x{hi:02X}{lo:02X}"),
+                bytes => bytes
+                    .iter()
+                    .map(|byte| format!("#d8 0x{byte:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            };
+        };
+        if let Some(target) = self.target() {
+            return format!("{} [0x{target:04X}]", opcode.info().mnemonic());
+        }
+        match (opcode, self.bytes.as_slice()) {
+            (Opcode::Halt, [_, code]) if *code != 0 => format!("HALT 0x{code:02X}"),
+            (_, &[hi, lo]) if encode(opcode) != u16::from_be_bytes([hi, lo]) => {
+                format!("{opcode} ; non-canonical encoding")
+            }
+            _ => opcode.to_string(),
+        }
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        write!(f, "{:04X}  {bytes:<5}  {}", self.address, self.text())
+    }
+}
+
+/// Disassemble a binary loaded at the `base` address.
+#[must_use]
+pub fn disassemble(bytes: &[u8], base: u16) -> Vec<Line> {
+    bytes
+        .chunks(2)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let address = base.wrapping_add((i * 2) as u16);
+            let kind = match chunk {
+                [hi, lo] => decode(u16::from_be_bytes([*hi, *lo]))
+                    .map_or(LineKind::Data, LineKind::Instruction),
+                _ => LineKind::Data,
+            };
+            Line {
+                address,
+                bytes: chunk.to_vec(),
+                kind,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::registers::Register;
+
+    use super::*;
+
+    fn lines(bytes: &[u8], base: u16) -> Vec<String> {
+        disassemble(bytes, base)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_disassemble_instructions() {
+        assert_eq!(
+            lines(&[0x21, 0x42, 0x11, 0x12, 0x56, 0x67, 0x01, 0x00], 0xE000),
+            vec![
+                "E000  21 42  LDI R1 0x42",
+                "E002  11 12  ADD R1 R2",
+                "E004  56 67  LD R6 [R6:R7]",
+                "E006  01 00  HALT",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disassemble_relative_jumps() {
+        let lines = disassemble(&[0x00, 0x00, 0x33, 0xFC, 0x31, 0x02], 0x1000);
+        assert_eq!(lines[1].target(), Some(0x1000));
+        assert_eq!(lines[1].text(), "JNZR [0x1000]");
+        assert_eq!(lines[2].text(), "JR [0x1008]");
+    }
+
+    #[test]
+    fn test_disassemble_halt_code() {
+        assert_eq!(lines(&[0x01, 0x05], 0), vec!["0000  01 05  HALT 0x05"]);
+    }
+
+    #[test]
+    fn test_disassemble_invalid_words_as_data() {
+        let lines = disassemble(&[0xFF, 0xFF, 0xAB], 0);
+        assert_eq!(lines[0].kind, LineKind::Data);
+        assert_eq!(lines[0].text(), "#d16 0xFFFF");
+        assert_eq!(lines[1].to_string(), "0002  AB     #d8 0xAB");
+    }
+
+    #[test]
+    fn test_disassemble_non_canonical_words() {
+        // The CPU runs NOP with garbage in its low bits like any other NOP
+        let lines = disassemble(&[0x00, 0x01], 0);
+        assert_eq!(lines[0].kind, LineKind::Instruction(Opcode::Nop));
+        assert_eq!(
+            lines[0].to_string(),
+            "0000  00 01  NOP ; non-canonical encoding"
+        );
+    }
+
+    #[test]
+    fn test_disassemble_round_trip() {
+        let opcode = Opcode::St {
+            src: Register::R3,
+            hi: Register::R10,
+            lo: Register::R9,
+        };
+        let lines = disassemble(&encode(&opcode).to_be_bytes(), 0);
+        assert_eq!(lines[0].kind, LineKind::Instruction(opcode));
+        assert_eq!(lines[0].text(), "ST [R10:R9] R3");
+    }
+}
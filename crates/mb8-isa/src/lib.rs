@@ -1,5 +1,7 @@
 pub mod decode;
+pub mod disasm;
 pub mod encode;
+pub mod meta;
 pub mod opcodes;
 pub mod registers;
 
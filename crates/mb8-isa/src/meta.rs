@@ -0,0 +1,218 @@
+//! Opcode metadata.
+//! Types describing the opcode table generated by `opcodes!` in `opcodes.rs`. The encoder,
+//! decoder, disassembler and the VM dispatch all read from that table.
+
+use crate::{
+    decode::decode_register,
+    encode::encode_register,
+    opcodes::{Opcode, OPCODES},
+    registers::Register,
+};
+
+/// Bits of an instruction word holding an operand.
+///
+/// Instructions are `0xGABC`, where `G` is the opcode group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    /// Bits 8..12, also used as the sub-opcode nibble.
+    A,
+    /// Bits 4..8.
+    B,
+    /// Bits 0..4.
+    C,
+    /// Bits 0..8, for 8-bit immediates and offsets.
+    BC,
+}
+
+impl Field {
+    #[must_use]
+    pub const fn shift(self) -> u16 {
+        match self {
+            Field::A => 8,
+            Field::B => 4,
+            Field::C | Field::BC => 0,
+        }
+    }
+
+    #[must_use]
+    pub const fn mask(self) -> u16 {
+        match self {
+            Field::A | Field::B | Field::C => 0xF,
+            Field::BC => 0xFF,
+        }
+    }
+
+    /// Read the field from an instruction word.
+    #[must_use]
+    pub const fn extract(self, word: u16) -> u16 {
+        (word >> self.shift()) & self.mask()
+    }
+
+    /// Place bits into the field of an instruction word.
+    #[must_use]
+    pub const fn insert(self, bits: u16) -> u16 {
+        (bits & self.mask()) << self.shift()
+    }
+}
+
+/// Kind of an instruction operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperandKind {
+    /// 4-bit register index.
+    Register,
+    /// Unsigned 8-bit immediate.
+    Immediate,
+    /// Signed 8-bit relative jump offset.
+    Offset,
+}
+
+/// Rust type of an operand field of [`Opcode`].
+pub trait OperandValue: Sized + Copy {
+    const KIND: OperandKind;
+
+    /// Build the value from the raw field bits.
+    fn from_bits(bits: u16) -> Option<Self>;
+
+    /// Raw field bits of the value.
+    fn to_bits(self) -> u16;
+
+    /// Format the value using the `asm/isa.asm` syntax.
+    fn format(self) -> String;
+}
+
+impl OperandValue for Register {
+    const KIND: OperandKind = OperandKind::Register;
+
+    fn from_bits(bits: u16) -> Option<Self> {
+        decode_register(bits)
+    }
+
+    fn to_bits(self) -> u16 {
+        encode_register(self) as u16
+    }
+
+    fn format(self) -> String {
+        self.to_string()
+    }
+}
+
+impl OperandValue for u8 {
+    const KIND: OperandKind = OperandKind::Immediate;
+
+    fn from_bits(bits: u16) -> Option<Self> {
+        Some(bits as u8)
+    }
+
+    fn to_bits(self) -> u16 {
+        self as u16
+    }
+
+    fn format(self) -> String {
+        format!("0x{self:02X}")
+    }
+}
+
+impl OperandValue for i8 {
+    const KIND: OperandKind = OperandKind::Offset;
+
+    fn from_bits(bits: u16) -> Option<Self> {
+        Some(bits as u8 as i8)
+    }
+
+    fn to_bits(self) -> u16 {
+        self as u8 as u16
+    }
+
+    fn format(self) -> String {
+        self.to_string()
+    }
+}
+
+/// Description of a single operand.
+#[derive(Debug, PartialEq, Eq)]
+pub struct OperandInfo {
+    /// Name used in the syntax template and in `asm/isa.asm`.
+    pub name: &'static str,
+    pub kind: OperandKind,
+    pub field: Field,
+}
+
+/// Description of a single opcode.
+#[derive(Debug)]
+pub struct OpcodeInfo {
+    /// Assembly syntax with `{name}` placeholders for the operands, e.g. `LD {dst} [{hi}:{lo}]`.
+    pub syntax: &'static str,
+    /// Instruction group, the top nibble of the word.
+    pub group: u8,
+    /// Sub-opcode in the `A` nibble, if the group has several opcodes.
+    pub nibble: Option<u8>,
+    pub operands: &'static [OperandInfo],
+    /// Flags the opcode depends on.
+    pub flags_read: u8,
+    /// Flags the opcode may change.
+    pub flags_written: u8,
+    /// Cost of the opcode in CPU cycles.
+    pub cycles: u8,
+    /// Build the opcode from an instruction word matching [`OpcodeInfo::matches`].
+    pub decode: fn(u16) -> Option<Opcode>,
+}
+
+impl OpcodeInfo {
+    #[must_use]
+    pub fn mnemonic(&self) -> &'static str {
+        self.syntax.split(' ').next().unwrap_or(self.syntax)
+    }
+
+    /// Bits of the instruction word identifying the opcode.
+    #[must_use]
+    pub fn base(&self) -> u16 {
+        let group = (self.group as u16) << 12;
+        match self.nibble {
+            Some(nibble) => group | Field::A.insert(nibble as u16),
+            None => group,
+        }
+    }
+
+    /// Whether the instruction word belongs to this opcode.
+    #[must_use]
+    pub fn matches(&self, word: u16) -> bool {
+        let group = (word >> 12) as u8;
+        group == self.group
+            && self
+                .nibble
+                .is_none_or(|nibble| Field::A.extract(word) == nibble as u16)
+    }
+}
+
+/// Table entry for an instruction word, if any opcode matches it.
+#[must_use]
+pub fn lookup(word: u16) -> Option<&'static OpcodeInfo> {
+    OPCODES.iter().find(|info| info.matches(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        assert_eq!(Field::A.extract(0x1234), 0x2);
+        assert_eq!(Field::BC.extract(0x1234), 0x34);
+        assert_eq!(Field::B.insert(0xA), 0x00A0);
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(lookup(0x1101).map(OpcodeInfo::mnemonic), Some("ADD"));
+        assert_eq!(lookup(0x2FFF).map(OpcodeInfo::mnemonic), Some("LDI"));
+        assert!(lookup(0x0F00).is_none());
+    }
+
+    #[test]
+    fn test_opcodes_do_not_overlap() {
+        for word in 0..=u16::MAX {
+            let matching = OPCODES.iter().filter(|info| info.matches(word)).count();
+            assert!(matching <= 1, "0x{word:04X} matches {matching} opcodes");
+        }
+    }
+}
@@ -1,129 +1,441 @@
 //! Opcodes for the MB8 ISA.
 //! This module defines the opcodes used by the MB8 ISA.
+//!
+//! Every opcode is declared once in the `opcodes!` table below. The table generates the
+//! [`Opcode`] enum, the [`OPCODES`] metadata, the encoder and decoder helpers, [`Display`]
+//! and the [`Execute`] trait the VM implements to dispatch instructions, with the
+//! `impl_execute!` macro implementing it.
 
-use crate::registers::Register;
+use std::fmt::{self, Display};
 
-/// Full list of MB8 opcodes used in VM.
-#[derive(Debug, PartialEq)]
-pub enum Opcode {
+use crate::{
+    meta::{Field, OpcodeInfo, OperandInfo, OperandValue},
+    registers::{
+        flags::{C_FLAG, N_FLAG, Z_FLAG},
+        Register,
+    },
+};
+
+/// Flags written by arithmetic and logic opcodes, and restored by `RETI`.
+const ALU_FLAGS: u8 = Z_FLAG | N_FLAG | C_FLAG;
+
+macro_rules! opcodes {
+    ($(
+        $(#[doc = $doc:literal])*
+        $variant:ident $({ $($field:ident: $ty:ty = $slot:ident),* $(,)? })? => $handler:ident {
+            syntax: $syntax:literal,
+            group: $group:literal,
+            nibble: $nibble:expr,
+            reads: $reads:expr,
+            writes: $writes:expr,
+            cycles: $cycles:literal $(,)?
+        }
+    )*) => {
+        /// Full list of MB8 opcodes used in VM.
+        #[derive(Debug, PartialEq, Clone)]
+        pub enum Opcode {
+            $(
+                $(#[doc = $doc])*
+                $variant $({ $($field: $ty),* })?,
+            )*
+        }
+
+        /// Metadata of every opcode, in encoding order.
+        pub static OPCODES: &[OpcodeInfo] = &[$(
+            OpcodeInfo {
+                syntax: $syntax,
+                group: $group,
+                nibble: $nibble,
+                operands: &[$($(OperandInfo {
+                    name: stringify!($field),
+                    kind: <$ty as OperandValue>::KIND,
+                    field: Field::$slot,
+                }),*)?],
+                flags_read: $reads,
+                flags_written: $writes,
+                cycles: $cycles,
+                decode: |_word| {
+                    Some(Opcode::$variant $({ $(
+                        $field: <$ty as OperandValue>::from_bits(Field::$slot.extract(_word))?
+                    ),* })?)
+                },
+            },
+        )*];
+
+        opcodes!(@execute [$] $($handler ($($($field: $ty),*)?))*);
+
+        impl Opcode {
+            /// Table entry describing the opcode.
+            #[must_use]
+            pub fn info(&self) -> &'static OpcodeInfo {
+                // One index per opcode; add more if the table outgrows them
+                opcodes!(@info self, [], [$($variant)*], [
+                    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+                    16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+                    32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+                    48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+                ])
+            }
+
+            /// Operand bits of the instruction word.
+            #[must_use]
+            pub fn operand_bits(&self) -> u16 {
+                match *self {
+                    $(
+                        Opcode::$variant $({ $($field),* })? => {
+                            0 $($(| Field::$slot.insert($field.to_bits()))*)?
+                        }
+                    )*
+                }
+            }
+
+            /// Run the handler for this opcode.
+            pub fn dispatch<E: Execute + ?Sized>(&self, executor: &mut E) {
+                match *self {
+                    $(
+                        Opcode::$variant $({ $($field),* })? => {
+                            executor.$handler($($($field),*)?);
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl Display for Opcode {
+            /// Format the opcode using the `asm/isa.asm` syntax.
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                #[allow(unused_mut)]
+                let mut text = self.info().syntax.to_string();
+                match *self {
+                    $(
+                        Opcode::$variant $({ $($field),* })? => {
+                            $($(
+                                text = text.replace(
+                                    concat!("{", stringify!($field), "}"),
+                                    &$field.format(),
+                                );
+                            )*)?
+                        }
+                    )*
+                }
+                f.write_str(&text)
+            }
+        }
+    };
+
+    // `$d` is a literal `$`, so the generated macro can declare its own metavariables
+    (@execute [$d:tt] $($handler:ident ($($field:ident: $ty:ty),*))*) => {
+        /// Handlers for every opcode, implemented by the VM.
+        pub trait Execute {
+            $(
+                fn $handler(&mut self $(, $field: $ty)*);
+            )*
+        }
+
+        /// Implement [`Execute`] for a type by forwarding every handler to the inherent method
+        /// of the same name, e.g. `impl_execute!(VirtualMachine)`.
+        #[macro_export]
+        macro_rules! impl_execute {
+            ($d executor:ty) => {
+                const _: () = {
+                    use $crate::registers::Register;
+
+                    impl $crate::opcodes::Execute for $d executor {
+                        $(
+                            fn $handler(&mut self $(, $field: $ty)*) {
+                                <$d executor>::$handler(self $(, $field)*);
+                            }
+                        )*
+                    }
+                };
+            };
+        }
+    };
+
+    // Pairs each variant with the next literal index, then matches on all of them at once
+    (@info $self:ident, [$($arms:tt)*], [], [$($unused:literal)*]) => {
+        match $self {
+            $($arms)*
+        }
+    };
+    (
+        @info $self:ident,
+        [$($arms:tt)*],
+        [$variant:ident $($rest:ident)*],
+        [$index:literal $($indices:literal)*]
+    ) => {
+        opcodes!(
+            @info $self,
+            [$($arms)* Opcode::$variant { .. } => &OPCODES[$index],],
+            [$($rest)*],
+            [$($indices)*]
+        )
+    };
+}
+
+opcodes! {
     /* Control group */
     /// No operation. Instruction does nothing.
-    Nop,
+    Nop => nop {
+        syntax: "NOP",
+        group: 0x0,
+        nibble: Some(0x0),
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
     /// Halt the VM.
-    Halt,
+    Halt => halt {
+        syntax: "HALT",
+        group: 0x0,
+        nibble: Some(0x1),
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
     /// System call.
-    Sys,
+    Sys => sys {
+        syntax: "SYS",
+        group: 0x0,
+        nibble: Some(0x2),
+        reads: 0,
+        writes: 0,
+        cycles: 4,
+    }
+    /// Enable maskable interrupts.
+    Ei => ei {
+        syntax: "EI",
+        group: 0x0,
+        nibble: Some(0x3),
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
+    /// Disable maskable interrupts.
+    Di => di {
+        syntax: "DI",
+        group: 0x0,
+        nibble: Some(0x4),
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
+    /// Return from an interrupt handler, restoring flags and enabling interrupts.
+    Reti => reti {
+        syntax: "RETI",
+        group: 0x0,
+        nibble: Some(0x5),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 4,
+    }
 
     /* reg-reg opcodes */
     /// Move value from one register to another.
-    Mov {
-        dst: Register,
-        src: Register,
-    },
+    Mov { dst: Register = B, src: Register = C } => mov {
+        syntax: "MOV {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x0),
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
     /// Add `dst` and `src1` and store the result in `dst`.
-    Add {
-        dst: Register,
-        src: Register,
-    },
+    Add { dst: Register = B, src: Register = C } => add {
+        syntax: "ADD {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x1),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Subtract `src` from `dst` and store the result in `dst`.
-    Sub {
-        dst: Register,
-        src: Register,
-    },
+    Sub { dst: Register = B, src: Register = C } => sub {
+        syntax: "SUB {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x2),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Logical AND `dst` and `src1` and store the result in `dst`.
-    And {
-        dst: Register,
-        src: Register,
-    },
+    And { dst: Register = B, src: Register = C } => and {
+        syntax: "AND {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x3),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Logical OR `dst` and `src1` and store the result in `dst`.
-    Or {
-        dst: Register,
-        src: Register,
-    },
+    Or { dst: Register = B, src: Register = C } => or {
+        syntax: "OR {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x4),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Logical XOR `dst` and `src1` and store the result in `dst`.
-    Xor {
-        dst: Register,
-        src: Register,
-    },
+    Xor { dst: Register = B, src: Register = C } => xor {
+        syntax: "XOR {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x5),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Shift `dst` right by `src` bits and store the result in `dst`.
-    Shr {
-        dst: Register,
-        src: Register,
-    },
+    Shr { dst: Register = B, src: Register = C } => shr {
+        syntax: "SHR {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x6),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Shift `dst` left by `src` bits and store the result in `dst`.
-    Shl {
-        dst: Register,
-        src: Register,
-    },
-
+    Shl { dst: Register = B, src: Register = C } => shl {
+        syntax: "SHL {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x7),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
     /// Logical CMP `dst` and `src1` and store the result in `dst`.
-    Cmp {
-        dst: Register,
-        src: Register,
-    },
+    Cmp { dst: Register = B, src: Register = C } => cmp {
+        syntax: "CMP {dst} {src}",
+        group: 0x1,
+        nibble: Some(0x8),
+        reads: 0,
+        writes: ALU_FLAGS,
+        cycles: 1,
+    }
 
     /* Load */
-    Ldi {
-        dst: Register,
-        value: u8,
-    },
+    /// Load an 8-bit immediate into `dst`.
+    Ldi { dst: Register = A, value: u8 = BC } => ldi {
+        syntax: "LDI {dst} {value}",
+        group: 0x2,
+        nibble: None,
+        reads: 0,
+        writes: 0,
+        cycles: 1,
+    }
 
     /* Jump instructions */
     /// Absolute jump to address stored in registers `hi` and `lo` 0x{hi:02x}{lo:02x}.
-    Jmp {
-        hi: Register,
-        lo: Register,
-    },
+    Jmp { hi: Register = B, lo: Register = C } => jmp {
+        syntax: "JMP [{hi}:{lo}]",
+        group: 0x3,
+        nibble: Some(0x0),
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
     /// Relative jump by signed offset.
     /// PC = PC + offset
-    Jr {
-        offset: i8,
-    },
+    Jr { offset: i8 = BC } => jr {
+        syntax: "JR {offset}",
+        group: 0x3,
+        nibble: Some(0x1),
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
     /// Relative jump if zero flag is set.
-    Jzr {
-        offset: i8,
-    },
+    Jzr { offset: i8 = BC } => jzr {
+        syntax: "JZR {offset}",
+        group: 0x3,
+        nibble: Some(0x2),
+        reads: Z_FLAG,
+        writes: 0,
+        cycles: 2,
+    }
     /// Relative jump if zero flag is not set.
-    Jnzr {
-        offset: i8,
-    },
+    Jnzr { offset: i8 = BC } => jnzr {
+        syntax: "JNZR {offset}",
+        group: 0x3,
+        nibble: Some(0x3),
+        reads: Z_FLAG,
+        writes: 0,
+        cycles: 2,
+    }
     /// Relative jump if carry flag is set.
-    Jcr {
-        offset: i8,
-    },
+    Jcr { offset: i8 = BC } => jcr {
+        syntax: "JCR {offset}",
+        group: 0x3,
+        nibble: Some(0x4),
+        reads: C_FLAG,
+        writes: 0,
+        cycles: 2,
+    }
     /// Relative jump if carry flag is not set.
-    Jncr {
-        offset: i8,
-    },
+    Jncr { offset: i8 = BC } => jncr {
+        syntax: "JNCR {offset}",
+        group: 0x3,
+        nibble: Some(0x5),
+        reads: C_FLAG,
+        writes: 0,
+        cycles: 2,
+    }
 
     /* Stack instructions */
     /// Call subroutine at address `addr`.
-    Call {
-        hi: Register,
-        lo: Register,
-    },
+    Call { hi: Register = B, lo: Register = C } => call {
+        syntax: "CALL [{hi}:{lo}]",
+        group: 0x4,
+        nibble: Some(0x0),
+        reads: 0,
+        writes: 0,
+        cycles: 4,
+    }
     /// Return from subroutine.
-    Ret,
+    Ret => ret {
+        syntax: "RET",
+        group: 0x4,
+        nibble: Some(0x1),
+        reads: 0,
+        writes: 0,
+        cycles: 4,
+    }
     /// Push value from register `src` onto stack.
-    Push {
-        src: Register,
-    },
+    Push { src: Register = B } => push {
+        syntax: "PUSH {src}",
+        group: 0x4,
+        nibble: Some(0x2),
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
     /// Pop value from stack into register `dst`.
-    Pop {
-        dst: Register,
-    },
+    Pop { dst: Register = B } => pop {
+        syntax: "POP {dst}",
+        group: 0x4,
+        nibble: Some(0x3),
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
 
     /* Memory instructions */
     /// Load byte from memory address in `hi` and `lo` registers into register `dst`.
-    Ld {
-        dst: Register,
-        hi: Register,
-        lo: Register,
-    },
+    Ld { dst: Register = A, hi: Register = B, lo: Register = C } => ld {
+        syntax: "LD {dst} [{hi}:{lo}]",
+        group: 0x5,
+        nibble: None,
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
     /// Store byte from register `src` into memory address in `hi` and `lo` registers.
-    St {
-        src: Register,
-        hi: Register,
-        lo: Register,
-    },
+    St { src: Register = A, hi: Register = B, lo: Register = C } => st {
+        syntax: "ST [{hi}:{lo}] {src}",
+        group: 0x6,
+        nibble: None,
+        reads: 0,
+        writes: 0,
+        cycles: 2,
+    }
 }
@@ -1,5 +1,9 @@
 //! Register definitions for the MB8 VM.
 
+use std::fmt::{self, Display};
+
+use crate::encode::encode_register;
+
 pub mod flags {
     /// Zero flag for the flag register
     pub const Z_FLAG: u8 = 0b0000_0001;
@@ -66,3 +70,10 @@ pub enum Register {
     /// Flag register
     F,
 }
+
+impl Display for Register {
+    /// Aliases are printed as the general-purpose register they refer to.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "R{}", encode_register(*self))
+    }
+}
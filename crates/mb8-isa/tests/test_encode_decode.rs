@@ -1,6 +1,7 @@
 use mb8_isa::{decode::decode, encode::encode, opcodes::Opcode, registers::Register};
 
 #[test]
+#[allow(clippy::too_many_lines)]
 fn test_round_trip() {
     {
         let opcode = Opcode::Nop {};
@@ -17,6 +18,21 @@ fn test_round_trip() {
         let bin = encode(&opcode);
         assert_eq!(decode(bin), Some(opcode));
     }
+    {
+        let opcode = Opcode::Ei;
+        let bin = encode(&opcode);
+        assert_eq!(decode(bin), Some(opcode));
+    }
+    {
+        let opcode = Opcode::Di;
+        let bin = encode(&opcode);
+        assert_eq!(decode(bin), Some(opcode));
+    }
+    {
+        let opcode = Opcode::Reti;
+        let bin = encode(&opcode);
+        assert_eq!(decode(bin), Some(opcode));
+    }
     {
         let opcode = Opcode::Mov {
             dst: Register::R0,
@@ -0,0 +1,159 @@
+use mb8_isa::{
+    decode::decode,
+    encode::encode,
+    meta::{Field, OpcodeInfo, OperandKind},
+    opcodes::OPCODES,
+    registers::flags::{C_FLAG, N_FLAG, Z_FLAG},
+};
+
+/// Build the `asm/isa.asm` rule for a table entry.
+fn rule(info: &OpcodeInfo) -> String {
+    let mut pattern = info.syntax.to_string();
+    for operand in info.operands {
+        let kind = match operand.kind {
+            OperandKind::Register => "register",
+            OperandKind::Immediate => "u8",
+            OperandKind::Offset => "i8",
+        };
+        pattern = pattern.replace(
+            &format!("{{{}}}", operand.name),
+            &format!("{{ {}: {kind} }}", operand.name),
+        );
+    }
+
+    let prefix = match info.nibble {
+        Some(nibble) => format!("0x{:X}{nibble:X}", info.group),
+        None => format!("0x{:X}", info.group),
+    };
+    let mut parts = vec![prefix];
+    // Walk the remaining nibbles, emitting operands and runs of zero nibbles
+    let mut nibble = if info.nibble.is_some() { 2 } else { 1 };
+    let mut zeros = 0;
+    while nibble < 4 {
+        let field = match nibble {
+            1 => Field::A,
+            2 => Field::B,
+            _ => Field::C,
+        };
+        let operand = info.operands.iter().find(|operand| {
+            operand.field == field || (field == Field::B && operand.field == Field::BC)
+        });
+        if let Some(operand) = operand {
+            if zeros > 0 {
+                parts.push(format!("0x{}", "0".repeat(zeros)));
+                zeros = 0;
+            }
+            parts.push(operand.name.to_string());
+            nibble += if operand.field == Field::BC { 2 } else { 1 };
+        } else {
+            zeros += 1;
+            nibble += 1;
+        }
+    }
+    if zeros > 0 {
+        if info.operands.is_empty() {
+            parts[0] += &"0".repeat(zeros);
+        } else {
+            parts.push(format!("0x{}", "0".repeat(zeros)));
+        }
+    }
+    let encoding = parts.join(" @ ");
+
+    format!("{pattern} => {encoding}")
+}
+
+/// Rules of the `mb8_isa` ruledef, with whitespace normalized.
+fn isa_rules() -> Vec<String> {
+    let source = include_str!("../../../asm/isa.asm");
+    let body = source.split("#ruledef mb8_isa").nth(1).unwrap_or_default();
+    body.lines()
+        .map(str::trim)
+        .filter(|line| line.contains("=>"))
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect()
+}
+
+#[test]
+fn test_table_matches_isa_asm() {
+    let rules = isa_rules();
+    assert!(!rules.is_empty(), "mb8_isa ruledef not found");
+    for info in OPCODES {
+        let rule = rule(info);
+        assert!(
+            rules.contains(&rule),
+            "`{rule}` is missing from asm/isa.asm"
+        );
+    }
+    for rule in &rules {
+        let mnemonic = rule.split(' ').next().unwrap_or_default();
+        assert!(
+            OPCODES.iter().any(|info| info.mnemonic() == mnemonic),
+            "`{rule}` in asm/isa.asm has no opcode in the table"
+        );
+    }
+}
+
+#[test]
+fn test_table_round_trip() {
+    for info in OPCODES {
+        let opcode = (info.decode)(info.base()).expect("decodable base word");
+        assert_eq!(opcode.info().syntax, info.syntax);
+        assert_eq!(encode(&opcode), info.base());
+        assert_eq!(decode(info.base()), Some(opcode));
+    }
+}
+
+/// Flag mask of a flags column in `docs/opcode.md`, e.g. `` `Z`, `N` `` or `—`.
+fn doc_flags(column: &str) -> u8 {
+    if column == "—" {
+        return 0;
+    }
+    column
+        .split(", ")
+        .map(|flag| match flag.trim_matches('`') {
+            "Z" => Z_FLAG,
+            "N" => N_FLAG,
+            "C" => C_FLAG,
+            other => panic!("unknown flag `{other}` in docs/opcode.md"),
+        })
+        .fold(0, |mask, flag| mask | flag)
+}
+
+#[test]
+fn test_table_matches_docs() {
+    let source = include_str!("../../../docs/opcode.md");
+    let table = source.split("## Opcode table").nth(1).unwrap_or_default();
+    let mut documented = Vec::new();
+    // Skip the header and separator rows
+    for row in table.lines().filter(|line| line.starts_with('|')).skip(2) {
+        let columns: Vec<_> = row.trim_matches('|').split('|').map(str::trim).collect();
+        let [mnemonics, _, reads, writes, cycles] = columns[..] else {
+            panic!("malformed row `{row}` in docs/opcode.md");
+        };
+        for mnemonic in mnemonics.split(", ") {
+            let info = OPCODES
+                .iter()
+                .find(|info| info.mnemonic() == mnemonic)
+                .unwrap_or_else(|| panic!("`{mnemonic}` in docs/opcode.md has no opcode"));
+            assert_eq!(
+                doc_flags(reads),
+                info.flags_read,
+                "flags read by {mnemonic}"
+            );
+            assert_eq!(
+                doc_flags(writes),
+                info.flags_written,
+                "flags written by {mnemonic}"
+            );
+            assert_eq!(cycles, info.cycles.to_string(), "cycles of {mnemonic}");
+            documented.push(mnemonic);
+        }
+    }
+    for info in OPCODES {
+        assert!(
+            documented.contains(&info.mnemonic()),
+            "`{}` is missing from docs/opcode.md",
+            info.mnemonic()
+        );
+    }
+}
@@ -0,0 +1,604 @@
+//! Debugger.
+//! [`Debugger`] runs a [`VirtualMachine`] until it reaches a breakpoint, touches a watched
+//! address or finishes a step. [`Symbols`] resolves label names loaded from a symbol file.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+};
+
+use mb8_isa::{decode::decode, opcodes::Opcode, registers::Register};
+
+use crate::{
+    fault::Fault,
+    registers::Registers,
+    trace::{Access, MemoryAccess},
+    vm::VirtualMachine,
+};
+
+/// Comparison made by a conditional breakpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Compare {
+    fn holds(self, left: u8, right: u8) -> bool {
+        match self {
+            Compare::Eq => left == right,
+            Compare::Ne => left != right,
+            Compare::Lt => left < right,
+            Compare::Le => left <= right,
+            Compare::Gt => left > right,
+            Compare::Ge => left >= right,
+        }
+    }
+}
+
+impl Display for Compare {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Compare::Eq => "==",
+            Compare::Ne => "!=",
+            Compare::Lt => "<",
+            Compare::Le => "<=",
+            Compare::Gt => ">",
+            Compare::Ge => ">=",
+        };
+        f.write_str(op)
+    }
+}
+
+/// Register test of a conditional breakpoint, e.g. `R0 == 0x41`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub compare: Compare,
+    pub value: u8,
+}
+
+impl Condition {
+    #[must_use]
+    pub fn holds(&self, registers: &Registers) -> bool {
+        self.compare
+            .holds(registers.read(self.register), self.value)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} 0x{:02X}", self.register, self.compare, self.value)
+    }
+}
+
+/// Stops the VM before the instruction at `addr` runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    /// Only stop when the condition holds.
+    pub condition: Option<Condition>,
+}
+
+/// Kind of accesses a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+}
+
+impl Watch {
+    fn matches(self, access: Access) -> bool {
+        match self {
+            Watch::Read => access == Access::Read,
+            Watch::Write => access == Access::Write,
+            Watch::Access => true,
+        }
+    }
+}
+
+/// Stops the VM after an instruction accesses `addr`.
+///
+/// Instruction fetches and the stack writes of interrupt entry are not watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub watch: Watch,
+}
+
+/// Why the debugger gave control back.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// The requested step finished.
+    Step,
+    /// The PC reached breakpoint `id`. The instruction there has not run yet.
+    Breakpoint { id: usize },
+    /// The last instruction made an access watched by watchpoint `id`.
+    Watchpoint { id: usize, access: MemoryAccess },
+    /// The CPU halted or faulted.
+    Fault(Fault),
+    /// The instruction limit passed to the debugger was reached.
+    Limit,
+    /// Reverse execution reached the oldest step in the VM history.
+    HistoryStart,
+}
+
+/// Breakpoints, watchpoints and stepping on top of [`VirtualMachine::step`].
+///
+/// Breakpoints and watchpoints share one sequence of ids, starting at 1. Resuming from a
+/// breakpoint runs its instruction instead of stopping on it again.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    watchpoints: BTreeMap<usize, Watchpoint>,
+    last_id: usize,
+}
+
+impl Debugger {
+    /// Add a breakpoint and return its id.
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.last_id += 1;
+        self.breakpoints.insert(self.last_id, breakpoint);
+        self.last_id
+    }
+
+    /// Add a watchpoint and return its id.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> usize {
+        self.last_id += 1;
+        self.watchpoints.insert(self.last_id, watchpoint);
+        self.last_id
+    }
+
+    /// Remove the breakpoint or watchpoint with `id`. Returns `false` if there is none.
+    pub fn remove(&mut self, id: usize) -> bool {
+        self.breakpoints.remove(&id).is_some() || self.watchpoints.remove(&id).is_some()
+    }
+
+    /// Breakpoints with their ids, in id order.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .map(|(&id, breakpoint)| (id, breakpoint))
+    }
+
+    /// Watchpoints with their ids, in id order.
+    pub fn watchpoints(&self) -> impl Iterator<Item = (usize, &Watchpoint)> {
+        self.watchpoints
+            .iter()
+            .map(|(&id, watchpoint)| (id, watchpoint))
+    }
+
+    /// Execute a single instruction, entering calls.
+    pub fn step_into(&mut self, vm: &mut VirtualMachine) -> Stop {
+        self.run(vm, 1, |_, _| true)
+    }
+
+    /// Execute a single instruction, running a `CALL` until it returns.
+    pub fn step_over(&mut self, vm: &mut VirtualMachine, limit: u64) -> Stop {
+        if !matches!(next_opcode(vm), Some(Opcode::Call { .. })) {
+            return self.step_into(vm);
+        }
+        let ret = vm.program_counter.wrapping_add(2);
+        let sp = stack_pointer(vm);
+        // The stack pointer tells the return of this call from recursive calls
+        self.run(vm, limit, |vm, _| {
+            vm.program_counter == ret && stack_pointer(vm) >= sp
+        })
+    }
+
+    /// Run until the current function returns with `RET` or `RETI`.
+    pub fn step_out(&mut self, vm: &mut VirtualMachine, limit: u64) -> Stop {
+        let sp = stack_pointer(vm);
+        self.run(vm, limit, |vm, opcode| {
+            matches!(opcode, Some(Opcode::Ret | Opcode::Reti)) && stack_pointer(vm) > sp
+        })
+    }
+
+    /// Run until a breakpoint, a watchpoint or a fault, executing at most `limit` instructions.
+    pub fn resume(&mut self, vm: &mut VirtualMachine, limit: u64) -> Stop {
+        self.run(vm, limit, |_, _| false)
+    }
+
+    /// Undo the last step recorded in the VM history.
+    pub fn step_back(&mut self, vm: &mut VirtualMachine) -> Stop {
+        match vm.step_back() {
+            Some(_) => Stop::Step,
+            None => Stop::HistoryStart,
+        }
+    }
+
+    /// Run backwards through the VM history until the PC reaches a breakpoint or a step that
+    /// wrote a watched address is undone.
+    ///
+    /// Reads are not recorded, so only watchpoints on writes stop reverse execution.
+    pub fn reverse_continue(&mut self, vm: &mut VirtualMachine) -> Stop {
+        while let Some(step) = vm.step_back() {
+            let writes: Vec<_> = step
+                .writes
+                .iter()
+                .map(|write| MemoryAccess {
+                    access: Access::Write,
+                    addr: write.addr,
+                    value: write.new,
+                })
+                .collect();
+            if let Some(stop) = self.watched(&writes) {
+                return stop;
+            }
+            if let Some(id) = self.breakpoint_at(vm) {
+                return Stop::Breakpoint { id };
+            }
+        }
+        Stop::HistoryStart
+    }
+
+    /// Step until `done` accepts the VM after an instruction, which it receives along with
+    /// the opcode that was about to run.
+    fn run(
+        &mut self,
+        vm: &mut VirtualMachine,
+        limit: u64,
+        mut done: impl FnMut(&VirtualMachine, Option<&Opcode>) -> bool,
+    ) -> Stop {
+        for executed in 0..limit {
+            if executed > 0 {
+                if let Some(id) = self.breakpoint_at(vm) {
+                    return Stop::Breakpoint { id };
+                }
+            }
+            let opcode = next_opcode(vm);
+            let accesses = match vm.step_inner(!self.watchpoints.is_empty()) {
+                Ok(accesses) => accesses,
+                Err(fault) => return Stop::Fault(fault),
+            };
+            if let Some(stop) = self.watched(&accesses) {
+                return stop;
+            }
+            if done(vm, opcode.as_ref()) {
+                return Stop::Step;
+            }
+        }
+        Stop::Limit
+    }
+
+    /// Breakpoint at the PC whose condition holds.
+    fn breakpoint_at(&self, vm: &VirtualMachine) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find(|(_, breakpoint)| {
+                breakpoint.addr == vm.program_counter
+                    && breakpoint
+                        .condition
+                        .is_none_or(|condition| condition.holds(&vm.registers))
+            })
+            .map(|(&id, _)| id)
+    }
+
+    /// First access watched by a watchpoint.
+    fn watched(&self, accesses: &[MemoryAccess]) -> Option<Stop> {
+        accesses.iter().find_map(|&access| {
+            self.watchpoints
+                .iter()
+                .find(|(_, watchpoint)| {
+                    watchpoint.addr == access.addr && watchpoint.watch.matches(access.access)
+                })
+                .map(|(&id, _)| Stop::Watchpoint { id, access })
+        })
+    }
+}
+
+/// Decode the instruction at the PC.
+fn next_opcode(vm: &VirtualMachine) -> Option<Opcode> {
+    let pc = vm.program_counter;
+    let word = u16::from_be_bytes([vm.devices.peek(pc), vm.devices.peek(pc.wrapping_add(1))]);
+    decode(word)
+}
+
+fn stack_pointer(vm: &VirtualMachine) -> u16 {
+    u16::from_be_bytes([
+        vm.registers.read(Register::SPH),
+        vm.registers.read(Register::SPL),
+    ])
+}
+
+/// Line of a symbol file that could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    /// Line number, starting at 1.
+    pub line: usize,
+}
+
+impl Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid symbol on line {}", self.line)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+/// Label addresses from a symbol file.
+///
+/// Each line of the file holds a hexadecimal address and a name, e.g. `E000 main`, as
+/// written by `mb8 asm --symbols`. Empty lines and `;` comments are skipped.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    addrs: HashMap<String, u16>,
+    /// First name given to each address.
+    names: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    /// Parse the contents of a symbol file.
+    ///
+    /// # Errors
+    /// Returns the first line that is not an address followed by a name.
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut symbols = Self::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let (addr, name) = match (fields.next(), fields.next(), fields.next()) {
+                (None, ..) => continue,
+                (Some(addr), Some(name), None) => (addr, name),
+                _ => return Err(SymbolError { line: index + 1 }),
+            };
+            let addr = u16::from_str_radix(addr.trim_start_matches("0x"), 16)
+                .map_err(|_| SymbolError { line: index + 1 })?;
+            symbols.insert(name, addr);
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.addrs.insert(name.to_string(), addr);
+        self.names.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    /// Address of the label `name`.
+    #[must_use]
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        self.addrs.get(name).copied()
+    }
+
+    /// Closest label at or before `addr`, with the offset of `addr` from it.
+    #[must_use]
+    pub fn locate(&self, addr: u16) -> Option<(&str, u16)> {
+        self.names
+            .range(..=addr)
+            .next_back()
+            .map(|(&label, name)| (name.as_str(), addr - label))
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.addrs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::encode::encode_program;
+
+    use crate::{fault::FaultKind, history::History};
+
+    use super::*;
+
+    /// Main program at `0xE000` calling the function at `0xE00A`.
+    fn vm_with_call() -> VirtualMachine {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xE0,
+            },
+            Opcode::Ldi {
+                dst: Register::R1,
+                value: 0x0A,
+            },
+            Opcode::Call {
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+            Opcode::Push { src: Register::R3 },
+            Opcode::Halt,
+            Opcode::Ldi {
+                dst: Register::R3,
+                value: 7,
+            },
+            Opcode::Ret,
+        ]));
+        vm
+    }
+
+    #[test]
+    fn stops_on_breakpoints() {
+        let mut vm = vm_with_call();
+        let mut debugger = Debugger::default();
+        let id = debugger.add_breakpoint(Breakpoint {
+            addr: 0xE00A,
+            condition: None,
+        });
+        assert_eq!(debugger.resume(&mut vm, 100), Stop::Breakpoint { id });
+        assert_eq!(vm.program_counter, 0xE00A);
+        assert_eq!(vm.registers.read(Register::R3), 0);
+
+        // Resuming runs the instruction under the breakpoint
+        assert_eq!(
+            debugger.resume(&mut vm, 100),
+            Stop::Fault(Fault {
+                pc: 0xE008,
+                kind: FaultKind::Halt
+            })
+        );
+    }
+
+    #[test]
+    fn checks_breakpoint_conditions() {
+        let mut vm = vm_with_call();
+        let mut debugger = Debugger::default();
+        let mut condition = Condition {
+            register: Register::R1,
+            compare: Compare::Eq,
+            value: 0x0B,
+        };
+        debugger.add_breakpoint(Breakpoint {
+            addr: 0xE004,
+            condition: Some(condition),
+        });
+        condition.value = 0x0A;
+        let id = debugger.add_breakpoint(Breakpoint {
+            addr: 0xE004,
+            condition: Some(condition),
+        });
+        assert_eq!(debugger.resume(&mut vm, 100), Stop::Breakpoint { id });
+        assert_eq!(condition.to_string(), "R1 == 0x0A");
+    }
+
+    #[test]
+    fn stops_on_watchpoints() {
+        let mut vm = vm_with_call();
+        let mut debugger = Debugger::default();
+        debugger.add_watchpoint(Watchpoint {
+            addr: 0xBFFF,
+            watch: Watch::Read,
+        });
+        let id = debugger.add_watchpoint(Watchpoint {
+            addr: 0xBFFF,
+            watch: Watch::Write,
+        });
+        assert_eq!(
+            debugger.resume(&mut vm, 100),
+            Stop::Watchpoint {
+                id,
+                access: MemoryAccess {
+                    access: Access::Write,
+                    addr: 0xBFFF,
+                    value: 0x06,
+                }
+            }
+        );
+        assert_eq!(vm.program_counter, 0xE00A);
+
+        assert!(debugger.remove(id));
+        assert!(!debugger.remove(id));
+        assert_eq!(
+            debugger.step_out(&mut vm, 100),
+            Stop::Watchpoint {
+                id: 1,
+                access: MemoryAccess {
+                    access: Access::Read,
+                    addr: 0xBFFF,
+                    value: 0x06,
+                }
+            }
+        );
+    }
+
+    #[test]
+    fn steps_over_calls() {
+        let mut vm = vm_with_call();
+        let mut debugger = Debugger::default();
+        assert_eq!(debugger.step_over(&mut vm, 100), Stop::Step);
+        assert_eq!(debugger.step_over(&mut vm, 100), Stop::Step);
+        assert_eq!(vm.program_counter, 0xE004);
+        assert_eq!(debugger.step_over(&mut vm, 100), Stop::Step);
+        assert_eq!(vm.program_counter, 0xE006);
+        assert_eq!(vm.registers.read(Register::R3), 7);
+    }
+
+    #[test]
+    fn steps_into_and_out_of_calls() {
+        let mut vm = vm_with_call();
+        let mut debugger = Debugger::default();
+        for _ in 0..3 {
+            assert_eq!(debugger.step_into(&mut vm), Stop::Step);
+        }
+        assert_eq!(vm.program_counter, 0xE00A);
+        assert_eq!(debugger.step_out(&mut vm, 100), Stop::Step);
+        assert_eq!(vm.program_counter, 0xE006);
+        assert_eq!(vm.registers.read(Register::R3), 7);
+    }
+
+    #[test]
+    fn stops_at_limit() {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(&[Opcode::Jr { offset: -2 }]));
+        let mut debugger = Debugger::default();
+        assert_eq!(debugger.resume(&mut vm, 10), Stop::Limit);
+        assert_eq!(vm.cycles, 20);
+    }
+
+    #[test]
+    fn runs_backwards_to_breakpoints_and_writes() {
+        let mut vm = vm_with_call();
+        vm.history = Some(History::default());
+        let mut debugger = Debugger::default();
+        assert_eq!(debugger.step_back(&mut vm), Stop::HistoryStart);
+        debugger.resume(&mut vm, 100);
+        assert!(vm.halted);
+
+        let id = debugger.add_watchpoint(Watchpoint {
+            addr: 0xBFFF,
+            watch: Watch::Write,
+        });
+        // PUSH R3 wrote the stack slot last
+        assert_eq!(
+            debugger.reverse_continue(&mut vm),
+            Stop::Watchpoint {
+                id,
+                access: MemoryAccess {
+                    access: Access::Write,
+                    addr: 0xBFFF,
+                    value: 7,
+                }
+            }
+        );
+        assert_eq!(vm.program_counter, 0xE006);
+        assert!(!vm.halted);
+        assert_eq!(vm.devices.read(0xBFFF), 0x06);
+
+        debugger.remove(id);
+        let id = debugger.add_breakpoint(Breakpoint {
+            addr: 0xE002,
+            condition: None,
+        });
+        assert_eq!(debugger.reverse_continue(&mut vm), Stop::Breakpoint { id });
+        assert_eq!(vm.registers.read(Register::R1), 0);
+        assert_eq!(vm.registers.read(Register::R3), 0);
+        assert_eq!(vm.devices.read(0xBFFF), 0);
+        assert_eq!(debugger.step_back(&mut vm), Stop::Step);
+        assert_eq!(debugger.reverse_continue(&mut vm), Stop::HistoryStart);
+        assert_eq!(vm.program_counter, 0xE000);
+    }
+
+    #[test]
+    fn parses_symbol_files() {
+        let symbols =
+            Symbols::parse("; kernel\nE000 main\nE000 main.start\n\nE010 loop ; hot\n").unwrap();
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.addr("main.start"), Some(0xE000));
+        assert_eq!(symbols.locate(0xE000), Some(("main", 0)));
+        assert_eq!(symbols.locate(0xE014), Some(("loop", 4)));
+        assert_eq!(symbols.locate(0x1000), None);
+
+        assert_eq!(
+            Symbols::parse("E000 main\nmain\n").unwrap_err(),
+            SymbolError { line: 2 }
+        );
+        assert_eq!(
+            Symbols::parse("XYZ main\n").unwrap_err(),
+            SymbolError { line: 1 }
+        );
+    }
+}
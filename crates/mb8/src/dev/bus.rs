@@ -1,57 +1,681 @@
-use super::{disk::Disk, gpu::GPU, keyboard::Keyboard, ram::RAM, rand::Rand, rom::ROM, Device};
+use std::{
+    any::{type_name, Any},
+    fmt::{self, Display},
+    ops::RangeInclusive,
+};
 
+use crate::{
+    fault::FaultKind,
+    history::MemoryWrite,
+    snapshot::{Reader, Snapshot, SnapshotResult, Writer},
+    trace::{Access, MemoryAccess},
+};
+
+use super::{
+    disk::Disk,
+    expansion::{Expansion, DEFAULT_BANKS},
+    gpu::GPU,
+    interrupts::{
+        registers::{IRQ_KEYBOARD, IRQ_TIMER, IRQ_UART},
+        InterruptController,
+    },
+    joystick::Joystick,
+    keyboard::Keyboard,
+    ram::RAM,
+    rand::Rand,
+    rom::ROM,
+    sound::Sound,
+    timer::Timer,
+    uart::Uart,
+    Device, DeviceError, DeviceResult,
+};
+
+/// What the bus does with accesses no device handles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// Record a [`FaultKind::BusError`]. Reads return `0`.
+    #[default]
+    Fault,
+    /// Reads return the value, writes are dropped.
+    OpenBus(u8),
+    /// Reads return `0`, writes are dropped.
+    Ignore,
+}
+
+/// Reason a device cannot be mapped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    /// The range holds no address.
+    Empty { range: RangeInclusive<u16> },
+    /// The range overlaps a device mapped before.
+    Overlap {
+        range: RangeInclusive<u16>,
+        existing: RangeInclusive<u16>,
+    },
+    /// The interrupt controller has no such line.
+    InvalidIrq { line: u8 },
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Empty { range } => write!(
+                f,
+                "empty range 0x{:04X}-0x{:04X}",
+                range.start(),
+                range.end()
+            ),
+            MapError::Overlap { range, existing } => write!(
+                f,
+                "0x{:04X}-0x{:04X} overlaps the device at 0x{:04X}-0x{:04X}",
+                range.start(),
+                range.end(),
+                existing.start(),
+                existing.end()
+            ),
+            MapError::InvalidIrq { line } => write!(f, "invalid IRQ line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// Device mapped at an address range.
+#[derive(Debug)]
+struct Region {
+    range: RangeInclusive<u16>,
+    /// Line raised on the interrupt controller when the device requests an interrupt.
+    irq: Option<u8>,
+    device: Box<dyn Device>,
+}
+
+impl Region {
+    fn new(range: RangeInclusive<u16>, irq: Option<u8>, device: impl Device) -> Self {
+        Self {
+            range,
+            irq,
+            device: Box::new(device),
+        }
+    }
+}
+
+/// Builds a [`Bus`] from devices mapped at address ranges.
+///
+/// ```
+/// use mb8::dev::{bus::BusBuilder, ram::RAM};
+///
+/// let bus = BusBuilder::standard()
+///     .map(0xF800..=0xF8FF, Box::new(RAM::default()))
+///     .unwrap()
+///     .build();
+/// ```
 #[derive(Debug, Default)]
+pub struct BusBuilder {
+    regions: Vec<Region>,
+    unmapped: UnmappedPolicy,
+}
+
+impl BusBuilder {
+    /// Start from an empty memory map.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from the standard MB8 memory map, described in `docs/memory.md`.
+    #[must_use]
+    pub fn standard() -> Self {
+        Self::with_banks(DEFAULT_BANKS)
+    }
+
+    /// Start from the standard MB8 memory map with `banks` banks of expansion memory.
+    #[must_use]
+    pub fn with_banks(banks: u8) -> Self {
+        let expansion = Expansion::new(banks);
+        let bank_select = expansion.bank_select();
+        // Listed in snapshot order
+        let regions = vec![
+            Region::new(0xE000..=0xEFFF, None, ROM::default()),
+            Region::new(0x0000..=0xBFFF, None, RAM::default()),
+            Region::new(0xF000..=0xF0FF, None, GPU::default()),
+            Region::new(0xF100..=0xF1FF, Some(IRQ_KEYBOARD), Keyboard::default()),
+            Region::new(0xF200..=0xF3FF, None, Disk::default()),
+            Region::new(0xF400..=0xF400, None, <Rand as Default>::default()),
+            Region::new(0xF500..=0xF5FF, None, InterruptController::default()),
+            Region::new(0xF600..=0xF6FF, Some(IRQ_TIMER), Timer::default()),
+            Region::new(0xC000..=0xDFFF, None, expansion),
+            Region::new(0xF700..=0xF7FF, None, bank_select),
+            Region::new(0xF900..=0xF9FF, None, Sound::default()),
+            Region::new(0xFA00..=0xFAFF, Some(IRQ_UART), Uart::default()),
+            Region::new(0xFB00..=0xFBFF, None, Joystick::default()),
+        ];
+        Self {
+            regions,
+            unmapped: UnmappedPolicy::default(),
+        }
+    }
+
+    /// Map `device` at `range`. The device sees addresses relative to the start of the range.
+    ///
+    /// # Errors
+    /// Returns an error if the range is empty or overlaps another device.
+    pub fn map(
+        self,
+        range: RangeInclusive<u16>,
+        device: Box<dyn Device>,
+    ) -> Result<Self, MapError> {
+        self.insert(range, None, device)
+    }
+
+    /// Map `device` at `range` and route its interrupt requests to IRQ `line`.
+    ///
+    /// # Errors
+    /// Returns an error if the range is empty or overlaps another device, or if the line
+    /// does not exist.
+    pub fn map_irq(
+        self,
+        range: RangeInclusive<u16>,
+        line: u8,
+        device: Box<dyn Device>,
+    ) -> Result<Self, MapError> {
+        if line >= 8 {
+            return Err(MapError::InvalidIrq { line });
+        }
+        self.insert(range, Some(line), device)
+    }
+
+    /// Set what happens to accesses no device handles.
+    #[must_use]
+    pub fn unmapped(mut self, policy: UnmappedPolicy) -> Self {
+        self.unmapped = policy;
+        self
+    }
+
+    #[must_use]
+    pub fn build(self) -> Bus {
+        Bus {
+            regions: self.regions,
+            unmapped: self.unmapped,
+            fault: None,
+            accesses: None,
+            writes: None,
+        }
+    }
+
+    fn insert(
+        mut self,
+        range: RangeInclusive<u16>,
+        irq: Option<u8>,
+        device: Box<dyn Device>,
+    ) -> Result<Self, MapError> {
+        if range.is_empty() {
+            return Err(MapError::Empty { range });
+        }
+        if let Some(region) = self.regions.iter().find(|region| {
+            range.start() <= region.range.end() && region.range.start() <= range.end()
+        }) {
+            return Err(MapError::Overlap {
+                range,
+                existing: region.range.clone(),
+            });
+        }
+        self.regions.push(Region { range, irq, device });
+        Ok(self)
+    }
+}
+
+/// Routes CPU accesses to the devices mapped by a [`BusBuilder`].
+///
+/// The default bus has the standard MB8 memory map.
+#[derive(Debug)]
 pub struct Bus {
-    rom: ROM,
-    ram: RAM,
-    gpu: GPU,
-    keyboard: Keyboard,
-    disk: Disk,
-    rand: Rand,
+    /// Mapped devices, in the order they were mapped.
+    regions: Vec<Region>,
+    unmapped: UnmappedPolicy,
+    /// First fault raised by an access since the last [`Bus::take_fault`].
+    fault: Option<FaultKind>,
+    /// Accesses recorded for the tracer, if recording.
+    accesses: Option<Vec<MemoryAccess>>,
+    /// Memory writes recorded for the execution history, if recording.
+    writes: Option<Vec<MemoryWrite>>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        BusBuilder::standard().build()
+    }
 }
 
 impl Bus {
+    /// First mapped device of type `T`, if any.
+    pub fn device<T: Device>(&mut self) -> Option<&mut T> {
+        self.regions
+            .iter_mut()
+            .find_map(|region| (region.device.as_mut() as &mut dyn Any).downcast_mut())
+    }
+
+    /// Standard device of type `T`.
+    ///
+    /// # Panics
+    /// Panics if the bus has no such device.
+    fn standard<T: Device>(&mut self) -> &mut T {
+        match self.device() {
+            Some(device) => device,
+            None => panic!("no {} on the bus", type_name::<T>()),
+        }
+    }
+
+    /// # Panics
+    /// Panics if the bus has no GPU.
     #[must_use]
     pub fn gpu(&mut self) -> &mut GPU {
-        &mut self.gpu
+        self.standard()
     }
 
+    /// # Panics
+    /// Panics if the bus has no keyboard.
     pub fn keyboard(&mut self) -> &mut Keyboard {
-        &mut self.keyboard
+        self.standard()
     }
 
+    /// # Panics
+    /// Panics if the bus has no disk.
     pub fn disk(&mut self) -> &mut Disk {
-        &mut self.disk
+        self.standard()
     }
 
+    /// # Panics
+    /// Panics if the bus has no random number generator.
     pub fn rand(&mut self) -> &mut Rand {
-        &mut self.rand
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no interrupt controller.
+    pub fn interrupts(&mut self) -> &mut InterruptController {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no timer.
+    pub fn timer(&mut self) -> &mut Timer {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no ROM.
+    pub fn rom(&mut self) -> &mut ROM {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no expansion memory.
+    pub fn expansion(&mut self) -> &mut Expansion {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no sound generator.
+    pub fn sound(&mut self) -> &mut Sound {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no UART.
+    pub fn uart(&mut self) -> &mut Uart {
+        self.standard()
+    }
+
+    /// # Panics
+    /// Panics if the bus has no joysticks.
+    pub fn joystick(&mut self) -> &mut Joystick {
+        self.standard()
+    }
+
+    /// Advance every device by `cycles` CPU cycles.
+    pub fn tick(&mut self, cycles: u64) {
+        for region in &mut self.regions {
+            region.device.tick(cycles);
+        }
+    }
+
+    /// Forward interrupt requests from devices to the interrupt controller.
+    pub fn poll_interrupts(&mut self) {
+        let mut lines = 0u8;
+        for region in &mut self.regions {
+            if let Some(line) = region.irq {
+                if region.device.take_interrupt() {
+                    lines |= 1 << line;
+                }
+            }
+        }
+        if lines == 0 {
+            return;
+        }
+        if let Some(interrupts) = self.device::<InterruptController>() {
+            for line in 0..8 {
+                if lines & (1 << line) != 0 {
+                    interrupts.raise(line);
+                }
+            }
+        }
     }
 
+    /// Save the state of every device.
     #[must_use]
-    pub fn read(&mut self, addr: u16) -> u8 {
-        match addr {
-            0x0000..=0xBFFF => self.ram.read(addr),
-            0xC000..=0xDFFF => unimplemented!(),
-            0xE000..=0xEFFF => self.rom.read(addr - 0xE000),
-            0xF000..=0xF0FF => self.gpu.read(addr - 0xF000),
-            0xF100..=0xF1FF => self.keyboard.read(addr - 0xF100),
-            0xF200..=0xF3FF => self.disk.read(addr - 0xF200),
-            0xF400 => self.rand.read(addr - 0xF400),
-            0xF401..=0xFFFF => unimplemented!(),
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        self.save(&mut writer);
+        writer.finish()
+    }
+
+    /// Restore a state saved by [`Bus::save_state`] on a bus with the same memory map.
+    /// The bus is left unchanged on error.
+    ///
+    /// # Errors
+    /// Returns an error if the snapshot is invalid or from an unsupported version.
+    pub fn load_state(&mut self, data: &[u8]) -> SnapshotResult {
+        let mut reader = Reader::new(data)?;
+        self.restore(|bus| {
+            bus.load(&mut reader)?;
+            reader.finish()
+        })
+    }
+
+    /// Run `load`, putting the devices back as they were if it fails.
+    pub(crate) fn restore(
+        &mut self,
+        load: impl FnOnce(&mut Self) -> SnapshotResult,
+    ) -> SnapshotResult {
+        let mut backup = Writer::new();
+        self.save(&mut backup);
+        let result = load(self);
+        if result.is_err() {
+            let backup = backup.finish();
+            // The backup was just written with the same devices, so it loads
+            let _ = Reader::new(&backup).and_then(|mut reader| self.load(&mut reader));
+        }
+        result
+    }
+
+    /// Replace the ROM contents, bypassing write protection, and lock the ROM.
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        if let Some(device) = self.device::<ROM>() {
+            device.load(rom);
+            device.lock();
+        }
+    }
+
+    /// Record a fault, keeping the first one until it is taken.
+    pub fn raise(&mut self, kind: FaultKind) {
+        self.fault.get_or_insert(kind);
+    }
+
+    /// Fault raised by a failed access, if any. Clears the fault.
+    pub fn take_fault(&mut self) -> Option<FaultKind> {
+        self.fault.take()
+    }
+
+    /// Start recording accesses, dropping any recorded before.
+    pub fn record_accesses(&mut self) {
+        self.accesses = Some(Vec::new());
+    }
+
+    /// Stop recording and return the recorded accesses.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        self.accesses.take().unwrap_or_default()
+    }
+
+    /// Start recording memory writes with the values they overwrite, dropping any recorded before.
+    pub fn record_writes(&mut self) {
+        self.writes = Some(Vec::new());
+    }
+
+    /// Stop recording and return the recorded memory writes.
+    pub fn take_writes(&mut self) -> Vec<MemoryWrite> {
+        self.writes.take().unwrap_or_default()
+    }
+
+    fn record(&mut self, access: Access, addr: u16, value: u8) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(MemoryAccess {
+                access,
+                addr,
+                value,
+            });
         }
     }
 
+    /// Device mapped at `addr` and the address relative to it.
+    fn region(&mut self, addr: u16) -> Option<(&mut Region, u16)> {
+        self.regions
+            .iter_mut()
+            .find(|region| region.range.contains(&addr))
+            .map(|region| {
+                let offset = addr - region.range.start();
+                (region, offset)
+            })
+    }
+
+    /// Read a byte. Failed reads return `0` and record a fault.
+    #[must_use]
+    pub fn read(&mut self, addr: u16) -> u8 {
+        let result = match self.region(addr) {
+            Some((region, offset)) => region.device.read(offset),
+            None => Err(DeviceError::Unmapped),
+        };
+        let value = match (result, self.unmapped) {
+            (Err(DeviceError::Unmapped), UnmappedPolicy::OpenBus(value)) => value,
+            (Err(DeviceError::Unmapped), UnmappedPolicy::Ignore) => 0,
+            (result, _) => self.check(addr, result).unwrap_or_default(),
+        };
+        self.record(Access::Read, addr, value);
+        value
+    }
+
+    /// Read a byte without side effects, for debuggers. Unmapped addresses and device
+    /// registers that cannot be read without side effects read as `0`.
+    #[must_use]
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.regions
+            .iter()
+            .find(|region| region.range.contains(&addr))
+            .and_then(|region| region.device.peek(addr - region.range.start()))
+            .unwrap_or_default()
+    }
+
+    /// Write a byte. Failed writes are dropped and record a fault.
     pub fn write(&mut self, addr: u16, value: u8) {
-        match addr {
-            0x0000..=0xBFFF => self.ram.write(addr, value),
-            0xC000..=0xDFFF => unimplemented!(),
-            0xE000..=0xEFFF => self.rom.write(addr - 0xE000, value),
-            0xF000..=0xF0FF => self.gpu.write(addr - 0xF000, value),
-            0xF100..=0xF1FF => self.keyboard.write(addr - 0xF100, value),
-            0xF200..=0xF3FF => self.disk.write(addr - 0xF200, value),
-            0xF400 => self.rand.write(addr - 0xF400, value),
-            0xF401..=0xFFFF => unimplemented!(),
+        let recording = self.writes.is_some();
+        let (old, result) = match self.region(addr) {
+            Some((region, offset)) => {
+                // Only memory can be peeked, reading device registers may have side effects
+                let old = recording.then(|| region.device.peek(offset)).flatten();
+                (old, region.device.write(offset, value))
+            }
+            None => (None, Err(DeviceError::Unmapped)),
+        };
+        if matches!(result, Err(DeviceError::Unmapped)) && self.unmapped != UnmappedPolicy::Fault {
+            return;
+        }
+        if self.check(addr, result).is_some() {
+            self.record(Access::Write, addr, value);
+            if let (Some(writes), Some(old)) = (&mut self.writes, old) {
+                writes.push(MemoryWrite {
+                    addr,
+                    old,
+                    new: value,
+                });
+            }
         }
     }
+
+    fn check<T>(&mut self, addr: u16, result: DeviceResult<T>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(err) => {
+                let rom = self
+                    .region(addr)
+                    .is_some_and(|(region, _)| (region.device.as_ref() as &dyn Any).is::<ROM>());
+                let kind = match err {
+                    DeviceError::ReadOnly if rom => FaultKind::RomWrite { addr },
+                    _ => FaultKind::BusError { addr },
+                };
+                self.raise(kind);
+                None
+            }
+        }
+    }
+}
+
+impl Snapshot for Bus {
+    fn save(&self, writer: &mut Writer) {
+        for region in &self.regions {
+            region.device.save(writer);
+        }
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        for region in &mut self.regions {
+            region.device.load(reader)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_unmapped_access() {
+        let mut bus = Bus::default();
+        assert_eq!(bus.read(0xF800), 0);
+        assert_eq!(bus.take_fault(), Some(FaultKind::BusError { addr: 0xF800 }));
+        assert_eq!(bus.take_fault(), None);
+    }
+
+    #[test]
+    fn keeps_first_fault() {
+        let mut bus = Bus::default();
+        bus.write(0xE010, 0x42);
+        bus.write(0xFFFF, 0x42);
+        assert_eq!(bus.take_fault(), Some(FaultKind::RomWrite { addr: 0xE010 }));
+    }
+
+    #[test]
+    fn peeks_without_side_effects() {
+        let mut bus = Bus::default();
+        bus.load_rom(&[0x12]);
+        bus.keyboard().key_pressed(b'a');
+        assert_eq!(bus.peek(0xE000), 0x12);
+        assert_eq!(bus.peek(0xF101), 0);
+        assert_eq!(bus.peek(0xF800), 0);
+        assert_eq!(bus.take_fault(), None);
+        assert_eq!(bus.read(0xF101), b'a');
+    }
+
+    #[test]
+    fn loads_rom_past_protection() {
+        let mut bus = Bus::default();
+        bus.load_rom(&[0x12, 0x34]);
+        assert_eq!(bus.read(0xE001), 0x34);
+        assert_eq!(bus.take_fault(), None);
+    }
+
+    /// Remembers the last write and requests an interrupt on every write.
+    #[derive(Debug, Default)]
+    struct Latch {
+        last: Option<(u16, u8)>,
+        interrupt: bool,
+    }
+
+    impl Device for Latch {
+        fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+            match addr {
+                0 => Ok(self.last.map_or(0, |(_, value)| value)),
+                _ => Err(DeviceError::Unmapped),
+            }
+        }
+
+        fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+            self.last = Some((addr, value));
+            self.interrupt = true;
+            Ok(())
+        }
+
+        fn take_interrupt(&mut self) -> bool {
+            std::mem::take(&mut self.interrupt)
+        }
+    }
+
+    impl Snapshot for Latch {
+        fn save(&self, _writer: &mut Writer) {}
+
+        fn load(&mut self, _reader: &mut Reader) -> SnapshotResult {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn maps_custom_devices() {
+        let mut bus = BusBuilder::standard()
+            .map_irq(0xF810..=0xF81F, 5, Box::new(Latch::default()))
+            .unwrap()
+            .build();
+        bus.write(0xF812, 0x42);
+        assert_eq!(bus.device::<Latch>().unwrap().last, Some((2, 0x42)));
+        assert_eq!(bus.read(0xF810), 0x42);
+        assert_eq!(bus.read(0xF811), 0);
+        assert_eq!(bus.take_fault(), Some(FaultKind::BusError { addr: 0xF811 }));
+
+        bus.poll_interrupts();
+        assert_eq!(bus.interrupts().read(0x01), Ok(1 << 5));
+    }
+
+    #[test]
+    fn rejects_overlapping_ranges() {
+        let err = BusBuilder::standard()
+            .map(0xBF00..=0xC0FF, Box::new(Latch::default()))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            MapError::Overlap {
+                range: 0xBF00..=0xC0FF,
+                existing: 0x0000..=0xBFFF,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "0xBF00-0xC0FF overlaps the device at 0x0000-0xBFFF"
+        );
+        assert!(matches!(
+            BusBuilder::new().map(
+                RangeInclusive::new(0xC0FF, 0xC000),
+                Box::new(Latch::default())
+            ),
+            Err(MapError::Empty { .. })
+        ));
+        assert!(matches!(
+            BusBuilder::new().map_irq(0xC000..=0xC000, 8, Box::new(Latch::default())),
+            Err(MapError::InvalidIrq { line: 8 })
+        ));
+    }
+
+    #[test]
+    fn applies_unmapped_policy() {
+        let mut bus = BusBuilder::standard()
+            .unmapped(UnmappedPolicy::OpenBus(0xFF))
+            .build();
+        assert_eq!(bus.read(0xF800), 0xFF);
+        bus.write(0xF800, 0x42);
+        assert_eq!(bus.take_fault(), None);
+
+        let mut bus = BusBuilder::new().unmapped(UnmappedPolicy::Ignore).build();
+        assert_eq!(bus.read(0x1000), 0);
+        bus.write(0x1000, 0x42);
+        bus.tick(10);
+        bus.poll_interrupts();
+        assert_eq!(bus.take_fault(), None);
+    }
 }
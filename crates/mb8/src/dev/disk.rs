@@ -1,74 +1,545 @@
-use super::{utils::empty_memory, Device};
+//! Disk controller.
+//! Up to [`DRIVES`] drives, each holding an image of up to 65536 blocks of 256 bytes. An image
+//! lives in memory or in a host file; file images are read a block at a time when the guest asks
+//! for it and every written block goes straight to the file, so work done in the VM persists.
+
+use std::{
+    fmt::{self, Display},
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::snapshot::{Reader, Snapshot, SnapshotError, SnapshotResult, Writer};
+
+use super::{utils::empty_memory, Device, DeviceError, DeviceResult};
 
 pub mod registers {
+    /// Block to operate on, low byte.
     pub const DISK_BLOCK: u16 = 0x0000;
+    /// Writing runs a command on the selected drive and block.
     pub const DISK_CMD: u16 = 0x0001;
     pub const DISK_BUFFER_START: u16 = 0x0002;
     pub const DISK_BUFFER_END: u16 = 0x0002 + 256;
+    /// Block to operate on, high byte.
+    pub const DISK_BLOCK_HI: u16 = 0x0102;
+    /// Selected drive.
+    pub const DISK_DRIVE: u16 = 0x0103;
+    /// Status bits, see the `DISK_STATUS_*` constants.
+    pub const DISK_STATUS: u16 = 0x0104;
+    /// Outcome of the last command, see the `DISK_ERR_*` constants.
+    pub const DISK_ERROR: u16 = 0x0105;
+    /// Highest block of the selected drive, `0` without an image.
+    pub const DISK_LAST_BLOCK_HI: u16 = 0x0106;
+    pub const DISK_LAST_BLOCK_LO: u16 = 0x0107;
 
     pub const DISK_CMD_NOP: u8 = 0x00;
     pub const DISK_CMD_READ: u8 = 0x01;
     pub const DISK_CMD_WRITE: u8 = 0x02;
+
+    /// The last command failed, `DISK_ERROR` says why.
+    pub const DISK_STATUS_ERROR: u8 = 0x01;
+    /// The selected drive holds an image.
+    pub const DISK_STATUS_READY: u8 = 0x02;
+
+    pub const DISK_ERR_NONE: u8 = 0x00;
+    /// The selected drive holds no image.
+    pub const DISK_ERR_NO_MEDIUM: u8 = 0x01;
+    /// The block lies past the end of the image.
+    pub const DISK_ERR_BAD_BLOCK: u8 = 0x02;
+    /// The command is unknown.
+    pub const DISK_ERR_BAD_COMMAND: u8 = 0x03;
+    /// The host file could not be read or written.
+    pub const DISK_ERR_IO: u8 = 0x04;
+}
+
+/// Drive slots of the controller.
+pub const DRIVES: usize = 4;
+pub const BLOCK_SIZE: usize = 256;
+/// Largest image, addressable with 16-bit block numbers.
+pub const MAX_BLOCKS: usize = 1 << 16;
+/// Size of drive `0` after reset and of new host files.
+pub const DEFAULT_IMAGE_SIZE: usize = 65536;
+
+pub type Block = [u8; BLOCK_SIZE];
+
+/// Reason a block transfer failed. Reported to the guest through `DISK_ERROR`.
+#[derive(Debug)]
+pub enum DiskError {
+    NoMedium,
+    BadBlock { block: u16 },
+    Io(io::Error),
+}
+
+impl DiskError {
+    #[must_use]
+    pub fn code(&self) -> u8 {
+        match self {
+            DiskError::NoMedium => registers::DISK_ERR_NO_MEDIUM,
+            DiskError::BadBlock { .. } => registers::DISK_ERR_BAD_BLOCK,
+            DiskError::Io(_) => registers::DISK_ERR_IO,
+        }
+    }
+}
+
+impl Display for DiskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiskError::NoMedium => write!(f, "no disk image in the drive"),
+            DiskError::BadBlock { block } => {
+                write!(f, "block {block} is past the end of the image")
+            }
+            DiskError::Io(err) => write!(f, "disk image I/O failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DiskError {}
+
+impl From<io::Error> for DiskError {
+    fn from(err: io::Error) -> Self {
+        DiskError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+enum Image {
+    Memory(Vec<u8>),
+    /// Read and written a block at a time. Blocks past the end of a shorter file read as zeros.
+    File {
+        file: File,
+        path: PathBuf,
+        blocks: usize,
+    },
+}
+
+impl Image {
+    fn blocks(&self) -> usize {
+        match self {
+            Image::Memory(data) => data.len() / BLOCK_SIZE,
+            Image::File { blocks, .. } => *blocks,
+        }
+    }
+
+    fn check(&self, block: u16) -> Result<u64, DiskError> {
+        if usize::from(block) >= self.blocks() {
+            return Err(DiskError::BadBlock { block });
+        }
+        Ok(u64::from(block) * BLOCK_SIZE as u64)
+    }
+
+    fn read(&self, block: u16, buf: &mut Block) -> Result<(), DiskError> {
+        let offset = self.check(block)?;
+        match self {
+            Image::Memory(data) => {
+                let offset = offset as usize;
+                buf.copy_from_slice(&data[offset..offset + BLOCK_SIZE]);
+            }
+            Image::File { file, .. } => {
+                let mut file: &File = file;
+                buf.fill(0);
+                file.seek(SeekFrom::Start(offset))?;
+                let mut filled = 0;
+                while filled < BLOCK_SIZE {
+                    match file.read(&mut buf[filled..]) {
+                        Ok(0) => break,
+                        Ok(read) => filled += read,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, block: u16, buf: &Block) -> Result<(), DiskError> {
+        let offset = self.check(block)?;
+        match self {
+            Image::Memory(data) => {
+                let offset = offset as usize;
+                data[offset..offset + BLOCK_SIZE].copy_from_slice(buf);
+            }
+            Image::File { file, .. } => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.write_all(buf)?;
+                file.flush()?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
 pub struct Disk {
-    img: Box<[u8; 65536]>,
-    buffer: Box<[u8; 256]>,
-    block: u8,
+    drives: [Option<Image>; DRIVES],
+    buffer: Box<Block>,
+    block: u16,
+    drive: u8,
+    error: u8,
 }
 
 impl Default for Disk {
     fn default() -> Self {
         Disk {
-            img: empty_memory(),
+            drives: [
+                Some(Image::Memory(vec![0; DEFAULT_IMAGE_SIZE])),
+                None,
+                None,
+                None,
+            ],
             buffer: empty_memory(),
             block: Default::default(),
+            drive: Default::default(),
+            error: registers::DISK_ERR_NONE,
         }
     }
 }
 
 impl Disk {
+    /// Replace drive `0` with the memory image `img`.
     pub fn set(&mut self, img: Box<[u8; 65536]>) {
-        self.img = img;
+        let img: Box<[u8]> = img;
+        self.drives[0] = Some(Image::Memory(img.into_vec()));
+    }
+
+    /// Write `data` over the first blocks of `drive`, padding the last block with zeros.
+    ///
+    /// # Errors
+    /// Returns an error if the drive is empty, the data does not fit or the file fails.
+    pub fn write_image(&mut self, drive: usize, data: &[u8]) -> Result<(), DiskError> {
+        for (block, chunk) in (0..=u16::MAX).zip(data.chunks(BLOCK_SIZE)) {
+            let mut buf = [0; BLOCK_SIZE];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            self.write_block(drive, block, &buf)?;
+        }
+        Ok(())
+    }
+
+    /// Contents of drive `0`, empty without an image.
+    #[must_use]
+    pub fn dump(&self) -> Vec<u8> {
+        let blocks = self.blocks(0);
+        let mut data = vec![0; blocks * BLOCK_SIZE];
+        for (block, chunk) in (0..=u16::MAX).zip(data.chunks_exact_mut(BLOCK_SIZE)) {
+            let mut buf = [0; BLOCK_SIZE];
+            if self.read_block(0, block, &mut buf).is_ok() {
+                chunk.copy_from_slice(&buf);
+            }
+        }
+        data
+    }
+
+    /// Put a memory image into `drive`. A partial last block is padded with zeros, and
+    /// anything past [`MAX_BLOCKS`] is cut off.
+    ///
+    /// # Panics
+    /// Panics if `drive` is not below [`DRIVES`].
+    pub fn insert(&mut self, drive: usize, mut image: Vec<u8>) {
+        let len = image.len().div_ceil(BLOCK_SIZE).min(MAX_BLOCKS) * BLOCK_SIZE;
+        image.resize(len, 0);
+        self.drives[drive] = Some(Image::Memory(image));
+    }
+
+    /// Back `drive` with the host file at `path`, creating a blank image of
+    /// [`DEFAULT_IMAGE_SIZE`] bytes if the file does not exist.
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be opened or holds more than [`MAX_BLOCKS`] blocks.
+    ///
+    /// # Panics
+    /// Panics if `drive` is not below [`DRIVES`].
+    pub fn attach(&mut self, drive: usize, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let mut len = usize::try_from(file.metadata()?.len()).unwrap_or(usize::MAX);
+        if len == 0 {
+            file.set_len(DEFAULT_IMAGE_SIZE as u64)?;
+            len = DEFAULT_IMAGE_SIZE;
+        }
+        let blocks = len.div_ceil(BLOCK_SIZE);
+        if blocks > MAX_BLOCKS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is larger than {MAX_BLOCKS} blocks", path.display()),
+            ));
+        }
+        self.drives[drive] = Some(Image::File {
+            file,
+            path: path.to_path_buf(),
+            blocks,
+        });
+        Ok(())
+    }
+
+    /// Remove the image from `drive`.
+    pub fn eject(&mut self, drive: usize) {
+        if let Some(slot) = self.drives.get_mut(drive) {
+            *slot = None;
+        }
+    }
+
+    /// Host file backing `drive`, if any.
+    #[must_use]
+    pub fn path(&self, drive: usize) -> Option<&Path> {
+        match self.drives.get(drive) {
+            Some(Some(Image::File { path, .. })) => Some(path),
+            _ => None,
+        }
     }
 
+    /// Size of the image in `drive` in blocks, `0` without one.
     #[must_use]
-    pub fn dump(&self) -> &[u8] {
-        self.img.as_slice()
+    pub fn blocks(&self, drive: usize) -> usize {
+        self.image(drive).map_or(0, Image::blocks)
+    }
+
+    /// Read `block` of `drive` into `buf`.
+    ///
+    /// # Errors
+    /// Returns an error if the drive is empty, the block is out of range or the file fails.
+    pub fn read_block(&self, drive: usize, block: u16, buf: &mut Block) -> Result<(), DiskError> {
+        self.image(drive)
+            .ok_or(DiskError::NoMedium)?
+            .read(block, buf)
+    }
+
+    /// Write `buf` to `block` of `drive`.
+    ///
+    /// # Errors
+    /// Returns an error if the drive is empty, the block is out of range or the file fails.
+    pub fn write_block(&mut self, drive: usize, block: u16, buf: &Block) -> Result<(), DiskError> {
+        self.drives
+            .get_mut(drive)
+            .and_then(Option::as_mut)
+            .ok_or(DiskError::NoMedium)?
+            .write(block, buf)
+    }
+
+    /// Make sure everything written to host files reached the disk.
+    ///
+    /// # Errors
+    /// Returns the first error syncing a file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for image in self.drives.iter_mut().flatten() {
+            if let Image::File { file, .. } = image {
+                file.sync_data()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn image(&self, drive: usize) -> Option<&Image> {
+        self.drives.get(drive).and_then(Option::as_ref)
+    }
+
+    fn command(&mut self, cmd: u8) -> Result<(), u8> {
+        let drive = usize::from(self.drive);
+        let result = match cmd {
+            registers::DISK_CMD_NOP => return Ok(()),
+            registers::DISK_CMD_READ => {
+                let mut buf = [0; BLOCK_SIZE];
+                let result = self.read_block(drive, self.block, &mut buf);
+                if result.is_ok() {
+                    *self.buffer = buf;
+                }
+                result
+            }
+            registers::DISK_CMD_WRITE => {
+                let buf = *self.buffer;
+                self.write_block(drive, self.block, &buf)
+            }
+            _ => return Err(registers::DISK_ERR_BAD_COMMAND),
+        };
+        result.map_err(|err| err.code())
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.error != registers::DISK_ERR_NONE {
+            status |= registers::DISK_STATUS_ERROR;
+        }
+        if self.image(usize::from(self.drive)).is_some() {
+            status |= registers::DISK_STATUS_READY;
+        }
+        status
+    }
+
+    fn last_block(&self) -> u16 {
+        let blocks = self.blocks(usize::from(self.drive));
+        u16::try_from(blocks.saturating_sub(1)).unwrap_or(u16::MAX)
     }
 }
 
 impl Device for Disk {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
         match addr {
-            registers::DISK_BLOCK => self.block,
-            registers::DISK_BUFFER_START..=registers::DISK_BUFFER_END => {
-                self.buffer[(addr - registers::DISK_BUFFER_START) as usize]
+            registers::DISK_BLOCK => Ok(self.block.to_be_bytes()[1]),
+            registers::DISK_BUFFER_START..registers::DISK_BUFFER_END => {
+                Ok(self.buffer[(addr - registers::DISK_BUFFER_START) as usize])
             }
-            _ => unimplemented!(),
+            registers::DISK_BLOCK_HI => Ok(self.block.to_be_bytes()[0]),
+            registers::DISK_DRIVE => Ok(self.drive),
+            registers::DISK_STATUS => Ok(self.status()),
+            registers::DISK_ERROR => Ok(self.error),
+            registers::DISK_LAST_BLOCK_HI => Ok(self.last_block().to_be_bytes()[0]),
+            registers::DISK_LAST_BLOCK_LO => Ok(self.last_block().to_be_bytes()[1]),
+            _ => Err(DeviceError::Unmapped),
         }
     }
 
-    fn write(&mut self, addr: u16, value: u8) {
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
         match addr {
-            registers::DISK_BLOCK => self.block = value,
-            registers::DISK_CMD => match value {
-                registers::DISK_CMD_NOP => {}
-                registers::DISK_CMD_READ => {
-                    let offset = self.block as usize * 256;
-                    self.buffer.copy_from_slice(&self.img[offset..offset + 256]);
+            registers::DISK_BLOCK => {
+                self.block = u16::from_be_bytes([self.block.to_be_bytes()[0], value]);
+            }
+            registers::DISK_CMD => {
+                self.error = self
+                    .command(value)
+                    .err()
+                    .unwrap_or(registers::DISK_ERR_NONE);
+            }
+            registers::DISK_BUFFER_START..registers::DISK_BUFFER_END => {
+                self.buffer[(addr - registers::DISK_BUFFER_START) as usize] = value;
+            }
+            registers::DISK_BLOCK_HI => {
+                self.block = u16::from_be_bytes([value, self.block.to_be_bytes()[1]]);
+            }
+            registers::DISK_DRIVE if usize::from(value) < DRIVES => self.drive = value,
+            registers::DISK_DRIVE => return Err(DeviceError::InvalidValue),
+            registers::DISK_STATUS..=registers::DISK_LAST_BLOCK_LO => {
+                return Err(DeviceError::ReadOnly)
+            }
+            _ => return Err(DeviceError::Unmapped),
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for Disk {
+    /// Memory images are saved whole. Images in host files are not: they already persist, and
+    /// loading a snapshot leaves the file attached to that drive as it is.
+    fn save(&self, writer: &mut Writer) {
+        for image in &self.drives {
+            match image {
+                None => writer.u8(0),
+                Some(Image::Memory(data)) => {
+                    writer.u8(1);
+                    writer.blob(data);
                 }
-                registers::DISK_CMD_WRITE => {
-                    let offset = self.block as usize * 256;
-                    self.img[offset..offset + 256].copy_from_slice(self.buffer.as_slice());
+                Some(Image::File { .. }) => writer.u8(2),
+            }
+        }
+        writer.bytes(self.buffer.as_slice());
+        writer.u16(self.block);
+        writer.u8(self.drive);
+        writer.u8(self.error);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        for image in &mut self.drives {
+            match reader.u8()? {
+                0 => *image = None,
+                1 => *image = Some(Image::Memory(reader.blob()?.to_vec())),
+                2 => {}
+                _ => {
+                    return Err(SnapshotError::Invalid {
+                        field: "disk image",
+                    })
                 }
-                _ => unimplemented!(),
-            },
-            registers::DISK_BUFFER_START..=registers::DISK_BUFFER_END => {
-                self.buffer[(addr - registers::DISK_BUFFER_START) as usize] = value;
             }
-            _ => unimplemented!(),
         }
+        reader.fill(self.buffer.as_mut_slice())?;
+        self.block = reader.u16()?;
+        self.drive = reader.u8()?;
+        if usize::from(self.drive) >= DRIVES {
+            return Err(SnapshotError::Invalid {
+                field: "disk drive",
+            });
+        }
+        self.error = reader.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    fn command(disk: &mut Disk, drive: u8, block: u16, cmd: u8) -> u8 {
+        let [hi, lo] = block.to_be_bytes();
+        disk.write(DISK_DRIVE, drive).unwrap();
+        disk.write(DISK_BLOCK_HI, hi).unwrap();
+        disk.write(DISK_BLOCK, lo).unwrap();
+        disk.write(DISK_CMD, cmd).unwrap();
+        disk.read(DISK_ERROR).unwrap()
+    }
+
+    #[test]
+    fn reports_errors() {
+        let mut disk = Disk::default();
+        assert_eq!(disk.read(DISK_STATUS), Ok(DISK_STATUS_READY));
+        assert_eq!(command(&mut disk, 0, 255, DISK_CMD_READ), DISK_ERR_NONE);
+        assert_eq!(
+            command(&mut disk, 0, 256, DISK_CMD_READ),
+            DISK_ERR_BAD_BLOCK
+        );
+        assert_eq!(
+            disk.read(DISK_STATUS),
+            Ok(DISK_STATUS_READY | DISK_STATUS_ERROR)
+        );
+        assert_eq!(command(&mut disk, 0, 0, 0x7F), DISK_ERR_BAD_COMMAND);
+        assert_eq!(command(&mut disk, 2, 0, DISK_CMD_WRITE), DISK_ERR_NO_MEDIUM);
+        assert_eq!(disk.read(DISK_STATUS), Ok(DISK_STATUS_ERROR));
+        assert_eq!(disk.read(DISK_LAST_BLOCK_LO), Ok(0));
+        assert_eq!(command(&mut disk, 0, 0, DISK_CMD_NOP), DISK_ERR_NONE);
+        assert_eq!(
+            disk.write(DISK_DRIVE, DRIVES as u8),
+            Err(DeviceError::InvalidValue)
+        );
+        assert_eq!(disk.write(DISK_ERROR, 0), Err(DeviceError::ReadOnly));
+    }
+
+    #[test]
+    fn addresses_large_images() {
+        let mut disk = Disk::default();
+        disk.insert(1, vec![0; MAX_BLOCKS * BLOCK_SIZE]);
+        disk.write(DISK_DRIVE, 1).unwrap();
+        assert_eq!(disk.read(DISK_LAST_BLOCK_HI), Ok(0xFF));
+        assert_eq!(disk.read(DISK_LAST_BLOCK_LO), Ok(0xFF));
+
+        disk.write(DISK_BUFFER_START, 0xAB).unwrap();
+        assert_eq!(command(&mut disk, 1, 0xFFFF, DISK_CMD_WRITE), DISK_ERR_NONE);
+        let mut buf = [0; BLOCK_SIZE];
+        disk.read_block(1, 0xFFFF, &mut buf).unwrap();
+        assert_eq!(buf[0], 0xAB);
+        assert!(disk.read_block(0, 0xFFFF, &mut buf).is_err());
+    }
+
+    #[test]
+    fn persists_file_images() {
+        let path = std::env::temp_dir().join(format!("mb8_disk_{}.img", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut disk = Disk::default();
+        disk.attach(3, &path).unwrap();
+        assert_eq!(disk.blocks(3), DEFAULT_IMAGE_SIZE / BLOCK_SIZE);
+        disk.write(DISK_BUFFER_START + 1, 0x42).unwrap();
+        assert_eq!(command(&mut disk, 3, 7, DISK_CMD_WRITE), DISK_ERR_NONE);
+        drop(disk);
+
+        let mut disk = Disk::default();
+        disk.attach(0, &path).unwrap();
+        assert_eq!(disk.path(0), Some(path.as_path()));
+        assert_eq!(command(&mut disk, 0, 7, DISK_CMD_READ), DISK_ERR_NONE);
+        assert_eq!(disk.read(DISK_BUFFER_START + 1), Ok(0x42));
+        assert_eq!(disk.dump()[7 * BLOCK_SIZE + 1], 0x42);
+        disk.flush().unwrap();
+        std::fs::remove_file(&path).unwrap();
     }
 }
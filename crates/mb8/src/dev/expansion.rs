@@ -0,0 +1,190 @@
+use std::{cell::Cell, rc::Rc};
+
+use crate::snapshot::{Reader, Snapshot, SnapshotError, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+/// Size of a bank and of the window it is seen through.
+pub const BANK_SIZE: usize = 0x2000;
+/// Number of banks on the standard machine.
+pub const DEFAULT_BANKS: u8 = 8;
+
+pub mod registers {
+    /// Bank shown in the window.
+    pub const BANK: u16 = 0x00;
+    /// Number of banks (read-only).
+    pub const BANKS: u16 = 0x01;
+}
+
+/// Extended RAM, seen one [`BANK_SIZE`] bank at a time through a window.
+///
+/// The bank is chosen by the [`BankSelect`] register returned by [`Expansion::bank_select`].
+#[derive(Debug)]
+pub struct Expansion {
+    banks: Vec<Box<[u8; BANK_SIZE]>>,
+    bank: Rc<Cell<u8>>,
+}
+
+impl Default for Expansion {
+    fn default() -> Self {
+        Self::new(DEFAULT_BANKS)
+    }
+}
+
+impl Expansion {
+    #[must_use]
+    pub fn new(banks: u8) -> Self {
+        Self {
+            banks: (0..banks).map(|_| Box::new([0; BANK_SIZE])).collect(),
+            bank: Rc::default(),
+        }
+    }
+
+    /// Register that selects the bank shown in the window.
+    #[must_use]
+    pub fn bank_select(&self) -> BankSelect {
+        BankSelect {
+            bank: Rc::clone(&self.bank),
+            banks: self.banks.len() as u8,
+        }
+    }
+
+    /// Bank shown in the window.
+    #[must_use]
+    pub fn bank(&self) -> u8 {
+        self.bank.get()
+    }
+
+    /// Contents of bank `bank`, if it exists.
+    #[must_use]
+    pub fn data(&self, bank: u8) -> Option<&[u8]> {
+        self.banks.get(bank as usize).map(|data| data.as_slice())
+    }
+
+    fn window(&mut self) -> DeviceResult<&mut [u8; BANK_SIZE]> {
+        self.banks
+            .get_mut(self.bank.get() as usize)
+            .map(AsMut::as_mut)
+            .ok_or(DeviceError::Unmapped)
+    }
+}
+
+impl Device for Expansion {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        Ok(self.window()?[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        self.window()?[addr as usize] = value;
+        Ok(())
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        self.data(self.bank.get()).map(|data| data[addr as usize])
+    }
+}
+
+impl Snapshot for Expansion {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.banks.len() as u8);
+        for data in &self.banks {
+            writer.bytes(data.as_slice());
+        }
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        if reader.u8()? as usize != self.banks.len() {
+            return Err(SnapshotError::Invalid {
+                field: "expansion bank count",
+            });
+        }
+        for data in &mut self.banks {
+            reader.fill(data.as_mut_slice())?;
+        }
+        Ok(())
+    }
+}
+
+/// Bank-select register of an [`Expansion`].
+#[derive(Debug)]
+pub struct BankSelect {
+    bank: Rc<Cell<u8>>,
+    banks: u8,
+}
+
+impl Device for BankSelect {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        match addr {
+            registers::BANK => Ok(self.bank.get()),
+            registers::BANKS => Ok(self.banks),
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        match addr {
+            registers::BANK if value < self.banks => {
+                self.bank.set(value);
+                Ok(())
+            }
+            registers::BANK => Err(DeviceError::InvalidValue),
+            registers::BANKS => Err(DeviceError::ReadOnly),
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    // Recorded with window writes, so stepping back switches to the bank they went to
+    fn peek(&self, addr: u16) -> Option<u8> {
+        (addr == registers::BANK).then(|| self.bank.get())
+    }
+}
+
+impl Snapshot for BankSelect {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.bank.get());
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        let bank = reader.u8()?;
+        if bank >= self.banks && bank != 0 {
+            return Err(SnapshotError::Invalid {
+                field: "expansion bank",
+            });
+        }
+        self.bank.set(bank);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_banks() {
+        let mut expansion = Expansion::new(2);
+        let mut select = expansion.bank_select();
+        expansion.write(0x10, 0xAA).unwrap();
+        select.write(registers::BANK, 1).unwrap();
+        assert_eq!(expansion.read(0x10), Ok(0));
+        expansion.write(0x10, 0xBB).unwrap();
+        assert_eq!(expansion.data(0).unwrap()[0x10], 0xAA);
+        assert_eq!(expansion.data(1).unwrap()[0x10], 0xBB);
+        assert_eq!(select.read(registers::BANKS), Ok(2));
+        assert_eq!(
+            select.write(registers::BANK, 2),
+            Err(DeviceError::InvalidValue)
+        );
+        assert_eq!(expansion.bank(), 1);
+    }
+
+    #[test]
+    fn has_no_window_without_banks() {
+        let mut expansion = Expansion::new(0);
+        assert_eq!(expansion.read(0), Err(DeviceError::Unmapped));
+        assert_eq!(
+            expansion.bank_select().write(registers::BANK, 0),
+            Err(DeviceError::InvalidValue)
+        );
+    }
+}
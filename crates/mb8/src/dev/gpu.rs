@@ -1,18 +1,106 @@
-use crate::dev::gpu::registers::VRAM_TTY_END;
+use crate::{
+    dev::gpu::registers::VRAM_TTY_END,
+    snapshot::{Reader, Snapshot, SnapshotError, SnapshotResult, Writer},
+};
 
-use super::{utils::empty_memory, Device};
+use super::{utils::empty_memory, Device, DeviceError, DeviceResult};
 
 pub mod registers {
     pub const TTY_ROWS: u8 = 25;
     pub const TTY_COLS: u8 = 40;
     pub const TTY_CELLS: usize = TTY_ROWS as usize * TTY_COLS as usize;
 
+    pub const BITMAP_WIDTH: u8 = 160;
+    pub const BITMAP_HEIGHT: u8 = 100;
+    pub const BITMAP_PIXELS: usize = BITMAP_WIDTH as usize * BITMAP_HEIGHT as usize;
+    pub const PALETTE_COLORS: usize = 16;
+
+    /// Tiles and sprites are 8x8 pixels, one palette index per pixel.
+    pub const TILE_SIZE: u8 = 8;
+    pub const TILE_PIXELS: usize = TILE_SIZE as usize * TILE_SIZE as usize;
+    pub const PATTERNS: usize = 256;
+    pub const PATTERNS_SIZE: usize = PATTERNS * TILE_PIXELS;
+    /// The tile map covers a 256x256 plane that wraps around when scrolled.
+    pub const MAP_COLS: u8 = 32;
+    pub const MAP_ROWS: u8 = 32;
+    pub const MAP_SIZE: usize = MAP_COLS as usize * MAP_ROWS as usize;
+    pub const SPRITES: usize = 8;
+
     pub const GPU_MODE_OFF: u8 = 0x00;
     pub const GPU_MODE_TTY: u8 = 0x01;
+    pub const GPU_MODE_BITMAP: u8 = 0x02;
+    pub const GPU_MODE_TILES: u8 = 0x03;
+
+    /// Memories reachable through the `DATA` port, selected by `PLANE`.
+    pub const GPU_PLANE_BITMAP: u8 = 0x00;
+    pub const GPU_PLANE_MAP: u8 = 0x01;
+    pub const GPU_PLANE_PATTERNS: u8 = 0x02;
 
     pub const GPU_REG_MODE: u16 = 0x0000;
     /// TTY mode registers
     pub const GPU_REG_TTY: u16 = 0x0001;
+    /// Bitmap registers
+    pub const GPU_REG_X: u16 = 0x0002;
+    pub const GPU_REG_Y: u16 = 0x0003;
+    pub const GPU_REG_PLOT: u16 = 0x0004;
+    pub const GPU_REG_CLEAR: u16 = 0x0005;
+    pub const GPU_REG_ADDR_HI: u16 = 0x0006;
+    pub const GPU_REG_ADDR_LO: u16 = 0x0007;
+    pub const GPU_REG_DATA: u16 = 0x0008;
+    pub const GPU_REG_PLANE: u16 = 0x0009;
+    /// Tile mode registers
+    pub const GPU_REG_SCROLL_X: u16 = 0x000A;
+    pub const GPU_REG_SCROLL_Y: u16 = 0x000B;
+    /// Status bits, see the `GPU_STATUS_*` constants. Writing `1` to a bit clears it.
+    pub const GPU_REG_STATUS: u16 = 0x000C;
+    /// Sprites that overlapped another sprite, one bit per sprite. Writing `1` to a bit clears it.
+    pub const GPU_REG_SPRITE_COLLISION: u16 = 0x000D;
+    /// Sprites that overlapped the background, one bit per sprite. Writing `1` to a bit clears it.
+    pub const GPU_REG_BG_COLLISION: u16 = 0x000E;
+    /// Palette, 3 bytes (R, G, B) per colour
+    pub const GPU_REG_PALETTE: u16 = 0x0010;
+    pub const GPU_REG_PALETTE_END: u16 = GPU_REG_PALETTE + PALETTE_COLORS as u16 * 3 - 1;
+    /// Sprite registers, `GPU_SPRITE_REGS` bytes per sprite
+    pub const GPU_REG_SPRITES: u16 = 0x0040;
+    pub const GPU_REG_SPRITES_END: u16 = GPU_REG_SPRITES + (SPRITES * GPU_SPRITE_REGS) as u16 - 1;
+
+    /// Offsets within a sprite's registers.
+    pub const GPU_SPRITE_X: usize = 0x00;
+    pub const GPU_SPRITE_Y: usize = 0x01;
+    pub const GPU_SPRITE_PATTERN: usize = 0x02;
+    pub const GPU_SPRITE_FLAGS: usize = 0x03;
+    pub const GPU_SPRITE_REGS: usize = 4;
+
+    pub const GPU_SPRITE_VISIBLE: u8 = 0b0000_0001;
+    pub const GPU_SPRITE_FLIP_X: u8 = 0b0000_0010;
+    pub const GPU_SPRITE_FLIP_Y: u8 = 0b0000_0100;
+
+    /// A frame was completed.
+    pub const GPU_STATUS_VBLANK: u8 = 0b0000_0001;
+
+    /// TTY cursor position, writable to place text anywhere on the screen.
+    pub const GPU_REG_TTY_CURSOR_X: u16 = 0x0060;
+    pub const GPU_REG_TTY_CURSOR_Y: u16 = 0x0061;
+    /// Colour of the next characters, foreground in the low nibble and background in the high
+    /// nibble, both palette indices.
+    pub const GPU_REG_TTY_COLOR: u16 = 0x0062;
+    /// Style of the next characters, see the `TTY_STYLE_*` constants.
+    pub const GPU_REG_TTY_STYLE: u16 = 0x0063;
+    /// Command register, see the `TTY_CMD_*` constants.
+    pub const GPU_REG_TTY_CMD: u16 = 0x0064;
+    /// Non-zero shows a blinking block cursor.
+    pub const GPU_REG_TTY_CURSOR: u16 = 0x0065;
+
+    pub const TTY_STYLE_INVERSE: u8 = 0b0000_0001;
+    pub const TTY_STYLE_BLINK: u8 = 0b0000_0010;
+
+    /// Blank the screen in the current colour and move the cursor home.
+    pub const TTY_CMD_CLEAR_SCREEN: u8 = 0x01;
+    /// Blank the cursor's line in the current colour and move the cursor to its start.
+    pub const TTY_CMD_CLEAR_LINE: u8 = 0x02;
+
+    /// Cyan on blue.
+    pub const TTY_DEFAULT_COLOR: u8 = 0x63;
 
     pub const VRAM_CURSOR_X: usize = 0x0000;
     pub const VRAM_CURSOR_Y: usize = 0x0001;
@@ -20,19 +108,52 @@ pub mod registers {
     pub const VRAM_TTY_END: usize = VRAM_TTY_START + TTY_CELLS;
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
-enum Mode {
+/// CPU cycles per frame, 60 frames per second at the default clock.
+pub const FRAME_CYCLES: u64 = 16_666;
+/// Blinking text and the cursor switch on or off every `BLINK_FRAMES` frames.
+pub const BLINK_FRAMES: u8 = 32;
+
+/// Default palette, `0x00RRGGBB`, after the C64 one.
+const DEFAULT_PALETTE: [u32; registers::PALETTE_COLORS] = [
+    0x0000_0000,
+    0x00ff_ffff,
+    0x0088_3932,
+    0x006a_bfc6,
+    0x008b_3f96,
+    0x0055_a049,
+    0x0050_459b,
+    0x00bf_ce72,
+    0x008b_5429,
+    0x0057_4200,
+    0x00b8_6962,
+    0x0050_5050,
+    0x0078_7878,
+    0x0094_e089,
+    0x0078_69c4,
+    0x009f_9f9f,
+];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
     #[default]
     Off,
     Tty,
+    /// 160x100 pixels, one palette index per pixel.
+    Bitmap,
+    /// 160x100 window into a scrolling tile map.
+    Tiles,
 }
 
-impl From<u8> for Mode {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for Mode {
+    type Error = DeviceError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            registers::GPU_MODE_OFF => Mode::Off,
-            registers::GPU_MODE_TTY => Mode::Tty,
-            _ => unimplemented!(),
+            registers::GPU_MODE_OFF => Ok(Mode::Off),
+            registers::GPU_MODE_TTY => Ok(Mode::Tty),
+            registers::GPU_MODE_BITMAP => Ok(Mode::Bitmap),
+            registers::GPU_MODE_TILES => Ok(Mode::Tiles),
+            _ => Err(DeviceError::InvalidValue),
         }
     }
 }
@@ -42,6 +163,8 @@ impl From<Mode> for u8 {
         match value {
             Mode::Off => registers::GPU_MODE_OFF,
             Mode::Tty => registers::GPU_MODE_TTY,
+            Mode::Bitmap => registers::GPU_MODE_BITMAP,
+            Mode::Tiles => registers::GPU_MODE_TILES,
         }
     }
 }
@@ -50,25 +173,145 @@ impl From<Mode> for u8 {
 pub struct GPU {
     mode: Mode,
     vram: Box<[u8; registers::TTY_CELLS + 2]>,
+    /// Colour of every TTY cell, as in `GPU_REG_TTY_COLOR`.
+    colors: Box<[u8; registers::TTY_CELLS]>,
+    /// Style of every TTY cell, as in `GPU_REG_TTY_STYLE`.
+    styles: Box<[u8; registers::TTY_CELLS]>,
+    tty_color: u8,
+    tty_style: u8,
+    tty_cursor: bool,
+    bitmap: Box<[u8; registers::BITMAP_PIXELS]>,
+    palette: [u8; registers::PALETTE_COLORS * 3],
+    map: Box<[u8; registers::MAP_SIZE]>,
+    patterns: Box<[u8; registers::PATTERNS_SIZE]>,
+    sprites: [u8; registers::SPRITES * registers::GPU_SPRITE_REGS],
+    x: u8,
+    y: u8,
+    scroll_x: u8,
+    scroll_y: u8,
+    /// Memory used by the `DATA` port, one of the `GPU_PLANE_*` constants.
+    plane: u8,
+    /// Address used by the `DATA` port.
+    addr: u16,
+    status: u8,
+    sprite_collision: u8,
+    bg_collision: u8,
+    /// Cycles since the last frame.
+    cycles: u64,
+    /// Frame counter for blinking.
+    frames: u8,
     redraw: bool,
 }
 
 impl Default for GPU {
     fn default() -> Self {
+        let mut palette = [0; registers::PALETTE_COLORS * 3];
+        for (rgb, color) in palette.chunks_exact_mut(3).zip(DEFAULT_PALETTE) {
+            rgb.copy_from_slice(&color.to_be_bytes()[1..]);
+        }
         Self {
             mode: Mode::Off,
             vram: empty_memory(),
+            colors: Box::new([registers::TTY_DEFAULT_COLOR; registers::TTY_CELLS]),
+            styles: empty_memory(),
+            tty_color: registers::TTY_DEFAULT_COLOR,
+            tty_style: 0,
+            tty_cursor: true,
+            bitmap: empty_memory(),
+            palette,
+            map: empty_memory(),
+            patterns: empty_memory(),
+            sprites: [0; registers::SPRITES * registers::GPU_SPRITE_REGS],
+            x: 0,
+            y: 0,
+            scroll_x: 0,
+            scroll_y: 0,
+            plane: registers::GPU_PLANE_BITMAP,
+            addr: 0,
+            status: 0,
+            sprite_collision: 0,
+            bg_collision: 0,
+            cycles: 0,
+            frames: 0,
             redraw: false,
         }
     }
 }
 
 impl GPU {
+    #[must_use]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Palette index of every bitmap pixel, row by row.
+    #[must_use]
+    pub fn bitmap(&self) -> &[u8] {
+        self.bitmap.as_slice()
+    }
+
+    /// Palette entry `index` as `0x00RRGGBB`.
+    #[must_use]
+    pub fn color(&self, index: u8) -> u32 {
+        let start = usize::from(index) % registers::PALETTE_COLORS * 3;
+        let [r, g, b] = [
+            self.palette[start],
+            self.palette[start + 1],
+            self.palette[start + 2],
+        ];
+        u32::from_be_bytes([0, r, g, b])
+    }
+
+    /// Render the bitmap or tile layer with the sprites on top into `frame`, one palette index
+    /// per pixel, row by row. `frame` holds `BITMAP_PIXELS` entries; it is left blank in the
+    /// text modes.
+    pub fn compose(&self, frame: &mut [u8]) {
+        for (index, pixel) in frame.iter_mut().take(registers::BITMAP_PIXELS).enumerate() {
+            let (x, y) = Self::position(index);
+            *pixel = (0..registers::SPRITES)
+                .find_map(|sprite| self.sprite_pixel(sprite, x, y))
+                .unwrap_or_else(|| self.background(x, y));
+        }
+    }
+
     #[must_use]
     pub fn tty_buffer(&self) -> &[u8] {
         &self.vram[registers::VRAM_TTY_START..registers::VRAM_TTY_END]
     }
 
+    /// Colour of every TTY cell, foreground in the low nibble and background in the high one.
+    #[must_use]
+    pub fn tty_colors(&self) -> &[u8] {
+        self.colors.as_slice()
+    }
+
+    /// Style of every TTY cell, see the `TTY_STYLE_*` constants.
+    #[must_use]
+    pub fn tty_styles(&self) -> &[u8] {
+        self.styles.as_slice()
+    }
+
+    /// Column and row of the TTY cursor.
+    #[must_use]
+    pub fn cursor(&self) -> (u8, u8) {
+        (
+            self.vram[registers::VRAM_CURSOR_X],
+            self.vram[registers::VRAM_CURSOR_Y],
+        )
+    }
+
+    /// Whether the blinking cursor is drawn in TTY mode.
+    #[must_use]
+    pub fn cursor_visible(&self) -> bool {
+        self.mode == Mode::Tty && self.tty_cursor
+    }
+
+    /// Blink phase: blinking text and the cursor are shown while this is `true`.
+    #[must_use]
+    pub fn blink(&self) -> bool {
+        (self.frames / BLINK_FRAMES).is_multiple_of(2)
+    }
+
     pub fn redraw(&mut self) -> bool {
         if self.redraw {
             self.redraw = false;
@@ -79,81 +322,629 @@ impl GPU {
     }
 }
 
+impl GPU {
+    /// Bitmap index of the pixel at `X`, `Y`, if it is on screen.
+    fn pixel(&self) -> Option<usize> {
+        (self.x < registers::BITMAP_WIDTH && self.y < registers::BITMAP_HEIGHT).then(|| {
+            usize::from(self.y) * usize::from(registers::BITMAP_WIDTH) + usize::from(self.x)
+        })
+    }
+
+    /// Put a character on the TTY and advance the cursor, scrolling at the bottom.
+    fn tty_write(&mut self, value: u8) {
+        self.redraw = true;
+        let (mut cursor_x, mut cursor_y) = self.cursor();
+
+        match value {
+            b'\n' => {
+                cursor_x = 0;
+                cursor_y += 1;
+            }
+
+            b'\x08' => {
+                if cursor_x > 0 {
+                    cursor_x -= 1;
+                } else if cursor_y > 0 {
+                    cursor_y -= 1;
+                    cursor_x = registers::TTY_COLS - 1;
+                } else {
+                    cursor_x = 0;
+                    cursor_y = 0;
+                }
+
+                self.tty_put(Self::cell(cursor_x, cursor_y), b' ');
+            }
+
+            _ => {
+                self.tty_put(Self::cell(cursor_x, cursor_y), value);
+
+                cursor_x += 1;
+                if cursor_x >= registers::TTY_COLS {
+                    cursor_x = 0;
+                    cursor_y += 1;
+                }
+            }
+        }
+
+        if cursor_y >= registers::TTY_ROWS {
+            let cols = usize::from(registers::TTY_COLS);
+            self.vram.copy_within(
+                registers::VRAM_TTY_START + cols..VRAM_TTY_END,
+                registers::VRAM_TTY_START,
+            );
+            self.colors.copy_within(cols.., 0);
+            self.styles.copy_within(cols.., 0);
+            self.tty_clear(Self::cell(0, registers::TTY_ROWS - 1)..registers::TTY_CELLS);
+
+            cursor_y = registers::TTY_ROWS - 1;
+        }
+
+        self.vram[registers::VRAM_CURSOR_X] = cursor_x;
+        self.vram[registers::VRAM_CURSOR_Y] = cursor_y;
+    }
+
+    /// Cell index of column `x`, row `y`.
+    fn cell(x: u8, y: u8) -> usize {
+        usize::from(y) * usize::from(registers::TTY_COLS) + usize::from(x)
+    }
+
+    /// Store a character with the current colour and style.
+    fn tty_put(&mut self, cell: usize, value: u8) {
+        self.vram[registers::VRAM_TTY_START + cell] = value;
+        self.colors[cell] = self.tty_color;
+        self.styles[cell] = self.tty_style;
+    }
+
+    /// Blank `cells` in the current colour.
+    fn tty_clear(&mut self, cells: std::ops::Range<usize>) {
+        for cell in cells {
+            self.tty_put(cell, b' ');
+        }
+        self.redraw = true;
+    }
+
+    fn tty_command(&mut self, command: u8) -> DeviceResult {
+        let (_, cursor_y) = self.cursor();
+        match command {
+            registers::TTY_CMD_CLEAR_SCREEN => {
+                self.tty_clear(0..registers::TTY_CELLS);
+                self.vram[registers::VRAM_CURSOR_X] = 0;
+                self.vram[registers::VRAM_CURSOR_Y] = 0;
+            }
+            registers::TTY_CMD_CLEAR_LINE => {
+                self.tty_clear(Self::cell(0, cursor_y)..Self::cell(0, cursor_y + 1));
+                self.vram[registers::VRAM_CURSOR_X] = 0;
+            }
+            _ => return Err(DeviceError::InvalidValue),
+        }
+        Ok(())
+    }
+
+    /// Memory selected by `PLANE`.
+    fn plane(&mut self) -> &mut [u8] {
+        match self.plane {
+            registers::GPU_PLANE_MAP => self.map.as_mut_slice(),
+            registers::GPU_PLANE_PATTERNS => self.patterns.as_mut_slice(),
+            _ => self.bitmap.as_mut_slice(),
+        }
+    }
+
+    /// Byte behind the `DATA` port; the address wraps around the selected plane.
+    fn data(&mut self) -> &mut u8 {
+        let addr = usize::from(self.addr);
+        let plane = self.plane();
+        let len = plane.len();
+        &mut plane[addr % len]
+    }
+
+    fn position(index: usize) -> (u8, u8) {
+        let width = usize::from(registers::BITMAP_WIDTH);
+        // Both fit in a byte, the frame is 160x100
+        #[allow(clippy::cast_possible_truncation)]
+        ((index % width) as u8, (index / width) as u8)
+    }
+
+    /// Palette index of the bitmap or tile layer at screen pixel `x`, `y`.
+    fn background(&self, x: u8, y: u8) -> u8 {
+        match self.mode {
+            Mode::Bitmap => {
+                self.bitmap[usize::from(y) * usize::from(registers::BITMAP_WIDTH) + usize::from(x)]
+            }
+            Mode::Tiles => {
+                // The 256x256 plane wraps with the byte-sized scroll registers
+                let (x, y) = (x.wrapping_add(self.scroll_x), y.wrapping_add(self.scroll_y));
+                let size = registers::TILE_SIZE;
+                let tile = self.map[usize::from(y / size) * usize::from(registers::MAP_COLS)
+                    + usize::from(x / size)];
+                self.pattern(tile, x % size, y % size)
+            }
+            Mode::Off | Mode::Tty => 0,
+        }
+    }
+
+    fn pattern(&self, pattern: u8, x: u8, y: u8) -> u8 {
+        self.patterns[usize::from(pattern) * registers::TILE_PIXELS
+            + usize::from(y) * usize::from(registers::TILE_SIZE)
+            + usize::from(x)]
+    }
+
+    /// Palette index of `sprite` at screen pixel `x`, `y`; colour `0` is transparent.
+    fn sprite_pixel(&self, sprite: usize, x: u8, y: u8) -> Option<u8> {
+        let regs = &self.sprites[sprite * registers::GPU_SPRITE_REGS..];
+        let flags = regs[registers::GPU_SPRITE_FLAGS];
+        if flags & registers::GPU_SPRITE_VISIBLE == 0 {
+            return None;
+        }
+        // Positions wrap, so a sprite at X = 252 shows its right half at the left edge
+        let mut dx = x.wrapping_sub(regs[registers::GPU_SPRITE_X]);
+        let mut dy = y.wrapping_sub(regs[registers::GPU_SPRITE_Y]);
+        let size = registers::TILE_SIZE;
+        if dx >= size || dy >= size {
+            return None;
+        }
+        if flags & registers::GPU_SPRITE_FLIP_X != 0 {
+            dx = size - 1 - dx;
+        }
+        if flags & registers::GPU_SPRITE_FLIP_Y != 0 {
+            dy = size - 1 - dy;
+        }
+        let color = self.pattern(regs[registers::GPU_SPRITE_PATTERN], dx, dy);
+        (color != 0).then_some(color)
+    }
+
+    /// End of a frame: latch the sprite collisions and raise vblank.
+    fn frame(&mut self) {
+        self.status |= registers::GPU_STATUS_VBLANK;
+        self.frames = self.frames.wrapping_add(1);
+        if !matches!(self.mode, Mode::Bitmap | Mode::Tiles) {
+            return;
+        }
+        if self
+            .sprites
+            .chunks_exact(registers::GPU_SPRITE_REGS)
+            .all(|regs| regs[registers::GPU_SPRITE_FLAGS] & registers::GPU_SPRITE_VISIBLE == 0)
+        {
+            return;
+        }
+        for index in 0..registers::BITMAP_PIXELS {
+            let (x, y) = Self::position(index);
+            let hits = (0..registers::SPRITES)
+                .filter(|&sprite| self.sprite_pixel(sprite, x, y).is_some())
+                .fold(0u8, |hits, sprite| hits | 1 << sprite);
+            if hits.count_ones() > 1 {
+                self.sprite_collision |= hits;
+            }
+            if hits != 0 && self.background(x, y) != 0 {
+                self.bg_collision |= hits;
+            }
+        }
+    }
+}
+
 impl Device for GPU {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
         match addr {
-            registers::GPU_REG_MODE => self.mode.into(),
-            _ => unimplemented!(),
+            registers::GPU_REG_MODE => Ok(self.mode.into()),
+            registers::GPU_REG_X => Ok(self.x),
+            registers::GPU_REG_Y => Ok(self.y),
+            registers::GPU_REG_PLOT => Ok(self.pixel().map_or(0, |index| self.bitmap[index])),
+            registers::GPU_REG_ADDR_HI => Ok(self.addr.to_be_bytes()[0]),
+            registers::GPU_REG_ADDR_LO => Ok(self.addr.to_be_bytes()[1]),
+            registers::GPU_REG_DATA => {
+                let value = *self.data();
+                self.addr = self.addr.wrapping_add(1);
+                Ok(value)
+            }
+            registers::GPU_REG_PLANE => Ok(self.plane),
+            registers::GPU_REG_SCROLL_X => Ok(self.scroll_x),
+            registers::GPU_REG_SCROLL_Y => Ok(self.scroll_y),
+            registers::GPU_REG_STATUS => Ok(self.status),
+            registers::GPU_REG_SPRITE_COLLISION => Ok(self.sprite_collision),
+            registers::GPU_REG_BG_COLLISION => Ok(self.bg_collision),
+            registers::GPU_REG_TTY_CURSOR_X => Ok(self.cursor().0),
+            registers::GPU_REG_TTY_CURSOR_Y => Ok(self.cursor().1),
+            registers::GPU_REG_TTY_COLOR => Ok(self.tty_color),
+            registers::GPU_REG_TTY_STYLE => Ok(self.tty_style),
+            registers::GPU_REG_TTY_CURSOR => Ok(self.tty_cursor.into()),
+            registers::GPU_REG_PALETTE..=registers::GPU_REG_PALETTE_END => {
+                Ok(self.palette[usize::from(addr - registers::GPU_REG_PALETTE)])
+            }
+            registers::GPU_REG_SPRITES..=registers::GPU_REG_SPRITES_END => {
+                Ok(self.sprites[usize::from(addr - registers::GPU_REG_SPRITES)])
+            }
+            _ => Err(DeviceError::Unmapped),
         }
     }
 
-    fn write(&mut self, addr: u16, value: u8) {
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
         match addr {
-            registers::GPU_REG_MODE => self.mode = value.into(),
-            registers::GPU_REG_TTY if self.mode == Mode::Tty => {
-                self.redraw = true;
-                let (mut cursor_x, mut cursor_y) = (
-                    self.vram[registers::VRAM_CURSOR_X],
-                    self.vram[registers::VRAM_CURSOR_Y],
-                );
-
-                let tty_buf = &mut self.vram[registers::VRAM_TTY_START..VRAM_TTY_END];
-                let cols = registers::TTY_COLS as usize;
-
-                match value {
-                    b'\n' => {
-                        cursor_x = 0;
-                        cursor_y += 1;
-                    }
-
-                    b'\x08' => {
-                        if cursor_x > 0 {
-                            cursor_x -= 1;
-                        } else if cursor_y > 0 {
-                            cursor_y -= 1;
-                            cursor_x = registers::TTY_COLS - 1;
-                        } else {
-                            cursor_x = 0;
-                            cursor_y = 0;
-                        }
-
-                        let index = cursor_y as usize * cols + cursor_x as usize;
-                        tty_buf[index] = b' ';
-                    }
-
-                    _ => {
-                        let index = cursor_y as usize * cols + cursor_x as usize;
-                        tty_buf[index] = value;
-
-                        cursor_x += 1;
-                        if cursor_x >= registers::TTY_COLS {
-                            cursor_x = 0;
-                            cursor_y += 1;
-                        }
-                    }
+            registers::GPU_REG_MODE => self.mode = value.try_into()?,
+            registers::GPU_REG_TTY if self.mode == Mode::Tty => self.tty_write(value),
+            // Output is dropped while the TTY is off
+            registers::GPU_REG_TTY => {}
+            registers::GPU_REG_X => self.x = value,
+            registers::GPU_REG_Y => self.y = value,
+            registers::GPU_REG_PLOT => {
+                // Pixels outside the screen are clipped
+                if let Some(index) = self.pixel() {
+                    self.bitmap[index] = value & 0x0F;
+                    self.redraw = true;
                 }
+            }
+            registers::GPU_REG_CLEAR => {
+                self.bitmap.fill(value & 0x0F);
+                self.redraw = true;
+            }
+            registers::GPU_REG_ADDR_HI => {
+                self.addr = u16::from_be_bytes([value, self.addr.to_be_bytes()[1]]);
+            }
+            registers::GPU_REG_ADDR_LO => {
+                self.addr = u16::from_be_bytes([self.addr.to_be_bytes()[0], value]);
+            }
+            registers::GPU_REG_DATA => {
+                // Map entries are tile numbers, the other planes hold palette indices
+                let mask = if self.plane == registers::GPU_PLANE_MAP {
+                    0xFF
+                } else {
+                    0x0F
+                };
+                *self.data() = value & mask;
+                self.addr = self.addr.wrapping_add(1);
+                self.redraw = true;
+            }
+            registers::GPU_REG_PLANE => match value {
+                registers::GPU_PLANE_BITMAP
+                | registers::GPU_PLANE_MAP
+                | registers::GPU_PLANE_PATTERNS => self.plane = value,
+                _ => return Err(DeviceError::InvalidValue),
+            },
+            registers::GPU_REG_SCROLL_X => {
+                self.scroll_x = value;
+                self.redraw = true;
+            }
+            registers::GPU_REG_SCROLL_Y => {
+                self.scroll_y = value;
+                self.redraw = true;
+            }
+            registers::GPU_REG_STATUS => self.status &= !value,
+            registers::GPU_REG_SPRITE_COLLISION => self.sprite_collision &= !value,
+            registers::GPU_REG_BG_COLLISION => self.bg_collision &= !value,
+            registers::GPU_REG_TTY_CURSOR_X if value < registers::TTY_COLS => {
+                self.vram[registers::VRAM_CURSOR_X] = value;
+                self.redraw = true;
+            }
+            registers::GPU_REG_TTY_CURSOR_Y if value < registers::TTY_ROWS => {
+                self.vram[registers::VRAM_CURSOR_Y] = value;
+                self.redraw = true;
+            }
+            registers::GPU_REG_TTY_CURSOR_X | registers::GPU_REG_TTY_CURSOR_Y => {
+                return Err(DeviceError::InvalidValue)
+            }
+            registers::GPU_REG_TTY_COLOR => self.tty_color = value,
+            registers::GPU_REG_TTY_STYLE => self.tty_style = value,
+            registers::GPU_REG_TTY_CMD => self.tty_command(value)?,
+            registers::GPU_REG_TTY_CURSOR => {
+                self.tty_cursor = value != 0;
+                self.redraw = true;
+            }
+            registers::GPU_REG_PALETTE..=registers::GPU_REG_PALETTE_END => {
+                self.palette[usize::from(addr - registers::GPU_REG_PALETTE)] = value;
+                self.redraw = true;
+            }
+            registers::GPU_REG_SPRITES..=registers::GPU_REG_SPRITES_END => {
+                self.sprites[usize::from(addr - registers::GPU_REG_SPRITES)] = value;
+                self.redraw = true;
+            }
+            _ => return Err(DeviceError::Unmapped),
+        }
+        Ok(())
+    }
 
-                if cursor_y >= registers::TTY_ROWS {
-                    for y in 1..registers::TTY_ROWS {
-                        for x in 0..registers::TTY_COLS {
-                            let src_index = (y as usize * cols) + x as usize;
-                            let dest_index = ((y - 1) as usize * cols) + x as usize;
-                            tty_buf[dest_index] = tty_buf[src_index];
-                        }
-                    }
-
-                    let last_line_start = (registers::TTY_ROWS - 1) as usize * cols;
-                    for x in 0..registers::TTY_COLS {
-                        tty_buf[last_line_start + x as usize] = b' ';
-                    }
-
-                    cursor_y = registers::TTY_ROWS - 1;
-                }
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        while self.cycles >= FRAME_CYCLES {
+            self.cycles -= FRAME_CYCLES;
+            self.frame();
+        }
+    }
+}
 
-                self.vram[registers::VRAM_CURSOR_X] = cursor_x;
-                self.vram[registers::VRAM_CURSOR_Y] = cursor_y;
-            }
-            _ => unimplemented!(),
+impl Snapshot for GPU {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.mode.into());
+        writer.bytes(self.vram.as_slice());
+        writer.bytes(self.colors.as_slice());
+        writer.bytes(self.styles.as_slice());
+        writer.u8(self.tty_color);
+        writer.u8(self.tty_style);
+        writer.bool(self.tty_cursor);
+        writer.bytes(self.bitmap.as_slice());
+        writer.bytes(&self.palette);
+        writer.bytes(self.map.as_slice());
+        writer.bytes(self.patterns.as_slice());
+        writer.bytes(&self.sprites);
+        writer.u8(self.x);
+        writer.u8(self.y);
+        writer.u8(self.scroll_x);
+        writer.u8(self.scroll_y);
+        writer.u8(self.plane);
+        writer.u16(self.addr);
+        writer.u8(self.status);
+        writer.u8(self.sprite_collision);
+        writer.u8(self.bg_collision);
+        writer.u64(self.cycles);
+        writer.u8(self.frames);
+        writer.bool(self.redraw);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.mode = reader
+            .u8()?
+            .try_into()
+            .map_err(|_| SnapshotError::Invalid { field: "GPU mode" })?;
+        reader.fill(self.vram.as_mut_slice())?;
+        reader.fill(self.colors.as_mut_slice())?;
+        reader.fill(self.styles.as_mut_slice())?;
+        self.tty_color = reader.u8()?;
+        self.tty_style = reader.u8()?;
+        self.tty_cursor = reader.bool()?;
+        reader.fill(self.bitmap.as_mut_slice())?;
+        reader.fill(&mut self.palette)?;
+        reader.fill(self.map.as_mut_slice())?;
+        reader.fill(self.patterns.as_mut_slice())?;
+        reader.fill(&mut self.sprites)?;
+        self.x = reader.u8()?;
+        self.y = reader.u8()?;
+        self.scroll_x = reader.u8()?;
+        self.scroll_y = reader.u8()?;
+        self.plane = reader.u8()?;
+        if self.plane > registers::GPU_PLANE_PATTERNS {
+            return Err(SnapshotError::Invalid { field: "GPU plane" });
+        }
+        self.addr = reader.u16()?;
+        self.status = reader.u8()?;
+        self.sprite_collision = reader.u8()?;
+        self.bg_collision = reader.u8()?;
+        self.cycles = reader.u64()?;
+        self.frames = reader.u8()?;
+        self.redraw = reader.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    fn bitmap_gpu() -> GPU {
+        let mut gpu = GPU::default();
+        gpu.write(GPU_REG_MODE, GPU_MODE_BITMAP).unwrap();
+        gpu
+    }
+
+    /// Tile mode with pattern 1 solid colour 2 and pattern 2 a single colour 3 pixel at 0, 0.
+    fn tiles_gpu() -> GPU {
+        let mut gpu = GPU::default();
+        gpu.write(GPU_REG_MODE, GPU_MODE_TILES).unwrap();
+        gpu.write(GPU_REG_PLANE, GPU_PLANE_PATTERNS).unwrap();
+        gpu.write(GPU_REG_ADDR_LO, 0x40).unwrap();
+        for _ in 0..TILE_PIXELS {
+            gpu.write(GPU_REG_DATA, 2).unwrap();
         }
+        gpu.write(GPU_REG_DATA, 3).unwrap();
+        gpu
+    }
+
+    fn sprite(gpu: &mut GPU, sprite: u16, x: u8, y: u8, pattern: u8, flags: u8) {
+        let base = GPU_REG_SPRITES + sprite * GPU_SPRITE_REGS as u16;
+        gpu.write(base, x).unwrap();
+        gpu.write(base + 1, y).unwrap();
+        gpu.write(base + 2, pattern).unwrap();
+        gpu.write(base + 3, flags).unwrap();
+    }
+
+    fn frame(gpu: &GPU) -> Vec<u8> {
+        let mut frame = vec![0; BITMAP_PIXELS];
+        gpu.compose(&mut frame);
+        frame
+    }
+
+    fn at(x: usize, y: usize) -> usize {
+        y * usize::from(BITMAP_WIDTH) + x
+    }
+
+    #[test]
+    fn plots_and_clears_pixels() {
+        let mut gpu = bitmap_gpu();
+        gpu.write(GPU_REG_CLEAR, 0x01).unwrap();
+        gpu.write(GPU_REG_X, 159).unwrap();
+        gpu.write(GPU_REG_Y, 99).unwrap();
+        gpu.write(GPU_REG_PLOT, 0x1C).unwrap();
+        assert_eq!(gpu.read(GPU_REG_PLOT), Ok(0x0C));
+        assert_eq!(gpu.bitmap()[BITMAP_PIXELS - 1], 0x0C);
+        assert_eq!(gpu.bitmap()[0], 0x01);
+        assert!(gpu.redraw());
+
+        // Off-screen pixels are clipped
+        gpu.write(GPU_REG_X, 160).unwrap();
+        gpu.write(GPU_REG_PLOT, 0x02).unwrap();
+        assert_eq!(gpu.read(GPU_REG_PLOT), Ok(0));
+        assert!(!gpu.redraw());
+    }
+
+    #[test]
+    fn streams_through_data_port() {
+        let mut gpu = bitmap_gpu();
+        gpu.write(GPU_REG_ADDR_HI, 0x00).unwrap();
+        gpu.write(GPU_REG_ADDR_LO, 0xA0).unwrap();
+        gpu.write(GPU_REG_DATA, 0x03).unwrap();
+        gpu.write(GPU_REG_DATA, 0x04).unwrap();
+        assert_eq!(gpu.read(GPU_REG_ADDR_LO), Ok(0xA2));
+
+        // 0x00A0 is the first pixel of the second row
+        gpu.write(GPU_REG_X, 1).unwrap();
+        gpu.write(GPU_REG_Y, 1).unwrap();
+        assert_eq!(gpu.read(GPU_REG_PLOT), Ok(0x04));
+    }
+
+    #[test]
+    fn palette_is_writable() {
+        let mut gpu = bitmap_gpu();
+        assert_eq!(gpu.color(7), 0x00bf_ce72);
+        gpu.write(GPU_REG_PALETTE + 3, 0x12).unwrap();
+        gpu.write(GPU_REG_PALETTE + 4, 0x34).unwrap();
+        gpu.write(GPU_REG_PALETTE + 5, 0x56).unwrap();
+        assert_eq!(gpu.color(1), 0x0012_3456);
+        assert_eq!(gpu.read(GPU_REG_PALETTE - 1), Err(DeviceError::Unmapped));
+        assert_eq!(
+            gpu.write(GPU_REG_MODE, 0x04),
+            Err(DeviceError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn scrolls_tile_map() {
+        let mut gpu = tiles_gpu();
+        gpu.write(GPU_REG_PLANE, GPU_PLANE_MAP).unwrap();
+        gpu.write(GPU_REG_ADDR_LO, 33).unwrap();
+        gpu.write(GPU_REG_DATA, 1).unwrap();
+
+        // Map entry 33 is the tile at column 1, row 1
+        let tiles = frame(&gpu);
+        assert_eq!(tiles[at(8, 8)], 2);
+        assert_eq!(tiles[at(15, 15)], 2);
+        assert_eq!(tiles[at(16, 8)], 0);
+
+        gpu.write(GPU_REG_SCROLL_X, 4).unwrap();
+        gpu.write(GPU_REG_SCROLL_Y, 0xFC).unwrap();
+        let scrolled = frame(&gpu);
+        assert_eq!(scrolled[at(4, 12)], 2);
+        assert_eq!(scrolled[at(3, 12)], 0);
+    }
+
+    #[test]
+    fn draws_flipped_sprites_over_background() {
+        let mut gpu = tiles_gpu();
+        sprite(&mut gpu, 0, 10, 20, 2, GPU_SPRITE_VISIBLE);
+        sprite(
+            &mut gpu,
+            1,
+            30,
+            40,
+            2,
+            GPU_SPRITE_VISIBLE | GPU_SPRITE_FLIP_X,
+        );
+        sprite(&mut gpu, 2, 50, 60, 2, GPU_SPRITE_FLIP_Y);
+        let sprites = frame(&gpu);
+        assert_eq!(sprites[at(10, 20)], 3);
+        assert_eq!(sprites[at(37, 40)], 3);
+        assert_eq!(sprites[at(30, 40)], 0);
+        // Hidden sprites are not drawn
+        assert_eq!(sprites[at(50, 67)], 0);
+    }
+
+    #[test]
+    fn latches_collisions_and_vblank() {
+        let mut gpu = tiles_gpu();
+        gpu.tick(FRAME_CYCLES - 1);
+        assert_eq!(gpu.read(GPU_REG_STATUS), Ok(0));
+        gpu.tick(1);
+        assert_eq!(gpu.read(GPU_REG_STATUS), Ok(GPU_STATUS_VBLANK));
+        gpu.write(GPU_REG_STATUS, GPU_STATUS_VBLANK).unwrap();
+        assert_eq!(gpu.read(GPU_REG_STATUS), Ok(0));
+
+        sprite(&mut gpu, 0, 10, 10, 2, GPU_SPRITE_VISIBLE);
+        sprite(&mut gpu, 1, 10, 10, 2, GPU_SPRITE_VISIBLE);
+        sprite(&mut gpu, 2, 100, 10, 2, GPU_SPRITE_VISIBLE);
+        gpu.tick(FRAME_CYCLES);
+        assert_eq!(gpu.read(GPU_REG_SPRITE_COLLISION), Ok(0b011));
+        assert_eq!(gpu.read(GPU_REG_BG_COLLISION), Ok(0));
+
+        // Map entry 12 is the solid tile under sprite 2
+        gpu.write(GPU_REG_PLANE, GPU_PLANE_MAP).unwrap();
+        gpu.write(GPU_REG_ADDR_LO, 32 + 12).unwrap();
+        gpu.write(GPU_REG_DATA, 1).unwrap();
+        gpu.write(GPU_REG_SPRITE_COLLISION, 0xFF).unwrap();
+        gpu.tick(FRAME_CYCLES);
+        assert_eq!(gpu.read(GPU_REG_SPRITE_COLLISION), Ok(0b011));
+        assert_eq!(gpu.read(GPU_REG_BG_COLLISION), Ok(0b100));
+        assert_eq!(gpu.write(GPU_REG_PLANE, 3), Err(DeviceError::InvalidValue));
+    }
+
+    fn tty_gpu() -> GPU {
+        let mut gpu = GPU::default();
+        gpu.write(GPU_REG_MODE, GPU_MODE_TTY).unwrap();
+        gpu
+    }
+
+    #[test]
+    fn positions_colored_text() {
+        let mut gpu = tty_gpu();
+        gpu.write(GPU_REG_TTY_CURSOR_X, 5).unwrap();
+        gpu.write(GPU_REG_TTY_CURSOR_Y, 2).unwrap();
+        gpu.write(GPU_REG_TTY_COLOR, 0x12).unwrap();
+        gpu.write(GPU_REG_TTY_STYLE, TTY_STYLE_INVERSE).unwrap();
+        gpu.write(GPU_REG_TTY, b'A').unwrap();
+
+        let cell = 2 * usize::from(TTY_COLS) + 5;
+        assert_eq!(gpu.tty_buffer()[cell], b'A');
+        assert_eq!(gpu.tty_colors()[cell], 0x12);
+        assert_eq!(gpu.tty_styles()[cell], TTY_STYLE_INVERSE);
+        assert_eq!(gpu.tty_colors()[0], TTY_DEFAULT_COLOR);
+        assert_eq!(gpu.read(GPU_REG_TTY_CURSOR_X), Ok(6));
+        assert_eq!(
+            gpu.write(GPU_REG_TTY_CURSOR_Y, TTY_ROWS),
+            Err(DeviceError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn clears_screen_and_line() {
+        let mut gpu = tty_gpu();
+        for &byte in b"ab\ncd" {
+            gpu.write(GPU_REG_TTY, byte).unwrap();
+        }
+        gpu.write(GPU_REG_TTY_COLOR, 0x21).unwrap();
+        gpu.write(GPU_REG_TTY_CMD, TTY_CMD_CLEAR_LINE).unwrap();
+        let cols = usize::from(TTY_COLS);
+        assert_eq!(&gpu.tty_buffer()[..2], b"ab");
+        assert_eq!(&gpu.tty_buffer()[cols..cols + 2], b"  ");
+        assert_eq!(gpu.tty_colors()[cols], 0x21);
+        assert_eq!(gpu.cursor(), (0, 1));
+
+        gpu.write(GPU_REG_TTY_CMD, TTY_CMD_CLEAR_SCREEN).unwrap();
+        assert!(gpu.tty_buffer().iter().all(|&byte| byte == b' '));
+        assert!(gpu.tty_colors().iter().all(|&color| color == 0x21));
+        assert_eq!(gpu.cursor(), (0, 0));
+        assert_eq!(
+            gpu.write(GPU_REG_TTY_CMD, 0x7F),
+            Err(DeviceError::InvalidValue)
+        );
+    }
+
+    #[test]
+    fn scrolls_attributes_with_text() {
+        let mut gpu = tty_gpu();
+        gpu.write(GPU_REG_TTY_CURSOR_Y, 1).unwrap();
+        gpu.write(GPU_REG_TTY_STYLE, TTY_STYLE_BLINK).unwrap();
+        gpu.write(GPU_REG_TTY, b'x').unwrap();
+        gpu.write(GPU_REG_TTY_CURSOR_Y, TTY_ROWS - 1).unwrap();
+        gpu.write(GPU_REG_TTY, b'\n').unwrap();
+        assert_eq!(gpu.tty_buffer()[0], b'x');
+        assert_eq!(gpu.tty_styles()[0], TTY_STYLE_BLINK);
+        assert_eq!(gpu.tty_styles()[usize::from(TTY_COLS)], 0);
+    }
+
+    #[test]
+    fn blinks_every_few_frames() {
+        let mut gpu = tty_gpu();
+        assert!(gpu.cursor_visible());
+        assert!(gpu.blink());
+        gpu.tick(FRAME_CYCLES * u64::from(BLINK_FRAMES));
+        assert!(!gpu.blink());
+        gpu.tick(FRAME_CYCLES * u64::from(BLINK_FRAMES));
+        assert!(gpu.blink());
+        gpu.write(GPU_REG_TTY_CURSOR, 0).unwrap();
+        assert!(!gpu.cursor_visible());
     }
 }
@@ -0,0 +1,114 @@
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+pub mod registers {
+    /// Mask of the IRQ lines allowed to interrupt the CPU.
+    pub const ENABLE: u16 = 0x00;
+    /// Lines waiting to be served. Writing `1` to a bit acknowledges that line.
+    pub const PENDING: u16 = 0x01;
+    /// Address of the vector table, high byte.
+    pub const VECTOR_HI: u16 = 0x02;
+    /// Address of the vector table, low byte.
+    pub const VECTOR_LO: u16 = 0x03;
+
+    pub const IRQ_TIMER: u8 = 0;
+    pub const IRQ_KEYBOARD: u8 = 1;
+    pub const IRQ_UART: u8 = 2;
+}
+
+/// Collects interrupt requests from devices and tells the CPU which one to serve.
+///
+/// The vector table holds one big-endian handler address per IRQ line.
+#[derive(Debug, Default)]
+pub struct InterruptController {
+    enable: u8,
+    pending: u8,
+    vector: u16,
+}
+
+impl InterruptController {
+    /// Request an interrupt on `line`.
+    pub fn raise(&mut self, line: u8) {
+        self.pending |= 1 << line;
+    }
+
+    /// Enabled pending line with the highest priority (lowest number), if any.
+    #[must_use]
+    pub fn active(&self) -> Option<u8> {
+        let active = self.enable & self.pending;
+        (active != 0).then(|| active.trailing_zeros() as u8)
+    }
+
+    /// Address of the vector table entry for `line`.
+    #[must_use]
+    pub fn vector(&self, line: u8) -> u16 {
+        self.vector.wrapping_add(u16::from(line) * 2)
+    }
+}
+
+impl Device for InterruptController {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        let [vector_hi, vector_lo] = self.vector.to_be_bytes();
+        match addr {
+            registers::ENABLE => Ok(self.enable),
+            registers::PENDING => Ok(self.pending),
+            registers::VECTOR_HI => Ok(vector_hi),
+            registers::VECTOR_LO => Ok(vector_lo),
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        let [vector_hi, vector_lo] = self.vector.to_be_bytes();
+        match addr {
+            registers::ENABLE => self.enable = value,
+            registers::PENDING => self.pending &= !value,
+            registers::VECTOR_HI => self.vector = u16::from_be_bytes([value, vector_lo]),
+            registers::VECTOR_LO => self.vector = u16::from_be_bytes([vector_hi, value]),
+            _ => return Err(DeviceError::Unmapped),
+        }
+        Ok(())
+    }
+}
+
+impl Snapshot for InterruptController {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.enable);
+        writer.u8(self.pending);
+        writer.u16(self.vector);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.enable = reader.u8()?;
+        self.pending = reader.u8()?;
+        self.vector = reader.u16()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serves_lowest_enabled_line() {
+        let mut pic = InterruptController::default();
+        pic.raise(registers::IRQ_KEYBOARD);
+        pic.raise(3);
+        assert_eq!(pic.active(), None);
+        pic.write(registers::ENABLE, 0b1010).unwrap();
+        assert_eq!(pic.active(), Some(registers::IRQ_KEYBOARD));
+        pic.write(registers::PENDING, 0b0010).unwrap();
+        assert_eq!(pic.active(), Some(3));
+        assert_eq!(pic.read(registers::PENDING), Ok(0b1000));
+    }
+
+    #[test]
+    fn locates_vector_entries() {
+        let mut pic = InterruptController::default();
+        pic.write(registers::VECTOR_HI, 0x12).unwrap();
+        pic.write(registers::VECTOR_LO, 0x00).unwrap();
+        assert_eq!(pic.vector(registers::IRQ_KEYBOARD), 0x1202);
+    }
+}
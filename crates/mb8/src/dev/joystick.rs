@@ -0,0 +1,92 @@
+//! Digital joysticks.
+//! One read-only byte per port with a bit for each direction and the fire button. The host
+//! sets the bits, so tests can drive games without a window.
+
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+pub mod registers {
+    pub const PORTS: usize = 2;
+    /// State of port `n` is at `PORT + n`.
+    pub const PORT: u16 = 0x00;
+
+    pub const UP: u8 = 0x01;
+    pub const DOWN: u8 = 0x02;
+    pub const LEFT: u8 = 0x04;
+    pub const RIGHT: u8 = 0x08;
+    pub const FIRE: u8 = 0x10;
+}
+
+#[derive(Debug, Default)]
+pub struct Joystick {
+    ports: [u8; registers::PORTS],
+}
+
+impl Joystick {
+    /// State of `port`, `0` for a port that does not exist.
+    #[must_use]
+    pub fn port(&self, port: usize) -> u8 {
+        self.ports.get(port).copied().unwrap_or_default()
+    }
+
+    /// Replace the state of `port`. Ports that do not exist are ignored.
+    pub fn set_port(&mut self, port: usize, state: u8) {
+        if let Some(current) = self.ports.get_mut(port) {
+            *current = state;
+        }
+    }
+
+    /// Set the `bits` of `port`, as if those directions or the button were pushed.
+    pub fn press(&mut self, port: usize, bits: u8) {
+        self.set_port(port, self.port(port) | bits);
+    }
+
+    /// Clear the `bits` of `port`.
+    pub fn release(&mut self, port: usize, bits: u8) {
+        self.set_port(port, self.port(port) & !bits);
+    }
+}
+
+impl Device for Joystick {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        let port = usize::from(addr.wrapping_sub(registers::PORT));
+        self.ports.get(port).copied().ok_or(DeviceError::Unmapped)
+    }
+
+    fn write(&mut self, addr: u16, _value: u8) -> DeviceResult {
+        self.read(addr)?;
+        Err(DeviceError::ReadOnly)
+    }
+}
+
+impl Snapshot for Joystick {
+    fn save(&self, writer: &mut Writer) {
+        writer.bytes(&self.ports);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        reader.fill(&mut self.ports)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    #[test]
+    fn reports_host_state() {
+        let mut joystick = Joystick::default();
+        joystick.press(1, UP | FIRE);
+        joystick.press(1, LEFT);
+        joystick.release(1, UP);
+        joystick.press(5, DOWN);
+        assert_eq!(joystick.read(PORT), Ok(0));
+        assert_eq!(joystick.read(PORT + 1), Ok(LEFT | FIRE));
+        assert_eq!(joystick.read(PORT + 2), Err(DeviceError::Unmapped));
+        assert_eq!(joystick.write(PORT, RIGHT), Err(DeviceError::ReadOnly));
+        assert_eq!(joystick.write(PORT + 2, 0), Err(DeviceError::Unmapped));
+        joystick.set_port(1, 0);
+        assert_eq!(joystick.port(1), 0);
+    }
+}
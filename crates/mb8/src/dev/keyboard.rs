@@ -1,33 +1,316 @@
+//! Keyboard.
+//! Typed characters queue up as ASCII in `DATA`, as they always have. Alongside, every key
+//! press and release queues a scan-code event, the modifier mask and the matrix of held keys
+//! are kept up to date, so games can poll key state instead of reading characters.
+
 use std::collections::VecDeque;
 
-use super::Device;
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
 
 pub mod registers {
+    /// `1` while an ASCII character is waiting in `DATA`.
     pub const STATUS: u16 = 0x00;
+    /// Reading pops the next ASCII character, `0` when none is waiting.
     pub const DATA: u16 = 0x01;
+    /// Reading pops the next scan-code event, `0` when none is waiting. Bit 7 is set for a
+    /// release, bits 0-6 hold the scan code.
+    pub const EVENT: u16 = 0x02;
+    /// Number of scan-code events waiting.
+    pub const EVENT_COUNT: u16 = 0x03;
+    /// Modifiers currently held, see the `MOD_*` constants.
+    pub const MODIFIERS: u16 = 0x04;
+    /// Writing runs a command, see the `CMD_*` constants.
+    pub const CMD: u16 = 0x05;
+    /// Entries each queue holds, `1`-`255`. Further keys are dropped until the guest reads.
+    pub const DEPTH: u16 = 0x06;
+    /// Interrupt sources, see the `CTRL_*` constants.
+    pub const CTRL: u16 = 0x07;
+    /// Held keys, one bit per scan code: bit `code % 8` of byte `MATRIX + code / 8`.
+    pub const MATRIX: u16 = 0x08;
+    pub const MATRIX_SIZE: u16 = 16;
+
+    /// Set in an event for a key release.
+    pub const EVENT_RELEASE: u8 = 0x80;
+
+    pub const MOD_SHIFT: u8 = 0x01;
+    pub const MOD_CTRL: u8 = 0x02;
+    pub const MOD_ALT: u8 = 0x04;
+    pub const MOD_META: u8 = 0x08;
+
+    /// Drop the waiting ASCII characters.
+    pub const CMD_CLEAR_ASCII: u8 = 0x01;
+    /// Drop the waiting scan-code events.
+    pub const CMD_CLEAR_EVENTS: u8 = 0x02;
+
+    /// Raise the keyboard IRQ when a character is typed. Set after reset.
+    pub const CTRL_ASCII_IRQ: u8 = 0x01;
+    /// Raise the keyboard IRQ on every scan-code event.
+    pub const CTRL_EVENT_IRQ: u8 = 0x02;
 }
 
-#[derive(Debug, Default)]
+/// Scan codes are USB HID keyboard usage IDs. The modifiers, `0xE0`-`0xE7` in HID, move to
+/// `0x70`-`0x77` to fit in 7 bits.
+pub mod scancodes {
+    /// `A` to `Z` are consecutive.
+    pub const A: u8 = 0x04;
+    /// `1` to `9` and then `0` are consecutive.
+    pub const KEY_1: u8 = 0x1E;
+    pub const KEY_0: u8 = 0x27;
+    pub const ENTER: u8 = 0x28;
+    pub const ESCAPE: u8 = 0x29;
+    pub const BACKSPACE: u8 = 0x2A;
+    pub const TAB: u8 = 0x2B;
+    pub const SPACE: u8 = 0x2C;
+    pub const MINUS: u8 = 0x2D;
+    pub const EQUAL: u8 = 0x2E;
+    pub const COMMA: u8 = 0x36;
+    pub const PERIOD: u8 = 0x37;
+    pub const SLASH: u8 = 0x38;
+    /// `F1` to `F12` are consecutive.
+    pub const F1: u8 = 0x3A;
+    pub const RIGHT: u8 = 0x4F;
+    pub const LEFT: u8 = 0x50;
+    pub const DOWN: u8 = 0x51;
+    pub const UP: u8 = 0x52;
+    pub const LEFT_CTRL: u8 = 0x70;
+    pub const LEFT_SHIFT: u8 = 0x71;
+    pub const LEFT_ALT: u8 = 0x72;
+    pub const LEFT_META: u8 = 0x73;
+    pub const RIGHT_CTRL: u8 = 0x74;
+    pub const RIGHT_SHIFT: u8 = 0x75;
+    pub const RIGHT_ALT: u8 = 0x76;
+    pub const RIGHT_META: u8 = 0x77;
+}
+
+/// Queue depth after reset.
+pub const DEFAULT_DEPTH: u8 = 32;
+
+#[derive(Debug)]
 pub struct Keyboard {
     queue: VecDeque<u8>,
+    events: VecDeque<u8>,
+    matrix: [u8; registers::MATRIX_SIZE as usize],
+    depth: u8,
+    ctrl: u8,
+    interrupt: bool,
+}
+
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            events: VecDeque::new(),
+            matrix: [0; registers::MATRIX_SIZE as usize],
+            depth: DEFAULT_DEPTH,
+            ctrl: registers::CTRL_ASCII_IRQ,
+            interrupt: false,
+        }
+    }
 }
 
 impl Keyboard {
+    /// Queue a typed ASCII character.
     pub fn key_pressed(&mut self, key: u8) {
+        if self.queue.len() >= usize::from(self.depth) {
+            return;
+        }
         self.queue.push_back(key);
+        if self.ctrl & registers::CTRL_ASCII_IRQ != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    /// Mark the key with scan code `code` as held and queue a press event. Repeats of a
+    /// held key are ignored.
+    pub fn key_down(&mut self, code: u8) {
+        let code = code & !registers::EVENT_RELEASE;
+        if self.is_held(code) {
+            return;
+        }
+        self.matrix[usize::from(code / 8)] |= 1 << (code % 8);
+        self.push_event(code);
+    }
+
+    /// Mark the key with scan code `code` as released and queue a release event.
+    pub fn key_up(&mut self, code: u8) {
+        let code = code & !registers::EVENT_RELEASE;
+        if !self.is_held(code) {
+            return;
+        }
+        self.matrix[usize::from(code / 8)] &= !(1 << (code % 8));
+        self.push_event(code | registers::EVENT_RELEASE);
+    }
+
+    /// Whether the key with scan code `code` is held.
+    #[must_use]
+    pub fn is_held(&self, code: u8) -> bool {
+        let code = code & !registers::EVENT_RELEASE;
+        self.matrix[usize::from(code / 8)] & (1 << (code % 8)) != 0
+    }
+
+    /// Mask of the held modifiers, see the `MOD_*` registers.
+    #[must_use]
+    pub fn modifiers(&self) -> u8 {
+        use scancodes::{
+            LEFT_ALT, LEFT_CTRL, LEFT_META, LEFT_SHIFT, RIGHT_ALT, RIGHT_CTRL, RIGHT_META,
+            RIGHT_SHIFT,
+        };
+
+        [
+            (LEFT_SHIFT, RIGHT_SHIFT, registers::MOD_SHIFT),
+            (LEFT_CTRL, RIGHT_CTRL, registers::MOD_CTRL),
+            (LEFT_ALT, RIGHT_ALT, registers::MOD_ALT),
+            (LEFT_META, RIGHT_META, registers::MOD_META),
+        ]
+        .into_iter()
+        .filter(|(left, right, _)| self.is_held(*left) || self.is_held(*right))
+        .fold(0, |mask, (_, _, bit)| mask | bit)
+    }
+
+    fn push_event(&mut self, event: u8) {
+        if self.events.len() >= usize::from(self.depth) {
+            return;
+        }
+        self.events.push_back(event);
+        if self.ctrl & registers::CTRL_EVENT_IRQ != 0 {
+            self.interrupt = true;
+        }
     }
 }
 
 impl Device for Keyboard {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        match addr {
+            registers::STATUS => Ok(!self.queue.is_empty() as u8),
+            registers::DATA => Ok(self.queue.pop_front().unwrap_or_default()),
+            registers::EVENT => Ok(self.events.pop_front().unwrap_or_default()),
+            // Never more than `depth` events
+            registers::EVENT_COUNT => Ok(self.events.len() as u8),
+            registers::MODIFIERS => Ok(self.modifiers()),
+            registers::CMD => Ok(0),
+            registers::DEPTH => Ok(self.depth),
+            registers::CTRL => Ok(self.ctrl),
+            addr if (registers::MATRIX..registers::MATRIX + registers::MATRIX_SIZE)
+                .contains(&addr) =>
+            {
+                Ok(self.matrix[usize::from(addr - registers::MATRIX)])
+            }
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
         match addr {
-            registers::STATUS => !self.queue.is_empty() as u8,
-            registers::DATA => self.queue.pop_front().unwrap_or_default(),
-            _ => unimplemented!(),
+            registers::CMD => {
+                if value & registers::CMD_CLEAR_ASCII != 0 {
+                    self.queue.clear();
+                }
+                if value & registers::CMD_CLEAR_EVENTS != 0 {
+                    self.events.clear();
+                }
+            }
+            registers::DEPTH if value == 0 => return Err(DeviceError::InvalidValue),
+            registers::DEPTH => {
+                self.depth = value;
+                self.queue.truncate(usize::from(value));
+                self.events.truncate(usize::from(value));
+            }
+            registers::CTRL => self.ctrl = value,
+            addr if addr < registers::MATRIX + registers::MATRIX_SIZE => {
+                return Err(DeviceError::ReadOnly)
+            }
+            _ => return Err(DeviceError::Unmapped),
         }
+        Ok(())
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+}
+
+impl Snapshot for Keyboard {
+    fn save(&self, writer: &mut Writer) {
+        let (front, back) = self.queue.as_slices();
+        writer.blob(&[front, back].concat());
+        let (front, back) = self.events.as_slices();
+        writer.blob(&[front, back].concat());
+        writer.bytes(&self.matrix);
+        writer.u8(self.depth);
+        writer.u8(self.ctrl);
+        writer.bool(self.interrupt);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.queue = reader.blob()?.iter().copied().collect();
+        self.events = reader.blob()?.iter().copied().collect();
+        reader.fill(&mut self.matrix)?;
+        self.depth = reader.u8()?;
+        self.ctrl = reader.u8()?;
+        self.interrupt = reader.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, scancodes::*, *};
+
+    #[test]
+    fn queues_press_and_release_events() {
+        let mut keyboard = Keyboard::default();
+        keyboard.key_down(LEFT);
+        keyboard.key_down(LEFT);
+        keyboard.key_down(LEFT_SHIFT);
+        assert!(keyboard.is_held(LEFT));
+        assert_eq!(keyboard.read(MODIFIERS), Ok(MOD_SHIFT));
+        assert_eq!(
+            keyboard.read(MATRIX + u16::from(LEFT / 8)),
+            Ok(1 << (LEFT % 8))
+        );
+        keyboard.key_up(LEFT);
+        keyboard.key_up(UP);
+
+        assert_eq!(keyboard.read(EVENT_COUNT), Ok(3));
+        assert_eq!(keyboard.read(EVENT), Ok(LEFT));
+        assert_eq!(keyboard.read(EVENT), Ok(LEFT_SHIFT));
+        assert_eq!(keyboard.read(EVENT), Ok(LEFT | EVENT_RELEASE));
+        assert_eq!(keyboard.read(EVENT), Ok(0));
+        assert!(!keyboard.is_held(LEFT));
+        // ASCII mode is untouched by scan codes
+        assert_eq!(keyboard.read(STATUS), Ok(0));
+        assert!(!keyboard.take_interrupt(), "event IRQ is off");
     }
 
-    fn write(&mut self, _addr: u16, _value: u8) {
-        unimplemented!()
+    #[test]
+    fn clears_queues_and_limits_depth() {
+        let mut keyboard = Keyboard::default();
+        keyboard.write(DEPTH, 2).unwrap();
+        for key in *b"abc" {
+            keyboard.key_pressed(key);
+        }
+        keyboard.key_down(A);
+        assert!(keyboard.take_interrupt());
+        assert_eq!(keyboard.read(DATA), Ok(b'a'));
+        assert_eq!(keyboard.read(DATA), Ok(b'b'));
+        assert_eq!(keyboard.read(DATA), Ok(0));
+
+        keyboard.key_pressed(b'd');
+        keyboard
+            .write(CMD, CMD_CLEAR_ASCII | CMD_CLEAR_EVENTS)
+            .unwrap();
+        assert_eq!(keyboard.read(STATUS), Ok(0));
+        assert_eq!(keyboard.read(EVENT_COUNT), Ok(0));
+        assert!(keyboard.is_held(A), "clearing keeps the matrix");
+
+        assert_eq!(keyboard.write(DEPTH, 0), Err(DeviceError::InvalidValue));
+        assert_eq!(keyboard.write(DATA, 0), Err(DeviceError::ReadOnly));
+        assert_eq!(keyboard.write(MATRIX, 0), Err(DeviceError::ReadOnly));
+        assert_eq!(
+            keyboard.read(MATRIX + MATRIX_SIZE),
+            Err(DeviceError::Unmapped)
+        );
     }
 }
@@ -1,13 +1,61 @@
+use std::{any::Any, fmt::Debug};
+
+use crate::snapshot::Snapshot;
+
 pub mod bus;
 pub mod disk;
+pub mod expansion;
 pub mod gpu;
+pub mod interrupts;
+pub mod joystick;
 pub mod keyboard;
 pub mod ram;
 pub mod rand;
 pub mod rom;
+pub mod sound;
+pub mod timer;
+pub mod uart;
 pub mod utils;
 
-pub trait Device {
-    fn read(&mut self, addr: u16) -> u8;
-    fn write(&mut self, addr: u16, value: u8);
+/// Reason a device refused an access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceError {
+    /// The device has nothing at the address.
+    Unmapped,
+    /// The address cannot be written.
+    ReadOnly,
+    /// The value is not valid for the register.
+    InvalidValue,
+}
+
+pub type DeviceResult<T = ()> = Result<T, DeviceError>;
+
+/// Peripheral the [`Bus`](bus::Bus) maps at an address range.
+///
+/// Devices save their state in machine snapshots, so they also implement [`Snapshot`].
+pub trait Device: Any + Debug + Snapshot {
+    /// Read a byte at an address relative to the device.
+    ///
+    /// # Errors
+    /// Returns an error if the device does not handle the address.
+    fn read(&mut self, addr: u16) -> DeviceResult<u8>;
+
+    /// Write a byte at an address relative to the device.
+    ///
+    /// # Errors
+    /// Returns an error if the device does not accept the write.
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult;
+
+    /// Read a byte without side effects, if the device supports it.
+    fn peek(&self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Advance the device by `cycles` CPU cycles.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Whether the device requested an interrupt since the last call.
+    fn take_interrupt(&mut self) -> bool {
+        false
+    }
 }
@@ -1,6 +1,8 @@
 use mb8_isa::RAM_SIZE;
 
-use super::{utils::empty_memory, Device};
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{utils::empty_memory, Device, DeviceResult};
 
 #[derive(Debug)]
 pub struct RAM {
@@ -16,11 +18,26 @@ impl Default for RAM {
 }
 
 impl Device for RAM {
-    fn read(&mut self, addr: u16) -> u8 {
-        self.data[addr as usize]
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        Ok(self.data[addr as usize])
     }
 
-    fn write(&mut self, addr: u16, value: u8) {
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
         self.data[addr as usize] = value;
+        Ok(())
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.data[addr as usize])
+    }
+}
+
+impl Snapshot for RAM {
+    fn save(&self, writer: &mut Writer) {
+        writer.bytes(self.data.as_slice());
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        reader.fill(self.data.as_mut_slice())
     }
 }
@@ -1,4 +1,6 @@
-use super::Device;
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
 
 pub mod registers {
     pub const DATA: u16 = 0x00;
@@ -35,12 +37,25 @@ impl Rand {
 }
 
 impl Device for Rand {
-    fn read(&mut self, addr: u16) -> u8 {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
         match addr {
-            registers::DATA => self.rand_gen(),
-            _ => unimplemented!(),
+            registers::DATA => Ok(self.rand_gen()),
+            _ => Err(DeviceError::Unmapped),
         }
     }
 
-    fn write(&mut self, _addr: u16, _value: u8) {}
+    fn write(&mut self, _addr: u16, _value: u8) -> DeviceResult {
+        Ok(())
+    }
+}
+
+impl Snapshot for Rand {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.number);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.number = reader.u8()?;
+        Ok(())
+    }
 }
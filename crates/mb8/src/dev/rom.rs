@@ -1,26 +1,112 @@
 use mb8_isa::ROM_SIZE;
 
-use super::{utils::empty_memory, Device};
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
 
+use super::{utils::empty_memory, Device, DeviceError, DeviceResult};
+
+/// What happens to writes while the ROM is locked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WritePolicy {
+    /// Refuse the write, which raises a [`FaultKind::RomWrite`](crate::fault::FaultKind::RomWrite).
+    #[default]
+    Fault,
+    /// Drop the write silently.
+    Ignore,
+}
+
+/// Program memory. It starts locked: the bus cannot write it until it is unlocked.
 #[derive(Debug)]
 pub struct ROM {
     data: Box<[u8; ROM_SIZE]>,
+    locked: bool,
+    /// Applied to writes while locked. A host setting, not saved in snapshots.
+    pub write_policy: WritePolicy,
 }
 
 impl Default for ROM {
     fn default() -> Self {
         Self {
             data: empty_memory::<ROM_SIZE>(),
+            locked: true,
+            write_policy: WritePolicy::default(),
         }
     }
 }
 
+impl ROM {
+    /// Replace the ROM contents, locked or not. Bytes past the end of the ROM are dropped.
+    pub fn load(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(ROM_SIZE);
+        self.data[..len].copy_from_slice(&bytes[..len]);
+    }
+
+    /// Refuse bus writes according to [`ROM::write_policy`].
+    pub fn lock(&mut self) {
+        self.locked = true;
+    }
+
+    /// Let bus writes through, for example to a boot loader that fills the ROM.
+    pub fn unlock(&mut self) {
+        self.locked = false;
+    }
+
+    #[must_use]
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+}
+
 impl Device for ROM {
-    fn read(&mut self, addr: u16) -> u8 {
-        self.data[addr as usize]
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        Ok(self.data[addr as usize])
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        match (self.locked, self.write_policy) {
+            (false, _) => {
+                self.data[addr as usize] = value;
+                Ok(())
+            }
+            (true, WritePolicy::Fault) => Err(DeviceError::ReadOnly),
+            (true, WritePolicy::Ignore) => Ok(()),
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        Some(self.data[addr as usize])
+    }
+}
+
+impl Snapshot for ROM {
+    fn save(&self, writer: &mut Writer) {
+        writer.bytes(self.data.as_slice());
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        reader.fill(self.data.as_mut_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_write_policy_while_locked() {
+        let mut rom = ROM::default();
+        assert_eq!(rom.write(0x10, 0x42), Err(DeviceError::ReadOnly));
+        rom.write_policy = WritePolicy::Ignore;
+        assert_eq!(rom.write(0x10, 0x42), Ok(()));
+        assert_eq!(rom.read(0x10), Ok(0));
     }
 
-    fn write(&mut self, addr: u16, value: u8) {
-        self.data[addr as usize] = value;
+    #[test]
+    fn accepts_writes_while_unlocked() {
+        let mut rom = ROM::default();
+        rom.unlock();
+        rom.write(0x10, 0x42).unwrap();
+        rom.lock();
+        assert_eq!(rom.write(0x10, 0x43), Err(DeviceError::ReadOnly));
+        assert_eq!(rom.read(0x10), Ok(0x42));
     }
 }
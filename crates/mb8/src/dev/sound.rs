@@ -0,0 +1,314 @@
+//! Programmable sound generator.
+//! Three square wave channels and a noise channel, each with a frequency, a volume and a decay
+//! envelope. Envelopes are clocked by CPU cycles. While a host capture is running, the channels
+//! are also synthesised into 16-bit mono PCM samples, which [`write_wav`] stores as a WAV file.
+
+use std::io::{self, Write};
+
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+pub mod registers {
+    pub const CHANNELS: usize = 4;
+    /// The last channel plays noise instead of a square wave.
+    pub const NOISE_CHANNEL: usize = 3;
+    /// Registers per channel; channel `n` starts at `n * CHANNEL_REGS`.
+    pub const CHANNEL_REGS: u16 = 4;
+
+    /// Frequency in Hz, high byte. For the noise channel, the rate of the noise generator.
+    pub const FREQ_HI: u16 = 0x00;
+    pub const FREQ_LO: u16 = 0x01;
+    /// Volume from `0` to `MAX_VOLUME`. Writing restarts the envelope, reading returns the
+    /// current, decayed volume.
+    pub const VOLUME: u16 = 0x02;
+    /// Decay rate: the volume drops by one every `ENVELOPE * ENVELOPE_CYCLES` cycles. `0` holds
+    /// the volume.
+    pub const ENVELOPE: u16 = 0x03;
+
+    pub const MAX_VOLUME: u8 = 15;
+}
+
+/// Cycles per envelope step at `ENVELOPE = 1`, a millisecond at the default clock.
+pub const ENVELOPE_CYCLES: u64 = 1_000;
+/// Sample rate used by the frontends for captures.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+/// Amplitude of one channel at full volume, so that all channels together fit in an `i16`.
+const AMPLITUDE: i16 = i16::MAX / (registers::CHANNELS as i16 * registers::MAX_VOLUME as i16);
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Channel {
+    freq: u16,
+    volume: u8,
+    envelope: u8,
+    /// Cycles since the last envelope step.
+    cycles: u64,
+}
+
+impl Channel {
+    fn tick(&mut self, cycles: u64) {
+        if self.envelope == 0 || self.volume == 0 {
+            return;
+        }
+        let period = u64::from(self.envelope) * ENVELOPE_CYCLES;
+        self.cycles += cycles;
+        while self.cycles >= period && self.volume > 0 {
+            self.cycles -= period;
+            self.volume -= 1;
+        }
+    }
+}
+
+/// Host-side synthesis state of a capture.
+#[derive(Debug)]
+struct Capture {
+    clock_hz: u32,
+    sample_rate: u32,
+    /// CPU cycles times the sample rate not yet turned into a sample.
+    cycles: u64,
+    /// Position within the current wave period, in `1 / sample_rate` steps of the frequency.
+    phases: [u32; registers::CHANNELS],
+    lfsr: u16,
+    samples: Vec<i16>,
+}
+
+impl Capture {
+    fn sample(&mut self, channels: &[Channel; registers::CHANNELS]) -> i16 {
+        let mut sample = 0;
+        for (index, channel) in channels.iter().enumerate() {
+            if channel.freq == 0 || channel.volume == 0 {
+                continue;
+            }
+            let phase = &mut self.phases[index];
+            *phase += u32::from(channel.freq);
+            let wrapped = *phase >= self.sample_rate;
+            *phase %= self.sample_rate;
+
+            let high = if index == registers::NOISE_CHANNEL {
+                if wrapped {
+                    // 15-bit white noise generator
+                    let bit = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+                    self.lfsr = (self.lfsr >> 1) | (bit << 14);
+                }
+                self.lfsr & 1 == 1
+            } else {
+                *phase * 2 < self.sample_rate
+            };
+            let level = AMPLITUDE * i16::from(channel.volume);
+            sample += if high { level } else { -level };
+        }
+        sample
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Sound {
+    channels: [Channel; registers::CHANNELS],
+    capture: Option<Capture>,
+}
+
+impl Sound {
+    /// Start synthesising samples at `sample_rate` for a CPU running at `clock_hz`.
+    /// Any samples of a previous capture are dropped.
+    ///
+    /// # Panics
+    /// Panics if `clock_hz` or `sample_rate` is `0`.
+    pub fn capture(&mut self, clock_hz: u32, sample_rate: u32) {
+        assert!(
+            clock_hz > 0 && sample_rate > 0,
+            "capture needs a non-zero clock and sample rate"
+        );
+        self.capture = Some(Capture {
+            clock_hz,
+            sample_rate,
+            cycles: 0,
+            phases: [0; registers::CHANNELS],
+            lfsr: 1,
+            samples: Vec::new(),
+        });
+    }
+
+    /// Stop capturing and return the remaining samples.
+    pub fn stop_capture(&mut self) -> Vec<i16> {
+        self.capture
+            .take()
+            .map(|capture| capture.samples)
+            .unwrap_or_default()
+    }
+
+    /// Samples synthesised since the last call.
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.capture
+            .as_mut()
+            .map(|capture| std::mem::take(&mut capture.samples))
+            .unwrap_or_default()
+    }
+
+    fn channel(addr: u16) -> Option<(usize, u16)> {
+        let index = usize::from(addr / registers::CHANNEL_REGS);
+        (index < registers::CHANNELS).then_some((index, addr % registers::CHANNEL_REGS))
+    }
+}
+
+impl Device for Sound {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        let (index, reg) = Self::channel(addr).ok_or(DeviceError::Unmapped)?;
+        let channel = &self.channels[index];
+        Ok(match reg {
+            registers::FREQ_HI => channel.freq.to_be_bytes()[0],
+            registers::FREQ_LO => channel.freq.to_be_bytes()[1],
+            registers::VOLUME => channel.volume,
+            _ => channel.envelope,
+        })
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        let (index, reg) = Self::channel(addr).ok_or(DeviceError::Unmapped)?;
+        let channel = &mut self.channels[index];
+        match reg {
+            registers::FREQ_HI => {
+                channel.freq = u16::from_be_bytes([value, channel.freq.to_be_bytes()[1]]);
+            }
+            registers::FREQ_LO => {
+                channel.freq = u16::from_be_bytes([channel.freq.to_be_bytes()[0], value]);
+            }
+            registers::VOLUME if value <= registers::MAX_VOLUME => {
+                channel.volume = value;
+                channel.cycles = 0;
+            }
+            registers::VOLUME => return Err(DeviceError::InvalidValue),
+            _ => channel.envelope = value,
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for channel in &mut self.channels {
+            channel.tick(cycles);
+        }
+        if let Some(capture) = &mut self.capture {
+            capture.cycles += cycles * u64::from(capture.sample_rate);
+            while capture.cycles >= u64::from(capture.clock_hz) {
+                capture.cycles -= u64::from(capture.clock_hz);
+                let sample = capture.sample(&self.channels);
+                capture.samples.push(sample);
+            }
+        }
+    }
+}
+
+impl Snapshot for Sound {
+    fn save(&self, writer: &mut Writer) {
+        for channel in &self.channels {
+            writer.u16(channel.freq);
+            writer.u8(channel.volume);
+            writer.u8(channel.envelope);
+            writer.u64(channel.cycles);
+        }
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        for channel in &mut self.channels {
+            channel.freq = reader.u16()?;
+            channel.volume = reader.u8()?;
+            channel.envelope = reader.u8()?;
+            channel.cycles = reader.u64()?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `samples` as a 16-bit mono PCM WAV file.
+///
+/// # Errors
+/// Returns an error if writing fails or the samples do not fit in a WAV file.
+pub fn write_wav(out: &mut impl Write, sample_rate: u32, samples: &[i16]) -> io::Result<()> {
+    let data_len = u32::try_from(samples.len() * 2)
+        .ok()
+        .filter(|len| *len <= u32::MAX - 36)
+        .ok_or_else(|| io::Error::other("too many samples for a WAV file"))?;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36 + data_len).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    // PCM, one channel
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&1u16.to_le_bytes())?;
+    out.write_all(&sample_rate.to_le_bytes())?;
+    out.write_all(&(sample_rate * 2).to_le_bytes())?;
+    out.write_all(&2u16.to_le_bytes())?;
+    out.write_all(&16u16.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&data_len.to_le_bytes())?;
+    for sample in samples {
+        out.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    fn tone(sound: &mut Sound, channel: u16, freq: u16, volume: u8, envelope: u8) {
+        let base = channel * CHANNEL_REGS;
+        let [hi, lo] = freq.to_be_bytes();
+        sound.write(base + FREQ_HI, hi).unwrap();
+        sound.write(base + FREQ_LO, lo).unwrap();
+        sound.write(base + ENVELOPE, envelope).unwrap();
+        sound.write(base + VOLUME, volume).unwrap();
+    }
+
+    #[test]
+    fn decays_volume_with_cycles() {
+        let mut sound = Sound::default();
+        tone(&mut sound, 1, 440, 10, 2);
+        sound.tick(2 * ENVELOPE_CYCLES - 1);
+        assert_eq!(sound.read(CHANNEL_REGS + VOLUME), Ok(10));
+        sound.tick(1 + 4 * ENVELOPE_CYCLES);
+        assert_eq!(sound.read(CHANNEL_REGS + VOLUME), Ok(7));
+        sound.tick(100 * ENVELOPE_CYCLES);
+        assert_eq!(sound.read(CHANNEL_REGS + VOLUME), Ok(0));
+        assert_eq!(sound.write(VOLUME, 16), Err(DeviceError::InvalidValue));
+        assert_eq!(sound.read(0x10), Err(DeviceError::Unmapped));
+    }
+
+    #[test]
+    fn synthesises_square_wave() {
+        let mut sound = Sound::default();
+        sound.capture(1_000_000, 8_000);
+        tone(&mut sound, 0, 1_000, MAX_VOLUME, 0);
+        // One millisecond is one period, 8 samples
+        sound.tick(1_000);
+        let level = AMPLITUDE * i16::from(MAX_VOLUME);
+        assert_eq!(
+            sound.take_samples(),
+            [level, level, level, -level, -level, -level, -level, level]
+        );
+        assert!(sound.take_samples().is_empty());
+
+        tone(&mut sound, 0, 1_000, 0, 0);
+        sound.tick(250);
+        assert_eq!(sound.stop_capture(), [0, 0]);
+        sound.tick(1_000);
+        assert!(sound.take_samples().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "non-zero clock")]
+    fn rejects_zero_clock() {
+        Sound::default().capture(0, 8_000);
+    }
+
+    #[test]
+    fn writes_wav_header() {
+        let mut wav = Vec::new();
+        write_wav(&mut wav, 8_000, &[1, -2]).unwrap();
+        assert_eq!(wav.len(), 44 + 4);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &8_000u32.to_le_bytes());
+        assert_eq!(&wav[40..], &[4, 0, 0, 0, 1, 0, 0xFE, 0xFF]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+pub mod registers {
+    /// Control bits, see the `CTRL_*` constants.
+    pub const CTRL: u16 = 0x00;
+    /// Status bits, see the `STATUS_*` constants. Writing `1` to a bit clears it.
+    pub const STATUS: u16 = 0x01;
+    /// The timer ticks once every `PRESCALER + 1` cycles.
+    pub const PRESCALER: u16 = 0x02;
+    /// Value loaded into the counter when the timer starts or restarts, high byte.
+    pub const RELOAD_HI: u16 = 0x03;
+    pub const RELOAD_LO: u16 = 0x04;
+    /// Remaining ticks until expiry. Reading the high byte latches the low byte.
+    pub const COUNTER_HI: u16 = 0x05;
+    pub const COUNTER_LO: u16 = 0x06;
+    /// Free-running tick counter. Reading the high byte latches the low byte.
+    pub const TICKS_HI: u16 = 0x07;
+    pub const TICKS_LO: u16 = 0x08;
+
+    /// Count down the counter.
+    pub const CTRL_ENABLE: u8 = 0b0000_0001;
+    /// Restart from the reload value on expiry instead of stopping.
+    pub const CTRL_PERIODIC: u8 = 0b0000_0010;
+    /// Raise an interrupt on expiry.
+    pub const CTRL_IRQ: u8 = 0b0000_0100;
+
+    /// The counter expired.
+    pub const STATUS_EXPIRED: u8 = 0b0000_0001;
+}
+
+/// Programmable interval timer, driven by CPU cycles.
+#[derive(Debug, Default)]
+pub struct Timer {
+    ctrl: u8,
+    status: u8,
+    prescaler: u8,
+    reload: u16,
+    counter: u16,
+    ticks: u16,
+    /// Cycles since the last tick.
+    cycles: u64,
+    /// Low byte captured when a high byte was read.
+    latch: u8,
+    interrupt: bool,
+}
+
+impl Timer {
+    fn tick_once(&mut self) {
+        self.ticks = self.ticks.wrapping_add(1);
+        if self.ctrl & registers::CTRL_ENABLE == 0 {
+            return;
+        }
+        if self.counter > 1 {
+            self.counter -= 1;
+            return;
+        }
+        self.status |= registers::STATUS_EXPIRED;
+        if self.ctrl & registers::CTRL_IRQ != 0 {
+            self.interrupt = true;
+        }
+        if self.ctrl & registers::CTRL_PERIODIC != 0 {
+            self.counter = self.reload;
+        } else {
+            self.counter = 0;
+            self.ctrl &= !registers::CTRL_ENABLE;
+        }
+    }
+
+    fn read_latched(&mut self, value: u16) -> u8 {
+        let [hi, lo] = value.to_be_bytes();
+        self.latch = lo;
+        hi
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        let [reload_hi, reload_lo] = self.reload.to_be_bytes();
+        match addr {
+            registers::CTRL => Ok(self.ctrl),
+            registers::STATUS => Ok(self.status),
+            registers::PRESCALER => Ok(self.prescaler),
+            registers::RELOAD_HI => Ok(reload_hi),
+            registers::RELOAD_LO => Ok(reload_lo),
+            registers::COUNTER_HI => Ok(self.read_latched(self.counter)),
+            registers::TICKS_HI => Ok(self.read_latched(self.ticks)),
+            registers::COUNTER_LO | registers::TICKS_LO => Ok(self.latch),
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        let [reload_hi, reload_lo] = self.reload.to_be_bytes();
+        match addr {
+            registers::CTRL => {
+                let starting =
+                    self.ctrl & registers::CTRL_ENABLE == 0 && value & registers::CTRL_ENABLE != 0;
+                if starting {
+                    self.counter = self.reload;
+                    self.cycles = 0;
+                }
+                self.ctrl = value;
+            }
+            registers::STATUS => self.status &= !value,
+            registers::PRESCALER => self.prescaler = value,
+            registers::RELOAD_HI => self.reload = u16::from_be_bytes([value, reload_lo]),
+            registers::RELOAD_LO => self.reload = u16::from_be_bytes([reload_hi, value]),
+            registers::COUNTER_HI
+            | registers::COUNTER_LO
+            | registers::TICKS_HI
+            | registers::TICKS_LO => return Err(DeviceError::ReadOnly),
+            _ => return Err(DeviceError::Unmapped),
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let period = u64::from(self.prescaler) + 1;
+        self.cycles += cycles;
+        while self.cycles >= period {
+            self.cycles -= period;
+            self.tick_once();
+        }
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+}
+
+impl Snapshot for Timer {
+    fn save(&self, writer: &mut Writer) {
+        writer.u8(self.ctrl);
+        writer.u8(self.status);
+        writer.u8(self.prescaler);
+        writer.u16(self.reload);
+        writer.u16(self.counter);
+        writer.u16(self.ticks);
+        writer.u64(self.cycles);
+        writer.u8(self.latch);
+        writer.bool(self.interrupt);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.ctrl = reader.u8()?;
+        self.status = reader.u8()?;
+        self.prescaler = reader.u8()?;
+        self.reload = reader.u16()?;
+        self.counter = reader.u16()?;
+        self.ticks = reader.u16()?;
+        self.cycles = reader.u64()?;
+        self.latch = reader.u8()?;
+        self.interrupt = reader.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    fn timer(prescaler: u8, reload: u16, ctrl: u8) -> Timer {
+        let mut timer = Timer::default();
+        let [hi, lo] = reload.to_be_bytes();
+        timer.write(PRESCALER, prescaler).unwrap();
+        timer.write(RELOAD_HI, hi).unwrap();
+        timer.write(RELOAD_LO, lo).unwrap();
+        timer.write(CTRL, ctrl).unwrap();
+        timer
+    }
+
+    #[test]
+    fn counts_free_running_ticks() {
+        let mut timer = timer(3, 0, 0);
+        timer.tick(10);
+        assert_eq!(timer.read(TICKS_HI), Ok(0));
+        assert_eq!(timer.read(TICKS_LO), Ok(2));
+        timer.tick(2);
+        assert_eq!(timer.read(TICKS_LO), Ok(2), "low byte stays latched");
+        timer.read(TICKS_HI).unwrap();
+        assert_eq!(timer.read(TICKS_LO), Ok(3));
+    }
+
+    #[test]
+    fn stops_after_one_shot() {
+        let mut timer = timer(0, 5, CTRL_ENABLE | CTRL_IRQ);
+        timer.tick(4);
+        assert_eq!(timer.read(STATUS), Ok(0));
+        timer.tick(1);
+        assert_eq!(timer.read(STATUS), Ok(STATUS_EXPIRED));
+        assert_eq!(timer.read(CTRL), Ok(CTRL_IRQ));
+        assert!(timer.take_interrupt());
+        assert!(!timer.take_interrupt());
+
+        timer.write(STATUS, STATUS_EXPIRED).unwrap();
+        timer.tick(100);
+        assert_eq!(timer.read(STATUS), Ok(0));
+    }
+
+    #[test]
+    fn reloads_when_periodic() {
+        let mut timer = timer(1, 3, CTRL_ENABLE | CTRL_PERIODIC);
+        timer.tick(6);
+        assert_eq!(timer.read(STATUS), Ok(STATUS_EXPIRED));
+        assert!(!timer.take_interrupt());
+        timer.read(COUNTER_HI).unwrap();
+        assert_eq!(timer.read(COUNTER_LO), Ok(3));
+        timer.tick(2);
+        timer.read(COUNTER_HI).unwrap();
+        assert_eq!(timer.read(COUNTER_LO), Ok(2));
+    }
+}
@@ -0,0 +1,185 @@
+//! Serial port.
+//! A byte-wide data register backed by a receive FIFO and a transmit buffer. The host side lives
+//! in [`serial`](crate::serial).
+
+use std::collections::VecDeque;
+
+use crate::snapshot::{Reader, Snapshot, SnapshotResult, Writer};
+
+use super::{Device, DeviceError, DeviceResult};
+
+pub mod registers {
+    /// Reading pops the next received byte, `0` when none is waiting. Writing sends a byte.
+    pub const DATA: u16 = 0x00;
+    /// Status bits, see the `STATUS_*` constants. Writing `1` to `STATUS_OVERRUN` clears it.
+    pub const STATUS: u16 = 0x01;
+    /// Control bits, see the `CTRL_*` constants.
+    pub const CTRL: u16 = 0x02;
+
+    /// A received byte is waiting in `DATA`.
+    pub const STATUS_RX_READY: u8 = 0b0000_0001;
+    /// The transmit buffer has room for another byte.
+    pub const STATUS_TX_READY: u8 = 0b0000_0010;
+    /// A byte arrived while the receive FIFO was full and was lost.
+    pub const STATUS_OVERRUN: u8 = 0b0000_0100;
+
+    /// Raise an interrupt when a byte arrives.
+    pub const CTRL_RX_IRQ: u8 = 0b0000_0001;
+}
+
+/// Bytes the receive FIFO holds.
+pub const RX_FIFO_SIZE: usize = 16;
+/// Bytes the transmit buffer holds until the host collects them.
+pub const TX_BUFFER_SIZE: usize = 256;
+
+/// Serial port. The host feeds received bytes with [`Uart::receive`] and collects sent bytes
+/// with [`Uart::take_output`], see [`SerialBridge`](crate::serial::SerialBridge).
+#[derive(Debug, Default)]
+pub struct Uart {
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+    ctrl: u8,
+    overrun: bool,
+    interrupt: bool,
+}
+
+impl Uart {
+    /// Whether the receive FIFO has room for another byte.
+    #[must_use]
+    pub fn can_receive(&self) -> bool {
+        self.rx.len() < RX_FIFO_SIZE
+    }
+
+    /// Queue a byte from the host. When the FIFO is full the byte is lost and the overrun
+    /// flag is set.
+    pub fn receive(&mut self, byte: u8) {
+        if !self.can_receive() {
+            self.overrun = true;
+            return;
+        }
+        self.rx.push_back(byte);
+        if self.ctrl & registers::CTRL_RX_IRQ != 0 {
+            self.interrupt = true;
+        }
+    }
+
+    /// Bytes sent by the guest since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.tx)
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if !self.rx.is_empty() {
+            status |= registers::STATUS_RX_READY;
+        }
+        if self.tx.len() < TX_BUFFER_SIZE {
+            status |= registers::STATUS_TX_READY;
+        }
+        if self.overrun {
+            status |= registers::STATUS_OVERRUN;
+        }
+        status
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, addr: u16) -> DeviceResult<u8> {
+        match addr {
+            registers::DATA => Ok(self.rx.pop_front().unwrap_or_default()),
+            registers::STATUS => Ok(self.status()),
+            registers::CTRL => Ok(self.ctrl),
+            _ => Err(DeviceError::Unmapped),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) -> DeviceResult {
+        match addr {
+            // Bytes sent while the buffer is full are dropped, like on a busy line
+            registers::DATA if self.tx.len() < TX_BUFFER_SIZE => self.tx.push(value),
+            registers::DATA => {}
+            registers::STATUS => {
+                if value & registers::STATUS_OVERRUN != 0 {
+                    self.overrun = false;
+                }
+            }
+            registers::CTRL => self.ctrl = value,
+            _ => return Err(DeviceError::Unmapped),
+        }
+        Ok(())
+    }
+
+    fn take_interrupt(&mut self) -> bool {
+        std::mem::take(&mut self.interrupt)
+    }
+}
+
+impl Snapshot for Uart {
+    fn save(&self, writer: &mut Writer) {
+        let (front, back) = self.rx.as_slices();
+        writer.blob(&[front, back].concat());
+        writer.blob(&self.tx);
+        writer.u8(self.ctrl);
+        writer.bool(self.overrun);
+        writer.bool(self.interrupt);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.rx = reader.blob()?.iter().copied().collect();
+        self.tx = reader.blob()?.to_vec();
+        self.ctrl = reader.u8()?;
+        self.overrun = reader.bool()?;
+        self.interrupt = reader.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registers::*, *};
+
+    #[test]
+    fn receives_into_fifo() {
+        let mut uart = Uart::default();
+        assert_eq!(uart.read(STATUS), Ok(STATUS_TX_READY));
+        uart.receive(b'a');
+        assert!(!uart.take_interrupt(), "RX interrupt is off");
+        uart.write(CTRL, CTRL_RX_IRQ).unwrap();
+        uart.receive(b'b');
+        assert!(uart.take_interrupt());
+        assert_eq!(uart.read(STATUS), Ok(STATUS_RX_READY | STATUS_TX_READY));
+        assert_eq!(uart.read(DATA), Ok(b'a'));
+        assert_eq!(uart.read(DATA), Ok(b'b'));
+        assert_eq!(uart.read(DATA), Ok(0));
+    }
+
+    #[test]
+    fn flags_overrun() {
+        let mut uart = Uart::default();
+        // One byte more than fits, the last one is dropped
+        for byte in 1..=RX_FIFO_SIZE as u8 + 1 {
+            uart.receive(byte);
+        }
+        assert!(!uart.can_receive());
+        assert_eq!(uart.read(STATUS).unwrap() & STATUS_OVERRUN, STATUS_OVERRUN);
+        uart.write(STATUS, STATUS_OVERRUN).unwrap();
+        assert_eq!(uart.read(STATUS).unwrap() & STATUS_OVERRUN, 0);
+        for byte in 1..=RX_FIFO_SIZE as u8 {
+            assert_eq!(uart.read(DATA), Ok(byte));
+        }
+        assert_eq!(uart.read(STATUS).unwrap() & STATUS_RX_READY, 0);
+        assert_eq!(uart.read(DATA), Ok(0));
+    }
+
+    #[test]
+    fn buffers_output() {
+        let mut uart = Uart::default();
+        for _ in 0..=TX_BUFFER_SIZE {
+            uart.write(DATA, b'x').unwrap();
+        }
+        assert_eq!(uart.read(STATUS).unwrap() & STATUS_TX_READY, 0);
+        assert_eq!(uart.take_output().len(), TX_BUFFER_SIZE);
+        assert_eq!(uart.read(STATUS), Ok(STATUS_TX_READY));
+        assert_eq!(uart.read(0x03), Err(DeviceError::Unmapped));
+    }
+}
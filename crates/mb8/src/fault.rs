@@ -0,0 +1,56 @@
+use std::fmt::{self, Display};
+
+use crate::mpu::Access;
+
+/// Reason the CPU stopped executing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// `HALT` was executed.
+    Halt,
+    /// The instruction word does not decode to any opcode.
+    InvalidOpcode { word: u16 },
+    /// Access to an address no device handles, or a device rejected the access.
+    BusError { addr: u16 },
+    /// The stack grew past its bottom.
+    StackOverflow,
+    /// More bytes were popped than pushed.
+    StackUnderflow,
+    /// Write into the ROM area.
+    RomWrite { addr: u16 },
+    /// `SYS` with a number no syscall handler implements.
+    UnknownSyscall { number: u8 },
+    /// User code made an access the memory protection unit does not allow.
+    ProtectionFault { addr: u16, access: Access },
+}
+
+impl Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::Halt => write!(f, "halted"),
+            FaultKind::InvalidOpcode { word } => write!(f, "invalid opcode 0x{word:04X}"),
+            FaultKind::BusError { addr } => write!(f, "bus error at 0x{addr:04X}"),
+            FaultKind::StackOverflow => write!(f, "stack overflow"),
+            FaultKind::StackUnderflow => write!(f, "stack underflow"),
+            FaultKind::RomWrite { addr } => write!(f, "write to ROM at 0x{addr:04X}"),
+            FaultKind::UnknownSyscall { number } => write!(f, "unknown syscall 0x{number:02X}"),
+            FaultKind::ProtectionFault { addr, access } => {
+                write!(f, "user {access} at 0x{addr:04X} not allowed")
+            }
+        }
+    }
+}
+
+/// CPU fault together with the address of the instruction that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub pc: u16,
+    pub kind: FaultKind,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at PC 0x{:04X}", self.kind, self.pc)
+    }
+}
+
+impl std::error::Error for Fault {}
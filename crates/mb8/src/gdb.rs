@@ -0,0 +1,676 @@
+//! GDB remote serial protocol stub.
+//! [`GdbStub`] lets GDB or any other RSP client control a [`VirtualMachine`] over a
+//! [`Connection`], e.g. a local TCP socket. Register numbers are `0..=15` for `R0`–`R15`,
+//! `16` for the stack pointer (`SPH:SPL`) and `17` for the PC; the 8-bit registers are sent
+//! as one byte and `SP` and the PC as two big-endian bytes.
+//!
+//! Reverse stepping (`bs`, `bc`) works while the VM records its [`History`](crate::history::History).
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use mb8_isa::registers::Register;
+
+use crate::{
+    debug::{Breakpoint, Debugger, Stop, Watch, Watchpoint},
+    fault::{Fault, FaultKind},
+    vm::VirtualMachine,
+};
+
+/// Register number of the stack pointer.
+pub const SP_REGISTER: usize = 16;
+/// Register number of the PC.
+pub const PC_REGISTER: usize = 17;
+
+/// Instructions run between checks for an interrupt from the client.
+const RUN_CHUNK: u64 = 10_000;
+/// Largest packet the stub accepts, advertised in `qSupported`.
+const PACKET_SIZE: usize = 0x1000;
+
+/// Byte stream to an RSP client.
+pub trait Connection: Read + Write {
+    /// Whether the client sent an interrupt (`0x03`) while the VM runs.
+    ///
+    /// # Errors
+    /// Returns an error if checking the connection fails.
+    fn interrupted(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> io::Result<bool> {
+        let mut byte = [0];
+        self.set_nonblocking(true)?;
+        let peeked = self.peek(&mut byte);
+        self.set_nonblocking(false)?;
+        match peeked {
+            Ok(1) if byte[0] == 0x03 => self.read_exact(&mut byte).map(|()| true),
+            Ok(_) => Ok(false),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Connection made of separate input and output streams, such as stdin and stdout.
+///
+/// The client cannot interrupt a running VM over a pipe.
+#[derive(Debug)]
+pub struct Pipe<R, W> {
+    pub input: R,
+    pub output: W,
+}
+
+impl<R: Read, W> Read for Pipe<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl<R, W: Write> Write for Pipe<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<R: Read, W: Write> Connection for Pipe<R, W> {}
+
+/// What the stub does after handling a packet.
+enum Reply {
+    Packet(String),
+    /// End the session without replying.
+    Close,
+}
+
+/// RSP server for a single client.
+#[derive(Debug)]
+pub struct GdbStub<C> {
+    connection: C,
+    debugger: Debugger,
+    /// Ids of the breakpoints and watchpoints set by the client, by `(type, address)`.
+    points: HashMap<(u8, u16), usize>,
+    /// Whether packets are acknowledged, until the client asks for `QStartNoAckMode`.
+    ack: bool,
+    last_packet: Vec<u8>,
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(connection: C) -> Self {
+        Self {
+            connection,
+            debugger: Debugger::default(),
+            points: HashMap::new(),
+            ack: true,
+            last_packet: Vec::new(),
+        }
+    }
+
+    /// Serve the client until it detaches, kills the VM or closes the connection.
+    ///
+    /// # Errors
+    /// Returns an error if the connection fails.
+    pub fn serve(&mut self, vm: &mut VirtualMachine) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match self.handle(vm, &packet)? {
+                Reply::Packet(reply) => self.send(&reply)?,
+                Reply::Close => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the next packet, acknowledging it. Returns `None` once the connection is closed.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            match byte {
+                b'$' => {}
+                b'-' => {
+                    self.connection.write_all(&self.last_packet)?;
+                    self.connection.flush()?;
+                    continue;
+                }
+                // Acks and interrupts while the VM is stopped need no answer
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.connection.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(checksum_of(&data));
+            if self.ack {
+                self.connection.write_all(if valid { b"+" } else { b"-" })?;
+                self.connection.flush()?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.connection.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${data}#{:02x}", checksum_of(data.as_bytes()));
+        self.connection.write_all(packet.as_bytes())?;
+        self.connection.flush()?;
+        self.last_packet = packet.into_bytes();
+        Ok(())
+    }
+
+    fn handle(&mut self, vm: &mut VirtualMachine, packet: &str) -> io::Result<Reply> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => read_registers(vm),
+            "G" => ok_or_error(write_registers(vm, args)),
+            "p" => parse_hex(args)
+                .and_then(|register| read_register(vm, register))
+                .unwrap_or_else(error),
+            "P" => ok_or_error(write_register(vm, args)),
+            "m" => read_memory(vm, args).unwrap_or_else(error),
+            "M" => ok_or_error(write_memory(vm, args)),
+            "s" | "c" => {
+                if let Some(addr) = parse_hex(args) {
+                    vm.program_counter = addr;
+                }
+                let stop = if command == "s" {
+                    Some(self.debugger.step_into(vm))
+                } else {
+                    self.resume(vm)?
+                };
+                stop_reply(&self.debugger, stop)
+            }
+            "b" => match args {
+                "s" => {
+                    let stop = self.debugger.step_back(vm);
+                    stop_reply(&self.debugger, Some(stop))
+                }
+                "c" => {
+                    let stop = self.debugger.reverse_continue(vm);
+                    stop_reply(&self.debugger, Some(stop))
+                }
+                _ => String::new(),
+            },
+            "Z" | "z" => self.update_point(command == "Z", args),
+            "H" => "OK".to_string(),
+            "D" => {
+                self.send("OK")?;
+                return Ok(Reply::Close);
+            }
+            "k" => return Ok(Reply::Close),
+            "q" | "Q" => self.query(packet),
+            _ => String::new(),
+        };
+        Ok(Reply::Packet(reply))
+    }
+
+    /// Run until the debugger stops the VM or the client interrupts it.
+    fn resume(&mut self, vm: &mut VirtualMachine) -> io::Result<Option<Stop>> {
+        loop {
+            match self.debugger.resume(vm, RUN_CHUNK) {
+                Stop::Limit => {
+                    if self.connection.interrupted()? {
+                        return Ok(None);
+                    }
+                }
+                stop => return Ok(Some(stop)),
+            }
+        }
+    }
+
+    /// Insert (`Z`) or remove (`z`) a breakpoint or watchpoint: `type,addr,kind`.
+    fn update_point(&mut self, insert: bool, args: &str) -> String {
+        let mut fields = args.split(',');
+        let (Some(kind), Some(addr)) = (
+            fields.next().and_then(|kind| kind.parse::<u8>().ok()),
+            fields.next().and_then(parse_hex),
+        ) else {
+            return error();
+        };
+        let watch = match kind {
+            // Software and hardware breakpoints are the same to the VM
+            0 | 1 => None,
+            2 => Some(Watch::Write),
+            3 => Some(Watch::Read),
+            4 => Some(Watch::Access),
+            _ => return String::new(),
+        };
+        if !insert {
+            if let Some(id) = self.points.remove(&(kind, addr)) {
+                self.debugger.remove(id);
+            }
+            return "OK".to_string();
+        }
+        if !self.points.contains_key(&(kind, addr)) {
+            let id = match watch {
+                None => self.debugger.add_breakpoint(Breakpoint {
+                    addr,
+                    condition: None,
+                }),
+                Some(watch) => self.debugger.add_watchpoint(Watchpoint { addr, watch }),
+            };
+            self.points.insert((kind, addr), id);
+        }
+        "OK".to_string()
+    }
+
+    fn query(&mut self, packet: &str) -> String {
+        let name = packet.split([':', ',']).next().unwrap_or_default();
+        match name {
+            "qSupported" => {
+                format!("PacketSize={PACKET_SIZE:x};QStartNoAckMode+;ReverseStep+;ReverseContinue+")
+            }
+            "QStartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+    result.map_or_else(error, |()| "OK".to_string())
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        let _ = write!(text, "{byte:02x}");
+    }
+    text
+}
+
+/// Reply to `?`, `s` and `c`. `None` means the client interrupted the VM.
+fn stop_reply(debugger: &Debugger, stop: Option<Stop>) -> String {
+    const SIGINT: u8 = 2;
+    const SIGILL: u8 = 4;
+    const SIGTRAP: u8 = 5;
+    const SIGSEGV: u8 = 11;
+    const SIGSYS: u8 = 31;
+
+    let signal = match stop {
+        None => SIGINT,
+        Some(Stop::Step | Stop::Breakpoint { .. } | Stop::Limit) => SIGTRAP,
+        Some(Stop::Watchpoint { id, access }) => {
+            let reason = match debugger.watchpoints().find(|&(other, _)| other == id) {
+                Some((
+                    _,
+                    Watchpoint {
+                        watch: Watch::Read, ..
+                    },
+                )) => "rwatch",
+                Some((
+                    _,
+                    Watchpoint {
+                        watch: Watch::Access,
+                        ..
+                    },
+                )) => "awatch",
+                _ => "watch",
+            };
+            return format!("T{SIGTRAP:02x}{reason}:{:04x};", access.addr);
+        }
+        Some(Stop::HistoryStart) => return format!("T{SIGTRAP:02x}replaylog:begin;"),
+        Some(Stop::Fault(Fault { kind, .. })) => match kind {
+            FaultKind::Halt => return "W00".to_string(),
+            FaultKind::InvalidOpcode { .. } => SIGILL,
+            FaultKind::BusError { .. }
+            | FaultKind::RomWrite { .. }
+            | FaultKind::StackOverflow
+            | FaultKind::StackUnderflow
+            | FaultKind::ProtectionFault { .. } => SIGSEGV,
+            FaultKind::UnknownSyscall { .. } => SIGSYS,
+        },
+    };
+    format!("S{signal:02x}")
+}
+
+fn stack_pointer(vm: &VirtualMachine) -> u16 {
+    u16::from_be_bytes([
+        vm.registers.read(Register::SPH),
+        vm.registers.read(Register::SPL),
+    ])
+}
+
+/// All registers in register number order.
+fn register_bytes(vm: &VirtualMachine) -> Vec<u8> {
+    let mut bytes = vm.registers.registers.to_vec();
+    bytes.extend_from_slice(&stack_pointer(vm).to_be_bytes());
+    bytes.extend_from_slice(&vm.program_counter.to_be_bytes());
+    bytes
+}
+
+fn read_registers(vm: &VirtualMachine) -> String {
+    encode_hex(&register_bytes(vm))
+}
+
+fn write_registers(vm: &mut VirtualMachine, args: &str) -> Option<()> {
+    let bytes = decode_hex(args)?;
+    let (registers, rest) = bytes.split_at_checked(vm.registers.registers.len())?;
+    let [sp_hi, sp_lo, pc_hi, pc_lo] = rest else {
+        return None;
+    };
+    vm.registers.registers.copy_from_slice(registers);
+    vm.registers.write(Register::SPH, *sp_hi);
+    vm.registers.write(Register::SPL, *sp_lo);
+    vm.program_counter = u16::from_be_bytes([*pc_hi, *pc_lo]);
+    Some(())
+}
+
+fn read_register(vm: &VirtualMachine, register: u16) -> Option<String> {
+    let bytes = match usize::from(register) {
+        SP_REGISTER => stack_pointer(vm).to_be_bytes().to_vec(),
+        PC_REGISTER => vm.program_counter.to_be_bytes().to_vec(),
+        index => vec![*vm.registers.registers.get(index)?],
+    };
+    Some(encode_hex(&bytes))
+}
+
+/// `P n=value`.
+fn write_register(vm: &mut VirtualMachine, args: &str) -> Option<()> {
+    let (register, value) = args.split_once('=')?;
+    let value = decode_hex(value)?;
+    match (usize::from(parse_hex(register)?), value.as_slice()) {
+        (SP_REGISTER, &[hi, lo]) => {
+            vm.registers.write(Register::SPH, hi);
+            vm.registers.write(Register::SPL, lo);
+        }
+        (PC_REGISTER, &[hi, lo]) => vm.program_counter = u16::from_be_bytes([hi, lo]),
+        (index, &[value]) => *vm.registers.registers.get_mut(index)? = value,
+        _ => return None,
+    }
+    Some(())
+}
+
+/// `m addr,len`. Reads peek, so they leave devices alone and device registers read as `0`.
+fn read_memory(vm: &VirtualMachine, args: &str) -> Option<String> {
+    let (addr, len) = args.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+    let bytes: Vec<u8> = (0..len.min(PACKET_SIZE as u16 / 2))
+        .map(|offset| vm.devices.peek(addr.wrapping_add(offset)))
+        .collect();
+    Some(encode_hex(&bytes))
+}
+
+/// `M addr,len:data`. Fails if a write faults, e.g. on locked ROM.
+fn write_memory(vm: &mut VirtualMachine, args: &str) -> Option<()> {
+    let (range, data) = args.split_once(':')?;
+    let (addr, len) = range.split_once(',')?;
+    let (addr, len) = (parse_hex(addr)?, parse_hex(len)?);
+    let bytes = decode_hex(data)?;
+    if bytes.len() != usize::from(len) {
+        return None;
+    }
+    let mut faulted = false;
+    for (offset, byte) in (0u16..).zip(bytes) {
+        vm.devices.write(addr.wrapping_add(offset), byte);
+        // Accesses made by the client are not the program's fault
+        faulted |= vm.devices.take_fault().is_some();
+    }
+    (!faulted).then_some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use mb8_isa::{encode::encode_program, opcodes::Opcode};
+
+    use crate::history::History;
+
+    use super::*;
+
+    fn packet(data: &str) -> String {
+        format!("${data}#{:02x}", checksum_of(data.as_bytes()))
+    }
+
+    /// Serve the scripted packets and return everything the stub sent.
+    fn serve(vm: &mut VirtualMachine, packets: &[&str]) -> String {
+        let input: String = packets.iter().map(|data| packet(data)).collect();
+        let mut stub = GdbStub::new(Pipe {
+            input: Cursor::new(input.into_bytes()),
+            output: Vec::new(),
+        });
+        stub.serve(vm).unwrap();
+        String::from_utf8(stub.connection.output).unwrap()
+    }
+
+    fn vm() -> VirtualMachine {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0x41,
+            },
+            Opcode::Push { src: Register::R0 },
+            Opcode::Halt,
+        ]));
+        vm
+    }
+
+    #[test]
+    fn acknowledges_packets() {
+        let mut vm = vm();
+        assert_eq!(
+            serve(&mut vm, &["?", "QStartNoAckMode", "qAttached"]),
+            format!("+{}+{}{}", packet("S05"), packet("OK"), packet("1"))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksums() {
+        let mut vm = vm();
+        let mut stub = GdbStub::new(Pipe {
+            input: Cursor::new(b"$?#00$?#3f".to_vec()),
+            output: Vec::new(),
+        });
+        stub.serve(&mut vm).unwrap();
+        assert_eq!(stub.connection.output, b"-+$S05#b8");
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let mut vm = vm();
+        let output = serve(
+            &mut vm,
+            &[
+                "QStartNoAckMode",
+                "P0=7f",
+                "P10=bf80",
+                "p0",
+                "p10",
+                "p11",
+                "g",
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                "+",
+                &packet("OK"),
+                &packet("OK"),
+                &packet("OK"),
+                &packet("7f"),
+                &packet("bf80"),
+                &packet("e000"),
+                &packet("7f000000000000000000000000bf8000bf80e000"),
+            ]
+            .concat()
+        );
+
+        serve(&mut vm, &["G000102030405060708090a0b0c0d0e0fbff0e004"]);
+        assert_eq!(vm.registers.read(Register::R15), 0x0F);
+        assert_eq!(stack_pointer(&vm), 0xBFF0);
+        assert_eq!(vm.program_counter, 0xE004);
+    }
+
+    #[test]
+    fn reads_and_writes_memory() {
+        let mut vm = vm();
+        let output = serve(
+            &mut vm,
+            &["QStartNoAckMode", "M1000,2:abcd", "m1000,3", "m0fff,1"],
+        );
+        assert_eq!(
+            output,
+            [
+                "+",
+                &packet("OK"),
+                &packet("OK"),
+                &packet("abcd00"),
+                &packet("00")
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn reads_devices_without_side_effects() {
+        let mut vm = vm();
+        vm.devices.keyboard().key_pressed(b'a');
+        let output = serve(&mut vm, &["QStartNoAckMode", "mf100,2", "Me000,1:00"]);
+        assert_eq!(
+            output,
+            ["+", &packet("OK"), &packet("0000"), &packet("E01")].concat()
+        );
+        assert_eq!(vm.devices.read(0xF101), b'a');
+        assert_eq!(vm.fault, None);
+    }
+
+    #[test]
+    fn steps_continues_and_stops_on_breakpoints() {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0x41,
+            },
+            Opcode::Push { src: Register::R0 },
+            Opcode::Pop { dst: Register::R1 },
+            Opcode::Push { src: Register::R1 },
+            Opcode::Halt,
+        ]));
+        let output = serve(
+            &mut vm,
+            &[
+                "QStartNoAckMode",
+                "Z0,e002,2",
+                "c",
+                "z0,e002,2",
+                "Z2,bfff,1",
+                "s",
+                "z2,bfff,1",
+                "Z3,bfff,1",
+                "c",
+                "z3,bfff,1",
+                "Z4,bfff,1",
+                "c",
+                "c",
+            ],
+        );
+        assert_eq!(
+            output,
+            [
+                "+",
+                &packet("OK"),
+                &packet("OK"),
+                &packet("S05"),
+                &packet("OK"),
+                &packet("OK"),
+                &packet("T05watch:bfff;"),
+                &packet("OK"),
+                &packet("OK"),
+                &packet("T05rwatch:bfff;"),
+                &packet("OK"),
+                &packet("OK"),
+                &packet("T05awatch:bfff;"),
+                &packet("W00"),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn steps_backwards() {
+        let mut vm = vm();
+        vm.history = Some(History::default());
+        let output = serve(&mut vm, &["QStartNoAckMode", "s", "s", "bs", "bc"]);
+        assert_eq!(
+            output,
+            [
+                "+",
+                &packet("OK"),
+                &packet("S05"),
+                &packet("S05"),
+                &packet("S05"),
+                &packet("T05replaylog:begin;"),
+            ]
+            .concat()
+        );
+        assert_eq!(vm.program_counter, 0xE000);
+        assert_eq!(vm.devices.read(0xBFFF), 0);
+    }
+
+    #[test]
+    fn detaches() {
+        let mut vm = vm();
+        assert_eq!(
+            serve(&mut vm, &["QStartNoAckMode", "D", "?"]),
+            format!("+{}{}", packet("OK"), packet("OK"))
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Execution history for reverse debugging.
+//! While [`VirtualMachine::history`](crate::vm::VirtualMachine::history) is set, every step
+//! records what it changed so it can be undone with
+//! [`VirtualMachine::step_back`](crate::vm::VirtualMachine::step_back). Only the CPU and memory
+//! are rewound; devices keep their current state.
+
+use std::collections::VecDeque;
+
+use crate::{mpu::Mode, trace::RegisterChange};
+
+/// Default number of steps kept by [`History`].
+pub const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Memory byte overwritten by a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Changes made by a single step: an instruction or an interrupt entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// PC before the step.
+    pub pc: u16,
+    /// Cycle counter before the step.
+    pub cycles: u64,
+    /// Interrupt enable flag before the step.
+    pub interrupts_enabled: bool,
+    /// Privilege level before the step.
+    pub mode: Mode,
+    /// Registers whose value changed, in index order.
+    pub registers: Vec<RegisterChange>,
+    /// Memory writes in the order they were made.
+    pub writes: Vec<MemoryWrite>,
+}
+
+/// Ring buffer of the most recent steps.
+#[derive(Debug, Clone)]
+pub struct History {
+    steps: VecDeque<Step>,
+    capacity: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl History {
+    /// Keep at most `capacity` steps, dropping the oldest ones.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            steps: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.steps.clear();
+    }
+
+    /// Recorded steps, oldest first.
+    #[must_use]
+    pub fn steps(&self) -> impl DoubleEndedIterator<Item = &Step> {
+        self.steps.iter()
+    }
+
+    /// Most recent step that wrote `addr`, with the write.
+    #[must_use]
+    pub fn last_write(&self, addr: u16) -> Option<(&Step, &MemoryWrite)> {
+        self.steps.iter().rev().find_map(|step| {
+            step.writes
+                .iter()
+                .rev()
+                .find(|write| write.addr == addr)
+                .map(|write| (step, write))
+        })
+    }
+
+    pub(crate) fn push(&mut self, step: Step) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.steps.len() == self.capacity {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(step);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<Step> {
+        self.steps.pop_back()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(pc: u16, writes: &[(u16, u8, u8)]) -> Step {
+        Step {
+            pc,
+            cycles: 0,
+            interrupts_enabled: false,
+            mode: Mode::Supervisor,
+            registers: Vec::new(),
+            writes: writes
+                .iter()
+                .map(|&(addr, old, new)| MemoryWrite { addr, old, new })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn drops_oldest_steps() {
+        let mut history = History::new(2);
+        for pc in [0xE000, 0xE002, 0xE004] {
+            history.push(step(pc, &[]));
+        }
+        let pcs: Vec<u16> = history.steps().map(|step| step.pc).collect();
+        assert_eq!(pcs, [0xE002, 0xE004]);
+        assert_eq!(history.pop().map(|step| step.pc), Some(0xE004));
+    }
+
+    #[test]
+    fn finds_last_write() {
+        let mut history = History::new(10);
+        history.push(step(0xE000, &[(0x1000, 0, 1)]));
+        history.push(step(
+            0xE002,
+            &[(0x1001, 0, 2), (0x1000, 1, 3), (0x1000, 3, 4)],
+        ));
+        history.push(step(0xE004, &[]));
+        let (step, write) = history.last_write(0x1000).unwrap();
+        assert_eq!(step.pc, 0xE002);
+        assert_eq!(
+            *write,
+            MemoryWrite {
+                addr: 0x1000,
+                old: 3,
+                new: 4
+            }
+        );
+        assert!(history.last_write(0x2000).is_none());
+    }
+}
@@ -1,4 +1,15 @@
+#![cfg_attr(test, allow(clippy::field_reassign_with_default))]
+
+pub mod debug;
 pub mod dev;
+pub mod fault;
+pub mod gdb;
+pub mod history;
+pub mod mpu;
 pub mod ops;
 pub mod registers;
+pub mod serial;
+pub mod snapshot;
+pub mod syscall;
+pub mod trace;
 pub mod vm;
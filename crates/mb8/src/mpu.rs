@@ -0,0 +1,200 @@
+//! Memory protection unit.
+//! While [`VirtualMachine::mpu`](crate::vm::VirtualMachine::mpu) is set, code runs in
+//! [`Mode::User`] or [`Mode::Supervisor`]. Supervisor code may access anything; user code only
+//! what the regions allow, and violations raise a [`FaultKind::ProtectionFault`].
+//!
+//! The mode follows the code being fetched: fetching from a gate, such as the syscall entry
+//! point, and entering an interrupt handler switch to supervisor mode, and fetching from a region
+//! user code may execute switches back to user mode.
+
+use std::{
+    fmt::{self, Display},
+    ops::RangeInclusive,
+};
+
+use crate::fault::FaultKind;
+
+/// Address of the kernel syscall entry point.
+pub const SYSCALL_ENTRY: u16 = 0xE500;
+
+/// Privilege level of the CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Supervisor,
+    User,
+}
+
+/// Kind of access checked against the regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+/// What user code may do in a region.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Self = Self::new(false, false, false);
+    pub const READ: Self = Self::new(true, false, false);
+    pub const READ_WRITE: Self = Self::new(true, true, false);
+    pub const READ_EXECUTE: Self = Self::new(true, false, true);
+    pub const ALL: Self = Self::new(true, true, true);
+
+    #[must_use]
+    pub const fn new(read: bool, write: bool, execute: bool) -> Self {
+        Self {
+            read,
+            write,
+            execute,
+        }
+    }
+
+    #[must_use]
+    pub fn allows(self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+/// Address range and the permissions user code has in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: RangeInclusive<u16>,
+    pub user: Permissions,
+}
+
+/// Regions and gates of the memory protection unit.
+///
+/// The first region containing an address decides; user code may not touch addresses outside
+/// every region.
+#[derive(Debug, Clone, Default)]
+pub struct Mpu {
+    regions: Vec<Region>,
+    gates: Vec<u16>,
+}
+
+impl Mpu {
+    /// No regions and no gates: user code can do nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Layout for the MB8 kernel, described in `docs/memory.md`.
+    #[must_use]
+    pub fn standard() -> Self {
+        Self::new()
+            .region(0x0100..=0x0FFF, Permissions::READ_WRITE)
+            .region(0x1000..=0xBEFF, Permissions::ALL)
+            .region(0xBF00..=0xBFFF, Permissions::READ_WRITE)
+            .region(0xC000..=0xDFFF, Permissions::READ_WRITE)
+            .gate(SYSCALL_ENTRY)
+    }
+
+    /// Give user code `user` permissions in `range`, unless an earlier region covers it.
+    #[must_use]
+    pub fn region(mut self, range: RangeInclusive<u16>, user: Permissions) -> Self {
+        self.regions.push(Region { range, user });
+        self
+    }
+
+    /// Let user code enter supervisor mode by jumping to `addr`.
+    #[must_use]
+    pub fn gate(mut self, addr: u16) -> Self {
+        self.gates.push(addr);
+        self
+    }
+
+    #[must_use]
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Permissions of user code at `addr`.
+    #[must_use]
+    pub fn permissions(&self, addr: u16) -> Permissions {
+        self.regions
+            .iter()
+            .find(|region| region.range.contains(&addr))
+            .map_or(Permissions::NONE, |region| region.user)
+    }
+
+    /// Check an access made in `mode`.
+    ///
+    /// # Errors
+    /// Returns a [`FaultKind::ProtectionFault`] if user code may not make the access.
+    pub fn check(&self, mode: Mode, addr: u16, access: Access) -> Result<(), FaultKind> {
+        if mode == Mode::User && !self.permissions(addr).allows(access) {
+            return Err(FaultKind::ProtectionFault { addr, access });
+        }
+        Ok(())
+    }
+
+    /// Mode of the CPU once it fetches the instruction at `pc` in `mode`.
+    ///
+    /// # Errors
+    /// Returns a [`FaultKind::ProtectionFault`] if user code may not execute at `pc`.
+    pub fn fetch(&self, mode: Mode, pc: u16) -> Result<Mode, FaultKind> {
+        if self.gates.contains(&pc) {
+            return Ok(Mode::Supervisor);
+        }
+        if self.permissions(pc).execute {
+            return Ok(Mode::User);
+        }
+        self.check(mode, pc, Access::Execute)?;
+        Ok(mode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switches_mode_on_fetch() {
+        let mpu = Mpu::standard();
+        assert_eq!(mpu.fetch(Mode::Supervisor, 0x1000), Ok(Mode::User));
+        assert_eq!(mpu.fetch(Mode::User, SYSCALL_ENTRY), Ok(Mode::Supervisor));
+        assert_eq!(mpu.fetch(Mode::Supervisor, 0xE502), Ok(Mode::Supervisor));
+        assert_eq!(
+            mpu.fetch(Mode::User, 0xE000),
+            Err(FaultKind::ProtectionFault {
+                addr: 0xE000,
+                access: Access::Execute
+            })
+        );
+    }
+
+    #[test]
+    fn checks_user_accesses() {
+        let mpu = Mpu::new()
+            .region(0x1000..=0x10FF, Permissions::READ)
+            .region(0x1000..=0x1FFF, Permissions::ALL);
+        assert_eq!(mpu.check(Mode::User, 0x1010, Access::Read), Ok(()));
+        assert!(mpu.check(Mode::User, 0x1010, Access::Write).is_err());
+        assert_eq!(mpu.check(Mode::User, 0x1100, Access::Write), Ok(()));
+        assert!(mpu.check(Mode::User, 0x2000, Access::Read).is_err());
+        assert_eq!(mpu.check(Mode::Supervisor, 0x2000, Access::Write), Ok(()));
+    }
+}
@@ -1,6 +1,6 @@
 use mb8_isa::{registers::Register, STACK_BOTTOM};
 
-use crate::vm::VirtualMachine;
+use crate::{fault::FaultKind, vm::VirtualMachine};
 
 impl VirtualMachine {
     pub fn call(&mut self, hi: Register, lo: Register) {
@@ -16,13 +16,16 @@ impl VirtualMachine {
         let addr = u16::from_be_bytes([hi, lo]);
 
         for byte in program_counter.to_le_bytes() {
-            self.devices.write(stack_pointer, byte);
-            stack_pointer -= 1;
+            self.write(stack_pointer, byte);
 
-            if stack_pointer as usize <= STACK_BOTTOM {
-                self.halted = true;
+            let Some(next) = stack_pointer
+                .checked_sub(1)
+                .filter(|next| usize::from(*next) > STACK_BOTTOM)
+            else {
+                self.raise(FaultKind::StackOverflow);
                 return;
-            }
+            };
+            stack_pointer = next;
         }
 
         let [sp_hi, sp_lo] = stack_pointer.to_be_bytes();
@@ -61,15 +64,30 @@ mod tests {
         assert_eq!(vm.devices.read(0xBFFF), 0x76);
     }
 
-    // #[test]
-    // fn test_opcode_call_stack_overflow() {
-    //     // VM halts when the stack overflows
-    //     let mut vm = VirtualMachine::default();
-    //     vm.registers.write(Register::SP, 0xBF00);
-    //     vm.execute(&Opcode::Call {
-    //         hi: Register::R0,
-    //         lo: Register::R1,
-    //     });
-    //     assert!(vm.halted);
-    // }
+    #[test]
+    fn halts_on_stack_overflow() {
+        // VM halts when the stack overflows
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0xBF);
+        vm.registers.write(Register::SPL, 0x00);
+        vm.execute(&Opcode::Call {
+            hi: Register::R0,
+            lo: Register::R1,
+        });
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackOverflow));
+        assert!(vm.halted);
+    }
+
+    #[test]
+    fn faults_when_sp_wraps() {
+        // VM faults instead of pushing below address zero
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0x00);
+        vm.registers.write(Register::SPL, 0x00);
+        vm.execute(&Opcode::Call {
+            hi: Register::R0,
+            lo: Register::R1,
+        });
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackOverflow));
+    }
 }
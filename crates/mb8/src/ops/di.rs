@@ -0,0 +1,23 @@
+use crate::vm::VirtualMachine;
+
+impl VirtualMachine {
+    pub fn di(&mut self) {
+        self.interrupts_enabled = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::opcodes::Opcode;
+
+    use super::*;
+
+    #[test]
+    fn disables_interrupts() {
+        // VM ignores interrupts after DI
+        let mut vm = VirtualMachine::default();
+        vm.interrupts_enabled = true;
+        vm.execute(&Opcode::Di);
+        assert!(!vm.interrupts_enabled);
+    }
+}
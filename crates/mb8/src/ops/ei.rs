@@ -0,0 +1,23 @@
+use crate::vm::VirtualMachine;
+
+impl VirtualMachine {
+    pub fn ei(&mut self) {
+        self.interrupts_enabled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::opcodes::Opcode;
+
+    use super::*;
+
+    #[test]
+    fn enables_interrupts() {
+        // VM accepts interrupts after EI
+        let mut vm = VirtualMachine::default();
+        assert!(!vm.interrupts_enabled);
+        vm.execute(&Opcode::Ei);
+        assert!(vm.interrupts_enabled);
+    }
+}
@@ -7,7 +7,7 @@ impl VirtualMachine {
         let addr_hi = self.registers.read(hi);
         let addr_lo = self.registers.read(lo);
         let addr = u16::from_be_bytes([addr_hi, addr_lo]);
-        let value = self.devices.read(addr);
+        let value = self.read(addr);
         self.registers.write(dst, value);
     }
 }
@@ -2,6 +2,8 @@ mod add;
 mod and;
 mod call;
 mod cmp;
+mod di;
+mod ei;
 mod halt;
 mod jcr;
 mod jmp;
@@ -17,6 +19,7 @@ mod or;
 mod pop;
 mod push;
 mod ret;
+mod reti;
 mod shl;
 mod shr;
 mod st;
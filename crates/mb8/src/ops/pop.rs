@@ -1,6 +1,6 @@
 use mb8_isa::registers::Register;
 
-use crate::vm::VirtualMachine;
+use crate::{fault::FaultKind, vm::VirtualMachine};
 
 impl VirtualMachine {
     pub fn pop(&mut self, dst: Register) {
@@ -8,13 +8,13 @@ impl VirtualMachine {
             self.registers.read(Register::SPH),
             self.registers.read(Register::SPL),
         ]);
-        stack_pointer += 1;
-        if stack_pointer > 0xBFFF {
-            self.halted = true;
+        if stack_pointer >= 0xBFFF {
+            self.raise(FaultKind::StackUnderflow);
             return;
         }
+        stack_pointer += 1;
         let [sp_hi, sp_lo] = stack_pointer.to_be_bytes();
-        let value = self.devices.read(stack_pointer);
+        let value = self.read(stack_pointer);
         self.registers.write(Register::SPH, sp_hi);
         self.registers.write(Register::SPL, sp_lo);
         self.registers.write(dst, value);
@@ -54,4 +54,14 @@ mod tests {
         vm.execute(&Opcode::Pop { dst: Register::R0 });
         assert!(vm.halted);
     }
+
+    #[test]
+    fn faults_when_sp_wraps() {
+        // VM faults instead of popping past the top of the address space
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0xFF);
+        vm.registers.write(Register::SPL, 0xFF);
+        vm.execute(&Opcode::Pop { dst: Register::R0 });
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackUnderflow));
+    }
 }
@@ -1,25 +1,27 @@
 use mb8_isa::{registers::Register, STACK_BOTTOM};
 
-use crate::vm::VirtualMachine;
+use crate::{fault::FaultKind, vm::VirtualMachine};
 
 impl VirtualMachine {
     pub fn push(&mut self, src: Register) {
-        let mut stack_pointer = u16::from_be_bytes([
+        let stack_pointer = u16::from_be_bytes([
             self.registers.read(Register::SPH),
             self.registers.read(Register::SPL),
         ]);
+        let Some(next) = stack_pointer.checked_sub(1) else {
+            self.raise(FaultKind::StackOverflow);
+            return;
+        };
         let value = self.registers.read(src);
 
-        self.devices.write(stack_pointer, value);
+        self.write(stack_pointer, value);
 
-        stack_pointer -= 1;
-
-        let [sp_hi, sp_lo] = stack_pointer.to_be_bytes();
+        let [sp_hi, sp_lo] = next.to_be_bytes();
         self.registers.write(Register::SPH, sp_hi);
         self.registers.write(Register::SPL, sp_lo);
 
-        if stack_pointer - 1 <= STACK_BOTTOM as u16 {
-            self.halted = true;
+        if usize::from(next) <= STACK_BOTTOM + 1 {
+            self.raise(FaultKind::StackOverflow);
         }
     }
 }
@@ -56,4 +58,14 @@ mod tests {
         vm.execute(&Opcode::Push { src: Register::R0 });
         assert!(vm.halted);
     }
+
+    #[test]
+    fn faults_when_sp_wraps() {
+        // VM faults instead of pushing below address zero
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0x00);
+        vm.registers.write(Register::SPL, 0x00);
+        vm.execute(&Opcode::Push { src: Register::R0 });
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackOverflow));
+    }
 }
@@ -1,6 +1,6 @@
 use mb8_isa::registers::Register;
 
-use crate::vm::VirtualMachine;
+use crate::{fault::FaultKind, vm::VirtualMachine};
 
 impl VirtualMachine {
     pub fn ret(&mut self) {
@@ -8,14 +8,14 @@ impl VirtualMachine {
             self.registers.read(Register::SPH),
             self.registers.read(Register::SPL),
         ]);
-        if stack_pointer + 2 > 0xBFFF {
-            self.halted = true;
+        if stack_pointer > 0xBFFF - 2 {
+            self.raise(FaultKind::StackUnderflow);
             return;
         }
         stack_pointer += 1;
-        let hi = self.devices.read(stack_pointer);
+        let hi = self.read(stack_pointer);
         stack_pointer += 1;
-        let lo = self.devices.read(stack_pointer);
+        let lo = self.read(stack_pointer);
         let [sp_hi, sp_lo] = stack_pointer.to_be_bytes();
         self.registers.write(Register::SPH, sp_hi);
         self.registers.write(Register::SPL, sp_lo);
@@ -76,4 +76,14 @@ mod tests {
         assert_eq!(vm.program_counter, 0xE000);
         assert!(vm.halted);
     }
+
+    #[test]
+    fn faults_when_sp_wraps() {
+        // VM faults instead of popping past the top of the address space
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0xFF);
+        vm.registers.write(Register::SPL, 0xFF);
+        vm.execute(&Opcode::Ret);
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackUnderflow));
+    }
 }
@@ -0,0 +1,67 @@
+use mb8_isa::registers::Register;
+
+use crate::{fault::FaultKind, vm::VirtualMachine};
+
+impl VirtualMachine {
+    pub fn reti(&mut self) {
+        let stack_pointer = u16::from_be_bytes([
+            self.registers.read(Register::SPH),
+            self.registers.read(Register::SPL),
+        ]);
+        let Some(top) = stack_pointer.checked_add(3).filter(|top| *top <= 0xBFFF) else {
+            self.raise(FaultKind::StackUnderflow);
+            return;
+        };
+        let flags = self.read(top - 2);
+        let hi = self.read(top - 1);
+        let lo = self.read(top);
+        let [sp_hi, sp_lo] = top.to_be_bytes();
+        self.registers.write(Register::SPH, sp_hi);
+        self.registers.write(Register::SPL, sp_lo);
+        self.registers.write(Register::F, flags);
+        self.program_counter = u16::from_be_bytes([hi, lo]);
+        self.interrupts_enabled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::opcodes::Opcode;
+
+    use super::*;
+
+    #[test]
+    fn restores_pc_and_flags() {
+        // VM returns from an interrupt handler
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPL, 0xFC);
+        vm.devices.write(0xBFFD, 0x05);
+        vm.devices.write(0xBFFE, 0x12);
+        vm.devices.write(0xBFFF, 0x34);
+        vm.execute(&Opcode::Reti);
+        assert_eq!(vm.registers.read(Register::SPL), 0xFF);
+        assert_eq!(vm.registers.read(Register::F), 0x05);
+        assert_eq!(vm.program_counter, 0x1234);
+        assert!(vm.interrupts_enabled);
+    }
+
+    #[test]
+    fn halts_on_reti_underflow() {
+        // VM halts when there is no interrupt frame on the stack
+        let mut vm = VirtualMachine::default();
+        vm.execute(&Opcode::Reti);
+        assert_eq!(vm.program_counter, 0xE000);
+        assert!(vm.halted);
+    }
+
+    #[test]
+    fn faults_when_sp_wraps() {
+        // VM faults instead of popping past the top of the address space
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::SPH, 0xFF);
+        vm.registers.write(Register::SPL, 0xFF);
+        vm.execute(&Opcode::Reti);
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::StackUnderflow));
+        assert!(!vm.interrupts_enabled);
+    }
+}
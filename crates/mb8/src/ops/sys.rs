@@ -1,7 +1,68 @@
-use crate::vm::VirtualMachine;
+use mb8_isa::registers::Register;
+
+use crate::{fault::FaultKind, vm::VirtualMachine};
 
 impl VirtualMachine {
-    pub fn sys(&self) {
-        todo!()
+    pub fn sys(&mut self) {
+        let number = self.registers.read(Register::R0);
+        let Some(mut handler) = self.syscalls.take() else {
+            self.raise(FaultKind::UnknownSyscall { number });
+            return;
+        };
+        let result = handler.syscall(number, self);
+        // Keep a handler installed by the syscall itself
+        self.syscalls.get_or_insert(handler);
+        if let Err(kind) = result {
+            self.raise(kind);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::opcodes::Opcode;
+
+    use super::*;
+
+    #[test]
+    fn faults_without_handler() {
+        // VM faults when no syscall handler is registered
+        let mut vm = VirtualMachine::default();
+        vm.registers.write(Register::R0, 0x05);
+        vm.execute(&Opcode::Sys);
+        assert!(vm.halted);
+        assert_eq!(
+            vm.devices.take_fault(),
+            Some(FaultKind::UnknownSyscall { number: 0x05 })
+        );
+    }
+
+    #[test]
+    fn calls_handler_with_registers() {
+        // VM passes the syscall number and registers to the handler
+        let mut vm = VirtualMachine::default();
+        vm.syscalls = Some(Box::new(|number: u8, vm: &mut VirtualMachine| {
+            let sum = number + vm.registers.read(Register::R1);
+            vm.registers.write(Register::R0, sum);
+            Ok(())
+        }));
+        vm.registers.write(Register::R0, 0x10);
+        vm.registers.write(Register::R1, 0x02);
+        vm.execute(&Opcode::Sys);
+        assert!(!vm.halted);
+        assert_eq!(vm.registers.read(Register::R0), 0x12);
+        assert!(vm.syscalls.is_some());
+    }
+
+    #[test]
+    fn raises_handler_fault() {
+        // VM halts with the fault returned by the handler
+        let mut vm = VirtualMachine::default();
+        vm.syscalls = Some(Box::new(|_: u8, _: &mut VirtualMachine| {
+            Err(FaultKind::Halt)
+        }));
+        vm.execute(&Opcode::Sys);
+        assert!(vm.halted);
+        assert_eq!(vm.devices.take_fault(), Some(FaultKind::Halt));
     }
 }
@@ -0,0 +1,146 @@
+//! Host side of the UART.
+//! A [`SerialBridge`] connects the [`Uart`] to a byte stream: stdin and stdout, a TCP client or
+//! any reader and writer, such as the ends of a pipe. Input is read on a background thread, so
+//! [`SerialBridge::pump`] never blocks the VM.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use crate::dev::uart::Uart;
+
+pub struct SerialBridge {
+    input: Receiver<u8>,
+    output: Box<dyn Write + Send>,
+    /// Bytes read from the host that did not fit into the receive FIFO yet.
+    pending: VecDeque<u8>,
+}
+
+impl fmt::Debug for SerialBridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerialBridge")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SerialBridge {
+    /// Bridge the UART to `input` and `output`.
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                let Ok(byte) = byte else {
+                    break;
+                };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            input: receiver,
+            output: Box::new(output),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Bridge the UART to the process's stdin and stdout.
+    #[must_use]
+    pub fn stdio() -> Self {
+        Self::new(io::stdin(), io::stdout())
+    }
+
+    /// Wait for the first client on `listener` and bridge the UART to it. Returns the bridge
+    /// and the client's address.
+    ///
+    /// # Errors
+    /// Returns an error if accepting the client fails.
+    pub fn tcp(listener: &TcpListener) -> io::Result<(Self, SocketAddr)> {
+        let (stream, peer) = listener.accept()?;
+        Ok((Self::new(stream.try_clone()?, stream), peer))
+    }
+
+    /// Move waiting input into the receive FIFO and write the UART output to the host.
+    ///
+    /// # Errors
+    /// Returns an error if writing the output fails.
+    pub fn pump(&mut self, uart: &mut Uart) -> io::Result<()> {
+        self.pending.extend(self.input.try_iter());
+        while uart.can_receive() {
+            let Some(byte) = self.pending.pop_front() else {
+                break;
+            };
+            uart.receive(byte);
+        }
+
+        let output = uart.take_output();
+        if !output.is_empty() {
+            self.output.write_all(&output)?;
+            self.output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        net::TcpStream,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::dev::{uart::registers::DATA, Device};
+
+    /// Writer whose contents stay readable after it moved into the bridge.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bridges_both_directions() {
+        let output = Shared::default();
+        let mut bridge = SerialBridge::new(Cursor::new(b"hi".to_vec()), output.clone());
+        let mut uart = Uart::default();
+        uart.write(DATA, b'o').unwrap();
+        uart.write(DATA, b'k').unwrap();
+
+        // Input arrives from the reader thread
+        for _ in 0..100 {
+            bridge.pump(&mut uart).unwrap();
+            if uart.can_receive() && uart.read(DATA) == Ok(b'h') {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(uart.read(DATA), Ok(b'i'));
+        assert_eq!(*output.0.lock().unwrap(), b"ok");
+    }
+
+    #[test]
+    fn accepts_a_tcp_client() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || TcpStream::connect(addr).unwrap());
+        let (_bridge, peer) = SerialBridge::tcp(&listener).unwrap();
+        assert_eq!(peer, client.join().unwrap().local_addr().unwrap());
+    }
+}
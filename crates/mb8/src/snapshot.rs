@@ -0,0 +1,338 @@
+//! Machine snapshots.
+//! A snapshot is [`MAGIC`], a `u16` [`VERSION`] and the state of the VM and its devices, all
+//! numbers big-endian. Each device writes its own state through the [`Snapshot`] trait.
+//! Host-side state, such as the tracer and the syscall handler, is not saved.
+
+use std::fmt::{self, Display};
+
+use crate::{
+    fault::{Fault, FaultKind},
+    mpu::Access,
+};
+
+/// File signature of a snapshot.
+pub const MAGIC: &[u8; 4] = b"MB8S";
+/// Current snapshot format version.
+pub const VERSION: u16 = 11;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data does not start with [`MAGIC`].
+    BadMagic,
+    /// The snapshot was written by an unsupported format version.
+    UnsupportedVersion { version: u16 },
+    /// The data ends in the middle of the snapshot.
+    Truncated,
+    /// A field holds a value that cannot be restored.
+    Invalid { field: &'static str },
+    /// Data is left over after the snapshot.
+    TrailingData,
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::BadMagic => write!(f, "not an MB8 snapshot"),
+            SnapshotError::UnsupportedVersion { version } => {
+                write!(f, "unsupported snapshot version {version}")
+            }
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::Invalid { field } => write!(f, "invalid {field} in snapshot"),
+            SnapshotError::TrailingData => write!(f, "unexpected data after snapshot"),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+pub type SnapshotResult<T = ()> = Result<T, SnapshotError>;
+
+/// State that can be saved into and restored from a snapshot.
+pub trait Snapshot {
+    fn save(&self, writer: &mut Writer);
+
+    /// Restore the state saved by [`Snapshot::save`].
+    ///
+    /// # Errors
+    /// Returns an error if the data is truncated or invalid. The state may be partially
+    /// restored in that case.
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult;
+}
+
+/// Serialises snapshot fields.
+#[derive(Debug, Default)]
+pub struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    /// Start a snapshot with the header.
+    #[must_use]
+    pub fn new() -> Self {
+        let mut writer = Self::default();
+        writer.bytes(MAGIC);
+        writer.u16(VERSION);
+        writer
+    }
+
+    #[must_use]
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_be_bytes());
+    }
+
+    /// Variable-length data, prefixed with its `u32` length.
+    pub fn blob(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes(bytes);
+    }
+
+    pub fn fault(&mut self, fault: Option<Fault>) {
+        let Some(Fault { pc, kind }) = fault else {
+            self.u8(0);
+            return;
+        };
+        match kind {
+            FaultKind::Halt => self.u8(1),
+            FaultKind::InvalidOpcode { word } => {
+                self.u8(2);
+                self.u16(word);
+            }
+            FaultKind::BusError { addr } => {
+                self.u8(3);
+                self.u16(addr);
+            }
+            FaultKind::StackOverflow => self.u8(4),
+            FaultKind::StackUnderflow => self.u8(5),
+            FaultKind::RomWrite { addr } => {
+                self.u8(6);
+                self.u16(addr);
+            }
+            FaultKind::UnknownSyscall { number } => {
+                self.u8(7);
+                self.u8(number);
+            }
+            FaultKind::ProtectionFault { addr, access } => {
+                self.u8(8);
+                self.u16(addr);
+                self.u8(match access {
+                    Access::Read => 0,
+                    Access::Write => 1,
+                    Access::Execute => 2,
+                });
+            }
+        }
+        self.u16(pc);
+    }
+}
+
+/// Reads snapshot fields.
+#[derive(Debug)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    /// Check the header and start reading the snapshot.
+    ///
+    /// # Errors
+    /// Returns an error if the header is missing or the version is not supported.
+    pub fn new(data: &'a [u8]) -> SnapshotResult<Self> {
+        let mut reader = Self { data };
+        if reader.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion { version });
+        }
+        Ok(reader)
+    }
+
+    /// Check that the whole snapshot was read.
+    ///
+    /// # Errors
+    /// Returns an error if data is left over.
+    pub fn finish(self) -> SnapshotResult {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SnapshotError::TrailingData)
+        }
+    }
+
+    /// # Errors
+    /// Returns an error if fewer than `len` bytes are left.
+    pub fn bytes(&mut self, len: usize) -> SnapshotResult<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(SnapshotError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Fill `buf` with the next bytes.
+    ///
+    /// # Errors
+    /// Returns an error if fewer than `buf.len()` bytes are left.
+    pub fn fill(&mut self, buf: &mut [u8]) -> SnapshotResult {
+        buf.copy_from_slice(self.bytes(buf.len())?);
+        Ok(())
+    }
+
+    fn array<const N: usize>(&mut self) -> SnapshotResult<[u8; N]> {
+        let mut buf = [0; N];
+        self.fill(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated.
+    pub fn u8(&mut self) -> SnapshotResult<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated or the value is not `0` or `1`.
+    pub fn bool(&mut self) -> SnapshotResult<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Invalid { field: "flag" }),
+        }
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated.
+    pub fn u16(&mut self) -> SnapshotResult<u16> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated.
+    pub fn u32(&mut self) -> SnapshotResult<u32> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated.
+    pub fn u64(&mut self) -> SnapshotResult<u64> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    /// Variable-length data written by [`Writer::blob`].
+    ///
+    /// # Errors
+    /// Returns an error if the data is truncated.
+    pub fn blob(&mut self) -> SnapshotResult<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    /// # Errors
+    /// Returns an error if the data is truncated or the fault is unknown.
+    pub fn fault(&mut self) -> SnapshotResult<Option<Fault>> {
+        let kind = match self.u8()? {
+            0 => return Ok(None),
+            1 => FaultKind::Halt,
+            2 => FaultKind::InvalidOpcode { word: self.u16()? },
+            3 => FaultKind::BusError { addr: self.u16()? },
+            4 => FaultKind::StackOverflow,
+            5 => FaultKind::StackUnderflow,
+            6 => FaultKind::RomWrite { addr: self.u16()? },
+            7 => FaultKind::UnknownSyscall { number: self.u8()? },
+            8 => FaultKind::ProtectionFault {
+                addr: self.u16()?,
+                access: match self.u8()? {
+                    0 => Access::Read,
+                    1 => Access::Write,
+                    2 => Access::Execute,
+                    _ => return Err(SnapshotError::Invalid { field: "fault" }),
+                },
+            },
+            _ => return Err(SnapshotError::Invalid { field: "fault" }),
+        };
+        Ok(Some(Fault {
+            pc: self.u16()?,
+            kind,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_fields() {
+        let fault = Some(Fault {
+            pc: 0xE010,
+            kind: FaultKind::BusError { addr: 0xC000 },
+        });
+        let protection = Some(Fault {
+            pc: 0x1000,
+            kind: FaultKind::ProtectionFault {
+                addr: 0x0010,
+                access: Access::Write,
+            },
+        });
+        let mut writer = Writer::new();
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u64(0x0123_4567_89AB_CDEF);
+        writer.blob(b"abc");
+        writer.fault(fault);
+        writer.fault(None);
+        writer.fault(protection);
+        let data = writer.finish();
+
+        let mut reader = Reader::new(&data).unwrap();
+        assert_eq!(reader.u8(), Ok(0x12));
+        assert_eq!(reader.bool(), Ok(true));
+        assert_eq!(reader.u64(), Ok(0x0123_4567_89AB_CDEF));
+        assert_eq!(reader.blob(), Ok(b"abc".as_slice()));
+        assert_eq!(reader.fault(), Ok(fault));
+        assert_eq!(reader.fault(), Ok(None));
+        assert_eq!(reader.fault(), Ok(protection));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert_eq!(Reader::new(b"MB8").unwrap_err(), SnapshotError::BadMagic);
+        assert_eq!(
+            Reader::new(b"MB8S\x00\x63").unwrap_err(),
+            SnapshotError::UnsupportedVersion { version: 99 }
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let mut reader = Reader::new(b"MB8S\x00\x0B\x00").unwrap();
+        assert_eq!(reader.u16(), Err(SnapshotError::Truncated));
+    }
+}
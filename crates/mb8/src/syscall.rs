@@ -0,0 +1,33 @@
+//! Host side of the `SYS` opcode.
+//! `SYS` traps into the [`SyscallHandler`] registered on the VM, which reads the syscall number
+//! from `R0` and its arguments from the other registers and memory. This is separate from the
+//! assembly syscalls in `kernel/syscalls.asm`, which are called with `CALL 0xE500`.
+
+use std::fmt;
+
+use crate::{fault::FaultKind, vm::VirtualMachine};
+
+/// Handler for `SYS` instructions.
+pub trait SyscallHandler {
+    /// Handle syscall `number`, the value of `R0`. Results are written back to the VM.
+    ///
+    /// # Errors
+    /// Returns the fault to raise, e.g. [`FaultKind::UnknownSyscall`] for numbers the handler
+    /// does not implement, or [`FaultKind::Halt`] to stop the program.
+    fn syscall(&mut self, number: u8, vm: &mut VirtualMachine) -> Result<(), FaultKind>;
+}
+
+impl<F> SyscallHandler for F
+where
+    F: FnMut(u8, &mut VirtualMachine) -> Result<(), FaultKind>,
+{
+    fn syscall(&mut self, number: u8, vm: &mut VirtualMachine) -> Result<(), FaultKind> {
+        self(number, vm)
+    }
+}
+
+impl fmt::Debug for dyn SyscallHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SyscallHandler")
+    }
+}
@@ -0,0 +1,246 @@
+//! Instruction tracing.
+//! The VM reports every executed instruction to its [`Tracer`]. Tracing is off by default
+//! ([`NoopTracer`]); [`TextTracer`] and [`BinaryTracer`] write the events to any output.
+
+use std::{
+    fmt,
+    io::{self, Write},
+};
+
+use mb8_isa::{encode::encode, opcodes::Opcode};
+
+/// Direction of a memory access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Memory access made by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub addr: u16,
+    pub value: u8,
+}
+
+/// Register changed by an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    /// Register index, `0x0..=0xF`.
+    pub index: u8,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Everything an instruction did.
+#[derive(Debug)]
+pub struct TraceEvent<'a> {
+    /// Address of the instruction.
+    pub pc: u16,
+    pub opcode: &'a Opcode,
+    /// Registers whose value changed, in index order.
+    pub registers: &'a [RegisterChange],
+    /// Memory accesses in the order they were made, not counting the instruction fetch.
+    pub memory: &'a [MemoryAccess],
+}
+
+/// Receiver of per-instruction events.
+pub trait Tracer {
+    /// Whether the VM should collect events. Collecting register and memory changes
+    /// costs time, so tracers that drop events return `false`.
+    fn enabled(&self) -> bool {
+        true
+    }
+
+    fn trace(&mut self, event: &TraceEvent);
+}
+
+impl fmt::Debug for dyn Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tracer")
+    }
+}
+
+/// Tracer that ignores every event.
+#[derive(Debug, Default)]
+pub struct NoopTracer;
+
+impl Tracer for NoopTracer {
+    fn enabled(&self) -> bool {
+        false
+    }
+
+    fn trace(&mut self, _event: &TraceEvent) {}
+}
+
+/// Writes one line per instruction, e.g. `E004  4200  PUSH R0  RE=FF->FE  W[BFFF]=45`.
+///
+/// Writing stops at the first I/O error.
+#[derive(Debug)]
+pub struct TextTracer<W: Write> {
+    out: Option<W>,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Some(out) }
+    }
+
+    fn write(out: &mut W, event: &TraceEvent) -> io::Result<()> {
+        write!(
+            out,
+            "{:04X}  {:04X}  {}",
+            event.pc,
+            encode(event.opcode),
+            event.opcode
+        )?;
+        for change in event.registers {
+            write!(
+                out,
+                "  R{:X}={:02X}->{:02X}",
+                change.index, change.old, change.new
+            )?;
+        }
+        for access in event.memory {
+            let direction = match access.access {
+                Access::Read => 'R',
+                Access::Write => 'W',
+            };
+            write!(
+                out,
+                "  {direction}[{:04X}]={:02X}",
+                access.addr, access.value
+            )?;
+        }
+        writeln!(out)
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if let Some(out) = &mut self.out {
+            if Self::write(out, event).is_err() {
+                self.out = None;
+            }
+        }
+    }
+}
+
+/// Writes a compact binary trace.
+///
+/// The trace starts with [`BinaryTracer::MAGIC`], followed by one record per instruction,
+/// all numbers big-endian:
+///
+/// - `u16` PC, `u16` instruction word
+/// - `u8` number of register changes, then `u8` index and `u8` new value for each
+/// - `u8` number of memory accesses, then `u8` direction (`0` read, `1` write), `u16` address
+///   and `u8` value for each
+///
+/// Writing stops at the first I/O error.
+#[derive(Debug)]
+pub struct BinaryTracer<W: Write> {
+    out: Option<W>,
+    header: bool,
+}
+
+impl<W: Write> BinaryTracer<W> {
+    /// File signature and format version.
+    pub const MAGIC: &'static [u8; 5] = b"MB8T\x01";
+
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Some(out),
+            header: false,
+        }
+    }
+
+    fn write(&mut self, event: &TraceEvent) -> io::Result<()> {
+        let Some(out) = &mut self.out else {
+            return Ok(());
+        };
+        if !self.header {
+            out.write_all(Self::MAGIC)?;
+            self.header = true;
+        }
+        let mut record = Vec::with_capacity(6 + event.registers.len() * 2 + event.memory.len() * 4);
+        record.extend_from_slice(&event.pc.to_be_bytes());
+        record.extend_from_slice(&encode(event.opcode).to_be_bytes());
+        record.push(event.registers.len() as u8);
+        for change in event.registers {
+            record.extend_from_slice(&[change.index, change.new]);
+        }
+        record.push(event.memory.len() as u8);
+        for access in event.memory {
+            record.push(u8::from(access.access == Access::Write));
+            record.extend_from_slice(&access.addr.to_be_bytes());
+            record.push(access.value);
+        }
+        out.write_all(&record)
+    }
+}
+
+impl<W: Write> Tracer for BinaryTracer<W> {
+    fn trace(&mut self, event: &TraceEvent) {
+        if self.write(event).is_err() {
+            self.out = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use mb8_isa::registers::Register;
+
+    use super::*;
+
+    fn event<'a>(registers: &'a [RegisterChange], memory: &'a [MemoryAccess]) -> TraceEvent<'a> {
+        static PUSH: Opcode = Opcode::Push { src: Register::R0 };
+        TraceEvent {
+            pc: 0xE004,
+            opcode: &PUSH,
+            registers,
+            memory,
+        }
+    }
+
+    const CHANGES: &[RegisterChange] = &[RegisterChange {
+        index: 0xE,
+        old: 0xFF,
+        new: 0xFE,
+    }];
+    const ACCESSES: &[MemoryAccess] = &[MemoryAccess {
+        access: Access::Write,
+        addr: 0xBFFF,
+        value: 0x45,
+    }];
+
+    #[test]
+    fn writes_text_lines() {
+        let mut out = Vec::new();
+        TextTracer::new(&mut out).trace(&event(CHANGES, ACCESSES));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "E004  4200  PUSH R0  RE=FF->FE  W[BFFF]=45\n"
+        );
+    }
+
+    #[test]
+    fn writes_binary_records() {
+        let mut out = Vec::new();
+        {
+            let mut tracer = BinaryTracer::new(&mut out);
+            tracer.trace(&event(CHANGES, ACCESSES));
+            tracer.trace(&event(&[], &[]));
+        }
+        assert_eq!(
+            out,
+            [
+                b"MB8T\x01".as_slice(),
+                &[0xE0, 0x04, 0x42, 0x00, 1, 0xE, 0xFE, 1, 1, 0xBF, 0xFF, 0x45],
+                &[0xE0, 0x04, 0x42, 0x00, 0, 0],
+            ]
+            .concat()
+        );
+    }
+}
@@ -1,6 +1,23 @@
-use mb8_isa::{decode::decode, opcodes::Opcode};
+use std::time::Duration;
 
-use crate::{dev::bus::Bus, registers::Registers};
+use mb8_isa::{decode::decode, opcodes::Opcode, registers::Register, STACK_BOTTOM};
+
+use crate::{
+    dev::{bus::Bus, interrupts::InterruptController},
+    fault::{Fault, FaultKind},
+    history::{History, Step as HistoryStep},
+    mpu::{Access, Mode, Mpu},
+    registers::Registers,
+    snapshot::{Reader, Snapshot, SnapshotResult, Writer},
+    syscall::SyscallHandler,
+    trace::{MemoryAccess, NoopTracer, RegisterChange, TraceEvent, Tracer},
+};
+
+/// Default CPU clock frequency in Hz.
+pub const DEFAULT_CLOCK_HZ: u32 = 1_000_000;
+
+/// Cost of entering an interrupt handler in CPU cycles.
+pub const INTERRUPT_CYCLES: u64 = 4;
 
 /// MB8 Virtual Machine
 #[derive(Debug)]
@@ -8,113 +25,867 @@ pub struct VirtualMachine {
     pub devices: Bus,
     pub registers: Registers,
     pub halted: bool,
+    /// Whether the CPU takes maskable interrupts, set by `EI` and cleared by `DI`.
+    pub interrupts_enabled: bool,
     pub program_counter: u16,
+    /// Number of CPU cycles executed since reset.
+    pub cycles: u64,
+    /// CPU clock frequency in Hz, used by frontends to throttle execution.
+    pub clock_hz: u32,
+    /// Fault that stopped the CPU, if any.
+    pub fault: Option<Fault>,
+    /// Receiver of per-instruction events.
+    pub tracer: Box<dyn Tracer>,
+    /// Handler `SYS` traps into. Without one, `SYS` raises [`FaultKind::UnknownSyscall`].
+    pub syscalls: Option<Box<dyn SyscallHandler>>,
+    /// Recent steps for [`VirtualMachine::step_back`]. Recording is off while `None`.
+    pub history: Option<History>,
+    /// Privilege level, which only changes while the MPU is set.
+    pub mode: Mode,
+    /// Memory protection for user code. Everything is allowed while `None`.
+    pub mpu: Option<Mpu>,
 }
 
 impl Default for VirtualMachine {
     fn default() -> Self {
+        Self::new(Bus::default())
+    }
+}
+
+impl VirtualMachine {
+    /// Machine with the devices mapped on `devices`.
+    #[must_use]
+    pub fn new(devices: Bus) -> Self {
         Self {
-            devices: Bus::default(),
+            devices,
             registers: Registers::default(),
             halted: false,
+            interrupts_enabled: false,
             program_counter: 0xE000,
+            cycles: 0,
+            clock_hz: DEFAULT_CLOCK_HZ,
+            fault: None,
+            tracer: Box::new(NoopTracer),
+            syscalls: None,
+            history: None,
+            mode: Mode::default(),
+            mpu: None,
         }
     }
-}
 
-impl VirtualMachine {
     /// Execute a single instruction.
     pub fn execute(&mut self, instruction: &Opcode) {
-        match instruction {
-            Opcode::Nop => self.nop(),
-            Opcode::Halt => self.halt(),
-            Opcode::Sys => self.sys(),
-            Opcode::Mov { dst, src } => self.mov(*dst, *src),
-            Opcode::Add { dst, src } => self.add(*dst, *src),
-            Opcode::Sub { dst, src } => self.sub(*dst, *src),
-            Opcode::And { dst, src } => self.and(*dst, *src),
-            Opcode::Or { dst, src } => self.or(*dst, *src),
-            Opcode::Xor { dst, src } => self.xor(*dst, *src),
-            Opcode::Shr { dst, src } => self.shr(*dst, *src),
-            Opcode::Shl { dst, src } => self.shl(*dst, *src),
-            Opcode::Cmp { dst, src } => self.cmp(*dst, *src),
-            Opcode::Ldi { dst, value } => self.ldi(*dst, *value),
-            Opcode::Ld { dst, hi, lo } => self.ld(*dst, *hi, *lo),
-            Opcode::Jmp { hi, lo } => self.jmp(*hi, *lo),
-            Opcode::Jr { offset } => self.jr(*offset),
-            Opcode::Jzr { offset } => self.jzr(*offset),
-            Opcode::Jnzr { offset } => self.jnzr(*offset),
-            Opcode::Jcr { offset } => self.jcr(*offset),
-            Opcode::Jncr { offset } => self.jncr(*offset),
-            Opcode::Call { hi, lo } => self.call(*hi, *lo),
-            Opcode::Ret => self.ret(),
-            Opcode::Push { src } => self.push(*src),
-            Opcode::Pop { dst } => self.pop(*dst),
-            Opcode::St { src, hi, lo } => self.st(*src, *hi, *lo),
-        }
-    }
-
-    pub fn step(&mut self) {
+        instruction.dispatch(self);
+    }
+
+    /// Raise a fault from the instruction being executed and halt the CPU.
+    pub(crate) fn raise(&mut self, kind: FaultKind) {
+        self.halted = true;
+        self.devices.raise(kind);
+    }
+
+    /// Halt the CPU on a fault raised by the instruction at `pc`.
+    fn stop(&mut self, pc: u16, kind: FaultKind) -> Fault {
+        let fault = Fault { pc, kind };
+        self.halted = true;
+        self.fault = Some(fault);
+        fault
+    }
+
+    /// Execute a single instruction.
+    ///
+    /// # Errors
+    /// Returns the fault raised by the instruction, or [`FaultKind::Halt`] once the CPU has halted.
+    pub fn step(&mut self) -> Result<(), Fault> {
+        self.step_inner(false).map(drop)
+    }
+
+    /// Execute a single instruction, returning the memory accesses it made when `record`
+    /// is set or the tracer is enabled.
+    pub(crate) fn step_inner(&mut self, record: bool) -> Result<Vec<MemoryAccess>, Fault> {
+        if self.halted {
+            return self.execute_step(record);
+        }
+        let Some(mut history) = self.history.take() else {
+            return self.execute_step(record);
+        };
+        let registers = self.registers.registers;
+        let pc = self.program_counter;
+        let cycles = self.cycles;
+        let interrupts_enabled = self.interrupts_enabled;
+        let mode = self.mode;
+        self.devices.record_writes();
+
+        let result = self.execute_step(record);
+
+        history.push(HistoryStep {
+            pc,
+            cycles,
+            interrupts_enabled,
+            mode,
+            registers: register_changes(&registers, &self.registers.registers),
+            writes: self.devices.take_writes(),
+        });
+        self.history = Some(history);
+        result
+    }
+
+    /// Undo the most recent step recorded in the history, restoring the CPU and memory.
+    ///
+    /// Returns the undone step, or `None` if there is nothing left to undo. Devices are not
+    /// rewound.
+    pub fn step_back(&mut self) -> Option<HistoryStep> {
+        let step = self.history.as_mut()?.pop()?;
+        for write in step.writes.iter().rev() {
+            self.devices.write(write.addr, write.old);
+        }
+        for change in &step.registers {
+            self.registers.registers[usize::from(change.index)] = change.old;
+        }
+        self.program_counter = step.pc;
+        self.cycles = step.cycles;
+        self.interrupts_enabled = step.interrupts_enabled;
+        self.mode = step.mode;
+        // Only steps that ran are recorded, so the CPU was not halted before
+        self.halted = false;
+        self.fault = None;
+        Some(step)
+    }
+
+    fn execute_step(&mut self, record: bool) -> Result<Vec<MemoryAccess>, Fault> {
         let pc = self.program_counter;
+        if self.halted {
+            return Err(self.fault.unwrap_or(Fault {
+                pc,
+                kind: FaultKind::Halt,
+            }));
+        }
+        // Accesses made by the host are not the program's fault
+        self.devices.take_fault();
+
+        self.devices.poll_interrupts();
+        if self.interrupts_enabled {
+            let active = self
+                .devices
+                .device::<InterruptController>()
+                .and_then(|interrupts| interrupts.active());
+            if let Some(line) = active {
+                self.enter_interrupt(line);
+                self.advance(INTERRUPT_CYCLES);
+                if let Some(kind) = self.devices.take_fault() {
+                    return Err(self.stop(pc, kind));
+                }
+                return Ok(Vec::new());
+            }
+        }
+
+        if let Some(mpu) = &self.mpu {
+            match mpu.fetch(self.mode, pc) {
+                Ok(mode) => self.mode = mode,
+                Err(kind) => return Err(self.stop(pc, kind)),
+            }
+        }
+
         self.program_counter = pc.saturating_add(2);
 
         let hi = self.devices.read(pc);
-        let lo = self.devices.read(pc + 1);
+        let lo = self.devices.read(pc.wrapping_add(1));
+        if let Some(kind) = self.devices.take_fault() {
+            return Err(self.stop(pc, kind));
+        }
         let binary_instruction = u16::from_be_bytes([hi, lo]);
         let Some(opcode) = decode(binary_instruction) else {
-            self.halted = true;
-            return;
+            return Err(self.stop(
+                pc,
+                FaultKind::InvalidOpcode {
+                    word: binary_instruction,
+                },
+            ));
         };
 
-        println!("{pc:X}:\t({binary_instruction:?})");
-        println!("{opcode:?}");
-        println!("{}", self.registers);
-        // println!("=");
+        let accesses = if record || self.tracer.enabled() {
+            self.execute_traced(pc, &opcode)
+        } else {
+            self.execute(&opcode);
+            Vec::new()
+        };
+        self.advance(u64::from(opcode.info().cycles));
 
-        self.execute(&opcode);
+        if let Some(kind) = self.devices.take_fault() {
+            return Err(self.stop(pc, kind));
+        }
+        if self.halted {
+            return Err(self.stop(pc, FaultKind::Halt));
+        }
+        Ok(accesses)
+    }
+
+    /// Account for `cycles` spent by the CPU and let devices catch up.
+    fn advance(&mut self, cycles: u64) {
+        self.cycles += cycles;
+        self.devices.tick(cycles);
+    }
+
+    /// Push the PC and flags, disable interrupts and jump to the handler of `line`.
+    ///
+    /// The handler returns with `RETI`, after acknowledging the line in the controller.
+    fn enter_interrupt(&mut self, line: u8) {
+        if self.mpu.is_some() {
+            self.mode = Mode::Supervisor;
+        }
+        let vector = self.devices.interrupts().vector(line);
+        let handler = u16::from_be_bytes([self.read(vector), self.read(vector.wrapping_add(1))]);
+        let mut stack_pointer = u16::from_be_bytes([
+            self.registers.read(Register::SPH),
+            self.registers.read(Register::SPL),
+        ]);
+        let [pc_hi, pc_lo] = self.program_counter.to_be_bytes();
+
+        for byte in [pc_lo, pc_hi, self.registers.read(Register::F)] {
+            if stack_pointer as usize <= STACK_BOTTOM {
+                self.raise(FaultKind::StackOverflow);
+                return;
+            }
+            self.write(stack_pointer, byte);
+            stack_pointer -= 1;
+        }
+
+        let [sp_hi, sp_lo] = stack_pointer.to_be_bytes();
+        self.registers.write(Register::SPH, sp_hi);
+        self.registers.write(Register::SPL, sp_lo);
+        self.interrupts_enabled = false;
+        self.program_counter = handler;
+    }
+
+    /// Read a byte on behalf of the program. Accesses the MPU denies record a fault and
+    /// read `0`.
+    pub(crate) fn read(&mut self, addr: u16) -> u8 {
+        match self.protect(addr, Access::Read) {
+            Ok(()) => self.devices.read(addr),
+            Err(kind) => {
+                self.devices.raise(kind);
+                0
+            }
+        }
     }
 
-    /// Execute a program.
-    pub fn run(&mut self) {
-        while !self.halted {
-            self.step();
+    /// Write a byte on behalf of the program. Accesses the MPU denies record a fault and
+    /// are dropped.
+    pub(crate) fn write(&mut self, addr: u16, value: u8) {
+        match self.protect(addr, Access::Write) {
+            Ok(()) => self.devices.write(addr, value),
+            Err(kind) => self.devices.raise(kind),
         }
     }
 
+    fn protect(&self, addr: u16, access: Access) -> Result<(), FaultKind> {
+        self.mpu
+            .as_ref()
+            .map_or(Ok(()), |mpu| mpu.check(self.mode, addr, access))
+    }
+
+    /// Execute an instruction, report what it changed to the tracer and return its memory
+    /// accesses.
+    fn execute_traced(&mut self, pc: u16, opcode: &Opcode) -> Vec<MemoryAccess> {
+        let before = self.registers.registers;
+        self.devices.record_accesses();
+        self.execute(opcode);
+        let memory = self.devices.take_accesses();
+        if !self.tracer.enabled() {
+            return memory;
+        }
+        let registers = register_changes(&before, &self.registers.registers);
+        self.tracer.trace(&TraceEvent {
+            pc,
+            opcode,
+            registers: &registers,
+            memory: &memory,
+        });
+        memory
+    }
+
+    /// Execute a program until it halts.
+    ///
+    /// # Errors
+    /// Returns the fault that stopped the program, unless it stopped on `HALT`.
+    pub fn run(&mut self) -> Result<(), Fault> {
+        loop {
+            match self.step() {
+                Ok(()) => {}
+                Err(Fault {
+                    kind: FaultKind::Halt,
+                    ..
+                }) => return Ok(()),
+                Err(fault) => return Err(fault),
+            }
+        }
+    }
+
+    /// Execute instructions until at least `budget` cycles have passed or the VM halts.
+    ///
+    /// Returns the number of cycles actually executed, which may exceed the budget by
+    /// the cost of the last instruction.
+    ///
+    /// # Errors
+    /// Returns the fault that stopped the program, unless it stopped on `HALT`.
+    pub fn run_for_cycles(&mut self, budget: u64) -> Result<u64, Fault> {
+        let start = self.cycles;
+        let target = start.saturating_add(budget);
+        while self.cycles < target {
+            match self.step() {
+                Ok(()) => {}
+                Err(Fault {
+                    kind: FaultKind::Halt,
+                    ..
+                }) => break,
+                Err(fault) => return Err(fault),
+            }
+        }
+        Ok(self.cycles - start)
+    }
+
+    /// Number of cycles the CPU executes in `duration` at its clock frequency.
+    #[must_use]
+    pub fn cycles_for(&self, duration: Duration) -> u64 {
+        (u128::from(self.clock_hz) * duration.as_nanos() / 1_000_000_000) as u64
+    }
+
+    /// Load the boot image into ROM and lock it, so stores into it fault or are dropped
+    /// according to its [`WritePolicy`](crate::dev::rom::WritePolicy).
     pub fn load_rom(&mut self, rom: &[u8]) {
-        for (i, &byte) in rom.iter().enumerate() {
-            self.devices.write((0xE000 + i) as u16, byte);
+        self.devices.load_rom(rom);
+    }
+
+    /// Save the CPU and device state.
+    ///
+    /// The clock frequency, tracer, syscall handler, history and MPU regions are host settings
+    /// and are not saved.
+    #[must_use]
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        writer.bytes(&self.registers.registers);
+        writer.u16(self.program_counter);
+        writer.bool(self.halted);
+        writer.bool(self.interrupts_enabled);
+        writer.bool(self.mode == Mode::User);
+        writer.u64(self.cycles);
+        writer.fault(self.fault);
+        self.devices.save(&mut writer);
+        writer.finish()
+    }
+
+    /// Restore a state saved by [`VirtualMachine::save_state`] on a VM with the same memory map,
+    /// clearing the history.
+    /// The VM is left unchanged on error.
+    ///
+    /// # Errors
+    /// Returns an error if the snapshot is invalid or from an unsupported version.
+    pub fn load_state(&mut self, data: &[u8]) -> SnapshotResult {
+        let mut reader = Reader::new(data)?;
+        let mut registers = Registers::default();
+        reader.fill(&mut registers.registers)?;
+        let program_counter = reader.u16()?;
+        let halted = reader.bool()?;
+        let interrupts_enabled = reader.bool()?;
+        let mode = if reader.bool()? {
+            Mode::User
+        } else {
+            Mode::Supervisor
+        };
+        let cycles = reader.u64()?;
+        let fault = reader.fault()?;
+        self.devices.restore(|devices| {
+            devices.load(&mut reader)?;
+            reader.finish()
+        })?;
+
+        self.registers = registers;
+        self.program_counter = program_counter;
+        self.halted = halted;
+        self.interrupts_enabled = interrupts_enabled;
+        self.mode = mode;
+        self.cycles = cycles;
+        self.fault = fault;
+        if let Some(history) = &mut self.history {
+            history.clear();
         }
+        Ok(())
     }
 }
 
+/// Registers that differ between `before` and `after`, in index order.
+fn register_changes(before: &[u8], after: &[u8]) -> Vec<RegisterChange> {
+    (0u8..)
+        .zip(before.iter().zip(after))
+        .filter(|(_, (old, new))| old != new)
+        .map(|(index, (&old, &new))| RegisterChange { index, old, new })
+        .collect()
+}
+
+mb8_isa::impl_execute!(VirtualMachine);
+
 #[cfg(test)]
 mod tests {
-    // use super::*;
-
-    // #[test]
-    // TODO
-    // fn test_vm() {
-    //     let mut vm = VirtualMachine::default();
-    //     vm.load_rom(&[0x00, 0x00, 0x01, 0x00]);
-    //     vm.run();
-    //     assert_eq!(vm.program_counter, 4);
-    // }
-
-    // #[test]
-    // fn test_end_of_memory() {
-    //     let mut vm = VirtualMachine::default();
-    //     vm.program_counter = 4095;
-    //     vm.step();
-    //     assert!(vm.halted);
-    // }
-
-    // TODO
-    // #[test]
-    // fn test_invalid_opcode() {
-    //     let mut vm = VirtualMachine::default();
-    //     vm.load_rom(&[0xFF]);
-    //     vm.step();
-    //     assert!(vm.halted);
-    // }
+    use std::{cell::RefCell, rc::Rc};
+
+    use mb8_isa::encode::encode_program;
+
+    use crate::{dev::rom::WritePolicy, history::MemoryWrite, trace::Access};
+
+    use super::*;
+
+    fn vm_with_program(program: &[Opcode]) -> VirtualMachine {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&encode_program(program));
+        vm
+    }
+
+    #[test]
+    fn counts_cycles_per_opcode() {
+        let mut vm = vm_with_program(&[
+            Opcode::Nop,
+            Opcode::Push { src: Register::R0 },
+            Opcode::Halt,
+        ]);
+        vm.step().unwrap();
+        assert_eq!(vm.cycles, 1);
+        vm.step().unwrap();
+        assert_eq!(vm.cycles, 3);
+        vm.run().unwrap();
+        assert_eq!(vm.cycles, 4);
+    }
+
+    #[test]
+    fn runs_for_cycles_budget() {
+        // JR -2 loops forever, 2 cycles per iteration
+        let mut vm = vm_with_program(&[Opcode::Jr { offset: -2 }]);
+        assert_eq!(vm.run_for_cycles(10), Ok(10));
+        assert_eq!(vm.run_for_cycles(5), Ok(6));
+        assert_eq!(vm.cycles, 16);
+        assert!(!vm.halted);
+    }
+
+    #[test]
+    fn stops_running_cycles_when_halted() {
+        let mut vm = vm_with_program(&[Opcode::Nop, Opcode::Halt]);
+        assert_eq!(vm.run_for_cycles(1000), Ok(2));
+        assert!(vm.halted);
+    }
+
+    type Event = (u16, Vec<RegisterChange>, Vec<MemoryAccess>);
+
+    #[derive(Default)]
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl Tracer for Recorder {
+        fn trace(&mut self, event: &TraceEvent) {
+            self.0
+                .borrow_mut()
+                .push((event.pc, event.registers.to_vec(), event.memory.to_vec()));
+        }
+    }
+
+    #[test]
+    fn traces_register_and_memory_changes() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0x45,
+            },
+            Opcode::Push { src: Register::R0 },
+            Opcode::Halt,
+        ]);
+        let recorder = Recorder::default();
+        let events = recorder.0.clone();
+        vm.tracer = Box::new(recorder);
+        vm.run().unwrap();
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0],
+            (
+                0xE000,
+                vec![RegisterChange {
+                    index: 0,
+                    old: 0,
+                    new: 0x45
+                }],
+                vec![]
+            )
+        );
+        assert_eq!(
+            events[1],
+            (
+                0xE002,
+                vec![RegisterChange {
+                    index: 0xE,
+                    old: 0xFF,
+                    new: 0xFE
+                }],
+                vec![MemoryAccess {
+                    access: Access::Write,
+                    addr: 0xBFFF,
+                    value: 0x45
+                }]
+            )
+        );
+    }
+
+    /// Loops forever with interrupts enabled; the keyboard handler at `0xE004` acknowledges
+    /// the IRQ and counts interrupts in `R0`.
+    fn vm_with_keyboard_handler() -> VirtualMachine {
+        let mut vm = vm_with_program(&[
+            Opcode::Ei,
+            Opcode::Jr { offset: -2 },
+            Opcode::Ldi {
+                dst: Register::R4,
+                value: 1,
+            },
+            Opcode::Add {
+                dst: Register::R0,
+                src: Register::R4,
+            },
+            Opcode::Ldi {
+                dst: Register::R1,
+                value: 0xF5,
+            },
+            Opcode::Ldi {
+                dst: Register::R2,
+                value: 0x01,
+            },
+            Opcode::Ldi {
+                dst: Register::R3,
+                value: 0x02,
+            },
+            Opcode::St {
+                src: Register::R3,
+                hi: Register::R1,
+                lo: Register::R2,
+            },
+            Opcode::Reti,
+        ]);
+        // Vector table at 0x1000, keyboard entry points at the handler
+        vm.devices.write(0xF502, 0x10);
+        vm.devices.write(0xF503, 0x00);
+        vm.devices.write(0x1002, 0xE0);
+        vm.devices.write(0x1003, 0x04);
+        vm.devices.write(0xF500, 0x02);
+        vm
+    }
+
+    #[test]
+    fn takes_keyboard_interrupt() {
+        let mut vm = vm_with_keyboard_handler();
+        vm.step().unwrap();
+        vm.step().unwrap();
+        vm.devices.keyboard().key_pressed(b'a');
+        vm.step().unwrap();
+        assert_eq!(vm.program_counter, 0xE004);
+        assert!(!vm.interrupts_enabled);
+        assert_eq!(vm.registers.read(Register::SPL), 0xFC);
+        assert_eq!(vm.devices.read(0xBFFE), 0xE0);
+        assert_eq!(vm.devices.read(0xBFFF), 0x02);
+
+        vm.run_for_cycles(20).unwrap();
+        assert_eq!(vm.registers.read(Register::R0), 1);
+        assert_eq!(vm.program_counter, 0xE002);
+        assert_eq!(vm.registers.read(Register::SPL), 0xFF);
+        assert!(vm.interrupts_enabled);
+    }
+
+    #[test]
+    fn ignores_masked_interrupts() {
+        let mut vm = vm_with_keyboard_handler();
+        vm.step().unwrap();
+        vm.devices.write(0xF500, 0x00);
+        vm.devices.keyboard().key_pressed(b'a');
+        vm.run_for_cycles(10).unwrap();
+        assert_eq!(vm.program_counter, 0xE002);
+
+        // The request stays pending until the line is enabled
+        vm.devices.write(0xF500, 0x02);
+        vm.run_for_cycles(30).unwrap();
+        assert_eq!(vm.registers.read(Register::R0), 1);
+    }
+
+    #[test]
+    fn reports_halt() {
+        let mut vm = vm_with_program(&[Opcode::Nop, Opcode::Halt]);
+        vm.step().unwrap();
+        let halt = Fault {
+            pc: 0xE002,
+            kind: FaultKind::Halt,
+        };
+        assert_eq!(vm.step(), Err(halt));
+        assert_eq!(vm.step(), Err(halt));
+        assert_eq!(vm.run(), Ok(()));
+    }
+
+    #[test]
+    fn runs_rom_until_halt() {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&[0x00, 0x00, 0x01, 0x00]);
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(vm.program_counter, 0xE004);
+        assert_eq!(
+            vm.fault,
+            Some(Fault {
+                pc: 0xE002,
+                kind: FaultKind::Halt,
+            })
+        );
+    }
+
+    #[test]
+    fn faults_on_invalid_first_opcode() {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&[0xFF]);
+        assert_eq!(
+            vm.step(),
+            Err(Fault {
+                pc: 0xE000,
+                kind: FaultKind::InvalidOpcode { word: 0xFF00 },
+            })
+        );
+        assert!(vm.halted);
+    }
+
+    #[test]
+    fn faults_on_invalid_opcode() {
+        let mut vm = VirtualMachine::default();
+        vm.load_rom(&[0x00, 0x00, 0xFF, 0xFF]);
+        assert_eq!(
+            vm.run(),
+            Err(Fault {
+                pc: 0xE002,
+                kind: FaultKind::InvalidOpcode { word: 0xFFFF },
+            })
+        );
+        assert!(vm.halted);
+    }
+
+    #[test]
+    fn faults_on_unmapped_access() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xF8,
+            },
+            Opcode::Ld {
+                dst: Register::R2,
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+        ]);
+        assert_eq!(
+            vm.run(),
+            Err(Fault {
+                pc: 0xE002,
+                kind: FaultKind::BusError { addr: 0xF800 },
+            })
+        );
+    }
+
+    #[test]
+    fn faults_on_rom_write() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xE0,
+            },
+            Opcode::St {
+                src: Register::R2,
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+        ]);
+        assert_eq!(
+            vm.run(),
+            Err(Fault {
+                pc: 0xE002,
+                kind: FaultKind::RomWrite { addr: 0xE000 },
+            })
+        );
+        assert_eq!(vm.devices.read(0xE000), 0x20);
+    }
+
+    #[test]
+    fn ignores_rom_writes_by_policy() {
+        let program = [
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xE0,
+            },
+            Opcode::St {
+                src: Register::R2,
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+            Opcode::Halt,
+        ];
+        let mut vm = vm_with_program(&program);
+        vm.devices.rom().write_policy = WritePolicy::Ignore;
+        vm.run().unwrap();
+        assert_eq!(vm.devices.read(0xE000), 0x20);
+
+        // Loading a new image locks the ROM again
+        let mut vm = vm_with_program(&program);
+        vm.devices.rom().unlock();
+        vm.load_rom(&encode_program(&program));
+        assert!(vm.devices.rom().is_locked());
+    }
+
+    #[test]
+    fn traps_sys_into_handler() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R1,
+                value: b'A',
+            },
+            Opcode::Sys,
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0x01,
+            },
+            Opcode::Sys,
+        ]);
+        let output = Rc::new(RefCell::new(Vec::new()));
+        let sink = output.clone();
+        // 0x00 prints R1, 0x01 exits
+        vm.syscalls = Some(Box::new(move |number: u8, vm: &mut VirtualMachine| {
+            match number {
+                0x00 => sink.borrow_mut().push(vm.registers.read(Register::R1)),
+                0x01 => return Err(FaultKind::Halt),
+                number => return Err(FaultKind::UnknownSyscall { number }),
+            }
+            Ok(())
+        }));
+        assert_eq!(vm.run(), Ok(()));
+        assert_eq!(*output.borrow(), b"A");
+        assert_eq!(
+            vm.fault,
+            Some(Fault {
+                pc: 0xE006,
+                kind: FaultKind::Halt
+            })
+        );
+    }
+
+    #[test]
+    fn faults_on_unknown_syscall() {
+        let mut vm = vm_with_program(&[Opcode::Sys]);
+        assert_eq!(
+            vm.run(),
+            Err(Fault {
+                pc: 0xE000,
+                kind: FaultKind::UnknownSyscall { number: 0 },
+            })
+        );
+    }
+
+    #[test]
+    fn faults_on_stack_underflow() {
+        let mut vm = vm_with_program(&[Opcode::Pop { dst: Register::R0 }]);
+        assert_eq!(
+            vm.run(),
+            Err(Fault {
+                pc: 0xE000,
+                kind: FaultKind::StackUnderflow,
+            })
+        );
+    }
+
+    #[test]
+    fn faults_on_stack_overflow() {
+        let mut vm = vm_with_program(&[
+            Opcode::Push { src: Register::R0 },
+            Opcode::Jr { offset: -4 },
+        ]);
+        let fault = vm.run().unwrap_err();
+        assert_eq!(fault.kind, FaultKind::StackOverflow);
+        assert_eq!(fault.pc, 0xE000);
+    }
+
+    #[test]
+    fn steps_back_through_history() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0x41,
+            },
+            Opcode::Push { src: Register::R0 },
+            Opcode::Halt,
+        ]);
+        vm.history = Some(History::new(2));
+        vm.run().unwrap();
+        assert_eq!(vm.history.as_ref().map(History::len), Some(2));
+
+        let step = vm.step_back().unwrap();
+        assert_eq!(step.pc, 0xE004);
+        assert!(!vm.halted);
+        assert_eq!(vm.fault, None);
+        let step = vm.step_back().unwrap();
+        assert_eq!(
+            step.writes,
+            [MemoryWrite {
+                addr: 0xBFFF,
+                old: 0,
+                new: 0x41
+            }]
+        );
+        assert_eq!(vm.devices.read(0xBFFF), 0);
+        assert_eq!(vm.registers.read(Register::SPL), 0xFF);
+        assert_eq!(vm.registers.read(Register::R0), 0x41);
+        assert_eq!(vm.program_counter, 0xE002);
+        assert_eq!(vm.cycles, 1);
+        // The LDI fell out of the history
+        assert_eq!(vm.step_back(), None);
+
+        vm.run().unwrap();
+        assert_eq!(vm.devices.read(0xBFFF), 0x41);
+    }
+
+    #[test]
+    fn steps_back_across_bank_switches() {
+        let mut vm = vm_with_program(&[
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xF7,
+            },
+            Opcode::Ldi {
+                dst: Register::R2,
+                value: 1,
+            },
+            Opcode::St {
+                src: Register::R2,
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+            Opcode::Ldi {
+                dst: Register::R0,
+                value: 0xC0,
+            },
+            Opcode::St {
+                src: Register::R2,
+                hi: Register::R0,
+                lo: Register::R1,
+            },
+            Opcode::Halt,
+        ]);
+        vm.history = Some(History::default());
+        vm.run().unwrap();
+        assert_eq!(vm.devices.expansion().data(1).unwrap()[0], 1);
+
+        while vm.step_back().is_some() {}
+        assert_eq!(vm.devices.expansion().bank(), 0);
+        assert_eq!(vm.devices.expansion().data(1).unwrap()[0], 0);
+    }
+
+    #[test]
+    fn converts_duration_to_cycles() {
+        let mut vm = VirtualMachine::default();
+        vm.clock_hz = 2_000_000;
+        assert_eq!(vm.cycles_for(Duration::from_millis(16)), 32_000);
+    }
 }
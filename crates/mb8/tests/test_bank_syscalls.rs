@@ -0,0 +1,40 @@
+use mb8::{dev::bus::BusBuilder, snapshot::SnapshotError, vm::VirtualMachine};
+use mb8_asm::assemble_file;
+
+#[test]
+fn test_sys_bank_switches_window() {
+    let bin = assemble_file("../../kernel/tests/test_sys_bank.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    assert_eq!(vm.devices.read(0x0200), 0);
+    // Bank 0 was never written
+    assert_eq!(vm.devices.read(0x0201), 0);
+    assert_eq!(vm.devices.read(0x0202), 1);
+    assert_eq!(vm.devices.expansion().bank(), 0);
+    assert_eq!(vm.devices.expansion().data(1).unwrap()[0], 0xAB);
+}
+
+#[test]
+fn test_snapshot_keeps_banks() {
+    let bin = assemble_file("../../kernel/tests/test_sys_bank.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+    let state = vm.save_state();
+
+    let mut restored = VirtualMachine::default();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.devices.expansion().data(1).unwrap()[0], 0xAB);
+
+    let mut small = VirtualMachine::new(BusBuilder::with_banks(2).build());
+    assert_eq!(
+        small.load_state(&state),
+        Err(SnapshotError::Invalid {
+            field: "expansion bank count"
+        })
+    );
+}
@@ -0,0 +1,103 @@
+use mb8::{
+    dev::{
+        bus::{BusBuilder, MapError},
+        Device, DeviceResult,
+    },
+    snapshot::{Reader, Snapshot, SnapshotResult, Writer},
+    vm::VirtualMachine,
+};
+use mb8_isa::{encode::encode_program, opcodes::Opcode, registers::Register};
+
+/// Output port that collects every byte written to it.
+#[derive(Debug, Default)]
+struct Port {
+    output: Vec<u8>,
+}
+
+impl Device for Port {
+    fn read(&mut self, _addr: u16) -> DeviceResult<u8> {
+        Ok(self.output.len() as u8)
+    }
+
+    fn write(&mut self, _addr: u16, value: u8) -> DeviceResult {
+        self.output.push(value);
+        Ok(())
+    }
+}
+
+impl Snapshot for Port {
+    fn save(&self, writer: &mut Writer) {
+        writer.blob(&self.output);
+    }
+
+    fn load(&mut self, reader: &mut Reader) -> SnapshotResult {
+        self.output = reader.blob()?.to_vec();
+        Ok(())
+    }
+}
+
+fn port_vm() -> Result<VirtualMachine, MapError> {
+    let mut vm = VirtualMachine::new(
+        BusBuilder::standard()
+            .map(0xF800..=0xF800, Box::new(Port::default()))?
+            .build(),
+    );
+    vm.load_rom(&encode_program(&[
+        Opcode::Ldi {
+            dst: Register::R0,
+            value: 0xF8,
+        },
+        Opcode::Ldi {
+            dst: Register::R1,
+            value: 0x00,
+        },
+        Opcode::Ldi {
+            dst: Register::R2,
+            value: b'h',
+        },
+        Opcode::St {
+            src: Register::R2,
+            hi: Register::R0,
+            lo: Register::R1,
+        },
+        Opcode::Ldi {
+            dst: Register::R2,
+            value: b'i',
+        },
+        Opcode::St {
+            src: Register::R2,
+            hi: Register::R0,
+            lo: Register::R1,
+        },
+        Opcode::Halt,
+    ]));
+    Ok(vm)
+}
+
+#[test]
+fn test_program_drives_custom_device() {
+    let mut vm = port_vm().unwrap();
+    vm.run().unwrap();
+    assert_eq!(vm.devices.device::<Port>().unwrap().output, b"hi");
+    assert_eq!(vm.devices.read(0xF800), 2);
+}
+
+#[test]
+fn test_snapshot_includes_custom_device() {
+    let mut vm = port_vm().unwrap();
+    for _ in 0..4 {
+        vm.step().unwrap();
+    }
+    let state = vm.save_state();
+    vm.run().unwrap();
+
+    let mut restored = port_vm().unwrap();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.devices.device::<Port>().unwrap().output, b"h");
+    restored.run().unwrap();
+    assert_eq!(restored.save_state(), vm.save_state());
+
+    // The standard machine has no port, so the snapshot does not fit it
+    let mut standard = VirtualMachine::default();
+    assert!(standard.load_state(&state).is_err());
+}
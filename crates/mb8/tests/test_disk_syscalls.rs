@@ -1,17 +1,25 @@
-use mb8::vm::VirtualMachine;
+use mb8::{
+    dev::disk::{
+        registers::{DISK_ERR_BAD_BLOCK, DISK_ERR_NONE},
+        BLOCK_SIZE,
+    },
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+use mb8_isa::registers::Register;
 
 #[test]
 fn test_sys_disk_set_block() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_disk_set_block.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_disk_set_block.asm").unwrap();
     let mut vm = VirtualMachine::default();
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
     assert_eq!(vm.devices.read(0xF200), 0x01);
 }
 
 #[test]
 fn test_sys_disk_read_block() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_disk_read_block.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_disk_read_block.asm").unwrap();
     let mut img = vec![0; 65536].into_boxed_slice();
     for i in 0..256 {
         img[i + 256] = i as u8;
@@ -19,8 +27,8 @@ fn test_sys_disk_read_block() {
 
     let mut vm = VirtualMachine::default();
     vm.devices.disk().set(img.try_into().unwrap());
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     for i in 0..256 {
         assert_eq!(vm.devices.read(0xF202 + i), i as u8);
@@ -29,12 +37,29 @@ fn test_sys_disk_read_block() {
 
 #[test]
 fn test_sys_disk_write_block() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_disk_write_block.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_disk_write_block.asm").unwrap();
     let mut vm = VirtualMachine::default();
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.devices.disk().dump()[255], 0);
     assert_eq!(vm.devices.disk().dump()[256], 228);
     assert_eq!(vm.devices.disk().dump()[257], 0);
 }
+
+#[test]
+fn test_sys_disk_select() {
+    let bin = assemble_file("../../kernel/tests/test_sys_disk_select.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.devices.disk().insert(1, vec![0; 300 * BLOCK_SIZE]);
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    assert_eq!(vm.registers.read(Register::R4), DISK_ERR_NONE);
+    assert_eq!(vm.registers.read(Register::R0), DISK_ERR_BAD_BLOCK);
+    let mut block = [0; BLOCK_SIZE];
+    vm.devices.disk().read_block(1, 0x0102, &mut block).unwrap();
+    assert_eq!(block[0], 0x5A);
+    // Drive 0 is untouched
+    assert!(vm.devices.disk().dump().iter().all(|byte| *byte == 0));
+}
@@ -1,17 +1,18 @@
 use mb8::vm::VirtualMachine;
+use mb8_asm::assemble_file;
 use mb8_isa::registers::Register;
 
 #[test]
 fn test_sys_fs_list() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_fs_list.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_fs_list.asm").unwrap();
     let mut vm = VirtualMachine::default();
     let mut img = vec![0; 65536].into_boxed_slice();
     for i in 0..256 {
         img[i] = i as u8;
     }
     vm.devices.disk().set(img.try_into().unwrap());
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     for i in 0..256 {
         assert_eq!(vm.devices.read(0x0150 + i), i as u8, "{i:?}");
@@ -20,7 +21,7 @@ fn test_sys_fs_list() {
 
 #[test]
 fn test_sys_fs_find() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_fs_find.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_fs_find.asm").unwrap();
     let mut img = vec![0; 65536].into_boxed_slice();
     img[0] = 1; // status
     img[1] = 2; // start block
@@ -29,8 +30,8 @@ fn test_sys_fs_find() {
 
     let mut vm = VirtualMachine::default();
     vm.devices.disk().set(img.try_into().unwrap());
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 0);
     assert_eq!(vm.registers.read(Register::R1), 2);
@@ -39,7 +40,7 @@ fn test_sys_fs_find() {
 
 #[test]
 fn test_sys_fs_find_not_exist() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_fs_find.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_fs_find.asm").unwrap();
     let mut img = vec![0; 65536].into_boxed_slice();
     img[0] = 1; // status
     img[1] = 2; // start block
@@ -48,15 +49,15 @@ fn test_sys_fs_find_not_exist() {
 
     let mut vm = VirtualMachine::default();
     vm.devices.disk().set(img.try_into().unwrap());
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 1);
 }
 
 #[test]
 fn test_sys_fs_read() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_fs_read.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_fs_read.asm").unwrap();
     let mut img = vec![0; 65536].into_boxed_slice();
     img[0] = 1; // status
     img[1] = 2; // start block
@@ -66,8 +67,8 @@ fn test_sys_fs_read() {
 
     let mut vm = VirtualMachine::default();
     vm.devices.disk().set(img.try_into().unwrap());
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 0);
 
@@ -0,0 +1,91 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use mb8::{gdb::GdbStub, vm::VirtualMachine};
+use mb8_isa::{encode::encode_program, opcodes::Opcode, registers::Register};
+
+/// Minimal RSP client that expects every packet to be acknowledged.
+struct Client(TcpStream);
+
+impl Client {
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, u8::wrapping_add);
+        write!(self.0, "${data}#{checksum:02x}")?;
+        let mut ack = [0];
+        self.0.read_exact(&mut ack)?;
+        if ack != *b"+" {
+            return Err(io::Error::other("packet not acknowledged"));
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<String> {
+        let mut packet = Vec::new();
+        let mut byte = [0];
+        while byte != *b"#" {
+            self.0.read_exact(&mut byte)?;
+            packet.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        self.0.read_exact(&mut checksum)?;
+        self.0.write_all(b"+")?;
+        let packet = String::from_utf8_lossy(&packet);
+        Ok(packet[1..packet.len() - 1].to_string())
+    }
+
+    fn request(&mut self, data: &str) -> io::Result<String> {
+        self.send(data)?;
+        self.receive()
+    }
+}
+
+#[test]
+fn test_gdb_session_over_tcp() {
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&encode_program(&[
+        Opcode::Ldi {
+            dst: Register::R0,
+            value: 0x41,
+        },
+        Opcode::Jr { offset: -2 },
+    ]));
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client(stream);
+        assert!(client.request("qSupported").unwrap().contains("PacketSize"));
+        assert_eq!(client.request("?").unwrap(), "S05");
+        assert_eq!(client.request("s").unwrap(), "S05");
+        assert_eq!(client.request("p0").unwrap(), "41");
+        assert_eq!(client.request("p11").unwrap(), "e002");
+
+        // The loop never ends on its own
+        client.send("c").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        client.0.write_all(&[0x03]).unwrap();
+        assert_eq!(client.receive().unwrap(), "S02");
+
+        assert_eq!(client.request("Z0,e002,2").unwrap(), "OK");
+        assert_eq!(client.request("c").unwrap(), "S05");
+        assert_eq!(client.request("M1000,1:5a").unwrap(), "OK");
+        assert_eq!(client.request("m1000,1").unwrap(), "5a");
+        assert_eq!(client.request("P0=00").unwrap(), "OK");
+        client.send("k").unwrap();
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    stream.set_nodelay(true).unwrap();
+    GdbStub::new(stream).serve(&mut vm).unwrap();
+    client.join().unwrap();
+
+    assert_eq!(vm.program_counter, 0xE002);
+    assert_eq!(vm.registers.read(Register::R0), 0x00);
+    assert_eq!(vm.devices.read(0x1000), 0x5A);
+}
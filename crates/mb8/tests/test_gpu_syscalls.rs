@@ -1,33 +1,61 @@
 use mb8::vm::VirtualMachine;
+use mb8_asm::assemble_file;
 
 #[test]
 fn test_sys_gpu_mode() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_gpu_mode.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_gpu_mode.asm").unwrap();
     let mut vm = VirtualMachine::default();
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
     assert_eq!(vm.devices.read(0xF000), 0x01);
 }
 
 #[test]
 fn test_sys_write() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_write.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_write.asm").unwrap();
     let mut vm = VirtualMachine::default();
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
     let expected = [b'1', b'2', b'3'];
     assert_eq!(vm.devices.gpu().tty_buffer()[0..3], expected);
 }
 
 #[test]
 fn test_sys_writeln() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_writeln.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_writeln.asm").unwrap();
     let mut vm = VirtualMachine::default();
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
     let expected = "Hello, World!\0"
         .chars()
         .map(|c| c as u8)
         .collect::<Vec<u8>>();
     assert_eq!(vm.devices.gpu().tty_buffer()[0..14], expected);
 }
+
+#[test]
+fn test_sys_gpu_plot() {
+    let bin = assemble_file("../../kernel/tests/test_sys_gpu_plot.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+    let bitmap = vm.devices.gpu().bitmap();
+    assert_eq!(bitmap[2 * 160 + 3], 0x08);
+    assert_eq!(bitmap[0], 0x01);
+    assert_eq!(bitmap[160 * 100 - 1], 0x01);
+}
+
+#[test]
+fn test_sys_tty_cursor() {
+    let bin = assemble_file("../../kernel/tests/test_sys_tty_cursor.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+    let gpu = vm.devices.gpu();
+    let cell = 3 * 40 + 10;
+    assert_eq!(gpu.tty_buffer()[0], b' ');
+    assert_eq!(gpu.tty_buffer()[cell], b'b');
+    assert_eq!(gpu.tty_colors()[cell], 0x12);
+    assert_eq!(gpu.tty_styles()[cell], 0x01);
+    assert_eq!(gpu.cursor(), (11, 3));
+}
@@ -0,0 +1,30 @@
+use mb8::{
+    dev::joystick::registers::{FIRE, RIGHT, UP},
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+use mb8_isa::registers::Register;
+
+#[test]
+fn test_sys_joystick() {
+    let bin = assemble_file("../../kernel/tests/test_sys_joystick.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.devices.joystick().press(0, UP);
+    vm.devices.joystick().press(1, RIGHT | FIRE);
+    vm.run().unwrap();
+
+    assert_eq!(vm.registers.read(Register::R0), RIGHT | FIRE);
+}
+
+#[test]
+fn test_joystick_state_survives_snapshot() {
+    let mut vm = VirtualMachine::default();
+    vm.devices.joystick().set_port(0, UP | FIRE);
+    let state = vm.save_state();
+
+    let mut restored = VirtualMachine::default();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.devices.joystick().port(0), UP | FIRE);
+    assert_eq!(restored.devices.read(0xFB00), UP | FIRE);
+}
@@ -0,0 +1,30 @@
+use mb8::{
+    dev::keyboard::{
+        registers::{EVENT_RELEASE, MOD_SHIFT},
+        scancodes::{LEFT_SHIFT, UP},
+    },
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+use mb8_isa::registers::Register;
+
+#[test]
+fn test_sys_key_event() {
+    let bin = assemble_file("../../kernel/tests/test_sys_key_event.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    let keyboard = vm.devices.keyboard();
+    keyboard.key_down(UP);
+    keyboard.key_down(LEFT_SHIFT);
+    keyboard.key_up(UP);
+    keyboard.key_pressed(b'x');
+    vm.run().unwrap();
+
+    assert_eq!(vm.registers.read(Register::R2), UP);
+    assert_eq!(vm.registers.read(Register::R3), MOD_SHIFT);
+    assert_eq!(vm.registers.read(Register::R0), LEFT_SHIFT);
+    // The release and the typed character are still waiting
+    assert_eq!(vm.devices.read(0xF103), 1);
+    assert_eq!(vm.devices.read(0xF102), UP | EVENT_RELEASE);
+    assert_eq!(vm.devices.read(0xF101), b'x');
+}
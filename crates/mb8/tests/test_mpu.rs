@@ -0,0 +1,109 @@
+use mb8::{
+    fault::{Fault, FaultKind},
+    mpu::{Access, Mode, Mpu, Permissions},
+    vm::VirtualMachine,
+};
+use mb8_isa::{encode::encode_program, opcodes::Opcode, registers::Register};
+
+const GATE: u16 = 0xE006;
+
+fn ldi(dst: Register, value: u8) -> Opcode {
+    Opcode::Ldi { dst, value }
+}
+
+/// Kernel that starts the user program at `0x1000` and serves one call at [`GATE`] by storing
+/// `0x42` at `R3:R4`. The user program asks it to store into `0x0050`, then tries itself.
+fn machine() -> VirtualMachine {
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&encode_program(&[
+        ldi(Register::R0, 0x10),
+        ldi(Register::R1, 0x00),
+        Opcode::Jmp {
+            hi: Register::R0,
+            lo: Register::R1,
+        },
+        ldi(Register::R2, 0x42),
+        Opcode::St {
+            src: Register::R2,
+            hi: Register::R3,
+            lo: Register::R4,
+        },
+        Opcode::Ret,
+    ]));
+    let user = encode_program(&[
+        ldi(Register::R3, 0x00),
+        ldi(Register::R4, 0x50),
+        ldi(Register::R0, 0xE0),
+        ldi(Register::R1, 0x06),
+        Opcode::Call {
+            hi: Register::R0,
+            lo: Register::R1,
+        },
+        Opcode::St {
+            src: Register::R4,
+            hi: Register::R3,
+            lo: Register::R4,
+        },
+    ]);
+    for (addr, byte) in (0x1000..).zip(user) {
+        vm.devices.write(addr, byte);
+    }
+    vm.mpu = Some(
+        Mpu::new()
+            .region(0x1000..=0x1FFF, Permissions::ALL)
+            .region(0xBF00..=0xBFFF, Permissions::READ_WRITE)
+            .gate(GATE),
+    );
+    vm
+}
+
+#[test]
+fn test_syscall_gate_raises_privilege() {
+    let mut vm = machine();
+    assert_eq!(
+        vm.run(),
+        Err(Fault {
+            pc: 0x100A,
+            kind: FaultKind::ProtectionFault {
+                addr: 0x0050,
+                access: Access::Write,
+            },
+        })
+    );
+    // The kernel's store went through, the user's did not
+    assert_eq!(vm.devices.read(0x0050), 0x42);
+    assert_eq!(vm.mode, Mode::User);
+}
+
+#[test]
+fn test_user_code_cannot_jump_into_kernel() {
+    let mut vm = machine();
+    // Skip the gate and land on the kernel's store
+    vm.devices.write(0x1007, 0x08);
+    assert_eq!(
+        vm.run(),
+        Err(Fault {
+            pc: 0xE008,
+            kind: FaultKind::ProtectionFault {
+                addr: 0xE008,
+                access: Access::Execute,
+            },
+        })
+    );
+    assert_eq!(vm.devices.read(0x0050), 0x00);
+}
+
+#[test]
+fn test_snapshot_keeps_mode() {
+    let mut vm = machine();
+    for _ in 0..4 {
+        vm.step().unwrap();
+    }
+    assert_eq!(vm.mode, Mode::User);
+    let state = vm.save_state();
+
+    let mut restored = machine();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.mode, Mode::User);
+    assert_eq!(restored.run(), vm.run());
+}
@@ -1,13 +1,15 @@
 use mb8::{dev::Device, vm::VirtualMachine};
+use mb8_asm::assemble_file;
 
 #[test]
+#[allow(clippy::needless_range_loop)]
 fn test_sys_rand_deterministic() {
-    let bin = include_bytes!("../../../kernel/tests/test_sys_rand.bin");
+    let bin = assemble_file("../../kernel/tests/test_sys_rand.asm").unwrap();
 
     let mut vm1 = VirtualMachine::default();
     vm1.devices.rand().seed(234);
-    vm1.load_rom(bin);
-    vm1.run();
+    vm1.load_rom(&bin);
+    vm1.run().unwrap();
 
     let mut out1 = [0u8; 16];
     for i in 0..16 {
@@ -16,8 +18,8 @@ fn test_sys_rand_deterministic() {
 
     let mut vm2 = VirtualMachine::default();
     vm2.devices.rand().seed(234);
-    vm2.load_rom(bin);
-    vm2.run();
+    vm2.load_rom(&bin);
+    vm2.run().unwrap();
 
     let mut out2 = [0u8; 16];
     for i in 0..16 {
@@ -26,6 +28,6 @@ fn test_sys_rand_deterministic() {
 
     assert_eq!(out1, out2);
 
-    let rng_value = vm1.devices.rand().read(0);
+    let rng_value = vm1.devices.rand().read(0).unwrap();
     assert_ne!(out1[0], rng_value);
 }
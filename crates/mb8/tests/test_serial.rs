@@ -0,0 +1,27 @@
+use mb8::{
+    dev::uart::registers::{CTRL, CTRL_RX_IRQ},
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+
+#[test]
+fn test_sys_serial() {
+    let bin = assemble_file("../../kernel/tests/test_sys_serial.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.devices.uart().receive(b'A');
+    vm.run().unwrap();
+
+    assert_eq!(vm.devices.uart().take_output(), b"B");
+    assert!(vm.devices.uart().take_output().is_empty());
+}
+
+#[test]
+fn test_rx_interrupt_reaches_controller() {
+    let mut vm = VirtualMachine::default();
+    vm.devices.write(0xFA00 + CTRL, CTRL_RX_IRQ);
+    vm.devices.uart().receive(b'x');
+    vm.devices.poll_interrupts();
+    // IRQ_UART is line 2
+    assert_eq!(vm.devices.read(0xF501) & 0b100, 0b100);
+}
@@ -0,0 +1,78 @@
+use mb8::{snapshot::SnapshotError, vm::VirtualMachine};
+use mb8_asm::assemble_file;
+
+fn tty_text(vm: &mut VirtualMachine) -> Vec<u8> {
+    vm.devices.gpu().tty_buffer().to_vec()
+}
+
+#[test]
+fn test_snapshot_resumes_identically() {
+    let bin = assemble_file("../../kernel/tests/test_timer_irq.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run_for_cycles(200).unwrap();
+    vm.devices.keyboard().key_pressed(b'x');
+    let state = vm.save_state();
+
+    vm.run().unwrap();
+
+    let mut restored = VirtualMachine::default();
+    restored.load_state(&state).unwrap();
+    restored.run().unwrap();
+
+    assert_eq!(restored.cycles, vm.cycles);
+    assert_eq!(restored.program_counter, vm.program_counter);
+    assert_eq!(restored.save_state(), vm.save_state());
+}
+
+#[test]
+fn test_snapshot_keeps_devices() {
+    let bin = assemble_file("../../kernel/tests/test_sys_writeln.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.devices.rand().seed(99);
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    let mut restored = VirtualMachine::default();
+    restored.load_state(&vm.save_state()).unwrap();
+
+    assert!(restored.halted);
+    assert!(tty_text(&mut vm).starts_with(b"Hello, World!"));
+    assert_eq!(tty_text(&mut restored), tty_text(&mut vm));
+    assert_eq!(restored.devices.rand().number, 99);
+    assert_eq!(restored.devices.read(0xE000), vm.devices.read(0xE000));
+}
+
+#[test]
+fn test_snapshot_rejects_corrupt_data() {
+    let vm = VirtualMachine {
+        program_counter: 0x1234,
+        ..Default::default()
+    };
+    let state = vm.save_state();
+
+    let mut restored = VirtualMachine::default();
+    assert_eq!(
+        restored.load_state(&state[..state.len() - 1]),
+        Err(SnapshotError::Truncated)
+    );
+    assert_eq!(
+        restored.load_state(&[state.as_slice(), &[0]].concat()),
+        Err(SnapshotError::TrailingData)
+    );
+    assert_eq!(restored.load_state(b"nope"), Err(SnapshotError::BadMagic));
+    assert_eq!(restored.program_counter, 0xE000);
+}
+
+#[test]
+fn test_bus_snapshot() {
+    let mut vm = VirtualMachine::default();
+    vm.devices.write(0x0100, 0x42);
+    let state = vm.devices.save_state();
+
+    let mut restored = VirtualMachine::default();
+    restored.devices.load_state(&state).unwrap();
+    assert_eq!(restored.devices.read(0x0100), 0x42);
+}
@@ -0,0 +1,45 @@
+use mb8::{
+    dev::sound::{registers::*, write_wav},
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+
+#[test]
+fn test_sys_sound() {
+    let bin = assemble_file("../../kernel/tests/test_sys_sound.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    let base = 0xF900 + 2 * CHANNEL_REGS;
+    assert_eq!(vm.devices.read(base + FREQ_HI), 0x01);
+    assert_eq!(vm.devices.read(base + FREQ_LO), 0xB8);
+    assert_eq!(vm.devices.read(base + VOLUME), 12);
+    assert_eq!(vm.devices.read(base + ENVELOPE), 0);
+}
+
+#[test]
+fn test_capture_to_wav() {
+    let bin = assemble_file("../../kernel/tests/test_sys_sound.asm").unwrap();
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.devices.sound().capture(vm.clock_hz, 8_000);
+    vm.run().unwrap();
+    // Let the 440 Hz tone play for a tenth of a second
+    let cycles = u64::from(vm.clock_hz / 10);
+    vm.devices.tick(cycles);
+
+    let samples = vm.devices.sound().stop_capture();
+    assert!(samples.len() >= 800);
+    // Roughly 2 * 44 edges in 800 samples of a 440 Hz square wave
+    let edges = samples
+        .windows(2)
+        .filter(|pair| pair[0].signum() != pair[1].signum())
+        .count();
+    assert!((80..=96).contains(&edges), "{edges} edges");
+
+    let mut wav = Vec::new();
+    write_wav(&mut wav, 8_000, &samples).unwrap();
+    assert_eq!(wav.len(), 44 + samples.len() * 2);
+    assert_eq!(&wav[36..40], b"data");
+}
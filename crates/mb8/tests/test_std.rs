@@ -1,17 +1,18 @@
 use mb8::vm::VirtualMachine;
+use mb8_asm::assemble_file;
 use mb8_isa::registers::Register;
 
 #[test]
 fn test_std_memcpy() {
-    let bin = include_bytes!("../../../kernel/tests/test_std_memcpy.bin");
+    let bin = assemble_file("../../kernel/tests/test_std_memcpy.asm").unwrap();
     let mut vm = VirtualMachine::default();
 
     for i in 0..256 {
         vm.devices.write(i, i as u8);
     }
 
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     for i in 0..256 {
         assert_eq!(vm.devices.read(0x150 + i), i as u8, "{i:?}");
@@ -20,7 +21,7 @@ fn test_std_memcpy() {
 
 #[test]
 fn test_std_strcmp_eq() {
-    let bin = include_bytes!("../../../kernel/tests/test_std_strcmp.bin");
+    let bin = assemble_file("../../kernel/tests/test_std_strcmp.asm").unwrap();
     let mut vm = VirtualMachine::default();
 
     for i in 0..10 {
@@ -28,15 +29,15 @@ fn test_std_strcmp_eq() {
         vm.devices.write(i + 0x14, 228);
     }
 
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 0);
 }
 
 #[test]
 fn test_std_strcmp_neq() {
-    let bin = include_bytes!("../../../kernel/tests/test_std_strcmp.bin");
+    let bin = assemble_file("../../kernel/tests/test_std_strcmp.asm").unwrap();
     let mut vm = VirtualMachine::default();
 
     for i in 0..10 {
@@ -45,15 +46,15 @@ fn test_std_strcmp_neq() {
     }
     vm.devices.write(1, 255);
 
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 1);
 }
 
 #[test]
 fn test_std_strcmp_neq_len() {
-    let bin = include_bytes!("../../../kernel/tests/test_std_strcmp.bin");
+    let bin = assemble_file("../../kernel/tests/test_std_strcmp.asm").unwrap();
     let mut vm = VirtualMachine::default();
 
     for i in 0..10 {
@@ -63,8 +64,8 @@ fn test_std_strcmp_neq_len() {
         vm.devices.write(i + 0x14, 228);
     }
 
-    vm.load_rom(bin);
-    vm.run();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
 
     assert_eq!(vm.registers.read(Register::R0), 1);
 }
@@ -0,0 +1,68 @@
+use mb8::{
+    dev::{timer::registers, Device},
+    vm::VirtualMachine,
+};
+use mb8_asm::assemble_file;
+
+fn elapsed_ticks(vm: &mut VirtualMachine) -> u16 {
+    let first = u16::from_be_bytes([vm.devices.read(0x0200), vm.devices.read(0x0201)]);
+    let second = u16::from_be_bytes([vm.devices.read(0x0202), vm.devices.read(0x0203)]);
+    second - first
+}
+
+#[test]
+fn test_sys_ticks_counts_cycles() {
+    let bin = assemble_file("../../kernel/tests/test_sys_ticks.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    let ticks = elapsed_ticks(&mut vm);
+    assert!(ticks > 0);
+    assert!(u64::from(ticks) <= vm.cycles);
+}
+
+#[test]
+fn test_sys_ticks_prescaler() {
+    let bin = assemble_file("../../kernel/tests/test_sys_ticks.asm").unwrap();
+
+    let mut fast = VirtualMachine::default();
+    fast.load_rom(&bin);
+    fast.run().unwrap();
+
+    let mut slow = VirtualMachine::default();
+    slow.devices.timer().write(registers::PRESCALER, 3).unwrap();
+    slow.load_rom(&bin);
+    slow.run().unwrap();
+
+    let fast_ticks = elapsed_ticks(&mut fast);
+    let slow_ticks = elapsed_ticks(&mut slow);
+    assert!((fast_ticks / 4).abs_diff(slow_ticks) <= 1);
+}
+
+#[test]
+fn test_timer_one_shot() {
+    let bin = assemble_file("../../kernel/tests/test_timer_one_shot.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    assert!(vm.devices.read(0x0200) > 1);
+    assert_eq!(vm.devices.read(0x0201), registers::STATUS_EXPIRED);
+    assert_eq!(vm.devices.read(0x0202) & registers::CTRL_ENABLE, 0);
+}
+
+#[test]
+fn test_timer_irq() {
+    let bin = assemble_file("../../kernel/tests/test_timer_irq.asm").unwrap();
+
+    let mut vm = VirtualMachine::default();
+    vm.load_rom(&bin);
+    vm.run().unwrap();
+
+    assert_eq!(vm.devices.read(0x0200), 3);
+    // Three periods of 0x20 ticks at 4 cycles per tick
+    assert!(vm.cycles >= 3 * 0x20 * 4);
+}
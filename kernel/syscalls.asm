@@ -18,6 +18,20 @@ SYS_FS_DELETE = 0x0D
 SYS_EXEC = 0x0E
 SYS_EXIT = 0x0F
 SYS_RAND = 0x10
+SYS_TICKS = 0x11
+SYS_BANK = 0x12
+SYS_GPU_PLOT = 0x13
+SYS_GPU_CLEAR = 0x14
+SYS_TTY_CURSOR = 0x15
+SYS_TTY_COLOR = 0x16
+SYS_TTY_CLEAR = 0x17
+SYS_SOUND = 0x18
+SYS_SERIAL_WRITE = 0x19
+SYS_SERIAL_READ = 0x1A
+SYS_KEY_EVENT = 0x1B
+SYS_JOYSTICK = 0x1C
+SYS_DISK_SELECT = 0x1D
+SYS_DISK_STATUS = 0x1E
 
 #addr 0xE500
 K_SYSCALL_ENTRY:
@@ -84,8 +98,64 @@ syscall_table:
     JMP [sys_exit]
 .sys_rand:
     CMPI R0 SYS_RAND
-    JNZR [.not_found]
+    JNZR [.sys_ticks]
     JMP [sys_rand]
+.sys_ticks:
+    CMPI R0 SYS_TICKS
+    JNZR [.sys_bank]
+    JMP [sys_ticks]
+.sys_bank:
+    CMPI R0 SYS_BANK
+    JNZR [.sys_gpu_plot]
+    JMP [sys_bank]
+.sys_gpu_plot:
+    CMPI R0 SYS_GPU_PLOT
+    JNZR [.sys_gpu_clear]
+    JMP [sys_gpu_plot]
+.sys_gpu_clear:
+    CMPI R0 SYS_GPU_CLEAR
+    JNZR [.sys_tty_cursor]
+    JMP [sys_gpu_clear]
+.sys_tty_cursor:
+    CMPI R0 SYS_TTY_CURSOR
+    JNZR [.sys_tty_color]
+    JMP [sys_tty_cursor]
+.sys_tty_color:
+    CMPI R0 SYS_TTY_COLOR
+    JNZR [.sys_tty_clear]
+    JMP [sys_tty_color]
+.sys_tty_clear:
+    CMPI R0 SYS_TTY_CLEAR
+    JNZR [.sys_sound]
+    JMP [sys_tty_clear]
+.sys_sound:
+    CMPI R0 SYS_SOUND
+    JNZR [.sys_serial_write]
+    JMP [sys_sound]
+.sys_serial_write:
+    CMPI R0 SYS_SERIAL_WRITE
+    JNZR [.sys_serial_read]
+    JMP [sys_serial_write]
+.sys_serial_read:
+    CMPI R0 SYS_SERIAL_READ
+    JNZR [.sys_key_event]
+    JMP [sys_serial_read]
+.sys_key_event:
+    CMPI R0 SYS_KEY_EVENT
+    JNZR [.sys_joystick]
+    JMP [sys_key_event]
+.sys_joystick:
+    CMPI R0 SYS_JOYSTICK
+    JNZR [.sys_disk_select]
+    JMP [sys_joystick]
+.sys_disk_select:
+    CMPI R0 SYS_DISK_SELECT
+    JNZR [.sys_disk_status]
+    JMP [sys_disk_select]
+.sys_disk_status:
+    CMPI R0 SYS_DISK_STATUS
+    JNZR [.not_found]
+    JMP [sys_disk_status]
 .not_found:
     RET
 
@@ -451,3 +521,270 @@ sys_rand:
     LDI R7 0x00
     LD R0 [R6:R7]
     RET
+
+; Returns the free-running timer tick counter
+;
+; Input:
+; R0: sys_ticks
+;
+; Output:
+; R0:R1: Ticks, high byte first
+sys_ticks:
+    LDI R6 0xF6
+    LDI R7 0x07
+    LD R0 [R6:R7]
+    LDI R7 0x08
+    LD R1 [R6:R7]
+    RET
+
+; Selects the expansion memory bank shown at 0xC000-0xDFFF
+;
+; Input
+; R1: The bank to select
+;
+; Output
+; R0 - status (0 = success, 1 = no such bank)
+sys_bank:
+    ; Locals
+    ; R6:R7 = 0xF700
+    LDI R6 0xF7
+    LDI R7 0x01
+    LD R0 [R6:R7]
+    CMP R1 R0
+    JNCR [.error]
+
+    LDI R7 0x00
+    ST [R6:R7] R1
+    LDI R0 0
+    RET
+
+.error:
+    LDI R0 1
+    RET
+
+; Plots a pixel in bitmap mode
+;
+; Input
+; R1: X coordinate
+; R2: Y coordinate
+; R3: Palette index
+;
+; Output
+; None
+sys_gpu_plot:
+    ; Locals
+    ; R6:R7 = 0xF002
+    LDI R6 0xF0
+    LDI R7 0x02
+    ST [R6:R7] R1
+    LDI R7 0x03
+    ST [R6:R7] R2
+    LDI R7 0x04
+    ST [R6:R7] R3
+    RET
+
+; Fills the bitmap with one colour
+;
+; Input
+; R1: Palette index
+;
+; Output
+; None
+sys_gpu_clear:
+    ; Locals
+    ; R6:R7 = 0xF005
+    LDI R6 0xF0
+    LDI R7 0x05
+    ST [R6:R7] R1
+    RET
+
+; Moves the terminal cursor
+;
+; Input
+; R1: Column
+; R2: Row
+;
+; Output
+; None
+sys_tty_cursor:
+    ; Locals
+    ; R6:R7 = 0xF060
+    LDI R6 0xF0
+    LDI R7 0x60
+    ST [R6:R7] R1
+    LDI R7 0x61
+    ST [R6:R7] R2
+    RET
+
+; Sets the colour and style of the next characters
+;
+; Input
+; R1: Colour, foreground in the low nibble and background in the high nibble
+; R2: Style bits (0x01 inverse, 0x02 blink)
+;
+; Output
+; None
+sys_tty_color:
+    ; Locals
+    ; R6:R7 = 0xF062
+    LDI R6 0xF0
+    LDI R7 0x62
+    ST [R6:R7] R1
+    LDI R7 0x63
+    ST [R6:R7] R2
+    RET
+
+; Clears the terminal screen or the cursor's line
+;
+; Input
+; R1: Command (0x01 screen, 0x02 line)
+;
+; Output
+; None
+sys_tty_clear:
+    ; Locals
+    ; R6:R7 = 0xF064
+    LDI R6 0xF0
+    LDI R7 0x64
+    ST [R6:R7] R1
+    RET
+
+; Plays a tone or noise on a sound channel
+;
+; Input
+; R1: Channel (0-2 square wave, 3 noise)
+; R2: High byte of the frequency in Hz
+; R3: Low byte of the frequency in Hz
+; R4: Volume (0-15)
+; R5: Envelope decay rate (0 holds the volume)
+;
+; Output
+; None
+sys_sound:
+    ; Locals
+    ; R6:R7 = 0xF900 + channel * 4
+    ; R8 - step
+    LDI R6 0xF9
+    MOV R7 R1
+    LDI R8 2
+    SHL R7 R8
+    ST [R6:R7] R2
+    LDI R8 1
+    ADD R7 R8
+    ST [R6:R7] R3
+    LDI R8 2
+    ADD R7 R8
+    ST [R6:R7] R5
+    LDI R8 1
+    SUB R7 R8
+    ST [R6:R7] R4
+    RET
+
+; Sends a byte over the serial port, waiting until the UART has room for it
+;
+; Input
+; R1: The byte to send
+;
+; Output
+; None
+sys_serial_write:
+    ; Locals
+    ; R5 - status
+    ; R8 - TX ready mask
+    ; R6:R7 = 0xFA01
+    LDI R6 0xFA
+    LDI R7 0x01
+    LDI R8 0x02
+.wait:
+    LD R5 [R6:R7]
+    AND R5 R8
+    JZR [.wait]
+    LDI R7 0x00
+    ST [R6:R7] R1
+    RET
+
+; Reads a byte received over the serial port
+;
+; Input
+; None
+;
+; Output
+; R0: The byte received, 0 if none is waiting
+sys_serial_read:
+    ; Locals
+    ; R0 - return value
+    ; R6:R7 = 0xFA00
+    LDI R6 0xFA
+    LDI R7 0x00
+    LD R0 [R6:R7]
+    RET
+
+; Reads the next key press or release
+;
+; Input
+; None
+;
+; Output
+; R0: Scan code with bit 7 set for a release, 0 if no event is waiting
+; R1: Modifiers held (0x01 shift, 0x02 ctrl, 0x04 alt, 0x08 meta)
+sys_key_event:
+    ; Locals
+    ; R6:R7 = 0xF102
+    LDI R6 0xF1
+    LDI R7 0x02
+    LD R0 [R6:R7]
+    LDI R7 0x04
+    LD R1 [R6:R7]
+    RET
+
+; Reads a joystick port
+;
+; Input
+; R1: Port (0 or 1)
+;
+; Output
+; R0: State (0x01 up, 0x02 down, 0x04 left, 0x08 right, 0x10 fire)
+sys_joystick:
+    ; Locals
+    ; R6:R7 = 0xFB00 + port
+    LDI R6 0xFB
+    MOV R7 R1
+    LD R0 [R6:R7]
+    RET
+
+; Selects a drive and a 16-bit block for the next disk read or write
+;
+; Input
+; R1: Drive (0-3)
+; R2: High byte of the block
+; R3: Low byte of the block
+;
+; Output
+; None
+sys_disk_select:
+    ; Locals
+    ; R6:R7 = 0xF303 (drive), 0xF302 (block high), 0xF200 (block low)
+    LDI R6 0xF3
+    LDI R7 0x03
+    ST [R6:R7] R1
+    LDI R7 0x02
+    ST [R6:R7] R2
+    LDI R6 0xF2
+    LDI R7 0x00
+    ST [R6:R7] R3
+    RET
+
+; Reports the outcome of the last disk read or write
+;
+; Input
+; None
+;
+; Output
+; R0: Error code (0 none, 1 no disk, 2 block out of range, 3 unknown command, 4 I/O error)
+sys_disk_status:
+    ; Locals
+    ; R6:R7 = 0xF305
+    LDI R6 0xF3
+    LDI R7 0x05
+    LD R0 [R6:R7]
+    RET
@@ -0,0 +1,32 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_BANK
+    LDI R1 1
+    CALL [K_SYSCALL_ENTRY]
+    ST [0x0200] R0
+
+    LDI R2 0xAB
+    ST [0xC000] R2
+
+    LDI R0 SYS_BANK
+    LDI R1 0
+    CALL [K_SYSCALL_ENTRY]
+    LD R3 [0xC000]
+    ST [0x0201] R3
+
+    LDI R0 SYS_BANK
+    LDI R1 0xFF
+    CALL [K_SYSCALL_ENTRY]
+    ST [0x0202] R0
+    HALT
+
+#include "../syscalls.asm"
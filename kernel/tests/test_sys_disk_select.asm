@@ -0,0 +1,41 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    ; Buffer byte 0 = 0x5A
+    LDI R6 0xF2
+    LDI R7 0x02
+    LDI R5 0x5A
+    ST [R6:R7] R5
+
+    LDI R0 SYS_DISK_SELECT
+    LDI R1 1
+    LDI R2 0x01
+    LDI R3 0x02
+    CALL [K_SYSCALL_ENTRY]
+    LDI R0 SYS_DISK_WRITE_BLOCK
+    CALL [K_SYSCALL_ENTRY]
+    LDI R0 SYS_DISK_STATUS
+    CALL [K_SYSCALL_ENTRY]
+    MOV R4 R0
+
+    LDI R0 SYS_DISK_SELECT
+    LDI R1 1
+    LDI R2 0xFF
+    LDI R3 0xFF
+    CALL [K_SYSCALL_ENTRY]
+    LDI R0 SYS_DISK_READ_BLOCK
+    CALL [K_SYSCALL_ENTRY]
+    LDI R0 SYS_DISK_STATUS
+    CALL [K_SYSCALL_ENTRY]
+
+    HALT
+
+#include "../syscalls.asm"
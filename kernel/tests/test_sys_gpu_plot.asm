@@ -0,0 +1,27 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_GPU_MODE
+    LDI R1 0x02
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_GPU_CLEAR
+    LDI R1 0x01
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_GPU_PLOT
+    LDI R1 3
+    LDI R2 2
+    LDI R3 0x08
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
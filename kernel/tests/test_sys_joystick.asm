@@ -0,0 +1,17 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_JOYSTICK
+    LDI R1 1
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
@@ -0,0 +1,20 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_KEY_EVENT
+    CALL [K_SYSCALL_ENTRY]
+    MOV R2 R0
+    MOV R3 R1
+    LDI R0 SYS_KEY_EVENT
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
@@ -0,0 +1,21 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_SERIAL_READ
+    CALL [K_SYSCALL_ENTRY]
+    MOV R1 R0
+    LDI R2 1
+    ADD R1 R2
+    LDI R0 SYS_SERIAL_WRITE
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
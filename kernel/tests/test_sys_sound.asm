@@ -0,0 +1,21 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_SOUND
+    LDI R1 2
+    LDI R2 0x01
+    LDI R3 0xB8
+    LDI R4 12
+    LDI R5 0
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
@@ -0,0 +1,28 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_TICKS
+    CALL [K_SYSCALL_ENTRY]
+    ST [0x0200] R0
+    ST [0x0201] R1
+
+    LDI R5 20
+.wait:
+    DEC R5
+    JNZR [.wait]
+
+    LDI R0 SYS_TICKS
+    CALL [K_SYSCALL_ENTRY]
+    ST [0x0202] R0
+    ST [0x0203] R1
+    HALT
+
+#include "../syscalls.asm"
@@ -0,0 +1,39 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+start:
+    LDI R0 SYS_GPU_MODE
+    LDI R1 0x01
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_WRITE
+    LDI R1 "a"
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_TTY_CLEAR
+    LDI R1 0x01
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_TTY_CURSOR
+    LDI R1 10
+    LDI R2 3
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_TTY_COLOR
+    LDI R1 0x12
+    LDI R2 0x01
+    CALL [K_SYSCALL_ENTRY]
+
+    LDI R0 SYS_WRITE
+    LDI R1 "b"
+    CALL [K_SYSCALL_ENTRY]
+    HALT
+
+#include "../syscalls.asm"
@@ -0,0 +1,67 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+IRQ_ENABLE = 0xF500
+IRQ_PENDING = 0xF501
+IRQ_VECTOR_HI = 0xF502
+IRQ_VECTOR_LO = 0xF503
+TIMER_CTRL = 0xF600
+TIMER_STATUS = 0xF601
+TIMER_PRESCALER = 0xF602
+TIMER_RELOAD_HI = 0xF603
+TIMER_RELOAD_LO = 0xF604
+
+VECTORS = 0x0300
+COUNT = 0x0200
+
+start:
+    ; Vector table at 0x0300, timer is line 0
+    LDI R1 R2 timer_irq
+    ST [VECTORS] R1
+    ST [VECTORS + 1] R2
+    LDI R1 (VECTORS >> 8)
+    ST [IRQ_VECTOR_HI] R1
+    LDI R1 (VECTORS & 0xFF)
+    ST [IRQ_VECTOR_LO] R1
+    LDI R1 0x01
+    ST [IRQ_ENABLE] R1
+
+    ; Periodic timer, 0x20 ticks of 4 cycles, with interrupt
+    LDI R1 0x03
+    ST [TIMER_PRESCALER] R1
+    LDI R1 0x00
+    ST [TIMER_RELOAD_HI] R1
+    LDI R1 0x20
+    ST [TIMER_RELOAD_LO] R1
+    LDI R1 0x07
+    ST [TIMER_CTRL] R1
+
+    LDI R1 0
+    ST [COUNT] R1
+    EI
+
+    ; Wait for three interrupts
+.wait:
+    LD R1 [COUNT]
+    CMPI R1 3
+    JNZR [.wait]
+    DI
+    HALT
+
+timer_irq:
+    LD R3 [COUNT]
+    INC R3
+    ST [COUNT] R3
+    LDI R3 0x01
+    ST [TIMER_STATUS] R3
+    ST [IRQ_PENDING] R3
+    RETI
+
+#include "../syscalls.asm"
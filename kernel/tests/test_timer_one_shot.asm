@@ -0,0 +1,39 @@
+#include "../../asm/isa.asm"
+
+#bankdef rom
+{
+    #addr 0xE000
+    #size 0x1000
+    #outp 0
+    #fill
+}
+
+TIMER_CTRL = 0xF600
+TIMER_STATUS = 0xF601
+TIMER_RELOAD_HI = 0xF603
+TIMER_RELOAD_LO = 0xF604
+
+start:
+    ; 0x0040 ticks, one-shot
+    LDI R1 0x00
+    ST [TIMER_RELOAD_HI] R1
+    LDI R1 0x40
+    ST [TIMER_RELOAD_LO] R1
+    LDI R1 0x01
+    ST [TIMER_CTRL] R1
+
+    ; Count polls until the timer expires
+    LDI R5 0
+.poll:
+    INC R5
+    LD R1 [TIMER_STATUS]
+    CMPI R1 0x00
+    JZR [.poll]
+
+    ST [0x0200] R5
+    ST [0x0201] R1
+    LD R1 [TIMER_CTRL]
+    ST [0x0202] R1
+    HALT
+
+#include "../syscalls.asm"